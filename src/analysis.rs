@@ -0,0 +1,280 @@
+//! Works out what code depends on without running it, e.g. which inputs a formula needs
+//! before it is evaluated.
+
+use std::collections::HashSet;
+
+use crate::{
+    constants::constants,
+    eval::{parse, EvalError},
+    expr::Expr,
+    functions::{builtints, FunctionDescriptor, FunctionType},
+};
+
+/// The variables `source` reads or assigns without declaring them first, in the order they
+/// first appear. Constants and functions aren't included, and names bound by `let`, loops and
+/// parameters only count outside of where they are bound.
+pub fn free_variables(source: &str) -> Result<Vec<String>, EvalError> {
+    Ok(Dependencies::of(&parse(source)?).free)
+}
+
+/// The functions `source` calls, in the order they first appear. Calls of closures held in
+/// local variables aren't included. Operators count as calls of the builtins they run, e.g.
+/// `a + b` of `add`, the same as `a[i]` counts as one of `index`.
+pub fn called_functions(source: &str) -> Result<Vec<String>, EvalError> {
+    Ok(Dependencies::of(&parse(source)?).calls)
+}
+
+struct Dependencies {
+    /// Names bound so far, innermost scope last.
+    scopes: Vec<HashSet<String>>,
+    /// Builtins, constants and every function the code declares, which are never free.
+    globals: HashSet<String>,
+    free: Vec<String>,
+    calls: Vec<String>,
+}
+
+impl Dependencies {
+    fn of(program: &[Expr]) -> Self {
+        let mut globals = builtints()
+            .keys()
+            .cloned()
+            .chain(constants().into_keys())
+            .collect::<HashSet<_>>();
+        // Declarations are hoisted, so a call can come before the function it calls.
+        declared_functions(program, &mut globals);
+
+        let mut dependencies = Self {
+            scopes: vec![HashSet::new()],
+            globals,
+            free: vec![],
+            calls: vec![],
+        };
+        dependencies.block(program);
+        dependencies
+    }
+
+    fn is_bound(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    fn bind(&mut self, name: &str) {
+        self.scopes.last_mut().unwrap().insert(name.to_string());
+    }
+
+    fn read(&mut self, name: &str) {
+        if !self.is_bound(name)
+            && !self.globals.contains(name)
+            && !self.free.iter().any(|n| n == name)
+        {
+            self.free.push(name.to_string());
+        }
+    }
+
+    fn call(&mut self, name: &str) {
+        if !self.is_bound(name) && !self.calls.iter().any(|n| n == name) {
+            self.calls.push(name.to_string());
+        }
+    }
+
+    /// Walks `block` in a scope of its own, with `bindings` already bound in it.
+    fn scoped<'a>(&mut self, bindings: impl IntoIterator<Item = &'a String>, block: &[Expr]) {
+        self.scopes.push(bindings.into_iter().cloned().collect());
+        self.block(block);
+        self.scopes.pop();
+    }
+
+    fn block(&mut self, block: &[Expr]) {
+        for e in block {
+            self.expr(e);
+        }
+    }
+
+    fn function(&mut self, desc: &FunctionDescriptor) {
+        if let FunctionType::Custom(body, params, defaults, _) = &desc.function {
+            for default in defaults.iter().flatten() {
+                self.expr(default);
+            }
+            self.scoped(params, body);
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        if let Some(name) = operator(e) {
+            self.call(name);
+        }
+
+        match e {
+            Expr::Num(_) | Expr::Bool(_) | Expr::String(_) | Expr::Null | Expr::Import(_, None) => {
+            }
+            Expr::Break(_, None) | Expr::Continue(_) | Expr::Return(None) | Expr::Construct(..) => {
+            }
+            Expr::Interpolate(parts) | Expr::Array(parts) | Expr::Tuple(parts) => self.block(parts),
+            Expr::Map(entries) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::FunctionValue(desc) | Expr::FunctionDeclaration(_, desc) => self.function(desc),
+            Expr::Assert(cond, message) => {
+                self.expr(cond);
+                if let Some(message) = message {
+                    self.expr(message);
+                }
+            }
+            Expr::Neg(e)
+            | Expr::Not(e)
+            | Expr::Return(Some(e))
+            | Expr::Break(_, Some(e))
+            | Expr::NamedArgument(_, e)
+            | Expr::Spread(e)
+            | Expr::Field(e, _) => self.expr(e),
+            Expr::Add(a, b)
+            | Expr::Sub(a, b)
+            | Expr::Mul(a, b)
+            | Expr::Div(a, b)
+            | Expr::IntDiv(a, b)
+            | Expr::Mod(a, b)
+            | Expr::Gt(a, b)
+            | Expr::Lt(a, b)
+            | Expr::Ge(a, b)
+            | Expr::Le(a, b)
+            | Expr::Eq(a, b)
+            | Expr::Ne(a, b)
+            | Expr::And(a, b)
+            | Expr::Or(a, b)
+            | Expr::Xor(a, b) => {
+                self.expr(a);
+                self.expr(b);
+            }
+            Expr::Chain(first, rest) => {
+                for (comparison, _) in rest {
+                    self.call(comparison.name());
+                }
+                self.expr(first);
+                for (_, e) in rest {
+                    self.expr(e);
+                }
+            }
+            Expr::Block(block) | Expr::Loop(_, block) => self.scoped([], block),
+            Expr::Function(name, args) | Expr::SafeCall(name, args) => {
+                self.call(name);
+                self.block(args);
+            }
+            Expr::Variable(name) => self.read(name),
+            // Calls on the module pass its name like a variable, and `Color.Red` reads `Color`.
+            Expr::Import(_, Some(name)) | Expr::EnumDeclaration(name, _) => self.bind(name),
+            Expr::Destructure(names, rest, value) => {
+                self.expr(value);
+                for name in names.iter().chain(rest) {
+                    self.bind(name);
+                }
+            }
+            Expr::VariableDeclaration(name, value) | Expr::ConstantDeclaration(name, value) => {
+                self.expr(value);
+                self.bind(name);
+            }
+            Expr::Assign(name, value) => {
+                self.expr(value);
+                self.read(name);
+            }
+            Expr::AssignPath(name, path, value) => {
+                self.block(path);
+                self.expr(value);
+                self.read(name);
+            }
+            Expr::If(cond, block, elifs, else_block) => {
+                self.expr(cond);
+                self.scoped([], block);
+                for (cond, block) in elifs {
+                    self.expr(cond);
+                    self.scoped([], block);
+                }
+                if let Some(block) = else_block {
+                    self.scoped([], block);
+                }
+            }
+            Expr::While(_, cond, block, else_block) => {
+                self.expr(cond);
+                self.scoped([], block);
+                if let Some(block) = else_block {
+                    self.scoped([], block);
+                }
+            }
+            Expr::TryCatch(block, name, catch_block) => {
+                self.scoped([], block);
+                self.scoped([name], catch_block);
+            }
+            Expr::DoWhile(_, block, cond) => {
+                self.scoped([], block);
+                self.expr(cond);
+            }
+            Expr::For(_, index, var, array, block, else_block) => {
+                self.expr(array);
+                self.scoped(index.iter().chain([var]), block);
+                if let Some(block) = else_block {
+                    self.scoped([], block);
+                }
+            }
+        }
+    }
+}
+
+/// The builtin an operator runs.
+fn operator(e: &Expr) -> Option<&'static str> {
+    let name = match e {
+        Expr::Neg(_) => "neg",
+        Expr::Not(_) => "not",
+        Expr::Add(..) => "add",
+        Expr::Sub(..) => "sub",
+        Expr::Mul(..) => "mul",
+        Expr::Div(..) => "div",
+        Expr::IntDiv(..) => "idiv",
+        Expr::Mod(..) => "mod",
+        Expr::Gt(..) => "gt",
+        Expr::Lt(..) => "lt",
+        Expr::Ge(..) => "ge",
+        Expr::Le(..) => "le",
+        Expr::Eq(..) => "eq",
+        Expr::Ne(..) => "ne",
+        Expr::And(..) => "and",
+        Expr::Or(..) => "or",
+        Expr::Xor(..) => "xor",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Adds the name of every function declared anywhere in `block` to `names`.
+fn declared_functions(block: &[Expr], names: &mut HashSet<String>) {
+    for e in block {
+        match e {
+            Expr::FunctionDeclaration(name, desc) => {
+                names.insert(name.clone());
+                if let FunctionType::Custom(body, ..) = &desc.function {
+                    declared_functions(body, names);
+                }
+            }
+            Expr::TryCatch(block, _, catch_block) => {
+                declared_functions(block, names);
+                declared_functions(catch_block, names);
+            }
+            Expr::Block(block)
+            | Expr::While(_, _, block, _)
+            | Expr::DoWhile(_, block, _)
+            | Expr::Loop(_, block)
+            | Expr::For(_, _, _, _, block, _) => declared_functions(block, names),
+            Expr::If(_, block, elifs, else_block) => {
+                declared_functions(block, names);
+                for (_, block) in elifs {
+                    declared_functions(block, names);
+                }
+                if let Some(block) = else_block {
+                    declared_functions(block, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,574 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
+
+use chumsky::Parser;
+use logos::Logos;
+
+use crate::{
+    expr::{ExecutionState, Expr},
+    functions::{FunctionDescriptor, FunctionType},
+    lexer::{end_lines, Token},
+    parser::parser,
+    warning::{Warning, WarningKind},
+};
+
+type Span = Range<usize>;
+
+/// Separates a call's name from the index of its token while the calls are checked.
+const TAG: char = '#';
+
+/// Looks for code that can never run and calls that can't work. This works on the token
+/// stream, parsing it only to check calls, so that warnings can point at the source and be
+/// silenced by comments. Input that doesn't lex produces no warnings, the lexer reports those errors
+/// itself.
+pub fn check(input: &str) -> Vec<Warning> {
+    let mut tokens = vec![];
+    // Lines with an `// allow(...)` comment, and the lint each one allows.
+    let mut allowed = vec![];
+
+    for (token, span) in Token::lexer(input).spanned() {
+        match token {
+            Ok(Token::Comment(comment)) => {
+                let lint = comment
+                    .trim_start_matches('/')
+                    .trim()
+                    .strip_prefix("allow(")
+                    .and_then(|lint| lint.strip_suffix(')'));
+                if let Some(lint) = lint {
+                    allowed.push((line_of(input, span.start), lint.to_string()));
+                }
+            }
+            Ok(Token::DocComment(_)) => {}
+            Ok(token) => tokens.push((token, span)),
+            Err(_) => return vec![],
+        }
+    }
+
+    let mut checker = Checker {
+        tokens: end_lines(input, tokens),
+        warnings: vec![],
+    };
+    checker.block(0);
+    checker.calls();
+    checker
+        .warnings
+        .sort_by_key(|w| w.span.clone().map(|s| s.start));
+
+    checker
+        .warnings
+        .into_iter()
+        .filter(|w| {
+            !w.span.as_ref().is_some_and(|s| {
+                let line = line_of(input, s.start);
+                allowed.contains(&(line, w.kind.name().to_string()))
+            })
+        })
+        .collect()
+}
+
+fn line_of(input: &str, offset: usize) -> usize {
+    input[..offset.min(input.len())].matches('\n').count()
+}
+
+struct Checker {
+    tokens: Vec<(Token, Span)>,
+    warnings: Vec<Warning>,
+}
+
+struct Statement {
+    span: Span,
+    /// Whether running this statement always leaves the enclosing block.
+    terminates: bool,
+}
+
+impl Checker {
+    fn token(&self, i: usize) -> Option<&Token> {
+        self.tokens.get(i).map(|(t, _)| t)
+    }
+
+    /// Checks the statements from `i` up to the `}` closing this block (or the end of the
+    /// input). Returns the index after that `}` and whether the block always terminates.
+    fn block(&mut self, mut i: usize) -> (usize, bool) {
+        let mut statements = vec![];
+
+        loop {
+            match self.token(i) {
+                None => break,
+                Some(Token::BlockEnd) => {
+                    i += 1;
+                    break;
+                }
+                // A stray `;`, see `parser`.
+                Some(Token::Eol) => i += 1,
+                Some(_) => {
+                    let (next, statement) = self.statement(i);
+                    statements.push(statement);
+                    i = next;
+                }
+            }
+        }
+
+        let terminator = statements.iter().position(|s| s.terminates);
+
+        if let Some(t) = terminator {
+            if let (Some(first), Some(last)) = (statements.get(t + 1), statements.last()) {
+                self.warnings.push(Warning {
+                    kind: WarningKind::Unreachable,
+                    message: "Unreachable code: the statement before this always leaves the block."
+                        .to_string(),
+                    span: Some(first.span.start..last.span.end),
+                });
+            }
+        }
+
+        (i, terminator.is_some())
+    }
+
+    /// Reads one statement starting at `start`, checking any blocks inside it.
+    fn statement(&mut self, start: usize) -> (usize, Statement) {
+        // A label, `'outer:`, comes before the loop keyword.
+        let keyword = match self.token(start) {
+            Some(Token::Label(_)) => start + 2,
+            _ => start,
+        };
+        let is_loop_or_fn = matches!(
+            self.token(keyword),
+            Some(Token::While | Token::For | Token::Fn)
+        );
+        let is_if = self.token(start) == Some(&Token::If);
+        let is_exit = matches!(
+            self.token(start),
+            Some(Token::Return | Token::Break | Token::Continue)
+        ) || matches!(self.token(start), Some(Token::Ident(name)) if name == "exit")
+            && self.token(start + 1) == Some(&Token::LParen);
+
+        let mut depth = 0;
+        let mut i = start;
+        // For an if statement: whether every branch seen so far terminates, and whether it has
+        // an else branch (without one, not running any branch is always possible).
+        let mut branches_terminate = true;
+        let mut has_else = false;
+
+        let end = loop {
+            let Some(token) = self.token(i).cloned() else {
+                break i;
+            };
+
+            match token {
+                Token::LParen | Token::ArrayStart => depth += 1,
+                Token::RParen | Token::ArrayEnd => depth -= 1,
+                Token::Eol if depth == 0 => break i + 1,
+                // The end of the enclosing block, for a trailing expression without a `;`.
+                Token::BlockEnd => break i,
+                Token::If | Token::ElseIf | Token::While => self.condition(i),
+                Token::Else if depth == 0 && is_if && self.token(i + 1) != Some(&Token::If) => {
+                    has_else = true
+                }
+                Token::BlockStart => {
+                    let (next, terminates) = self.block(i + 1);
+                    if depth == 0 && is_if {
+                        branches_terminate &= terminates;
+                    }
+
+                    let continues = matches!(self.token(next), Some(Token::Else | Token::ElseIf));
+                    if depth == 0 && is_loop_or_fn && !continues {
+                        break next;
+                    }
+
+                    i = next;
+                    continue;
+                }
+                _ => {}
+            }
+
+            i += 1;
+        };
+
+        let span = self.tokens[start].1.start..self.tokens[end.max(start + 1) - 1].1.end;
+        let terminates = is_exit || (is_if && has_else && branches_terminate);
+
+        (end, Statement { span, terminates })
+    }
+
+    /// Warns about `if`, `elif` and `while` conditions that are just `true` or `false`.
+    fn condition(&mut self, i: usize) {
+        let (keyword, span) = self.tokens[i].clone();
+
+        let Some(value) = (match self.token(i + 1) {
+            Some(Token::True) => Some(true),
+            Some(Token::False) => Some(false),
+            _ => None,
+        }) else {
+            return;
+        };
+        if self.token(i + 2) != Some(&Token::BlockStart) {
+            return;
+        }
+
+        let message = match (keyword, value) {
+            (_, false) => "This condition is always false, so its block never runs.".to_string(),
+            (Token::While, true) => return,
+            (_, true) => {
+                let has_later_branches = self.later_branches(i + 2);
+                if !has_later_branches {
+                    return;
+                }
+                "This condition is always true, so the branches after it never run.".to_string()
+            }
+        };
+
+        self.warnings.push(Warning {
+            kind: WarningKind::ConstantCondition,
+            message,
+            span: Some(span.start..self.tokens[i + 1].1.end),
+        });
+    }
+
+    /// Whether the block starting at `i` is followed by an `elif` or `else`.
+    fn later_branches(&self, i: usize) -> bool {
+        let mut depth = 0;
+
+        for (j, (token, _)) in self.tokens.iter().enumerate().skip(i) {
+            match token {
+                Token::BlockStart => depth += 1,
+                Token::BlockEnd => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return matches!(self.token(j + 1), Some(Token::Else | Token::ElseIf));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    /// Checks every call of a builtin or declared function against its parameters: the
+    /// number of arguments, and the type of any argument `Expr::data_type` knows. Anything
+    /// it doesn't is assumed to be fine, and input that doesn't parse isn't checked, the
+    /// parser reports those errors itself.
+    ///
+    /// The program is parsed with each call's name tagged with the index of its token,
+    /// `name#index`, which is only used to point the warnings at the source. Calls without
+    /// one, like the `index` that `a[i]` stands for, aren't in the source to point at.
+    fn calls(&mut self) {
+        let tokens = self
+            .tokens
+            .iter()
+            .enumerate()
+            .map(|(i, (token, _))| match token {
+                Token::Ident(name) if self.is_call(i) => Token::Ident(format!("{name}{TAG}{i}")),
+                token => token.clone(),
+            })
+            .collect::<Vec<_>>();
+        let Ok(mut program) = parser().parse(tokens) else {
+            return;
+        };
+
+        let mut declarations = Declarations::default();
+        for e in &mut program {
+            declarations.collect(e);
+        }
+        let mut state = ExecutionState::new();
+        for (name, desc) in declarations.functions.drain() {
+            Arc::make_mut(&mut state.functions).insert(name, desc);
+        }
+
+        for e in &mut program {
+            self.check_calls(e, &state, &declarations);
+        }
+    }
+
+    /// Whether the token at `i` is the name of a call, rather than of a declaration.
+    fn is_call(&self, i: usize) -> bool {
+        let before = i.checked_sub(1).and_then(|i| self.token(i));
+
+        matches!(self.token(i), Some(Token::Ident(_)))
+            && self.token(i + 1) == Some(&Token::LParen)
+            && !matches!(before, Some(Token::Fn | Token::Record | Token::Struct))
+    }
+
+    /// Checks the calls in `e`, innermost first, taking the tags off their names on the way
+    /// so that `data_type` can look them up.
+    fn check_calls(&mut self, e: &mut Expr, state: &ExecutionState, declarations: &Declarations) {
+        for child in children(e) {
+            self.check_calls(child, state, declarations);
+        }
+
+        let (Expr::Function(name, args) | Expr::SafeCall(name, args)) = e else {
+            return;
+        };
+        let Some((untagged, site)) = name
+            .rsplit_once(TAG)
+            .and_then(|(name, site)| Some((name.to_string(), site.parse::<usize>().ok()?)))
+        else {
+            return;
+        };
+        *name = untagged;
+
+        if declarations.ambiguous.contains(name) {
+            return;
+        }
+        let Some(desc) = state.functions.get(name) else {
+            return;
+        };
+        // Method calls pass the receiver as the first argument.
+        let before = site.checked_sub(1).and_then(|i| self.token(i));
+        let method = matches!(before, Some(Token::Dot | Token::SafeDot));
+        // `vec.length()` calls a function of a module, which isn't known here.
+        if method
+            && matches!(args.first(), Some(Expr::Variable(m)) if declarations.modules.contains(m))
+        {
+            return;
+        }
+        // A spread argument can stand for any number of them.
+        if args
+            .iter()
+            .any(|arg| matches!(arg, Expr::NamedArgument(..) | Expr::Spread(_)))
+        {
+            return;
+        }
+
+        let params = &desc.inputs;
+        let rest = &desc.inputs_rest;
+        // Parameters with a default value come last.
+        let required = match &desc.function {
+            FunctionType::Custom(_, _, defaults, _) => defaults
+                .iter()
+                .position(Option::is_some)
+                .unwrap_or(params.len()),
+            _ => params.len(),
+        };
+        let close = self.closing(site + 1);
+        let call_span = self.tokens[site].1.start..self.tokens[close].1.end;
+
+        let count = args.len();
+        if count < required || (rest.is_none() && count > params.len()) {
+            let takes = if rest.is_some() {
+                format!("at least {required}")
+            } else if required == params.len() {
+                params.len().to_string()
+            } else {
+                format!("{required} to {}", params.len())
+            };
+            self.warnings.push(Warning {
+                kind: WarningKind::ArgumentMismatch,
+                message: format!(
+                    "`{name}` takes {takes} argument{}, but {count} {} passed.",
+                    if params.len() == 1 && rest.is_none() {
+                        ""
+                    } else {
+                        "s"
+                    },
+                    if count == 1 { "is" } else { "are" },
+                ),
+                span: Some(call_span),
+            });
+            return;
+        }
+
+        let spans = self.argument_spans(site + 2, close);
+        let expected_types = params.iter().chain(rest.iter().cycle());
+        for (n, (arg, expected)) in args
+            .iter()
+            .zip(expected_types)
+            .enumerate()
+            .skip(method as usize)
+        {
+            let found = arg.data_type(state);
+            if expected.admits(&found, |name| state.is_record(name)) {
+                continue;
+            }
+
+            // The tokens only place the warning, when they split up like the arguments did.
+            let span = match spans.get(n - method as usize) {
+                Some(span) if spans.len() + method as usize == count => span.clone(),
+                _ => call_span.clone(),
+            };
+            self.warnings.push(Warning {
+                kind: WarningKind::ArgumentMismatch,
+                message: format!(
+                    "Argument {} of `{name}` should be a `{expected}`, but this is a `{found}`.",
+                    n + 1
+                ),
+                span: Some(span),
+            });
+        }
+    }
+
+    /// The index of the `)` closing the `(` at `open`.
+    fn closing(&self, open: usize) -> usize {
+        let mut depth = 0;
+
+        for (i, (token, _)) in self.tokens.iter().enumerate().skip(open) {
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 1 => return i,
+                Token::RParen => depth -= 1,
+                _ => {}
+            }
+        }
+
+        self.tokens.len() - 1
+    }
+
+    /// The source spans of the arguments from `start` up to the `)` at `close`, split on top
+    /// level commas.
+    fn argument_spans(&self, start: usize, close: usize) -> Vec<Span> {
+        let mut spans = vec![];
+        let mut depth = 0;
+        // Inside a closure's `|a, b|`, whose commas don't separate arguments.
+        let mut params = false;
+        let mut arg_start = start;
+
+        for i in start..close {
+            match self.tokens[i].0 {
+                Token::LParen | Token::ArrayStart | Token::BlockStart => depth += 1,
+                Token::RParen | Token::ArrayEnd | Token::BlockEnd => depth -= 1,
+                Token::Bar => params = !params,
+                Token::Comma if depth == 0 && !params => {
+                    if arg_start < i {
+                        spans.push(self.tokens[arg_start].1.start..self.tokens[i - 1].1.end);
+                    }
+                    arg_start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if arg_start < close {
+            spans.push(self.tokens[arg_start].1.start..self.tokens[close - 1].1.end);
+        }
+
+        spans
+    }
+}
+
+/// What calls are checked against: the functions a program declares, the names declared more
+/// than once, since either declaration could be the one called, and the names files are
+/// imported as, with `import "path" as name;`.
+#[derive(Default)]
+struct Declarations {
+    functions: HashMap<String, FunctionDescriptor>,
+    ambiguous: HashSet<String>,
+    modules: HashSet<String>,
+}
+
+impl Declarations {
+    fn collect(&mut self, e: &mut Expr) {
+        match e {
+            Expr::FunctionDeclaration(name, desc) => {
+                let redeclared = self.functions.insert(name.clone(), desc.clone()).is_some();
+                if redeclared {
+                    self.ambiguous.insert(name.clone());
+                }
+            }
+            Expr::Import(_, Some(name)) => {
+                self.modules.insert(name.clone());
+            }
+            _ => {}
+        }
+
+        for child in children(e) {
+            self.collect(child);
+        }
+    }
+}
+
+/// The expressions directly inside `e`, including the bodies and default values of the
+/// functions it declares.
+fn children(e: &mut Expr) -> Vec<&mut Expr> {
+    match e {
+        Expr::Num(_)
+        | Expr::Bool(_)
+        | Expr::String(_)
+        | Expr::Null
+        | Expr::Variable(_)
+        | Expr::Construct(..)
+        | Expr::EnumDeclaration(..)
+        | Expr::Import(..)
+        | Expr::Continue(_)
+        | Expr::Return(None)
+        | Expr::Break(_, None) => vec![],
+        Expr::Interpolate(parts)
+        | Expr::Array(parts)
+        | Expr::Tuple(parts)
+        | Expr::Block(parts)
+        | Expr::Loop(_, parts)
+        | Expr::Function(_, parts)
+        | Expr::SafeCall(_, parts) => parts.iter_mut().collect(),
+        Expr::Map(entries) => entries
+            .iter_mut()
+            .flat_map(|(key, value)| [key, value])
+            .collect(),
+        Expr::FunctionValue(desc) | Expr::FunctionDeclaration(_, desc) => {
+            match &mut desc.function {
+                FunctionType::Custom(body, _, defaults, _) => body
+                    .iter_mut()
+                    .chain(defaults.iter_mut().flatten())
+                    .collect(),
+                FunctionType::BuiltIn(_) | FunctionType::BuiltInWithState(_) => vec![],
+            }
+        }
+        Expr::Neg(e)
+        | Expr::Not(e)
+        | Expr::Return(Some(e))
+        | Expr::Break(_, Some(e))
+        | Expr::NamedArgument(_, e)
+        | Expr::Spread(e)
+        | Expr::Field(e, _)
+        | Expr::VariableDeclaration(_, e)
+        | Expr::ConstantDeclaration(_, e)
+        | Expr::Destructure(_, _, e)
+        | Expr::Assign(_, e) => vec![e],
+        Expr::Add(a, b)
+        | Expr::Sub(a, b)
+        | Expr::Mul(a, b)
+        | Expr::Div(a, b)
+        | Expr::IntDiv(a, b)
+        | Expr::Mod(a, b)
+        | Expr::Gt(a, b)
+        | Expr::Lt(a, b)
+        | Expr::Ge(a, b)
+        | Expr::Le(a, b)
+        | Expr::Eq(a, b)
+        | Expr::Ne(a, b)
+        | Expr::And(a, b)
+        | Expr::Or(a, b)
+        | Expr::Xor(a, b) => vec![a, b],
+        Expr::Chain(first, rest) => [&mut **first]
+            .into_iter()
+            .chain(rest.iter_mut().map(|(_, e)| e))
+            .collect(),
+        Expr::Assert(cond, message) => [&mut **cond]
+            .into_iter()
+            .chain(message.as_deref_mut())
+            .collect(),
+        Expr::AssignPath(_, path, value) => path.iter_mut().chain([&mut **value]).collect(),
+        Expr::TryCatch(block, _, catch_block) => block.iter_mut().chain(catch_block).collect(),
+        Expr::If(cond, block, elifs, else_block) => [&mut **cond]
+            .into_iter()
+            .chain(block)
+            .chain(
+                elifs
+                    .iter_mut()
+                    .flat_map(|(cond, block)| [cond].into_iter().chain(block)),
+            )
+            .chain(else_block.iter_mut().flatten())
+            .collect(),
+        Expr::While(_, cond, block, else_block) => [&mut **cond]
+            .into_iter()
+            .chain(block)
+            .chain(else_block.iter_mut().flatten())
+            .collect(),
+        Expr::DoWhile(_, block, cond) => block.iter_mut().chain([&mut **cond]).collect(),
+        Expr::For(_, _, _, array, block, else_block) => [&mut **array]
+            .into_iter()
+            .chain(block)
+            .chain(else_block.iter_mut().flatten())
+            .collect(),
+    }
+}
@@ -0,0 +1,170 @@
+use std::{fmt::Display, str::FromStr};
+
+use strum::VariantArray;
+
+use calculator::{functions::Capability, warning::WarningPolicy};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Expressions evaluated in order against one state, printing the last (or every) result.
+    Calc {
+        expressions: Vec<String>,
+        print_all: bool,
+    },
+    Run(String),
+    /// Report code that can never run without running anything.
+    Check(String),
+    /// Read, evaluate and print one line at a time.
+    Repl,
+    Help,
+    Version,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    /// Don't print the AST and execution banner.
+    pub quiet: bool,
+    /// Print every statement to stderr before it is evaluated.
+    pub trace: bool,
+    pub seed: Option<u64>,
+    /// Capabilities builtins may use, or `None` to allow everything. `--sandbox` starts from an
+    /// empty set and every `--allow` adds to it.
+    pub allowed: Option<Vec<Capability>>,
+    /// Everything after a literal `--`, exposed to scripts through `args()`.
+    pub script_args: Vec<String>,
+    pub warnings: WarningPolicy,
+}
+
+const FLAGS: &str = "--quiet, --trace, --sandbox, --allow <capabilities>, --seed <number>, \
+    --deny-warnings, --no-warnings, --expr/-e <expression> (calc), --print-all (calc)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgError(String);
+
+impl Display for ArgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses `<command> [flags] <input> [-- script args]`, without the binary name.
+///
+/// Interpreter flags must come before the script path (or expression); anything meant for
+/// the script goes after `--` so it can never be mistaken for an interpreter flag.
+pub fn parse_args<I>(args: I) -> Result<(Command, RunOptions), ArgError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let mut options = RunOptions::default();
+
+    let command = match args.next().as_deref() {
+        Some("calc") => "calc",
+        Some("run") => "run",
+        Some("repl") => "repl",
+        Some("check") => "check",
+        Some("help" | "--help" | "-h") | None => return Ok((Command::Help, options)),
+        Some("version" | "--version" | "-V") => return Ok((Command::Version, options)),
+        Some(other) => return Err(ArgError(format!("Invalid command `{other}`."))),
+    };
+
+    let mut expressions = vec![];
+    let mut print_all = false;
+
+    let input = loop {
+        match args.next().as_deref() {
+            Some("--quiet" | "-q") => options.quiet = true,
+            Some("--trace") => options.trace = true,
+            Some("--deny-warnings") => options.warnings = WarningPolicy::Deny,
+            Some("--no-warnings") => options.warnings = WarningPolicy::Ignore,
+            Some("--sandbox") => {
+                options.allowed.get_or_insert_with(Vec::new);
+            }
+            Some("--allow") => {
+                let list = args.next().ok_or(ArgError(
+                    "Expected a comma separated list of capabilities after `--allow`.".into(),
+                ))?;
+                let allowed = options.allowed.get_or_insert_with(Vec::new);
+
+                for name in list.split(',') {
+                    allowed.push(Capability::from_str(name.trim()).map_err(|_| {
+                        ArgError(format!(
+                            "Unknown capability `{name}`. Valid capabilities are: {}.",
+                            Capability::VARIANTS
+                                .iter()
+                                .map(|c| c.to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ))
+                    })?);
+                }
+            }
+            Some("--seed") => {
+                let seed = args
+                    .next()
+                    .ok_or(ArgError("Expected a number after `--seed`.".into()))?;
+                options.seed = Some(seed.parse().map_err(|_| {
+                    ArgError(format!(
+                        "Invalid seed `{seed}`, expected a positive integer."
+                    ))
+                })?);
+            }
+            Some("--expr" | "-e") if command == "calc" => expressions.push(
+                args.next()
+                    .ok_or(ArgError("Expected an expression after `--expr`.".into()))?,
+            ),
+            Some("--print-all") if command == "calc" => print_all = true,
+            Some("--") => {
+                options.script_args = args.by_ref().collect();
+                break None;
+            }
+            Some(flag) if flag.starts_with("--") => {
+                return Err(ArgError(format!(
+                    "Unknown flag `{flag}`. Valid options are: {FLAGS}."
+                )))
+            }
+            Some(input) => break Some(input.to_string()),
+            None => break None,
+        }
+    };
+
+    if let Some(input) = &input {
+        match args.next().as_deref() {
+            Some("--") => options.script_args = args.collect(),
+            Some(other) => {
+                return Err(ArgError(format!(
+                    "Unexpected argument `{other}` after `{input}`. Interpreter flags ({FLAGS}) go before it, script arguments go after `--`."
+                )))
+            }
+            None => {}
+        }
+    }
+
+    let command = match (command, input) {
+        ("calc", input) => {
+            expressions.extend(input);
+
+            if expressions.is_empty() {
+                return Err(ArgError(
+                    "Expected expression as second arguement (e.g. `1 + 7 * (3 - 4) / 5`)".into(),
+                ));
+            }
+
+            Command::Calc {
+                expressions,
+                print_all,
+            }
+        }
+        ("repl", None) => Command::Repl,
+        ("repl", Some(input)) => {
+            return Err(ArgError(format!(
+                "Unexpected argument `{input}`, `repl` doesn't take an input."
+            )))
+        }
+        ("check", Some(path)) => Command::Check(path),
+        (_, Some(path)) => Command::Run(path),
+        (_, None) => return Err(ArgError("Expected file path as second argument.".into())),
+    };
+
+    Ok((command, options))
+}
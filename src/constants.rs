@@ -2,10 +2,22 @@ use std::collections::HashMap;
 
 use rust_decimal::Decimal;
 
-use crate::{
-    data::{Data, ToData},
-    expr::VariableMap,
-};
+use crate::{data::Data, expr::VariableMap};
+
+/// Every optional cargo feature and whether this build was compiled with it.
+pub const FEATURES: &[(&str, bool)] = &[
+    ("net", cfg!(feature = "net")),
+    ("exec", cfg!(feature = "exec")),
+    ("wasm", cfg!(feature = "wasm")),
+];
+
+pub fn enabled_features() -> Vec<&'static str> {
+    FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect()
+}
 
 pub fn constants() -> VariableMap {
     let mut map = HashMap::new();
@@ -1,18 +1,34 @@
-use std::fmt::{Display, Write};
+use std::{collections::BTreeMap, fmt::Display};
 
 use rust_decimal::Decimal;
-use strum::{EnumIs, EnumString, VariantArray};
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use strum::{EnumIs, EnumString};
 
-use crate::{expr::EResult, functions::FunctionDescriptor, utils::strings::DotDisplay};
+use crate::{
+    expr::{error::ExprError, EResult},
+    functions::FunctionDescriptor,
+    utils::strings::DotDisplay,
+};
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+pub type DataMap = BTreeMap<String, Data>;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Data {
     Number(Decimal),
     Bool(bool),
     String(String),
     Null,
     Array(Vec<Data>),
+    /// A fixed group of values, e.g. `(1, "x")`, for pairing up values of different types.
+    Tuple(Vec<Data>),
     Function(FunctionDescriptor),
+    Map(DataMap),
+    /// A map made by a `record` constructor, tagged with the record's name. It is a `Map` to
+    /// the type system, so map builtins take it too.
+    Record(String, DataMap),
+    /// A variant of an `enum`, e.g. `Color.Red`, as the enum's name and the variant's.
+    Enum(String, String),
     // Function(String),
     // Array(Array),
 }
@@ -28,15 +44,17 @@ impl Display for Data {
                 Self::Null => "null".into(),
                 Self::String(s) => s.clone(),
                 Self::Array(a) => format_vec(a),
-                Self::Function(f) => {
-                    format!("fn({}) -> {}", format_types(f.inputs.clone()), f.output)
-                }
+                Self::Tuple(t) => format_tuple(t),
+                Self::Map(m) => format_map(m),
+                Self::Record(name, fields) => format!("{name} {}", format_map(fields)),
+                Self::Enum(name, variant) => format!("{name}.{variant}"),
+                Self::Function(f) => f.signature(),
             }
         )
     }
 }
 
-#[derive(Clone, Debug, Copy, PartialEq, EnumIs, VariantArray, EnumString, Eq)]
+#[derive(Clone, Debug, PartialEq, EnumIs, EnumString, Eq, Serialize, Deserialize)]
 pub enum DataType {
     Number,
     Bool,
@@ -44,7 +62,12 @@ pub enum DataType {
     Any,
     String,
     Array,
+    Tuple,
     Function,
+    Map,
+    /// An enum or record declared with this name, taking only its own variants or records.
+    #[strum(disabled)]
+    Named(String),
 }
 
 impl Data {
@@ -55,7 +78,10 @@ impl Data {
             Data::Null => DataType::Null,
             Data::String(_) => DataType::String,
             Data::Array(_) => DataType::Array,
+            Data::Tuple(_) => DataType::Tuple,
             Data::Function(_) => DataType::Function,
+            Data::Map(_) | Data::Record(..) => DataType::Map,
+            Data::Enum(name, _) => DataType::Named(name.clone()),
         }
     }
 
@@ -91,6 +117,14 @@ impl Data {
         }
     }
 
+    /// USE WITH CAUTION: panics if input type is not map!!!
+    pub fn map(&self) -> DataMap {
+        match self {
+            Data::Map(m) | Data::Record(_, m) => m.clone(),
+            _ => unreachable!(),
+        }
+    }
+
     /// USE WITH CAUTION: panics if input type is not function!!!
     pub fn function(&self) -> &FunctionDescriptor {
         match self {
@@ -99,18 +133,50 @@ impl Data {
         }
     }
 
-    pub fn is_true(&self) -> bool {
+    /// Interprets the value as a condition. Only `Bool` is accepted, anything else is an
+    /// `InvalidDataType` error naming the construct in `loc`.
+    pub fn condition(&self, loc: &str) -> EResult<bool> {
         if let Data::Bool(b) = self {
-            *b
+            Ok(*b)
         } else {
-            false
+            Err(ExprError::InvalidDataType {
+                expected: DataType::Bool.to_string(),
+                found: self._type().to_string(),
+                loc: loc.to_string(),
+            })
+        }
+    }
+}
+
+impl DataType {
+    /// Whether `data` can be passed where this type is expected. A record is a `Map` as well as
+    /// its own type.
+    pub fn accepts(&self, data: &Data) -> bool {
+        match (self, data) {
+            (DataType::Any, _) => true,
+            (DataType::Named(name), Data::Enum(of, _) | Data::Record(of, _)) => name == of,
+            (expected, data) => data._type() == *expected,
+        }
+    }
+
+    /// Whether an expression typed `found` by `Expr::data_type` fits where this type is
+    /// expected. `Any` on either side could be anything, and a record is a `Map` as well as its
+    /// own type, which `is_record` tells apart from an enum.
+    pub fn admits(&self, found: &DataType, is_record: impl Fn(&str) -> bool) -> bool {
+        match (self, found) {
+            (DataType::Any, _) | (_, DataType::Any) => true,
+            (DataType::Map, DataType::Named(name)) => is_record(name),
+            (expected, found) => expected == found,
         }
     }
 }
 
 impl Display for DataType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Self::Named(name) => write!(f, "{name}"),
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
@@ -142,6 +208,12 @@ impl ToData for Vec<Data> {
     }
 }
 
+impl ToData for DataMap {
+    fn data(self) -> EResult<Data> {
+        Ok(Data::Map(self))
+    }
+}
+
 impl ToData for usize {
     fn data(self) -> EResult<Data> {
         Ok(Data::Number(Decimal::from(self)))
@@ -154,6 +226,12 @@ impl ToData for isize {
     }
 }
 
+impl ToData for i64 {
+    fn data(self) -> EResult<Data> {
+        Ok(Data::Number(Decimal::from(self)))
+    }
+}
+
 impl ToData for &str {
     fn data(self) -> EResult<Data> {
         Ok(Data::String(self.to_string()))
@@ -190,29 +268,59 @@ impl Ord for Data {
         match (self, other) {
             (Data::Number(a), Data::Number(b)) => a.cmp(b),
             (Data::String(a), Data::String(b)) => a.cmp(b),
-            (Data::Array(a), Data::Array(b)) => a.cmp(b),
-            _ => panic!("Cannot compare data types"),
+            (Data::Array(a), Data::Array(b)) | (Data::Tuple(a), Data::Tuple(b)) => a.cmp(b),
+            (Data::Map(a), Data::Map(b)) => a.cmp(b),
+            (Data::Record(a, x), Data::Record(b, y)) => (a, x).cmp(&(b, y)),
+            (Data::Enum(a, x), Data::Enum(b, y)) => (a, x).cmp(&(b, y)),
+            (Data::Bool(a), Data::Bool(b)) => a.cmp(b),
+            // Functions have no meaningful order, they just need to sort somewhere.
+            (a, b) => a.rank().cmp(&b.rank()),
         }
     }
 }
 
-pub fn format_types(types: Vec<DataType>) -> String {
-    let mut t = String::new();
-
-    write!(t, "(").unwrap();
-    let len = types.len();
-
-    for (i, ty) in types.into_iter().enumerate() {
-        if i < len - 1 {
-            write!(t, "{}, ", ty).unwrap();
-        } else {
-            write!(t, "{}", ty).unwrap();
+impl Data {
+    /// Where values of this type sort relative to other types, so that sorting a mixed array
+    /// groups values by type instead of failing.
+    fn rank(&self) -> u8 {
+        match self {
+            Data::Null => 0,
+            Data::Bool(_) => 1,
+            Data::Number(_) => 2,
+            Data::String(_) => 3,
+            Data::Array(_) => 4,
+            Data::Tuple(_) => 5,
+            Data::Map(_) => 6,
+            Data::Record(..) => 7,
+            Data::Enum(..) => 8,
+            Data::Function(_) => 9,
         }
     }
+}
 
-    write!(t, ")").unwrap();
+pub fn format_types(types: Vec<DataType>) -> String {
+    format!(
+        "({})",
+        types
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
 
-    t
+/// `(1, 2)`, with a trailing comma for a single item, `(1,)`, so it doesn't read as grouping.
+pub fn format_tuple<T>(v: &[T]) -> String
+where
+    T: Display,
+{
+    match v {
+        [single] => format!("({single},)"),
+        _ => format!(
+            "({})",
+            v.iter().map(|e| e.display()).collect::<Vec<_>>().join(", ")
+        ),
+    }
 }
 
 pub fn format_vec<T>(v: &[T]) -> String
@@ -224,3 +332,125 @@ where
         &v.iter().map(|e| e.display()).collect::<Vec<_>>().join(", ")
     )
 }
+
+/// How many elements of an array or map are shown before the rest are elided, unless changed
+/// with `set_display_limit`.
+pub const DEFAULT_DISPLAY_LIMIT: usize = 100;
+
+/// How `print`, `println` and the REPL write numbers, set with `set_number_display`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum NumberDisplay {
+    /// Written out in full, unless so large or so small that scientific notation is easier to
+    /// read, see `AUTO_SCIENTIFIC_ABOVE` and `AUTO_SCIENTIFIC_BELOW`.
+    #[default]
+    Auto,
+    /// One digit before the point, e.g. `4.7e-6`.
+    Scientific,
+    /// Exponents in multiples of 3 to match SI prefixes, e.g. `47e-6` for 47 micro.
+    Engineering,
+}
+
+/// Numbers at least this large are written in scientific notation in `auto` mode.
+pub const AUTO_SCIENTIFIC_ABOVE: Decimal = dec!(1e21);
+/// Numbers smaller than this, other than zero, are written in scientific notation in `auto`
+/// mode.
+pub const AUTO_SCIENTIFIC_BELOW: Decimal = dec!(1e-7);
+
+impl NumberDisplay {
+    pub fn format(self, n: Decimal) -> String {
+        let magnitude = n.abs();
+
+        match self {
+            _ if n.is_zero() => "0".to_string(),
+            Self::Auto
+                if magnitude < AUTO_SCIENTIFIC_ABOVE && magnitude >= AUTO_SCIENTIFIC_BELOW =>
+            {
+                n.to_string()
+            }
+            Self::Auto | Self::Scientific => exponent_notation(n, 1),
+            Self::Engineering => exponent_notation(n, 3),
+        }
+    }
+}
+
+/// `n` as a mantissa and an exponent that is a multiple of `step`, with as few digits before
+/// the point as that allows. All of `n`'s digits are kept.
+fn exponent_notation(n: Decimal, step: i64) -> String {
+    let n = n.normalize();
+    let all_digits = n.mantissa().unsigned_abs().to_string();
+    let exponent = all_digits.len() as i64 - 1 - n.scale() as i64;
+    let digits = all_digits.trim_end_matches('0');
+    let shown = exponent.div_euclid(step) * step;
+
+    // How many digits go before the point.
+    let whole = (exponent - shown + 1) as usize;
+    let mantissa = if digits.len() > whole {
+        format!("{}.{}", &digits[..whole], &digits[whole..])
+    } else {
+        format!("{digits:0<whole$}")
+    };
+    let sign = if n.is_sign_negative() { "-" } else { "" };
+
+    format!("{sign}{mantissa}e{shown}")
+}
+
+impl Data {
+    /// Like `to_string`, but arrays and maps with more than `limit` elements show the first
+    /// `limit` and how many more there are. For output meant to be read, `string()` and the
+    /// like stay exact.
+    pub fn truncated(&self, limit: usize) -> String {
+        self.shown(limit, NumberDisplay::Auto)
+    }
+
+    /// Like `truncated`, with numbers written as `numbers` says. A `limit` of 0 shows every
+    /// element.
+    pub fn shown(&self, limit: usize, numbers: NumberDisplay) -> String {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let elided = |len: usize| (len > limit).then(|| format!("… {} more", len - limit));
+
+        match self {
+            Data::Number(n) => numbers.format(*n),
+            Data::Tuple(t) => format_tuple(
+                &t.iter()
+                    .map(|e| e.shown(limit, numbers))
+                    .collect::<Vec<_>>(),
+            ),
+            Data::Array(a) => {
+                let items = a.iter().take(limit).map(|e| e.shown(limit, numbers));
+                format!(
+                    "[{}]",
+                    items.chain(elided(a.len())).collect::<Vec<_>>().join(", ")
+                )
+            }
+            Data::Map(m) | Data::Record(_, m) => {
+                let entries = m
+                    .iter()
+                    .take(limit)
+                    .map(|(k, v)| format!("{k}: {}", v.shown(limit, numbers)));
+                let name = match self {
+                    Data::Record(name, _) => format!("{name} "),
+                    _ => String::new(),
+                };
+                format!(
+                    "{name}{{{}}}",
+                    entries
+                        .chain(elided(m.len()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
+            other => other.to_string(),
+        }
+    }
+}
+
+pub fn format_map(m: &DataMap) -> String {
+    format!(
+        "{{{}}}",
+        m.iter()
+            .map(|(k, v)| format!("{k}: {v}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
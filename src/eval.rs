@@ -0,0 +1,122 @@
+use std::fmt::Display;
+
+use chumsky::{error::Simple, BoxedParser, Parser};
+use rayon::prelude::*;
+
+use crate::{
+    data::Data,
+    execute_program,
+    expr::{error::ExprError, ExecutionState, Expr, SharedState},
+    lexer::{tokens, validate_input, LexerError, Token},
+    parser::{error_message, parser},
+};
+
+/// Anything that can go wrong between source text and a value.
+#[derive(Debug)]
+pub enum EvalError {
+    Lexer(LexerError),
+    Parser(Vec<Simple<Token>>),
+    Runtime(ExprError),
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lexer(e) => write!(f, "{}", e.message()),
+            Self::Parser(errs) => write!(
+                f,
+                "{}",
+                errs.iter()
+                    .map(error_message)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+            Self::Runtime(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+// The binary only prints errors, these are for code embedding the interpreter.
+impl EvalError {
+    /// The kind of IO failure behind a runtime error, see [`ExprError::io_kind`].
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        match self {
+            Self::Runtime(e) => e.io_kind(),
+            _ => None,
+        }
+    }
+}
+
+impl From<ExprError> for EvalError {
+    fn from(e: ExprError) -> Self {
+        Self::Runtime(e)
+    }
+}
+
+thread_local! {
+    /// Building the parser takes longer than running it on a short input, so each thread
+    /// builds it once.
+    static PARSER: BoxedParser<'static, Token, Vec<Expr>, Simple<Token>> = parser().boxed();
+}
+
+/// Lexes and parses `input` into the statements it is made of.
+pub fn parse(input: &str) -> Result<Vec<Expr>, EvalError> {
+    let tokens = tokens(input).map_err(EvalError::Lexer)?;
+
+    validate_input(input).map_err(EvalError::Lexer)?;
+
+    PARSER
+        .with(|parser| parser.parse(tokens))
+        .map_err(EvalError::Parser)
+}
+
+/// Evaluates `input` against `state`, keeping any declarations it makes.
+pub fn eval_with_state(input: &str, state: &mut ExecutionState) -> Result<Data, EvalError> {
+    let expressions = parse(input)?;
+
+    // Nothing is kept from an input that fails.
+    let mut new_state = state.clone();
+    let output = execute_program(&expressions, &mut new_state)?;
+    *state = new_state;
+
+    Ok(output)
+}
+
+/// Evaluates each input in order against one state. On failure, returns the index of the
+/// input that failed alongside the error.
+pub fn eval_all(
+    inputs: &[String],
+    state: &mut ExecutionState,
+) -> Result<Vec<Data>, (usize, EvalError)> {
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| eval_with_state(input, state).map_err(|e| (i, e)))
+        .collect()
+}
+
+/// Evaluates each input on its own against a child of `base`, so nothing one does is seen by
+/// another, returning every result in order.
+pub fn eval_many(base: &SharedState, inputs: &[&str]) -> Vec<Result<Data, EvalError>> {
+    inputs
+        .iter()
+        .map(|input| eval_in_child(base, input))
+        .collect()
+}
+
+/// `eval_many`, spread across threads. Builtins that print or use random numbers may
+/// interleave differently from one run to the next.
+pub fn par_eval_many(base: &SharedState, inputs: &[&str]) -> Vec<Result<Data, EvalError>> {
+    inputs
+        .par_iter()
+        .map(|input| eval_in_child(base, input))
+        .collect()
+}
+
+/// The child is thrown away afterwards, so unlike `eval_with_state` there is nothing to
+/// roll back on failure and no copy to make.
+fn eval_in_child(base: &SharedState, input: &str) -> Result<Data, EvalError> {
+    let expressions = parse(input)?;
+
+    Ok(execute_program(&expressions, &mut base.child())?)
+}
@@ -0,0 +1,137 @@
+//! Runs the `>>> ` examples embedded in user facing text (the CLI help, function docs) so they
+//! can't silently go out of date.
+//!
+//! An example is a line starting with `>>> `, optionally continued by lines starting with
+//! `... `, followed by the expected output lines. The expected output ends at the next example
+//! or blank line. Examples are evaluated in a sandboxed state with a fixed random seed.
+
+use chumsky::Parser;
+
+use crate::{
+    execute_program, expr::ExecutionState, functions::seed_rng, lexer::tokens, parser::parser,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Example {
+    pub code: String,
+    pub expected: Vec<String>,
+}
+
+pub fn extract_examples(text: &str) -> Vec<Example> {
+    let mut examples: Vec<Example> = vec![];
+    let mut in_example = false;
+
+    for line in text.lines().map(str::trim) {
+        if let Some(code) = line.strip_prefix(">>> ") {
+            examples.push(Example {
+                code: code.to_string(),
+                expected: vec![],
+            });
+            in_example = true;
+        } else if !in_example {
+            continue;
+        } else if line.is_empty() {
+            in_example = false;
+        } else if let (Some(code), Some(example)) = (line.strip_prefix("... "), examples.last_mut())
+        {
+            example.code.push('\n');
+            example.code.push_str(code);
+        } else if let Some(example) = examples.last_mut() {
+            example.expected.push(line.to_string());
+        }
+    }
+
+    examples
+}
+
+/// Evaluates an example, returning what a user would see: the value or the error message.
+pub fn evaluate(code: &str) -> String {
+    let tokens = match tokens(code) {
+        Ok(tokens) => tokens,
+        Err(e) => return format!("lexer error: {e:?}"),
+    };
+
+    let expressions = match parser().parse(tokens) {
+        Ok(expressions) => expressions,
+        Err(errs) => return format!("parser error: {errs:?}"),
+    };
+
+    seed_rng(0);
+
+    match execute_program(&expressions, &mut ExecutionState::sandboxed()) {
+        Ok(output) => output.to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Checks every example in `text`, describing each mismatch along with `source`.
+pub fn check_examples(source: &str, text: &str) -> Result<(), String> {
+    let failures = extract_examples(text)
+        .into_iter()
+        .filter_map(|example| {
+            let found = evaluate(&example.code);
+            let expected = example.expected.join("\n");
+
+            (found != expected).then(|| {
+                format!(
+                    "example in {source} failed:\n>>> {}\n- expected: {expected}\n+ found:    {found}",
+                    example.code
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("\n\n"))
+    }
+}
+
+#[test]
+fn help_examples() {
+    if let Err(e) = check_examples("HELP", crate::HELP) {
+        panic!("{e}");
+    }
+}
+
+#[test]
+fn builtin_doc_examples() {
+    let failures = crate::functions::builtin_groups()
+        .into_iter()
+        .flat_map(|(_, functions)| functions)
+        .filter_map(|(name, desc)| check_examples(name, desc.doc.as_ref()?).err())
+        .collect::<Vec<_>>();
+
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn wrong_examples_are_reported() {
+    let err = check_examples("sqrt", ">>> sqrt(16);\n5").unwrap_err();
+
+    assert!(err.contains("example in sqrt failed"));
+    assert!(err.contains("- expected: 5"));
+    assert!(err.contains("+ found:    4"));
+}
+
+#[test]
+fn examples_are_extracted() {
+    let examples = extract_examples(
+        "Some text\n  >>> let x := 2;\n  ... x * 3;\n  6\n\n  3\n  >>> PI > 3;\n  true",
+    );
+
+    assert_eq!(
+        examples,
+        vec![
+            Example {
+                code: "let x := 2;\nx * 3;".to_string(),
+                expected: vec!["6".to_string()],
+            },
+            Example {
+                code: "PI > 3;".to_string(),
+                expected: vec!["true".to_string()],
+            },
+        ]
+    );
+}
@@ -1,6 +1,13 @@
+use strum::IntoStaticStr;
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+use crate::{
+    data::{Data, DataMap, DataType},
+    functions::Capability,
+};
+
+/// The variant names double as the `kind` of an error caught by `catch`.
+#[derive(Debug, Error, IntoStaticStr)]
 pub enum ExprError {
     #[error("Attempted to divide by 0.")]
     DivideBy0,
@@ -12,10 +19,25 @@ pub enum ExprError {
         // name: String,
     },
 
+    /// `call` is the function's name and parameter list, e.g. `center(s: String, ...)`.
+    #[error("There is no parameter called `{name}` in `{call}`.")]
+    UnknownArgument { name: String, call: String },
+    #[error("Argument `{name}` is passed to `{function}` more than once.")]
+    DuplicateArgument { function: String, name: String },
+    #[error("No argument passed to `{function}` for parameter `{name}`.")]
+    MissingArgument { function: String, name: String },
+    #[error("Positional arguments to `{function}` must come before named ones.")]
+    PositionalAfterNamed { function: String },
+
     #[error("Function `{name}` not found.")]
     FunctionNotFound { name: String },
     #[error("Variable {name} does not exist. Has it been declared?")]
     VariableNotFound { name: String },
+    #[error("Cannot assign to `{name}` before it is declared. Use `let {name} := ...;` first.")]
+    UndeclaredAssignment { name: String },
+
+    #[error("`{name}` is a constant and can't be assigned to.")]
+    ConstantRedefined { name: String },
 
     #[error("Invalid data type found in {loc}. Expected `{expected}`, found `{found}`.")]
     InvalidDataType {
@@ -24,12 +46,211 @@ pub enum ExprError {
         loc: String,
     },
 
-    #[error("Failed to parse regex: {0}")]
-    InvalidRegex(String),
+    #[error("Failed to parse regex: {pattern}")]
+    InvalidRegex {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+    #[error("A regex can't be run on {length} bytes of input, the limit is {limit}.")]
+    RegexInputTooLong { length: usize, limit: usize },
+
+    #[error("Could not access `{path}`: {source}")]
+    IoError {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("`{path}` is not a valid session file: {reason}.")]
+    InvalidFormat { path: String, reason: String },
+
+    #[error("`{path}` could not be imported: {reason}")]
+    InvalidImport { path: String, reason: String },
+
+    #[error("Imports can't be circular: {chain}.")]
+    CircularImport { chain: String },
+
+    #[error("`{operation}` is undefined: {reason}.")]
+    MathDomain { operation: String, reason: String },
 
     #[error("Array is empty.")]
     ArrayIsEmpty,
 
+    #[error("Index {index} is out of bounds for an array of length {length}.")]
+    IndexOutOfBounds { index: String, length: usize },
+
+    #[error("Key `{key}` not found in map.")]
+    KeyNotFound { key: String },
+    #[error("Can't destructure an array of length {found} into {expected} names.")]
+    DestructureTooShort { expected: usize, found: usize },
+    #[error("There is no snapshot with handle {handle}. It may have been dropped.")]
+    UnknownSnapshot { handle: String },
+    #[error("Record `{record}` has no field `{field}`.")]
+    UnknownField { record: String, field: String },
+    /// A signature naming a type that is neither builtin nor a declared enum or record. `loc`
+    /// is where, e.g. "function `f` signature".
+    #[error("Unknown type `{name}` in {loc}.")]
+    UnknownType { name: String, loc: String },
+    #[error("Enum `{name}` has no variant `{variant}`.")]
+    UnknownVariant { name: String, variant: String },
+    #[error("Can't compare a `{left}` with a `{right}`, they are different enums.")]
+    EnumMismatch { left: String, right: String },
+
+    #[error("Cannot assign to `{name}` at index step {position}: {source}")]
+    AssignPath {
+        name: String,
+        position: usize,
+        #[source]
+        source: Box<ExprError>,
+    },
+
+    /// An error from the function passed to `bench`, with the run it happened on (from 1).
+    #[error(
+        "`bench` failed on {}iteration {iteration}: {source}",
+        if *warm_up { "warm-up " } else { "" }
+    )]
+    BenchFailed {
+        iteration: usize,
+        warm_up: bool,
+        #[source]
+        source: Box<ExprError>,
+    },
+
+    #[error("Key `{key}` appears more than once in a map literal.")]
+    DuplicateKey { key: String },
+
     #[error("Invalid numeric string: {0}. Could not decode.")]
     BadNumber(String),
+
+    #[error("Function `{name}` output type does not match block data type. Expected `{expected}`, found `{found}`. If you don't know what the output will be, you can use the Any type.")]
+    OutputTypeMismatch {
+        name: String,
+        expected: DataType,
+        found: DataType,
+    },
+
+    /// Not really an error: carries a `return` value up to the function call it leaves. Boxed,
+    /// like `Break`'s, to keep errors small.
+    #[error("`return` can only be used inside a function.")]
+    Return(Box<Data>),
+
+    /// Signals that stop or skip a loop iteration, caught by the enclosing loop, or by the
+    /// loop with their label if they have one. A `break` carries its value out of a `loop`,
+    /// the other loops drop it.
+    #[error("`break` can only be used inside a loop.")]
+    Break(Option<String>, Box<Data>),
+    #[error("`continue` can only be used inside a loop.")]
+    Continue(Option<String>),
+
+    /// A `break` or `continue` at the top of a script, or in a function body but not in a loop
+    /// inside it.
+    #[error("`{keyword}` used outside of a loop.")]
+    LoopControlOutsideLoop { keyword: String },
+
+    #[error("No enclosing loop is labeled `'{label}`.")]
+    UnknownLabel { label: String },
+
+    /// Execution was stopped by Ctrl-C.
+    #[error("interrupted")]
+    Interrupted,
+
+    /// The state's `steps_left` ran out, e.g. in a loop that never ends.
+    #[error("Ran out of steps.")]
+    OutOfSteps,
+
+    /// Raised by `exit(code)`. Whoever runs the script decides what ending it means, e.g. the
+    /// binary exits the process with `code`.
+    #[error("Exited with code {0}.")]
+    Exit(i32),
+
+    #[error("Warning treated as an error: {message}")]
+    DeniedWarning { message: String },
+
+    #[error("`{name}` requires the `{capability}` capability, which is not allowed.")]
+    SandboxViolation {
+        name: String,
+        capability: Capability,
+    },
+
+    /// An `assert` whose condition was false. `message` has the condition, and the message
+    /// passed to `assert` if there is one.
+    #[error("Assertion failed: {message}.")]
+    AssertionFailed { message: String },
+
+    /// Raised by a script with `error(message)`.
+    #[error("{message}")]
+    Raised { message: String },
+}
+
+impl ExprError {
+    /// Turns a `break` or `continue` that reached a function or program boundary into an
+    /// error, so it can't stop a loop in the caller.
+    pub fn outside_loop(self) -> Self {
+        match self {
+            Self::Break(Some(label), _) | Self::Continue(Some(label)) => {
+                Self::UnknownLabel { label }
+            }
+            Self::Break(None, _) => Self::LoopControlOutsideLoop {
+                keyword: "break".to_string(),
+            },
+            Self::Continue(None) => Self::LoopControlOutsideLoop {
+                keyword: "continue".to_string(),
+            },
+            e => e,
+        }
+    }
+
+    /// Whether `try` can catch this. Control flow isn't an error, and Ctrl-C, running out of
+    /// steps and `exit` have to stop the script whatever it's doing.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            Self::Return(_)
+                | Self::Break(..)
+                | Self::Continue(_)
+                | Self::Interrupted
+                | Self::OutOfSteps
+                | Self::Exit(_)
+        )
+    }
+
+    /// What `catch e` binds `e` to: an `Error` record with the `message` and the `kind`, the
+    /// name of the variant, e.g. `DivideBy0`.
+    pub fn to_data(&self) -> Data {
+        let kind: &'static str = self.into();
+        let fields = DataMap::from([
+            ("message".to_string(), Data::String(self.to_string())),
+            ("kind".to_string(), Data::String(kind.to_string())),
+        ]);
+
+        Data::Record("Error".to_string(), fields)
+    }
+
+    /// Whether this is a `break` or `continue` for a loop further out than the one labeled
+    /// `label`, which has to let it through.
+    pub fn escapes(&self, label: &Option<String>) -> bool {
+        match self {
+            Self::Break(Some(target), _) | Self::Continue(Some(target)) => {
+                label.as_ref() != Some(target)
+            }
+            _ => false,
+        }
+    }
+
+    /// What kind of IO failure this was, if it was one, e.g. to tell a missing file apart from
+    /// a permission problem.
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        match self {
+            Self::IoError { source, .. } => Some(source.kind()),
+            _ => None,
+        }
+    }
+
+    pub fn regex_error(&self) -> Option<&regex::Error> {
+        match self {
+            Self::InvalidRegex { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
@@ -1,42 +1,300 @@
 use std::{
-    borrow::Borrow,
+    cell::{Cell, RefCell},
     collections::HashMap,
     fmt::{Display, Write},
+    mem,
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use error::ExprError;
-use rust_decimal::{prelude::FromPrimitive, Decimal};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use scopes::Scopes;
+use serde::{Deserialize, Serialize};
+use strum::VariantArray;
 
 use crate::{
     constants::constants,
-    data::{format_types, format_vec, Data, DataType},
-    execute_block,
+    data::{
+        format_tuple, format_types, format_vec, Data, DataMap, DataType, NumberDisplay,
+        DEFAULT_DISPLAY_LIMIT,
+    },
+    execute_block, execute_statements,
     functions::{
-        add_descriptor, and_descriptor, builtints, div_descriptor, eq_descriptor, ge_descriptor,
-        gt_descriptor, le_descriptor, lt_descriptor, mod_descriptor, mul_descriptor, ne_descriptor,
-        neg_descriptor, not_descriptor, or_descriptor, sub_descriptor, xor_descriptor,
-        FunctionDescriptor, FunctionMap, FunctionType,
+        add_descriptor, and_descriptor, assign_in, builtints, div_descriptor, eq_descriptor,
+        extend, ge_descriptor, gt_descriptor, idiv_descriptor, le_descriptor, lt_descriptor,
+        mod_descriptor, mul_descriptor, ne_descriptor, neg_descriptor, not_descriptor,
+        or_descriptor, repeat, sub_descriptor, xor_descriptor, Capability, FunctionDescriptor,
+        FunctionMap, FunctionType,
     },
-    utils::strings::{indent, DotDebug, DotDisplay},
+    import::{import, Module},
+    lexer::escape,
+    output::OutputSink,
+    patterns::RegexCache,
+    utils::strings::{indent, DotDisplay},
+    warning::{WarningPolicy, WarningSink},
 };
 
 pub mod error;
+pub mod scopes;
 
 pub type VariableMap = HashMap<String, Data>;
+/// The variants of each declared enum, in order.
+pub type EnumMap = HashMap<String, Vec<String>>;
 
 #[derive(Debug, Clone)]
 pub struct ExecutionState {
-    pub functions: FunctionMap,
-    pub variables: VariableMap,
-    pub constants: VariableMap,
+    /// Shared until changed, so states made from the same base (and the frames of function
+    /// calls) don't each copy every builtin. Change them through `Arc::make_mut`.
+    pub functions: Arc<FunctionMap>,
+    pub variables: Scopes,
+    pub constants: Arc<VariableMap>,
+    /// Changed through `Arc::make_mut`, like `functions`.
+    pub enums: Arc<EnumMap>,
+    pub trace: bool,
+    /// Capabilities builtins may use. Everything is allowed unless the state is sandboxed.
+    pub allowed: Vec<Capability>,
+    pub warnings: WarningSink,
+    /// Where `print` and `println` write to.
+    pub output: OutputSink,
+    /// Set from the Ctrl-C handler, checked before every statement and loop iteration.
+    pub interrupted: Arc<AtomicBool>,
+    /// How many more statements and loop iterations may run, counted down where
+    /// `interrupted` is checked, or None for no limit. Shared between clones like `output`.
+    pub steps_left: Option<Rc<Cell<usize>>>,
+    /// How many elements of an array or map `show` prints, 0 for all of them. Shared between
+    /// clones like `output`, so `set_display_limit` in a function applies afterwards too.
+    pub display_limit: Rc<Cell<usize>>,
+    /// How `show` writes numbers, shared between clones like `display_limit`.
+    pub number_display: Rc<Cell<NumberDisplay>>,
+    /// Patterns compiled by `matches`, and the limits they are compiled and run under.
+    pub regex: RegexCache,
+    /// Variables saved by `snapshot`. Shared between clones, so a handle taken in one block
+    /// can be restored in another.
+    pub snapshots: Rc<RefCell<Snapshots>>,
+    /// The script files being run, the innermost import last. Imports are relative to the
+    /// last one, and importing one that's already here would never end.
+    pub files: Vec<PathBuf>,
+    /// Files imported with `as`, by the name they were imported as.
+    pub modules: HashMap<String, Module>,
+}
+
+/// Saved copies of the variables, by the handle `snapshot` returned for them.
+#[derive(Debug, Default)]
+pub struct Snapshots {
+    next: usize,
+    saved: HashMap<usize, VariableMap>,
+}
+
+impl Snapshots {
+    pub fn save(&mut self, variables: VariableMap) -> usize {
+        self.next += 1;
+        self.saved.insert(self.next, variables);
+        self.next
+    }
+
+    pub fn get(&self, handle: usize) -> Option<&VariableMap> {
+        self.saved.get(&handle)
+    }
+
+    pub fn remove(&mut self, handle: usize) -> Option<VariableMap> {
+        self.saved.remove(&handle)
+    }
 }
 
 impl ExecutionState {
     pub fn new() -> Self {
         Self {
             functions: builtints(),
-            variables: HashMap::new(),
-            constants: constants(),
+            variables: Scopes::default(),
+            constants: Arc::new(constants()),
+            enums: Arc::default(),
+            trace: false,
+            allowed: Capability::VARIANTS.to_vec(),
+            warnings: WarningSink::default(),
+            output: OutputSink::default(),
+            interrupted: Arc::default(),
+            steps_left: None,
+            display_limit: Rc::new(Cell::new(DEFAULT_DISPLAY_LIMIT)),
+            number_display: Rc::default(),
+            regex: RegexCache::default(),
+            snapshots: Rc::default(),
+            files: vec![],
+            modules: HashMap::new(),
+        }
+    }
+
+    /// A state in which every builtin that touches the outside world raises `SandboxViolation`.
+    pub fn sandboxed() -> Self {
+        Self::with_capabilities(&[])
+    }
+
+    /// A state in which only builtins needing nothing beyond `allowed` may run.
+    pub fn with_capabilities(allowed: &[Capability]) -> Self {
+        Self {
+            allowed: allowed.to_vec(),
+            ..Self::new()
+        }
+    }
+
+    pub fn check_capabilities(&self, name: &str, func: &FunctionDescriptor) -> EResult<()> {
+        match func.capabilities.iter().find(|c| !self.allowed.contains(c)) {
+            Some(capability) => Err(ExprError::SandboxViolation {
+                name: name.to_string(),
+                capability: *capability,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Freezes what has been defined so far, along with the settings, for `SharedState::child`.
+    pub fn freeze(&self) -> SharedState {
+        SharedState {
+            functions: self.functions.clone(),
+            variables: Arc::new(self.variables.visible()),
+            constants: self.constants.clone(),
+            enums: self.enums.clone(),
+            modules: self.modules.clone(),
+            trace: self.trace,
+            allowed: self.allowed.clone(),
+            warning_policy: self.warnings.policy,
+            display_limit: self.display_limit.get(),
+            number_display: self.number_display.get(),
+        }
+    }
+
+    /// `data` as it should be printed, with long arrays and maps cut short and numbers in the
+    /// notation chosen with `set_number_display`.
+    pub fn show(&self, data: &Data) -> String {
+        data.shown(self.display_limit.get(), self.number_display.get())
+    }
+
+    /// Whether `name` is a declared record, whose constructor is the function of that name.
+    pub fn is_record(&self, name: &str) -> bool {
+        self.functions.get(name).is_some_and(|f| {
+            matches!(&f.function, FunctionType::Custom(body, ..) if matches!(body[..], [Expr::Construct(..)]))
+        })
+    }
+
+    /// Fails with `Interrupted` once Ctrl-C has been pressed, and with `OutOfSteps` once
+    /// `steps_left` has run out.
+    pub fn check_interrupted(&self) -> EResult<()> {
+        if self.interrupted.load(Ordering::Relaxed) {
+            return Err(ExprError::Interrupted);
+        }
+
+        match &self.steps_left {
+            Some(steps) if steps.get() == 0 => Err(ExprError::OutOfSteps),
+            Some(steps) => {
+                steps.set(steps.get() - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Constants (including the REPL's `out`) can't be declared over or assigned to, unless a
+    /// parameter or loop variable is shadowing them.
+    pub fn check_assignable(&self, name: &str) -> EResult<()> {
+        if self.constants.contains_key(name) && !self.variables.contains_key(name) {
+            Err(ExprError::ConstantRedefined {
+                name: name.to_string(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Runs `f` in a new innermost scope holding `bindings`, e.g. a loop variable. What is
+    /// declared in it, functions, constants and enums included, is gone afterwards, even when
+    /// `f` fails.
+    pub fn scoped<T>(
+        &mut self,
+        bindings: impl IntoIterator<Item = (String, Data)>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        self.enter(bindings, false, f)
+    }
+
+    /// Like `scoped`, for the body of a function with its parameters (and what a closure
+    /// captured) as `bindings`. The body sees the caller's functions and constants, so it can
+    /// call other functions and recurse, and the global variables, but not the caller's local
+    /// ones. Assigning to a global only changes the body's own copy.
+    pub fn call<T>(
+        &mut self,
+        bindings: impl IntoIterator<Item = (String, Data)>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        self.enter(bindings, true, f)
+    }
+
+    fn enter<T>(
+        &mut self,
+        bindings: impl IntoIterator<Item = (String, Data)>,
+        call: bool,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        // Only the pointers are copied, the tables themselves are only copied if the scope
+        // declares something in them.
+        let functions = self.functions.clone();
+        let constants = self.constants.clone();
+        let enums = self.enums.clone();
+
+        self.variables.push(bindings.into_iter().collect(), call);
+        let output = f(self);
+        self.variables.pop();
+
+        self.functions = functions;
+        self.constants = constants;
+        self.enums = enums;
+
+        output
+    }
+}
+
+/// The functions, variables and constants of a state, frozen to be the base of many separate
+/// evaluations, e.g. one per formula a server is sent. It can be shared between threads, and a
+/// `child` is cheap to make since the functions and constants aren't copied.
+#[derive(Debug, Clone)]
+pub struct SharedState {
+    functions: Arc<FunctionMap>,
+    variables: Arc<VariableMap>,
+    constants: Arc<VariableMap>,
+    enums: Arc<EnumMap>,
+    modules: HashMap<String, Module>,
+    trace: bool,
+    allowed: Vec<Capability>,
+    warning_policy: WarningPolicy,
+    display_limit: usize,
+    number_display: NumberDisplay,
+}
+
+impl SharedState {
+    /// A fresh state with what was frozen. Anything it declares or changes stays in it, and
+    /// it gets its own output, warnings and snapshots. The frozen variables are shared, not
+    /// copied, and sit under the child's own globals.
+    pub fn child(&self) -> ExecutionState {
+        ExecutionState {
+            functions: self.functions.clone(),
+            variables: Scopes::from(self.variables.clone()),
+            constants: self.constants.clone(),
+            enums: self.enums.clone(),
+            trace: self.trace,
+            allowed: self.allowed.clone(),
+            warnings: WarningSink::new(self.warning_policy),
+            display_limit: Rc::new(Cell::new(self.display_limit)),
+            number_display: Rc::new(Cell::new(self.number_display)),
+            output: OutputSink::default(),
+            interrupted: Arc::default(),
+            steps_left: None,
+            regex: RegexCache::default(),
+            snapshots: Rc::default(),
+            files: vec![],
+            modules: self.modules.clone(),
         }
     }
 }
@@ -49,12 +307,17 @@ impl Default for ExecutionState {
 
 type BExpr = Box<Expr>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Expr {
     Num(Decimal),
     Bool(bool),
     String(String),
+    /// A string with `${...}` placeholders, as its text and placeholder parts in order.
+    Interpolate(Vec<Expr>),
     Array(Vec<Expr>),
+    Tuple(Vec<Expr>),
+    /// A map literal, as its key and value expressions in source order.
+    Map(Vec<(Expr, Expr)>),
     FunctionValue(FunctionDescriptor),
     Null,
 
@@ -64,6 +327,7 @@ pub enum Expr {
     Sub(BExpr, BExpr),
     Mul(BExpr, BExpr),
     Div(BExpr, BExpr),
+    IntDiv(BExpr, BExpr),
     Mod(BExpr, BExpr),
 
     Gt(BExpr, BExpr),
@@ -72,6 +336,9 @@ pub enum Expr {
     Le(BExpr, BExpr),
     Eq(BExpr, BExpr),
     Ne(BExpr, BExpr),
+    /// `a < b <= c`, meaning `a < b && b <= c` with `b` evaluated once. Only used for two or
+    /// more comparisons in a row.
+    Chain(BExpr, Vec<(Comparison, Expr)>),
     Not(BExpr),
     And(BExpr, BExpr),
     Or(BExpr, BExpr),
@@ -80,43 +347,156 @@ pub enum Expr {
     Block(Vec<Expr>),
 
     Function(String, Vec<Expr>),
+    /// `receiver?.name(args)`, stored like a method call with the receiver first. Evaluates to
+    /// Null without calling anything when the receiver is Null.
+    SafeCall(String, Vec<Expr>),
+    /// `name: value` among a call's arguments, passed to the parameter called `name`.
+    NamedArgument(String, BExpr),
+    /// `...xs` in an array literal or call, which stands for the elements of `xs`.
+    Spread(BExpr),
+    /// `record.field`. Only records have fields, maps are read with `get`.
+    Field(BExpr, String),
+    /// The body of a record's constructor: a record of the given name, with each field taken
+    /// from the parameter of the same name.
+    Construct(String, Vec<String>),
     FunctionDeclaration(String, FunctionDescriptor),
     VariableDeclaration(String, BExpr),
+    /// `let [a, b, ...rest] := value`, declaring a variable for each element of an array in
+    /// order, and optionally one for the elements left over.
+    Destructure(Vec<String>, Option<String>, BExpr),
+    /// `const name := value`, declaring a constant that can't be assigned to or redeclared.
+    ConstantDeclaration(String, BExpr),
+    /// `assert condition, message;`, where the message is optional.
+    Assert(BExpr, Option<BExpr>),
+    /// `try { } catch e { }`, running the second block with the error bound to the name if
+    /// the first fails.
+    TryCatch(Vec<Expr>, String, Vec<Expr>),
+    /// `enum Name { A, B }`, with the variants in order.
+    EnumDeclaration(String, Vec<String>),
+    /// Changes an existing variable, unlike `VariableDeclaration`.
+    Assign(String, BExpr),
+    /// `name[a][b] := value`, with the index expressions in order.
+    AssignPath(String, Vec<Expr>, BExpr),
     Variable(String),
+    Return(Option<BExpr>),
+    /// `break` and `continue` take an optional label, `break 'outer`, and `break` a value.
+    Break(Option<String>, Option<BExpr>),
+    Continue(Option<String>),
 
     If(BExpr, Vec<Expr>, Vec<(Expr, Vec<Expr>)>, Option<Vec<Expr>>),
-    For(String, BExpr, Vec<Expr>),
-    While(BExpr, Vec<Expr>),
+    /// Loops start with an optional label, `'outer:`, which `break` and `continue` can name to
+    /// leave more than the innermost loop. `for` and `while` take an optional `else` block,
+    /// which runs when the loop ends without a `break`. `for i, x in` also binds the index,
+    /// which comes before the element's name.
+    For(
+        Option<String>,
+        Option<String>,
+        String,
+        BExpr,
+        Vec<Expr>,
+        Option<Vec<Expr>>,
+    ),
+    While(Option<String>, BExpr, Vec<Expr>, Option<Vec<Expr>>),
+    /// `do { } while cond`, which checks its condition after each run of the body.
+    DoWhile(Option<String>, Vec<Expr>, BExpr),
+    /// `loop { }`, which runs until a `break` and takes the value it carries.
+    Loop(Option<String>, Vec<Expr>),
+    /// `import "path";`, or `import "path" as name;` to keep its functions under `name.`.
+    Import(String, Option<String>),
+}
+
+/// An operator in a chained comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    /// The builtin that performs this comparison.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gt => "gt",
+            Self::Lt => "lt",
+            Self::Ge => "ge",
+            Self::Le => "le",
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+        }
+    }
+
+    pub fn descriptor(self) -> FunctionDescriptor {
+        match self {
+            Self::Gt => gt_descriptor(),
+            Self::Lt => lt_descriptor(),
+            Self::Ge => ge_descriptor(),
+            Self::Le => le_descriptor(),
+            Self::Eq => eq_descriptor(),
+            Self::Ne => ne_descriptor(),
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Gt => ">",
+            Self::Lt => "<",
+            Self::Ge => ">=",
+            Self::Le => "<=",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+        }
+    }
 }
 
 pub type EResult<T> = Result<T, ExprError>;
 
 #[macro_export]
 macro_rules! run {
-    ($func: ident, $inputs: ident, $state: ident) => {{
-        let matching_types = $inputs
-            .iter()
-            .map(|i| i._type())
-            .zip($func.inputs.iter())
-            .all(|(input, expected)| input == *expected || expected.is_any());
+    ($func: ident, $inputs: ident, $state: ident, $name: expr) => {{
+        // A variadic function takes any number of arguments of its rest type after the others.
+        let matching_arity = match $func.inputs_rest {
+            Some(_) => $inputs.len() >= $func.inputs.len(),
+            None => $inputs.len() == $func.inputs.len(),
+        };
+        let matching_types = matching_arity
+            && $inputs
+                .iter()
+                .zip($func.inputs.iter().chain($func.inputs_rest.iter().cycle()))
+                .all(|(input, expected)| expected.accepts(input));
 
         if matching_types {
+            $state.check_capabilities($name, &$func)?;
+
             Ok(match $func.function {
                 FunctionType::BuiltIn(f) => f($inputs)?,
-                FunctionType::Custom(block, input_names) => {
-                    let mut state = ExecutionState::new();
-
-                    for (i, name) in input_names.iter().enumerate() {
-                        state.variables.insert(name.clone(), $inputs[i].clone());
+                FunctionType::BuiltInWithState(f) => f($inputs, $state)?,
+                FunctionType::Custom(block, input_names, _, captures) => {
+                    let mut inputs = $inputs;
+                    // The last parameter of a variadic function gets the rest as an array.
+                    if $func.inputs_rest.is_some() {
+                        let rest = inputs.split_off($func.inputs.len());
+                        inputs.push(Data::Array(rest));
                     }
+                    let bindings = captures
+                        .iter()
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .chain(input_names.iter().cloned().zip(inputs));
 
-                    execute_block(&block, &state).0
+                    match $state.call(bindings, |state| execute_statements(&block, state)) {
+                        Ok(output) => output,
+                        Err(ExprError::Return(output)) => *output,
+                        Err(e) => return Err(e.outside_loop()),
+                    }
                 }
             })
         } else {
             let input_types = $inputs.iter().map(|i| i._type()).collect::<Vec<_>>();
             Err(ExprError::InvalidFunctionArguements {
-                expected: format_types($func.inputs),
+                expected: format!("({})", $func.params()),
                 found: format_types(input_types),
                 // name:  name.clone(),
             })
@@ -125,6 +505,7 @@ macro_rules! run {
 }
 
 pub fn run_fn(
+    name: &str,
     func: FunctionDescriptor,
     inputs: &[&BExpr],
     state: &mut ExecutionState,
@@ -134,20 +515,232 @@ pub fn run_fn(
         .map(|e| e.eval(state))
         .collect::<Result<Vec<_>, _>>()?;
 
-    run!(func, inputs, state)
+    run!(func, inputs, state, name)
 }
 
 pub fn run_fn_owned(
+    name: &str,
     func: FunctionDescriptor,
     inputs: &[Expr],
     state: &mut ExecutionState,
 ) -> EResult<Data> {
-    let inputs = inputs
-        .iter()
-        .map(|e| e.eval(state))
-        .collect::<Result<Vec<_>, _>>()?;
+    let inputs = eval_arguments(inputs, state)?;
+    let inputs = bind_arguments(name, &func, inputs, state)?;
+
+    run!(func, inputs, state, name)
+}
+
+/// The enum `Color.Red` takes a variant of, by its name. Like modules, a variable of the same
+/// name hides it.
+fn enum_of<'a>(name: &Expr, state: &'a ExecutionState) -> Option<(&'a String, &'a Vec<String>)> {
+    match name {
+        Expr::Variable(name) if !state.variables.contains_key(name) => {
+            state.enums.get_key_value(name)
+        }
+        _ => None,
+    }
+}
+
+/// The module a call like `vec.length(v)` is made on, which parses as the method call
+/// `length(vec, v)`. A variable of the same name hides the module.
+fn module_of<'a>(inputs: &[Expr], state: &'a ExecutionState) -> Option<&'a Module> {
+    match inputs.first() {
+        Some(Expr::Variable(name)) if !state.variables.contains_key(name) => {
+            state.modules.get(name)
+        }
+        _ => None,
+    }
+}
+
+/// Calls `func` of a module. The arguments are evaluated here, but the body runs among the
+/// module's own `functions`.
+fn run_module_fn(
+    qualified: &str,
+    func: FunctionDescriptor,
+    functions: Arc<FunctionMap>,
+    inputs: &[Expr],
+    state: &mut ExecutionState,
+) -> EResult<Data> {
+    let inputs = eval_arguments(inputs, state)?;
+    let caller = mem::replace(&mut state.functions, functions);
+
+    let output = (|| {
+        let inputs = bind_arguments(qualified, &func, inputs, state)?;
+        run!(func, inputs, state, qualified)
+    })();
+
+    state.functions = caller;
+    output
+}
+
+/// Fails with `UnknownType` if the signature of `desc` names an enum or record that hasn't
+/// been declared. `function` is the declared function's name, None for a closure.
+fn check_types(
+    function: Option<&str>,
+    desc: &FunctionDescriptor,
+    state: &ExecutionState,
+) -> EResult<()> {
+    let record = match &desc.function {
+        FunctionType::Custom(body, ..) => match &body[..] {
+            [Expr::Construct(record, _)] => Some(record),
+            _ => None,
+        },
+        _ => None,
+    };
+    let names = desc.names();
+    let params = desc.inputs.iter().chain(&desc.inputs_rest).map(Some);
+
+    for (i, t) in params.chain([None]).enumerate() {
+        let (param, DataType::Named(name)) = (t.and(names.get(i)), t.unwrap_or(&desc.output))
+        else {
+            continue;
+        };
+        if state.enums.contains_key(name) || state.is_record(name) {
+            continue;
+        }
+
+        let loc = match (record, param, function) {
+            (Some(record), Some(field), _) => format!("field `{field}` of record `{record}`"),
+            (_, _, Some(function)) => format!("function `{function}` signature"),
+            _ => "closure signature".to_string(),
+        };
+        return Err(ExprError::UnknownType {
+            name: name.clone(),
+            loc,
+        });
+    }
+
+    Ok(())
+}
+
+/// Evaluates `lhs`, returning it without evaluating `rhs` when it is `decided_by`. Otherwise
+/// both sides go through the builtin, which also reports a non-bool left side.
+fn short_circuit(
+    name: &str,
+    func: FunctionDescriptor,
+    decided_by: bool,
+    lhs: &Expr,
+    rhs: &Expr,
+    state: &mut ExecutionState,
+) -> EResult<Data> {
+    let lhs = lhs.eval(state)?;
+    if lhs == Data::Bool(decided_by) {
+        return Ok(lhs);
+    }
+
+    let inputs = vec![lhs, rhs.eval(state)?];
+    run!(func, inputs, state, name)
+}
+
+/// Evaluates a call's arguments in source order, keeping the names of named ones and
+/// expanding spread ones into positional arguments.
+fn eval_arguments(
+    inputs: &[Expr],
+    state: &mut ExecutionState,
+) -> EResult<Vec<(Option<String>, Data)>> {
+    let mut args = vec![];
+
+    for e in inputs {
+        match e {
+            Expr::NamedArgument(name, value) => args.push((Some(name.clone()), value.eval(state)?)),
+            Expr::Spread(array) => {
+                args.extend(spread(array, state)?.into_iter().map(|value| (None, value)))
+            }
+            e => args.push((None, e.eval(state)?)),
+        }
+    }
+
+    Ok(args)
+}
+
+/// Evaluates the operand of a `...`, which has to be an array.
+fn spread(array: &Expr, state: &mut ExecutionState) -> EResult<Vec<Data>> {
+    match array.eval(state)? {
+        Data::Array(items) => Ok(items),
+        other => Err(ExprError::InvalidDataType {
+            expected: DataType::Array.to_string(),
+            found: other._type().to_string(),
+            loc: "spread".to_string(),
+        }),
+    }
+}
+
+/// Puts each argument in its parameter's position. Positional arguments fill parameters from
+/// the start and have to come before named ones. Parameters left without an argument take
+/// their default value, evaluated in the function's scope so it can use the parameters before
+/// it.
+fn bind_arguments(
+    function: &str,
+    func: &FunctionDescriptor,
+    args: Vec<(Option<String>, Data)>,
+    state: &mut ExecutionState,
+) -> EResult<Vec<Data>> {
+    let positional = args.iter().all(|(name, _)| name.is_none());
+    let names = func.names();
+    let mut bound = vec![None; func.inputs.len()];
+    // Extra positional arguments, left for the arity check to report.
+    let mut extra = vec![];
+    let mut seen_named = false;
+
+    for (i, (name, value)) in args.into_iter().enumerate() {
+        match name {
+            None if seen_named => {
+                return Err(ExprError::PositionalAfterNamed {
+                    function: function.to_string(),
+                })
+            }
+            None => match bound.get_mut(i) {
+                Some(slot) => *slot = Some(value),
+                None => extra.push(value),
+            },
+            Some(name) => {
+                seen_named = true;
+
+                // A variadic function's rest can't be passed by name.
+                let Some(position) = names[..bound.len().min(names.len())]
+                    .iter()
+                    .position(|n| *n == name)
+                else {
+                    return Err(ExprError::UnknownArgument {
+                        name,
+                        call: format!("{function}({})", func.params()),
+                    });
+                };
+                if bound[position].replace(value).is_some() {
+                    return Err(ExprError::DuplicateArgument {
+                        function: function.to_string(),
+                        name,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut values = vec![];
+    for (i, value) in bound.into_iter().enumerate() {
+        let value = match (value, func.default(i)) {
+            (Some(value), _) => value,
+            (None, Some(default)) => {
+                let params = names.iter().map(|n| n.to_string());
+                let bindings = params.zip(values.iter().cloned());
+                state.scoped(bindings, |state| default.eval(state))?
+            }
+            // Too few positional arguments, also left for the arity check.
+            (None, None) if positional => break,
+            (None, None) => {
+                return Err(ExprError::MissingArgument {
+                    function: function.to_string(),
+                    name: names
+                        .get(i)
+                        .map_or(format!("#{}", i + 1), |n| n.to_string()),
+                })
+            }
+        };
+        values.push(value);
+    }
+    values.extend(extra);
 
-    run!(func, inputs, state)
+    Ok(values)
 }
 
 impl Expr {
@@ -157,134 +750,442 @@ impl Expr {
             Expr::Bool(b) => Ok(Data::Bool(*b)),
             Expr::Null => Ok(Data::Null),
             Expr::String(s) => Ok(Data::String(s.clone())),
-            Expr::Array(a) => Ok(Data::Array(
-                a.iter()
+            Expr::Interpolate(parts) => Ok(Data::String(
+                parts
+                    .iter()
+                    .map(|part| Ok(part.eval(state)?.display()))
+                    .collect::<EResult<String>>()?,
+            )),
+            Expr::Array(a) => {
+                let mut items = vec![];
+                for e in a {
+                    match e {
+                        Expr::Spread(array) => items.extend(spread(array, state)?),
+                        e => items.push(e.eval(state)?),
+                    }
+                }
+
+                Ok(Data::Array(items))
+            }
+            Expr::Tuple(t) => Ok(Data::Tuple(
+                t.iter()
                     .map(|e| e.eval(state))
                     .collect::<EResult<Vec<_>>>()?,
             )),
-            Expr::FunctionValue(f) => Ok(Data::Function(f.clone())),
-
-            Expr::Neg(n) => run_fn(neg_descriptor(), &[n], state),
-            Expr::Add(lhs, rhs) => run_fn(add_descriptor(), &[lhs, rhs], state),
-            Expr::Sub(lhs, rhs) => run_fn(sub_descriptor(), &[lhs, rhs], state),
-            Expr::Mul(lhs, rhs) => run_fn(mul_descriptor(), &[lhs, rhs], state),
-            Expr::Div(lhs, rhs) => run_fn(div_descriptor(), &[lhs, rhs], state),
-            Expr::Mod(lhs, rhs) => run_fn(mod_descriptor(), &[lhs, rhs], state),
-
-            Expr::Le(lhs, rhs) => run_fn(le_descriptor(), &[lhs, rhs], state),
-            Expr::Gt(lhs, rhs) => run_fn(gt_descriptor(), &[lhs, rhs], state),
-            Expr::Ge(lhs, rhs) => run_fn(ge_descriptor(), &[lhs, rhs], state),
-            Expr::Lt(lhs, rhs) => run_fn(lt_descriptor(), &[lhs, rhs], state),
-            Expr::Eq(lhs, rhs) => run_fn(eq_descriptor(), &[lhs, rhs], state),
-            Expr::Ne(lhs, rhs) => run_fn(ne_descriptor(), &[lhs, rhs], state),
-
-            Expr::Not(n) => run_fn(not_descriptor(), &[n], state),
-            Expr::And(lhs, rhs) => run_fn(and_descriptor(), &[lhs, rhs], state),
-            Expr::Or(lhs, rhs) => run_fn(or_descriptor(), &[lhs, rhs], state),
-            Expr::Xor(lhs, rhs) => run_fn(xor_descriptor(), &[lhs, rhs], state),
-
-            Expr::Block(block) => Ok(execute_block(block, state).0),
-            Expr::If(cond, if_block, elifs, else_block) => {
-                let cond = cond.eval(state)?;
-
-                if let Data::Bool(b) = cond {
-                    if b {
-                        Ok(execute_block(if_block, state).0)
-                    } else {
-                        for (cond, block) in elifs {
-                            let cond = cond.eval(state)?;
-                            if let Data::Bool(b) = cond {
-                                if b {
-                                    return Ok(execute_block(block, state).0);
-                                }
-                            } else {
-                                return Err(ExprError::InvalidDataType {
-                                    expected: "Bool".to_string(),
-                                    found: cond._type().to_string(),
-                                    loc: "if condition".to_string(),
-                                });
-                            }
-                        }
+            Expr::Map(entries) => {
+                let mut map = DataMap::new();
 
-                        if let Some(block) = else_block {
-                            Ok(execute_block(block, state).0)
-                        } else {
-                            Ok(Data::Null)
+                for (key, value) in entries {
+                    let key = match key.eval(state)? {
+                        Data::String(key) => key,
+                        other => {
+                            return Err(ExprError::InvalidDataType {
+                                expected: "String".to_string(),
+                                found: other._type().to_string(),
+                                loc: "map key".to_string(),
+                            })
                         }
+                    };
+                    let value = value.eval(state)?;
+
+                    if map.insert(key.clone(), value).is_some() {
+                        return Err(ExprError::DuplicateKey { key });
                     }
-                } else {
-                    Err(ExprError::InvalidDataType {
-                        expected: "Bool".to_string(),
-                        found: cond._type().to_string(),
-                        loc: "if condition".to_string(),
-                    })
                 }
+
+                Ok(Data::Map(map))
             }
+            Expr::FunctionValue(f) => {
+                check_types(None, f, state)?;
 
-            Expr::While(cond, block) => {
-                let initial_state = state.clone();
-                let mut inner_state = state.clone();
+                // A closure keeps the local variables around it, since it may be called after
+                // they are gone, e.g. when it is returned.
+                let mut f = f.clone();
+                if let FunctionType::Custom(.., captures) = &mut f.function {
+                    *captures = Arc::new(state.variables.locals());
+                }
 
-                let is_true = |data| {
-                    if let Data::Bool(b) = data {
-                        b
-                    } else {
-                        false
+                Ok(Data::Function(f))
+            }
+            Expr::Field(record, field) => {
+                if let Some((name, variants)) = enum_of(record, state) {
+                    if !variants.contains(field) {
+                        return Err(ExprError::UnknownVariant {
+                            name: name.clone(),
+                            variant: field.clone(),
+                        });
                     }
+
+                    return Ok(Data::Enum(name.clone(), field.clone()));
+                }
+
+                match record.eval(state)? {
+                    Data::Record(name, fields) => {
+                        fields
+                            .get(field)
+                            .cloned()
+                            .ok_or_else(|| ExprError::UnknownField {
+                                record: name,
+                                field: field.clone(),
+                            })
+                    }
+                    other => Err(ExprError::InvalidDataType {
+                        expected: "record".to_string(),
+                        found: other._type().to_string(),
+                        loc: format!("field access `.{field}`"),
+                    }),
+                }
+            }
+            Expr::Construct(name, fields) => Ok(Data::Record(
+                name.clone(),
+                fields
+                    .iter()
+                    .map(|field| (field.clone(), state.variables[field.as_str()].clone()))
+                    .collect(),
+            )),
+
+            Expr::Neg(n) => run_fn("neg", neg_descriptor(), &[n], state),
+            Expr::Add(lhs, rhs) => match (lhs.eval(state)?, rhs.eval(state)?) {
+                (lhs @ Data::Array(_), rhs @ Data::Array(_)) => extend(vec![lhs, rhs]),
+                (lhs, rhs) => {
+                    let (func, inputs) = (add_descriptor(), vec![lhs, rhs]);
+                    run!(func, inputs, state, "add")
+                }
+            },
+            Expr::Sub(lhs, rhs) => run_fn("sub", sub_descriptor(), &[lhs, rhs], state),
+            Expr::Mul(lhs, rhs) => {
+                // A String or Array times a Number, either way round, repeats it.
+                match (lhs.eval(state)?, rhs.eval(state)?) {
+                    (value @ (Data::String(_) | Data::Array(_)), Data::Number(count))
+                    | (Data::Number(count), value @ (Data::String(_) | Data::Array(_))) => {
+                        repeat(value, count)
+                    }
+                    (lhs, rhs) => {
+                        let (func, inputs) = (mul_descriptor(), vec![lhs, rhs]);
+                        run!(func, inputs, state, "mul")
+                    }
+                }
+            }
+            Expr::Div(lhs, rhs) => run_fn("div", div_descriptor(), &[lhs, rhs], state),
+            Expr::IntDiv(lhs, rhs) => run_fn("idiv", idiv_descriptor(), &[lhs, rhs], state),
+            Expr::Mod(lhs, rhs) => run_fn("mod", mod_descriptor(), &[lhs, rhs], state),
+
+            Expr::Le(lhs, rhs) => run_fn("le", le_descriptor(), &[lhs, rhs], state),
+            Expr::Gt(lhs, rhs) => run_fn("gt", gt_descriptor(), &[lhs, rhs], state),
+            Expr::Ge(lhs, rhs) => run_fn("ge", ge_descriptor(), &[lhs, rhs], state),
+            Expr::Lt(lhs, rhs) => run_fn("lt", lt_descriptor(), &[lhs, rhs], state),
+            Expr::Eq(lhs, rhs) => run_fn("eq", eq_descriptor(), &[lhs, rhs], state),
+            Expr::Ne(lhs, rhs) => run_fn("ne", ne_descriptor(), &[lhs, rhs], state),
+            Expr::Chain(first, rest) => {
+                let mut lhs = first.eval(state)?;
+
+                for (comparison, rhs) in rest {
+                    let rhs = rhs.eval(state)?;
+                    let inputs = vec![lhs, rhs.clone()];
+                    let func = comparison.descriptor();
+
+                    if run!(func, inputs, state, comparison.name())? == Data::Bool(false) {
+                        return Ok(Data::Bool(false));
+                    }
+                    lhs = rhs;
+                }
+
+                Ok(Data::Bool(true))
+            }
+
+            Expr::Not(n) => run_fn("not", not_descriptor(), &[n], state),
+            Expr::And(lhs, rhs) => short_circuit("and", and_descriptor(), false, lhs, rhs, state),
+            Expr::Or(lhs, rhs) => short_circuit("or", or_descriptor(), true, lhs, rhs, state),
+            Expr::Xor(lhs, rhs) => run_fn("xor", xor_descriptor(), &[lhs, rhs], state),
+
+            Expr::Block(block) => execute_block(block, state),
+            Expr::If(cond, if_block, elifs, else_block) => {
+                if cond.eval(state)?.condition("if condition")? {
+                    return execute_block(if_block, state);
+                }
+
+                for (cond, block) in elifs {
+                    if cond.eval(state)?.condition("elif condition")? {
+                        return execute_block(block, state);
+                    }
+                }
+
+                if let Some(block) = else_block {
+                    execute_block(block, state)
+                } else {
+                    Ok(Data::Null)
+                }
+            }
+
+            Expr::Assert(cond, message) => {
+                if cond.eval(state)?.condition("assert condition")? {
+                    return Ok(Data::Null);
+                }
+
+                let message = match message {
+                    Some(message) => format!("`{cond}`, {}", message.eval(state)?),
+                    None => format!("`{cond}`"),
                 };
 
-                while is_true(cond.eval(&mut inner_state)?) {
-                    let (_, s) = execute_block(block, &inner_state);
-                    inner_state = s;
+                Err(ExprError::AssertionFailed { message })
+            }
+            Expr::TryCatch(block, name, catch_block) => match execute_block(block, state) {
+                Ok(output) => Ok(output),
+                Err(e) if e.is_catchable() => {
+                    let bindings = [(name.clone(), e.to_data())];
+
+                    state.scoped(bindings, |state| execute_statements(catch_block, state))
                 }
+                Err(e) => Err(e),
+            },
+
+            Expr::While(label, cond, block, else_block) => {
+                let broke = conditional_loop(label, block, cond, true, "while condition", state)?;
+
+                if let (Some(else_block), false) = (else_block, broke) {
+                    execute_block(else_block, state)?;
+                }
+
+                Ok(Data::Null)
+            }
+            Expr::DoWhile(label, block, cond) => {
+                conditional_loop(label, block, cond, false, "do-while condition", state)?;
+
+                Ok(Data::Null)
+            }
+            Expr::Loop(label, block) => loop {
+                state.check_interrupted()?;
 
-                *state = initial_state;
+                match execute_block(block, state) {
+                    Err(e) if e.escapes(label) => return Err(e),
+                    Err(ExprError::Break(_, value)) => return Ok(*value),
+                    Ok(_) | Err(ExprError::Continue(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            },
+            Expr::Import(path, alias) => {
+                import(path, alias.as_deref(), state)?;
 
                 Ok(Data::Null)
             }
-            Expr::For(var_name, maybe_array, block) => {
-                let maybe_array = maybe_array.eval(state)?;
+            Expr::For(label, index_name, var_name, iterable, block, else_block) => {
+                let items: Box<dyn Iterator<Item = Data>> = match iterable.eval(state)? {
+                    Data::Array(array) => Box::new(array.into_iter()),
+                    // Each character as a string of its own.
+                    Data::String(s) => Box::new(
+                        s.chars()
+                            .map(|c| Data::String(c.to_string()))
+                            .collect::<Vec<_>>()
+                            .into_iter(),
+                    ),
+                    // `for i in n` counts from 0 up to n, like `range(0, n)`.
+                    Data::Number(n) => {
+                        let count =
+                            n.to_u64().filter(|_| n.fract().is_zero()).ok_or_else(|| {
+                                ExprError::InvalidDataType {
+                                    expected: "non-negative integer".to_string(),
+                                    found: n.to_string(),
+                                    loc: "for loop input".to_string(),
+                                }
+                            })?;
+                        Box::new((0..count).map(|i| Data::Number(i.into())))
+                    }
+                    other => {
+                        return Err(ExprError::InvalidDataType {
+                            expected: "Array, String or Number".to_string(),
+                            found: other._type().to_string(),
+                            loc: "for loop input".to_string(),
+                        })
+                    }
+                };
+                let mut broke = false;
 
-                if let Data::Array(array) = maybe_array {
-                    for data in array {
-                        let mut inner_state = state.clone();
-                        inner_state.variables.insert(var_name.clone(), data);
+                for (i, data) in items.enumerate() {
+                    state.check_interrupted()?;
 
-                        execute_block(block, &inner_state);
+                    let index = index_name
+                        .clone()
+                        .map(|name| (name, Data::Number(i.into())));
+                    let bindings = index.into_iter().chain([(var_name.clone(), data)]);
+
+                    match state.scoped(bindings, |state| execute_statements(block, state)) {
+                        Err(e) if e.escapes(label) => return Err(e),
+                        Ok(_) | Err(ExprError::Continue(_)) => {}
+                        Err(ExprError::Break(..)) => {
+                            broke = true;
+                            break;
+                        }
+                        Err(e) => return Err(e),
                     }
+                }
 
-                    Ok(Data::Null)
-                } else {
-                    Err(ExprError::InvalidDataType {
-                        expected: "Array".to_string(),
-                        found: maybe_array._type().to_string(),
-                        loc: "for loop input".to_string(),
-                    })
+                if let (Some(else_block), false) = (else_block, broke) {
+                    execute_block(else_block, state)?;
                 }
+
+                Ok(Data::Null)
             }
 
             Expr::Function(name, inputs) => {
-                if let Some(func) = state.functions.get(name) {
-                    run_fn_owned(func.clone(), inputs, state)
+                if let Some(module) = module_of(inputs, state) {
+                    let qualified = format!("{}.{name}", inputs[0]);
+                    let Some(func) = module.export(name) else {
+                        return Err(ExprError::FunctionNotFound { name: qualified });
+                    };
+                    let functions = module.functions.clone();
+
+                    run_module_fn(&qualified, func, functions, &inputs[1..], state)
+                } else if let Some(func) = state.functions.get(name) {
+                    run_fn_owned(name, func.clone(), inputs, state)
+                } else if let Some(Data::Function(func)) = state.variables.get(name) {
+                    // A closure stored with `let`.
+                    run_fn_owned(name, func.clone(), inputs, state)
                 } else {
-                    // TODO: Allow users to define their own functions.
                     Err(ExprError::FunctionNotFound { name: name.clone() })
                 }
             }
-            Expr::FunctionDeclaration(_, _) => Ok(Data::Null),
+            Expr::SafeCall(name, inputs) => {
+                let receiver = inputs[0].eval(state)?;
+                if receiver == Data::Null {
+                    return Ok(Data::Null);
+                }
+
+                let Some(func) = state.functions.get(name).cloned() else {
+                    return Err(ExprError::FunctionNotFound { name: name.clone() });
+                };
+
+                let inputs = std::iter::once((None, receiver))
+                    .chain(eval_arguments(&inputs[1..], state)?)
+                    .collect();
+                let inputs = bind_arguments(name, &func, inputs, state)?;
+
+                run!(func, inputs, state, name)
+            }
+            // Calls bind these to parameters themselves, so this is only reached outside one.
+            Expr::NamedArgument(_, value) => value.eval(state),
+            // Likewise expanded by array literals and calls.
+            Expr::Spread(array) => Ok(Data::Array(spread(array, state)?)),
+            // Hoisted by `execute_statements`, which leaves the types for when it's reached,
+            // after any imports before it.
+            Expr::FunctionDeclaration(name, desc) => {
+                check_types(Some(name), desc, state)?;
+
+                Ok(Data::Null)
+            }
             Expr::Variable(name) => {
                 if let Some(v) = state.variables.get(name) {
                     Ok(v.clone())
                 } else if let Some(v) = state.constants.get(name) {
                     Ok(v.clone())
+                } else if let Some(func) = state.functions.get(name) {
+                    // Declared functions replace builtins in the table, so they win here too.
+                    Ok(Data::Function(func.clone()))
                 } else {
                     Err(ExprError::VariableNotFound { name: name.clone() })
                 }
             }
             Expr::VariableDeclaration(name, value) => {
+                state.check_assignable(name)?;
+                let value = value.eval(state)?;
+
+                state.variables.declare(name.clone(), value);
+
+                Ok(Data::Null)
+            }
+            Expr::Destructure(names, rest, value) => {
+                for name in names.iter().chain(rest) {
+                    state.check_assignable(name)?;
+                }
+
+                let mut items = match value.eval(state)? {
+                    Data::Array(items) => items,
+                    other => {
+                        return Err(ExprError::InvalidDataType {
+                            expected: "Array".to_string(),
+                            found: other._type().to_string(),
+                            loc: "destructuring let".to_string(),
+                        })
+                    }
+                };
+                if items.len() < names.len() {
+                    return Err(ExprError::DestructureTooShort {
+                        expected: names.len(),
+                        found: items.len(),
+                    });
+                }
+
+                let leftover = items.split_off(names.len());
+                for (name, item) in names.iter().zip(items) {
+                    state.variables.declare(name.clone(), item);
+                }
+                if let Some(rest) = rest {
+                    state.variables.declare(rest.clone(), Data::Array(leftover));
+                }
+
+                Ok(Data::Null)
+            }
+            Expr::ConstantDeclaration(name, value) => {
+                state.check_assignable(name)?;
+                let value = value.eval(state)?;
+
+                // A variable of the same name would be read instead of the constant.
+                state.variables.remove(name);
+                Arc::make_mut(&mut state.constants).insert(name.clone(), value);
+
+                Ok(Data::Null)
+            }
+            Expr::EnumDeclaration(name, variants) => {
+                Arc::make_mut(&mut state.enums).insert(name.clone(), variants.clone());
+
+                Ok(Data::Null)
+            }
+            Expr::Return(value) => {
+                let value = match value {
+                    Some(value) => value.eval(state)?,
+                    None => Data::Null,
+                };
+
+                Err(ExprError::Return(Box::new(value)))
+            }
+            Expr::Break(label, value) => {
+                let value = match value {
+                    Some(value) => value.eval(state)?,
+                    None => Data::Null,
+                };
+
+                Err(ExprError::Break(label.clone(), Box::new(value)))
+            }
+            Expr::Continue(label) => Err(ExprError::Continue(label.clone())),
+            Expr::Assign(name, value) => {
+                state.check_assignable(name)?;
+                if !state.variables.contains_key(name) {
+                    return Err(ExprError::UndeclaredAssignment { name: name.clone() });
+                }
+
+                let value = value.eval(state)?;
+                state.variables.assign(name, value);
+
+                Ok(Data::Null)
+            }
+            Expr::AssignPath(name, path, value) => {
+                state.check_assignable(name)?;
+                let path = path
+                    .iter()
+                    .map(|step| step.eval(state))
+                    .collect::<EResult<Vec<_>>>()?;
                 let value = value.eval(state)?;
 
-                state.variables.insert(name.clone(), value);
+                let Some(current) = state.variables.get(name).cloned() else {
+                    return Err(ExprError::UndeclaredAssignment { name: name.clone() });
+                };
+                let updated = assign_in(current, &path, value).map_err(|(position, source)| {
+                    ExprError::AssignPath {
+                        name: name.clone(),
+                        position,
+                        source: Box::new(source),
+                    }
+                })?;
+                state.variables.assign(name, updated);
 
                 Ok(Data::Null)
             }
@@ -315,17 +1216,43 @@ impl Display for Expr {
                 Self::Bool(b) => format!("{b}"),
                 Self::Num(n) => format!("{n}"),
                 Self::Null => "null".into(),
-                Self::String(s) => format!("\"{s}\""),
+                Self::String(s) => format!("\"{}\"", escape(s)),
+                Self::Interpolate(parts) => format!(
+                    "\"{}\"",
+                    parts
+                        .iter()
+                        .map(|part| match part {
+                            Self::String(s) => escape(s),
+                            e => format!("${{{e}}}"),
+                        })
+                        .collect::<String>()
+                ),
                 Self::Array(a) => format_vec(a),
+                Self::Tuple(t) => format_tuple(t),
+                Self::Map(entries) => format!(
+                    "{{{}}}",
+                    entries
+                        .iter()
+                        .map(|(k, v)| format!("{k}: {v}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
 
                 Self::Neg(e) => format!("(-{e})"),
                 Self::Add(l, r) => format!("({l} + {r})"),
                 Self::Sub(l, r) => format!("({l} - {r})"),
                 Self::Mul(l, r) => format!("({l} * {r})"),
                 Self::Div(l, r) => format!("({l} / {r})"),
+                Self::IntDiv(l, r) => format!("({l} ~/ {r})"),
                 Self::Mod(l, r) => format!("({l} % {r})"),
 
                 Self::Ge(l, r) => format!("({l} >= {r})"),
+                Self::Chain(first, rest) => format!(
+                    "({first}{})",
+                    rest.iter()
+                        .map(|(comparison, e)| format!(" {} {e}", comparison.symbol()))
+                        .collect::<String>()
+                ),
                 Self::Gt(l, r) => format!("({l} > {r})"),
                 Self::Le(l, r) => format!("({l} <= {r})"),
                 Self::Lt(l, r) => format!("({l} < {r})"),
@@ -337,28 +1264,97 @@ impl Display for Expr {
                 Self::Xor(l, r) => format!("({l} ^ {r})"),
                 Self::Not(e) => format!("!{e}"),
 
-                Self::While(cond, block) => format!("while {cond} {}", format_block(block)),
-                Self::For(name, array, block) =>
-                    format!("for {name} in {array} {}", format_block(block)),
+                Self::While(label, cond, block, else_block) => format!(
+                    "{}while {cond} {}{}",
+                    format_label(label),
+                    format_block(block),
+                    format_loop_else(else_block)
+                ),
+                Self::DoWhile(label, block, cond) => format!(
+                    "{}do {} while {cond}",
+                    format_label(label),
+                    format_block(block)
+                ),
+                Self::Import(path, None) => format!("import \"{}\"", escape(path)),
+                Self::Import(path, Some(alias)) => {
+                    format!("import \"{}\" as {alias}", escape(path))
+                }
+                Self::Loop(label, block) => {
+                    format!("{}loop {}", format_label(label), format_block(block))
+                }
+                Self::For(label, index, name, array, block, else_block) => format!(
+                    "{}for {}{name} in {array} {}{}",
+                    format_label(label),
+                    index.as_ref().map(|i| format!("{i}, ")).unwrap_or_default(),
+                    format_block(block),
+                    format_loop_else(else_block)
+                ),
 
-                Self::If(cond, if_block, elif_blocks, else_block) => format!(
-                    "if ({cond}) {} {} {}",
-                    format_block(if_block),
-                    elif_blocks
-                        .iter()
-                        .map(|(cond, block)| format!("elif ({}) {}", cond, format_block(block)))
-                        .collect::<String>(),
+                // Each branch goes on its own line:
+                //
+                // if cond {
+                //     ...
+                // }
+                // elif cond {
+                //     ...
+                // }
+                // else {
+                //     ...
+                // }
+                Self::If(cond, if_block, elif_blocks, else_block) => {
+                    let mut s = format!("if {cond} {}", format_block(if_block));
+
+                    for (cond, block) in elif_blocks {
+                        write!(s, "\nelif {cond} {}", format_block(block)).unwrap();
+                    }
                     if let Some(block) = else_block {
-                        format!("else {}", format_block(block))
-                    } else {
-                        String::new()
+                        write!(s, "\nelse {}", format_block(block)).unwrap();
                     }
+
+                    s
+                }
+
+                Self::Assert(cond, None) => format!("assert {cond}"),
+                Self::Assert(cond, Some(message)) => format!("assert {cond}, {message}"),
+                Self::TryCatch(block, name, catch_block) => format!(
+                    "try {}\ncatch {name} {}",
+                    format_block(block),
+                    format_block(catch_block)
                 ),
 
                 Self::Block(exps) => format_block(exps),
 
                 Self::Variable(name) => name.to_string(),
                 Self::VariableDeclaration(name, value) => format!("let {name} := {value}"),
+                Self::ConstantDeclaration(name, value) => format!("const {name} := {value}"),
+                Self::EnumDeclaration(name, variants) => {
+                    format!("enum {name} {{ {} }}", variants.join(", "))
+                }
+                Self::Destructure(names, rest, value) => format!(
+                    "let [{}] := {value}",
+                    names
+                        .iter()
+                        .cloned()
+                        .chain(rest.iter().map(|rest| format!("...{rest}")))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                Self::Assign(name, value) => format!("{name} := {value}"),
+                Self::AssignPath(name, path, value) => format!(
+                    "{name}{} := {value}",
+                    path.iter()
+                        .map(|step| format!("[{step}]"))
+                        .collect::<String>()
+                ),
+                Self::Return(Some(value)) => format!("return {value}"),
+                Self::Return(None) => "return".into(),
+                Self::Break(label, value) => {
+                    let label = label.as_ref().map(|l| format!(" '{l}")).unwrap_or_default();
+                    let value = value.as_ref().map(|v| format!(" {v}")).unwrap_or_default();
+                    format!("break{label}{value}")
+                }
+                Self::Continue(Some(label)) => format!("continue '{label}"),
+                Self::Continue(None) => "continue".into(),
                 Self::Function(name, inputs) => {
                     let mut s = name.clone();
 
@@ -377,11 +1373,40 @@ impl Display for Expr {
                     s
                 }
 
-                Self::FunctionDeclaration(_name, _block) => {
-                    todo!()
+                Self::NamedArgument(name, value) => format!("{name}: {value}"),
+                Self::Spread(array) => format!("...{array}"),
+                Self::Field(record, field) => format!("{record}.{field}"),
+                Self::Construct(name, fields) => format!(
+                    "{name} {{{}}}",
+                    fields
+                        .iter()
+                        .map(|field| format!("{field}: {field}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                Self::SafeCall(name, inputs) => format!(
+                    "{}?.{name}({})",
+                    inputs[0],
+                    inputs[1..]
+                        .iter()
+                        .map(|arg| arg.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+
+                Self::FunctionDeclaration(name, desc) => {
+                    let (inputs, body) = format_function(desc);
+                    let doc = desc
+                        .doc
+                        .iter()
+                        .flat_map(|doc| doc.lines())
+                        .map(|line| format!("/// {line}\n"))
+                        .collect::<String>();
+                    format!("{doc}fn {name}({inputs}) -> {} {body}", desc.output)
                 }
-                Self::FunctionValue(_) => {
-                    todo!()
+                Self::FunctionValue(desc) => {
+                    let (inputs, body) = format_function(desc);
+                    format!("|{inputs}| -> {} {body}", desc.output)
                 }
             }
         )
@@ -391,11 +1416,23 @@ impl Display for Expr {
 impl Expr {
     pub fn data_type(&self, state: &ExecutionState) -> DataType {
         match self {
-            Expr::String(_) => DataType::String,
-            Expr::Add(_, _)
-            | Expr::Sub(_, _)
-            | Expr::Mul(_, _)
+            Expr::String(_) | Expr::Interpolate(_) => DataType::String,
+            // Both repeat a String or Array, and `+` joins two arrays, so what they give depends
+            // on what they're given.
+            Expr::Mul(lhs, rhs) => match (lhs.data_type(state), rhs.data_type(state)) {
+                (repeated @ (DataType::String | DataType::Array), _)
+                | (_, repeated @ (DataType::String | DataType::Array)) => repeated,
+                (DataType::Any, _) | (_, DataType::Any) => DataType::Any,
+                _ => DataType::Number,
+            },
+            Expr::Add(lhs, rhs) => match (lhs.data_type(state), rhs.data_type(state)) {
+                (DataType::Array, _) | (_, DataType::Array) => DataType::Array,
+                (DataType::Any, _) | (_, DataType::Any) => DataType::Any,
+                _ => DataType::Number,
+            },
+            Expr::Sub(_, _)
             | Expr::Div(_, _)
+            | Expr::IntDiv(_, _)
             | Expr::Mod(_, _)
             | Expr::Neg(_)
             | Expr::Num(_) => DataType::Number,
@@ -409,30 +1446,115 @@ impl Expr {
             | Expr::Gt(_, _)
             | Expr::Ge(_, _)
             | Expr::Eq(_, _)
-            | Expr::Ne(_, _) => DataType::Bool,
+            | Expr::Ne(_, _)
+            | Expr::Chain(_, _) => DataType::Bool,
             Expr::Null => DataType::Null,
             Expr::Variable(_) => DataType::Any,
-            Expr::Function(name, _) => state.functions.get(name).map(|f| f.output).unwrap(),
-            Expr::FunctionValue(f) => f.output,
+            Expr::Function(name, _) => state
+                .functions
+                .get(name)
+                .map_or(DataType::Any, |f| f.output.clone()),
+            Expr::SafeCall(_, _) => DataType::Any,
+            Expr::FunctionValue(_) => DataType::Function,
+            Expr::Return(Some(value)) => value.data_type(state),
+            Expr::Return(None) | Expr::Break(..) | Expr::Continue(_) => DataType::Null,
             Expr::FunctionDeclaration(_, _) => DataType::Null,
             Expr::Array(_) => DataType::Array,
-            Expr::Block(block) => block.last().unwrap().data_type(state),
-            Expr::VariableDeclaration(_, _) => DataType::Null,
-            Expr::If(_, b, _, _) => b.last().unwrap().data_type(state),
-            Expr::For(_, _, _) | Expr::While(_, _) => DataType::Null,
+            Expr::Tuple(_) => DataType::Tuple,
+            Expr::Map(_) => DataType::Map,
+            Expr::Block(block) => block.last().map_or(DataType::Null, |e| e.data_type(state)),
+            Expr::VariableDeclaration(_, _)
+            | Expr::Destructure(_, _, _)
+            | Expr::ConstantDeclaration(_, _)
+            | Expr::EnumDeclaration(_, _)
+            | Expr::Assert(_, _)
+            | Expr::Assign(_, _)
+            | Expr::AssignPath(_, _, _) => DataType::Null,
+            Expr::If(_, b, _, _) => b.last().map_or(DataType::Null, |e| e.data_type(state)),
+            Expr::For(..) | Expr::While(..) | Expr::DoWhile(..) => DataType::Null,
+            Expr::Loop(..) | Expr::TryCatch(..) => DataType::Any,
+            Expr::Import(..) => DataType::Null,
+            Expr::NamedArgument(_, value) => value.data_type(state),
+            Expr::Spread(_) => DataType::Array,
+            Expr::Field(record, _) => match enum_of(record, state) {
+                Some((name, _)) => DataType::Named(name.clone()),
+                None => DataType::Any,
+            },
+            Expr::Construct(name, _) => DataType::Named(name.clone()),
+        }
+    }
+}
+
+/// Splits a function into its formatted parameter list and body.
+fn format_function(desc: &FunctionDescriptor) -> (String, String) {
+    let body = match &desc.function {
+        FunctionType::Custom(block, ..) => format_block(block),
+        FunctionType::BuiltIn(_) | FunctionType::BuiltInWithState(_) => "{ <builtin> }".to_string(),
+    };
+
+    (desc.params(), body)
+}
+
+/// Runs `block` for as long as `cond` holds, checking it before each run, or after each run
+/// when `check_first` is false. Returns whether the loop was left with `break`.
+fn conditional_loop(
+    label: &Option<String>,
+    block: &[Expr],
+    cond: &Expr,
+    check_first: bool,
+    loc: &str,
+    state: &mut ExecutionState,
+) -> EResult<bool> {
+    let mut check = check_first;
+
+    loop {
+        if check && !cond.eval(state)?.condition(loc)? {
+            return Ok(false);
+        }
+        check = true;
+
+        state.check_interrupted()?;
+
+        match execute_block(block, state) {
+            Err(e) if e.escapes(label) => return Err(e),
+            Err(ExprError::Break(..)) => return Ok(true),
+            Ok(_) | Err(ExprError::Continue(_)) => {}
+            Err(e) => return Err(e),
         }
     }
 }
 
+fn format_label(label: &Option<String>) -> String {
+    match label {
+        Some(label) => format!("'{label}: "),
+        None => String::new(),
+    }
+}
+
+fn format_loop_else(else_block: &Option<Vec<Expr>>) -> String {
+    match else_block {
+        Some(block) => format!(" else {}", format_block(block)),
+        None => String::new(),
+    }
+}
+
 pub fn format_block(block: &[Expr]) -> String {
     format!(
         "{{\n{}}}",
         indent(
             &block
                 .iter()
-                .map(|e| e.display())
+                .map(format_statement)
                 .collect::<Vec<String>>()
                 .join("\n")
         )
     )
 }
+
+/// Formats `e` as a statement, with a `;` unless it ends in a block that doesn't need one.
+pub fn format_statement(e: &Expr) -> String {
+    match e {
+        Expr::While(..) | Expr::For(..) | Expr::FunctionDeclaration(..) => e.display(),
+        e => format!("{e};"),
+    }
+}
@@ -0,0 +1,181 @@
+//! Variables, kept in a stack of scopes. Blocks, loop iterations and function calls push a
+//! scope and pop it when they are done, so nothing they declare outlives them, while
+//! assignments change the variable where it was declared.
+//!
+//! A function body only sees the global scope and its own: the scope of a call hides those of
+//! the caller, so what a body can read depends on where it was written, not where it is
+//! called from.
+
+use std::{ops::Index, sync::Arc};
+
+use super::VariableMap;
+use crate::data::Data;
+
+#[derive(Debug, Clone)]
+pub struct Scopes {
+    /// Globals shared with other states, under the global scope, e.g. those of a
+    /// `SharedState`. Assigning to one copies it into the global scope, which hides it.
+    frozen: Arc<VariableMap>,
+    /// The innermost scope last. There is always at least the global one.
+    scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    variables: VariableMap,
+    /// Set on the scope a function body runs in, which hides the scopes between it and the
+    /// global one. Assigning to a global makes a copy in it instead, so changes never flow back
+    /// to the caller.
+    call: bool,
+}
+
+impl Scopes {
+    /// The variable `name` from the innermost scope that has it.
+    pub fn get(&self, name: &str) -> Option<&Data> {
+        self.reachable()
+            .rev()
+            .find_map(|(_, scope)| scope.variables.get(name))
+            .or_else(|| self.frozen.get(name))
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Declares `name` in the innermost scope, shadowing any outer variable of that name.
+    pub fn declare(&mut self, name: String, value: Data) {
+        self.innermost().variables.insert(name, value);
+    }
+
+    /// Changes the variable `name` where it was declared, returning false if there is none.
+    pub fn assign(&mut self, name: &str, value: Data) -> bool {
+        let Some(declared) = self
+            .position(name)
+            .or_else(|| self.frozen.contains_key(name).then_some(0))
+        else {
+            return false;
+        };
+        let call = self.scopes.iter().rposition(|scope| scope.call);
+
+        let scope = match call {
+            Some(call) if call > declared => call,
+            _ => declared,
+        };
+        self.scopes[scope].variables.insert(name.to_string(), value);
+
+        true
+    }
+
+    /// Assigns `name` if it exists and declares it otherwise, returning the value it had.
+    pub fn set(&mut self, name: &str, value: Data) -> Option<Data> {
+        let old = self.get(name).cloned();
+
+        if !self.assign(name, value.clone()) {
+            self.declare(name.to_string(), value);
+        }
+
+        old
+    }
+
+    /// Removes the variable `name` from the innermost scope that has it.
+    pub fn remove(&mut self, name: &str) -> Option<Data> {
+        match self.position(name) {
+            Some(scope) => self.scopes[scope].variables.remove(name),
+            None if self.frozen.contains_key(name) => Arc::make_mut(&mut self.frozen).remove(name),
+            None => None,
+        }
+    }
+
+    /// Every variable that can be read, by name.
+    pub fn visible(&self) -> VariableMap {
+        let mut variables = (*self.frozen).clone();
+        for (_, scope) in self.reachable() {
+            variables.extend(scope.variables.clone());
+        }
+        variables
+    }
+
+    /// The variables that can be read other than the globals, which a closure made here
+    /// captures.
+    pub fn locals(&self) -> VariableMap {
+        let mut variables = VariableMap::new();
+        for (_, scope) in self.reachable().skip(1) {
+            variables.extend(scope.variables.clone());
+        }
+        variables
+    }
+
+    pub(super) fn push(&mut self, variables: VariableMap, call: bool) {
+        self.scopes.push(Scope { variables, call });
+    }
+
+    pub(super) fn pop(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    fn innermost(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.reachable()
+            .rev()
+            .find(|(_, scope)| scope.variables.contains_key(name))
+            .map(|(i, _)| i)
+    }
+
+    /// The scopes that can be read from the innermost one, with their positions: the global
+    /// scope, then the innermost call's scope and those after it.
+    fn reachable(&self) -> impl DoubleEndedIterator<Item = (usize, &Scope)> {
+        let call = self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.call)
+            .unwrap_or(0);
+
+        self.scopes
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| *i == 0 || *i >= call)
+    }
+}
+
+impl Default for Scopes {
+    fn default() -> Self {
+        Self::from(VariableMap::new())
+    }
+}
+
+/// Global variables only.
+impl From<VariableMap> for Scopes {
+    fn from(variables: VariableMap) -> Self {
+        Self {
+            frozen: Arc::default(),
+            scopes: vec![Scope {
+                variables,
+                call: false,
+            }],
+        }
+    }
+}
+
+/// The globals of a frozen state, shared rather than copied, under an empty global scope.
+impl From<Arc<VariableMap>> for Scopes {
+    fn from(frozen: Arc<VariableMap>) -> Self {
+        Self {
+            frozen,
+            ..Self::default()
+        }
+    }
+}
+
+impl Index<&str> for Scopes {
+    type Output = Data;
+
+    fn index(&self, name: &str) -> &Data {
+        self.get(name)
+            .unwrap_or_else(|| panic!("no variable `{name}`"))
+    }
+}
@@ -1,10 +1,37 @@
-use super::{FunctionDescriptor, FunctionType, Input, Output};
+use super::{
+    numeric::overflow, string::byte_index, FunctionDescriptor, FunctionType, Input, Output,
+};
 use crate::{
-    data::{Data, DataType, ToData},
-    expr::error::ExprError,
+    data::{format_types, Data, DataType, ToData},
+    expr::{error::ExprError, EResult},
     utils::strings::DotDisplay,
 };
-use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+pub(super) fn checked_index(index: Decimal, length: usize) -> EResult<usize> {
+    index
+        .to_usize()
+        .filter(|i| index.fract().is_zero() && *i < length)
+        .ok_or(ExprError::IndexOutOfBounds {
+            index: index.to_string(),
+            length,
+        })
+}
+
+/// Like [`checked_index`], but negative indices count back from the end, so -1 is the last
+/// element.
+pub(super) fn wrapped_index(index: Decimal, length: usize) -> EResult<usize> {
+    let wrapped = if index < Decimal::ZERO {
+        index + Decimal::from(length)
+    } else {
+        index
+    };
+
+    checked_index(wrapped, length).map_err(|_| ExprError::IndexOutOfBounds {
+        index: index.to_string(),
+        length,
+    })
+}
 
 fn join_array(i: Input) -> Output {
     let a = i[0].array();
@@ -20,8 +47,16 @@ fn join_array(i: Input) -> Output {
 pub fn join_array_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::String],
+        inputs_rest: None,
+        param_names: &["array", "separator"],
         function: FunctionType::BuiltIn(join_array),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some(
+            "Joins the elements of `array` into a string, with `separator` between them.\n\n\
+             >>> join_array([\"a\", \"b\"], \"-\");\na-b"
+                .to_string(),
+        ),
     }
 }
 
@@ -35,34 +70,132 @@ fn sort(i: Input) -> Output {
 pub fn sort_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
         function: FunctionType::BuiltIn(sort),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some("`array` sorted in ascending order.".to_string()),
     }
 }
 
 fn length(i: Input) -> Output {
-    i[0].array().len().data()
+    match &i[0] {
+        Data::Array(a) | Data::Tuple(a) => a.len().data(),
+        Data::Map(m) => m.len().data(),
+        Data::String(s) => s.chars().count().data(),
+        other => Err(ExprError::InvalidDataType {
+            expected: "Array, Tuple, Map or String".to_string(),
+            found: other._type().to_string(),
+            loc: "length input".to_string(),
+        }),
+    }
 }
 
 pub fn length_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
-        inputs: vec![DataType::Array],
+        inputs: vec![DataType::Any],
+        inputs_rest: None,
+        param_names: &["collection"],
         function: FunctionType::BuiltIn(length),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some(
+            "The number of elements in an array, tuple or map, or of characters in a string."
+                .to_string(),
+        ),
     }
 }
 
+/// `collection[key]` is sugar for `index(collection, key)`. Arrays take a number, maps a string.
 fn index(i: Input) -> Output {
-    let a = i[0].array();
-    let i = i[1].number().to_usize().unwrap();
-    Ok(a[i].clone())
+    match (&i[0], &i[1]) {
+        (Data::Array(a) | Data::Tuple(a), Data::Number(n)) => {
+            Ok(a[wrapped_index(*n, a.len())?].clone())
+        }
+        (Data::Map(m), Data::String(key)) => m
+            .get(key)
+            .cloned()
+            .ok_or_else(|| ExprError::KeyNotFound { key: key.clone() }),
+        (Data::Array(_) | Data::Tuple(_) | Data::Map(_), key) => Err(ExprError::InvalidDataType {
+            expected: match i[0] {
+                Data::Array(_) | Data::Tuple(_) => DataType::Number.to_string(),
+                _ => DataType::String.to_string(),
+            },
+            found: key._type().to_string(),
+            loc: "index key".to_string(),
+        }),
+        (other, _) => Err(ExprError::InvalidDataType {
+            expected: "Array, Tuple or Map".to_string(),
+            found: other._type().to_string(),
+            loc: "index input".to_string(),
+        }),
+    }
 }
 
 pub fn index_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
-        inputs: vec![DataType::Array, DataType::Number],
+        inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["collection", "index"],
         function: FunctionType::BuiltIn(index),
         output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The element of an array at `index`, or the value of a map at a key. `collection[index]` is the same.".to_string()),
+    }
+}
+
+/// `collection[start:end]` is sugar for `slice(collection, start, end)`, with an omitted bound
+/// passed as null. Negative bounds count back from the end, and bounds past either end are
+/// clamped to it.
+fn slice(i: Input) -> Output {
+    let bound = |data: &Data, default: usize, length: usize| -> EResult<usize> {
+        match data {
+            Data::Null => Ok(default),
+            Data::Number(n) if *n < Decimal::ZERO => Ok((*n + Decimal::from(length))
+                .max(Decimal::ZERO)
+                .to_usize()
+                .unwrap_or(0)),
+            Data::Number(n) => Ok((*n).to_usize().unwrap_or(usize::MAX).min(length)),
+            other => Err(ExprError::InvalidDataType {
+                expected: "Number or Null".to_string(),
+                found: other._type().to_string(),
+                loc: "slice bound".to_string(),
+            }),
+        }
+    };
+    let bounds = |length: usize| -> EResult<(usize, usize)> {
+        let start = bound(&i[1], 0, length)?;
+        let end = bound(&i[2], length, length)?;
+        Ok((start, end.max(start)))
+    };
+
+    match &i[0] {
+        Data::Array(a) => {
+            let (start, end) = bounds(a.len())?;
+            a[start..end].to_vec().data()
+        }
+        Data::String(s) => {
+            let (start, end) = bounds(s.chars().count())?;
+            s[byte_index(s, start)..byte_index(s, end)].data()
+        }
+        other => Err(ExprError::InvalidDataType {
+            expected: "Array or String".to_string(),
+            found: other._type().to_string(),
+            loc: "slice input".to_string(),
+        }),
+    }
+}
+
+pub fn slice_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Any, DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["collection", "start", "end"],
+        function: FunctionType::BuiltIn(slice),
+        output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The part of `collection` from `start` up to `end`, where negative bounds count from the end. `collection[start:end]` is the same.\n\n>>> slice([1, 2, 3, 4], 1, -1);\n[2, 3]".to_string()),
     }
 }
 
@@ -78,8 +211,12 @@ fn append(i: Input) -> Output {
 pub fn append_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::Any],
+        inputs_rest: None,
+        param_names: &["array", "item"],
         function: FunctionType::BuiltIn(append),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some("`array` with `item` added to the end.".to_string()),
     }
 }
 
@@ -98,8 +235,12 @@ fn flatten(i: Input) -> Output {
 pub fn flatten_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
         function: FunctionType::BuiltIn(flatten),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some("`array` with the arrays in it replaced by their elements.".to_string()),
     }
 }
 
@@ -113,12 +254,17 @@ fn reverse(i: Input) -> Output {
 pub fn reverse_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
         function: FunctionType::BuiltIn(reverse),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some("`array` in reverse order.".to_string()),
     }
 }
 
-fn extend(i: Input) -> Output {
+/// Also what `+` does with two arrays.
+pub fn extend(i: Input) -> Output {
     let mut a = i[0].array();
     a.extend(i[1].array());
 
@@ -128,16 +274,23 @@ fn extend(i: Input) -> Output {
 pub fn extend_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::Array],
+        inputs_rest: None,
+        param_names: &["array", "other"],
         function: FunctionType::BuiltIn(extend),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some(
+            "The elements of `array` followed by those of `other`, like `array + other`."
+                .to_string(),
+        ),
     }
 }
 
 fn without(i: Input) -> Output {
     let mut a = i[0].array();
-    let index = i[1].number();
+    let index = wrapped_index(i[1].number(), a.len())?;
 
-    a.remove(index.to_usize().unwrap());
+    a.remove(index);
 
     a.data()
 }
@@ -145,17 +298,30 @@ fn without(i: Input) -> Output {
 pub fn without_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::Number],
+        inputs_rest: None,
+        param_names: &["array", "index"],
         function: FunctionType::BuiltIn(without),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some(
+            "`array` with the element at `index` removed. Negative indexes count from the end.\n\n\
+             >>> without([1, 2, 3], -1);\n[1, 2]"
+                .to_string(),
+        ),
     }
 }
 
 fn with_insert(i: Input) -> Output {
     let mut a = i[0].array();
-    let index = i[1].number();
     let item = i[2].clone();
+    // Inserting at the length appends.
+    let index = if i[1].number() == Decimal::from(a.len()) {
+        a.len()
+    } else {
+        wrapped_index(i[1].number(), a.len())?
+    };
 
-    a.insert(index.to_usize().unwrap(), item);
+    a.insert(index, item);
 
     a.data()
 }
@@ -163,22 +329,88 @@ fn with_insert(i: Input) -> Output {
 pub fn with_insert_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::Number, DataType::Any],
+        inputs_rest: None,
+        param_names: &["array", "index", "item"],
         function: FunctionType::BuiltIn(with_insert),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some(
+            "`array` with `item` inserted at `index`, or appended if `index` is its length."
+                .to_string(),
+        ),
+    }
+}
+
+/// The most numbers `range` will produce, so a typo doesn't try to allocate the world.
+const MAX_RANGE_LENGTH: i64 = 10_000_000;
+
+/// The longest array (in items) or string (in bytes) that `*` repeats something into.
+const MAX_REPEAT_LENGTH: usize = 10_000_000;
+
+/// `value * count` for a String or Array `value`: `count` copies of it, one after another.
+pub fn repeat(value: Data, count: Decimal) -> Output {
+    let domain_error = |reason: &str| ExprError::MathDomain {
+        operation: format!("{} * {count}", value._type()),
+        reason: reason.to_string(),
+    };
+
+    let Some(count) = count.to_usize().filter(|_| count.fract().is_zero()) else {
+        return Err(domain_error(
+            "it can only be repeated a whole number of times, 0 or more",
+        ));
+    };
+    let length = match &value {
+        Data::String(s) => s.len(),
+        Data::Array(a) => a.len(),
+        _ => unreachable!(),
+    };
+    if length.saturating_mul(count) > MAX_REPEAT_LENGTH {
+        return Err(domain_error(&format!(
+            "it would be longer than {MAX_REPEAT_LENGTH}"
+        )));
+    }
+
+    match value {
+        Data::String(s) => s.repeat(count).data(),
+        Data::Array(a) => a
+            .iter()
+            .cycle()
+            .take(length * count)
+            .cloned()
+            .collect::<Vec<_>>()
+            .data(),
+        _ => unreachable!(),
     }
 }
 
 fn range(i: Input) -> Output {
-    ((i[0].number().to_usize().unwrap())..(i[1].number().to_usize().unwrap()))
-        .collect::<Vec<_>>()
-        .data()
+    let (start, end) = (i[0].number(), i[1].number());
+    let domain_error = |reason: &str| ExprError::MathDomain {
+        operation: format!("range({start}, {end})"),
+        reason: reason.to_string(),
+    };
+
+    let (Some(from), Some(to)) = (start.trunc().to_i64(), end.trunc().to_i64()) else {
+        return Err(domain_error("the bounds must fit in a 64 bit integer"));
+    };
+    if to.saturating_sub(from) > MAX_RANGE_LENGTH {
+        return Err(domain_error(&format!(
+            "it would have more than {MAX_RANGE_LENGTH} numbers"
+        )));
+    }
+
+    (from..to).collect::<Vec<_>>().data()
 }
 
 pub fn range_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["start", "end"],
         function: FunctionType::BuiltIn(range),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The integers from `start` up to but not including `end`.".to_string()),
     }
 }
 
@@ -192,8 +424,12 @@ fn max_array(i: Input) -> Output {
 pub fn max_array_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
         function: FunctionType::BuiltIn(max_array),
         output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The largest element of `array`.".to_string()),
     }
 }
 
@@ -207,8 +443,12 @@ fn min_array(i: Input) -> Output {
 pub fn min_array_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
         function: FunctionType::BuiltIn(min_array),
         output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The smallest element of `array`.".to_string()),
     }
 }
 
@@ -219,8 +459,12 @@ fn first(i: Input) -> Output {
 pub fn first_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
         function: FunctionType::BuiltIn(first),
         output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The first element of `array`.".to_string()),
     }
 }
 
@@ -231,7 +475,186 @@ fn last(i: Input) -> Output {
 pub fn last_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
         function: FunctionType::BuiltIn(last),
         output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The last element of `array`.".to_string()),
+    }
+}
+
+fn swap(i: Input) -> Output {
+    let mut a = i[0].array();
+    let first = checked_index(i[1].number(), a.len())?;
+    let second = checked_index(i[2].number(), a.len())?;
+
+    a.swap(first, second);
+
+    a.data()
+}
+
+pub fn swap_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array, DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["array", "a", "b"],
+        function: FunctionType::BuiltIn(swap),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("`array` with the elements at `a` and `b` swapped.".to_string()),
+    }
+}
+
+/// Normalizes a (possibly negative or oversized) left rotation amount into `0..length`.
+fn rotation(n: Decimal, length: usize) -> usize {
+    if length == 0 {
+        return 0;
+    }
+
+    n.trunc().to_i128().unwrap_or(0).rem_euclid(length as i128) as usize
+}
+
+fn rotate_left(i: Input) -> Output {
+    let mut a = i[0].array();
+    let n = rotation(i[1].number(), a.len());
+
+    a.rotate_left(n);
+
+    a.data()
+}
+
+pub fn rotate_left_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array, DataType::Number],
+        inputs_rest: None,
+        param_names: &["array", "n"],
+        function: FunctionType::BuiltIn(rotate_left),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some(
+            "`array` with its elements moved `n` places towards the start, wrapping around."
+                .to_string(),
+        ),
+    }
+}
+
+fn rotate_right(i: Input) -> Output {
+    let mut a = i[0].array();
+    let n = rotation(-i[1].number(), a.len());
+
+    a.rotate_left(n);
+
+    a.data()
+}
+
+pub fn rotate_right_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array, DataType::Number],
+        inputs_rest: None,
+        param_names: &["array", "n"],
+        function: FunctionType::BuiltIn(rotate_right),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some(
+            "`array` with its elements moved `n` places towards the end, wrapping around."
+                .to_string(),
+        ),
+    }
+}
+
+fn pairwise(i: Input) -> Output {
+    i[0].array()
+        .windows(2)
+        .map(|w| Data::Array(w.to_vec()))
+        .collect::<Vec<_>>()
+        .data()
+}
+
+pub fn pairwise_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
+        function: FunctionType::BuiltIn(pairwise),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some(
+            "Every pair of neighbouring elements of `array`, as two element arrays.".to_string(),
+        ),
+    }
+}
+
+/// Pairs up the elements of two arrays as tuples, stopping at the end of the shorter one.
+fn zip(i: Input) -> Output {
+    i[0].array()
+        .iter()
+        .zip(i[1].array())
+        .map(|(a, b)| Data::Tuple(vec![a.clone(), b.clone()]))
+        .collect::<Vec<_>>()
+        .data()
+}
+
+pub fn zip_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array, DataType::Array],
+        inputs_rest: None,
+        param_names: &["a", "b"],
+        function: FunctionType::BuiltIn(zip),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The elements of `a` and `b` paired up as tuples, stopping at the end of the shorter one.".to_string()),
+    }
+}
+
+fn contains(i: Input) -> Output {
+    match (&i[0], &i[1]) {
+        (Data::Array(a), item) => a.contains(item).data(),
+        (Data::Map(m), Data::String(key)) => m.contains_key(key).data(),
+        (Data::String(s), Data::String(search)) => s.contains(search.as_str()).data(),
+        (collection, item) => Err(ExprError::InvalidFunctionArguements {
+            expected: "(Array, Any), (Map, String) or (String, String)".to_string(),
+            found: format_types(vec![collection._type(), item._type()]),
+        }),
+    }
+}
+
+pub fn contains_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["collection", "item"],
+        function: FunctionType::BuiltIn(contains),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether an array has `item`, a map has the key `item`, or a string has the substring `item`.".to_string()),
+    }
+}
+
+fn sum(i: Input) -> Output {
+    i[0].array()
+        .iter()
+        .try_fold(Decimal::ZERO, |total, n| match n {
+            Data::Number(n) => total
+                .checked_add(*n)
+                .ok_or_else(|| overflow(format!("sum({})", i[0]))),
+            other => Err(ExprError::InvalidDataType {
+                expected: DataType::Number.to_string(),
+                found: other._type().to_string(),
+                loc: "sum input".to_string(),
+            }),
+        })?
+        .data()
+}
+
+pub fn sum_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
+        function: FunctionType::BuiltIn(sum),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The sum of the numbers in `array`.".to_string()),
     }
 }
@@ -1,5 +1,36 @@
+use std::cmp::Ordering;
+
 use super::{FunctionDescriptor, FunctionType, Input, Output};
-use crate::data::{DataType, ToData};
+use crate::{
+    data::{format_types, Data, DataType, ToData},
+    expr::{error::ExprError, EResult},
+};
+
+/// Orders two Numbers or two Strings (lexicographically). Anything else can't be compared.
+fn compare(i: &Input) -> EResult<Ordering> {
+    match (&i[0], &i[1]) {
+        (Data::Number(_), Data::Number(_)) | (Data::String(_), Data::String(_)) => {
+            Ok(i[0].cmp(&i[1]))
+        }
+        _ => Err(ExprError::InvalidFunctionArguements {
+            expected: "(Number, Number) or (String, String)".to_string(),
+            found: format_types(vec![i[0]._type(), i[1]._type()]),
+        }),
+    }
+}
+
+/// Fails if `i` are variants of different enums, which can never be equal.
+fn same_enum(i: &Input) -> EResult<()> {
+    match (&i[0], &i[1]) {
+        (Data::Enum(left, _), Data::Enum(right, _)) if left != right => {
+            Err(ExprError::EnumMismatch {
+                left: left.clone(),
+                right: right.clone(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
 
 pub fn and(i: Input) -> Output {
     (i[0].bool() && i[1].bool()).data()
@@ -8,8 +39,12 @@ pub fn and(i: Input) -> Output {
 pub fn and_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Bool, DataType::Bool],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(and),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `a` and `b` are both true, like `a && b`.".to_string()),
     }
 }
 
@@ -20,32 +55,46 @@ pub fn or(i: Input) -> Output {
 pub fn or_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Bool, DataType::Bool],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(or),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `a` or `b` is true, like `a || b`.".to_string()),
     }
 }
 
 pub fn eq(i: Input) -> Output {
+    same_enum(&i)?;
     (i[0] == i[1]).data()
 }
 
 pub fn eq_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(eq),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `a` equals `b`, like `a == b`.".to_string()),
     }
 }
 
 pub fn ne(i: Input) -> Output {
+    same_enum(&i)?;
     (i[0] != i[1]).data()
 }
 
 pub fn ne_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(ne),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `a` doesn't equal `b`, like `a != b`.".to_string()),
     }
 }
 
@@ -56,8 +105,12 @@ pub fn not(i: Input) -> Output {
 pub fn not_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Bool],
+        inputs_rest: None,
+        param_names: &["a"],
         function: FunctionType::BuiltIn(not),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("The opposite of `a`, like `!a`.".to_string()),
     }
 }
 
@@ -68,55 +121,75 @@ pub fn xor(i: Input) -> Output {
 pub fn xor_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Bool, DataType::Bool],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(xor),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether exactly one of `a` and `b` is true, like `a ^ b`.".to_string()),
     }
 }
 
 pub fn gt(i: Input) -> Output {
-    (i[0].number().gt(&i[1].number())).data()
+    compare(&i)?.is_gt().data()
 }
 
 pub fn gt_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
-        inputs: vec![DataType::Number, DataType::Number],
+        inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(gt),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `a` is greater than `b`, like `a > b`.".to_string()),
     }
 }
 
 pub fn lt(i: Input) -> Output {
-    (i[0].number().lt(&i[1].number())).data()
+    compare(&i)?.is_lt().data()
 }
 
 pub fn lt_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
-        inputs: vec![DataType::Number, DataType::Number],
+        inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(lt),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `a` is less than `b`, like `a < b`.".to_string()),
     }
 }
 
 pub fn ge(i: Input) -> Output {
-    (i[0].number().ge(&i[1].number())).data()
+    compare(&i)?.is_ge().data()
 }
 
 pub fn ge_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
-        inputs: vec![DataType::Number, DataType::Number],
+        inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(ge),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `a` is greater than or equal to `b`, like `a >= b`.".to_string()),
     }
 }
 
 pub fn le(i: Input) -> Output {
-    (i[0].number().le(&i[1].number())).data()
+    compare(&i)?.is_le().data()
 }
 
 pub fn le_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
-        inputs: vec![DataType::Number, DataType::Number],
+        inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(le),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `a` is less than or equal to `b`, like `a <= b`.".to_string()),
     }
 }
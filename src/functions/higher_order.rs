@@ -1,47 +1,29 @@
-use super::{FunctionDescriptor, FunctionType, Input, Output};
+use rust_decimal::Decimal;
+
+use super::{numeric::overflow, FunctionDescriptor, FunctionType, Input, Output};
 use crate::{
     data::{format_types, Data, DataType, ToData},
-    execute_block,
+    execute_statements,
     expr::{error::ExprError, EResult, ExecutionState},
+    run,
 };
 
-fn run(inputs: Vec<Data>, func: FunctionDescriptor, state: &ExecutionState) -> EResult<Data> {
-    let matching_types = inputs
-        .iter()
-        .map(|i| i._type())
-        .zip(func.inputs.iter())
-        .all(|(input, expected)| input == *expected || expected.is_any());
-
-    if matching_types {
-        Ok(match func.function {
-            FunctionType::BuiltIn(f) => f(inputs)?,
-            FunctionType::Custom(block, input_names) => {
-                let mut state = state.clone();
-
-                for (i, name) in input_names.iter().enumerate() {
-                    state.variables.insert(name.clone(), inputs[i].clone());
-                }
-
-                execute_block(&block, &state).0
-            }
-        })
-    } else {
-        let input_types = inputs.iter().map(|i| i._type()).collect::<Vec<_>>();
-        Err(ExprError::InvalidFunctionArguements {
-            expected: format_types(func.inputs),
-            found: format_types(input_types),
-            // name:  name.clone(),
-        })
-    }
+/// Calls a function value with already evaluated inputs.
+pub(super) fn call(
+    inputs: Vec<Data>,
+    func: FunctionDescriptor,
+    state: &mut ExecutionState,
+) -> EResult<Data> {
+    run!(func, inputs, state, "function value")
 }
 
-fn map(i: Input) -> Output {
+fn map(i: Input, state: &mut ExecutionState) -> Output {
     let array = i[0].array().clone();
     let func = i[1].function().clone();
 
     array
         .iter()
-        .map(|i| run(vec![i.clone()], func.clone(), &ExecutionState::new()))
+        .map(|i| call(vec![i.clone()], func.clone(), state))
         .collect::<EResult<Vec<_>>>()
         .map(|i| i.data())?
 }
@@ -49,18 +31,22 @@ fn map(i: Input) -> Output {
 pub fn map_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::Function],
-        function: FunctionType::BuiltIn(map),
+        inputs_rest: None,
+        param_names: &["array", "f"],
+        function: FunctionType::BuiltInWithState(map),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some("Calls `f` on each element of `array`, returning the results.".to_string()),
     }
 }
 
-fn for_each(i: Input) -> Output {
+fn for_each(i: Input, state: &mut ExecutionState) -> Output {
     let array = i[0].array().clone();
     let func = i[1].function().clone();
 
-    array.iter().for_each(|i| {
-        run(vec![i.clone()], func.clone(), &ExecutionState::new()).unwrap();
-    });
+    for i in array {
+        call(vec![i], func.clone(), state)?;
+    }
 
     Ok(Data::Null)
 }
@@ -68,56 +54,200 @@ fn for_each(i: Input) -> Output {
 pub fn for_each_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::Function],
-        function: FunctionType::BuiltIn(for_each),
+        inputs_rest: None,
+        param_names: &["array", "f"],
+        function: FunctionType::BuiltInWithState(for_each),
         output: DataType::Null,
+        capabilities: &[],
+        doc: Some("Calls `f` on each element of `array`.".to_string()),
     }
 }
 
-fn filter(i: Input) -> Output {
+fn filter(i: Input, state: &mut ExecutionState) -> Output {
     let array = i[0].array().clone();
     let func = i[1].function().clone();
 
-    array
-        .iter()
-        .filter(|&i| {
-            run(vec![i.clone()], func.clone(), &ExecutionState::new())
-                .unwrap()
-                .is_true()
-        })
-        .collect::<Vec<_>>()
-        .data()
+    let mut output = vec![];
+
+    for i in array {
+        if call(vec![i.clone()], func.clone(), state)?.condition("filter predicate")? {
+            output.push(i);
+        }
+    }
+
+    output.data()
 }
 
 pub fn filter_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::Function],
-        function: FunctionType::BuiltIn(filter),
+        inputs_rest: None,
+        param_names: &["array", "f"],
+        function: FunctionType::BuiltInWithState(filter),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The elements of `array` that `f` returns true for.".to_string()),
     }
 }
 
-fn fold(i: Input) -> Output {
+fn fold(i: Input, state: &mut ExecutionState) -> Output {
     let array = i[0].array().clone();
     let initial = i[1].clone();
     let func = i[2].function().clone();
 
     array
-        .iter()
-        .fold(initial, |acc, i| {
-            run(
-                vec![acc.clone(), i.clone()],
-                func.clone(),
-                &ExecutionState::new(),
-            )
-            .unwrap()
-        })
-        .data()
+        .into_iter()
+        .try_fold(initial, |acc, i| call(vec![acc, i], func.clone(), state))
 }
 
 pub fn fold_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Array, DataType::Any, DataType::Function],
-        function: FunctionType::BuiltIn(fold),
-        output: DataType::Null,
+        inputs_rest: None,
+        param_names: &["array", "initial", "f"],
+        function: FunctionType::BuiltInWithState(fold),
+        output: DataType::Any,
+        capabilities: &[],
+        doc: Some("Combines the elements of `array` into one value, starting from `initial` and calling `f(acc, element)` for each one.\n\n>>> fold([1, 2, 3], 0, fn(acc, x) { acc + x; });\n6".to_string()),
+    }
+}
+
+fn scan(i: Input, state: &mut ExecutionState) -> Output {
+    let array = i[0].array().clone();
+    let mut acc = i[1].clone();
+    let func = i[2].function().clone();
+
+    let mut output = Vec::with_capacity(array.len());
+
+    for i in array {
+        acc = call(vec![acc, i], func.clone(), state)?;
+        output.push(acc.clone());
+    }
+
+    output.data()
+}
+
+pub fn scan_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array, DataType::Any, DataType::Function],
+        inputs_rest: None,
+        param_names: &["array", "initial", "f"],
+        function: FunctionType::BuiltInWithState(scan),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("Like `fold`, but returns every intermediate value.\n\n>>> scan([1, 2, 3], 0, fn(acc, x) { acc + x; });\n[1, 3, 6]".to_string()),
+    }
+}
+
+fn cumsum(i: Input) -> Output {
+    let mut total = Decimal::ZERO;
+
+    i[0].array()
+        .into_iter()
+        .map(|n| match n {
+            Data::Number(n) => {
+                total = total
+                    .checked_add(n)
+                    .ok_or_else(|| overflow(format!("cumsum({})", i[0])))?;
+                total.data()
+            }
+            other => Err(ExprError::InvalidDataType {
+                expected: DataType::Number.to_string(),
+                found: other._type().to_string(),
+                loc: "cumsum input".to_string(),
+            }),
+        })
+        .collect::<EResult<Vec<_>>>()
+        .map(|i| i.data())?
+}
+
+pub fn cumsum_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["array"],
+        function: FunctionType::BuiltIn(cumsum),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The running totals of the numbers in `array`.".to_string()),
+    }
+}
+
+fn take_while(i: Input, state: &mut ExecutionState) -> Output {
+    let array = i[0].array().clone();
+    let func = i[1].function().clone();
+
+    let mut output = vec![];
+
+    for i in array {
+        if !call(vec![i.clone()], func.clone(), state)?.condition("take_while predicate")? {
+            break;
+        }
+
+        output.push(i);
+    }
+
+    output.data()
+}
+
+pub fn take_while_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array, DataType::Function],
+        inputs_rest: None,
+        param_names: &["array", "f"],
+        function: FunctionType::BuiltInWithState(take_while),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The elements at the start of `array` that `f` returns true for, up to the first it doesn't.".to_string()),
+    }
+}
+
+fn any(i: Input, state: &mut ExecutionState) -> Output {
+    let array = i[0].array().clone();
+    let func = i[1].function().clone();
+
+    for i in array {
+        if call(vec![i], func.clone(), state)?.condition("any predicate")? {
+            return true.data();
+        }
+    }
+
+    false.data()
+}
+
+pub fn any_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array, DataType::Function],
+        inputs_rest: None,
+        param_names: &["array", "f"],
+        function: FunctionType::BuiltInWithState(any),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `f` returns true for any element of `array`.".to_string()),
+    }
+}
+
+fn all(i: Input, state: &mut ExecutionState) -> Output {
+    let array = i[0].array().clone();
+    let func = i[1].function().clone();
+
+    for i in array {
+        if !call(vec![i], func.clone(), state)?.condition("all predicate")? {
+            return false.data();
+        }
+    }
+
+    true.data()
+}
+
+pub fn all_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array, DataType::Function],
+        inputs_rest: None,
+        param_names: &["array", "f"],
+        function: FunctionType::BuiltInWithState(all),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `f` returns true for every element of `array`.".to_string()),
     }
 }
@@ -0,0 +1,316 @@
+use rust_decimal::prelude::ToPrimitive;
+
+use super::{
+    array::{checked_index, wrapped_index},
+    higher_order::call,
+    FunctionDescriptor, FunctionType, Input, Output,
+};
+use crate::{
+    data::{Data, DataMap, DataType, ToData, DEFAULT_DISPLAY_LIMIT},
+    expr::{error::ExprError, EResult, ExecutionState},
+};
+
+fn key(data: &Data, loc: &str) -> EResult<String> {
+    match data {
+        Data::String(s) => Ok(s.clone()),
+        other => Err(ExprError::InvalidDataType {
+            expected: DataType::String.to_string(),
+            found: other._type().to_string(),
+            loc: loc.to_string(),
+        }),
+    }
+}
+
+/// Splits a `[key, value]` array into its parts.
+fn entry(data: &Data, loc: &str) -> EResult<(String, Data)> {
+    match data {
+        Data::Array(pair) if pair.len() == 2 => Ok((key(&pair[0], loc)?, pair[1].clone())),
+        other => Err(ExprError::InvalidDataType {
+            expected: "[String, Any]".to_string(),
+            found: other.truncated(DEFAULT_DISPLAY_LIMIT),
+            loc: loc.to_string(),
+        }),
+    }
+}
+
+fn to_map(i: Input) -> Output {
+    i[0].array()
+        .iter()
+        .map(|e| entry(e, "to_map entry"))
+        .collect::<EResult<DataMap>>()?
+        .data()
+}
+
+pub fn to_map_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Array],
+        inputs_rest: None,
+        param_names: &["entries"],
+        function: FunctionType::BuiltIn(to_map),
+        output: DataType::Map,
+        capabilities: &[],
+        doc: Some("A map from an array of `[key, value]` entries.".to_string()),
+    }
+}
+
+fn keys(i: Input) -> Output {
+    i[0].map().into_keys().collect::<Vec<_>>().data()
+}
+
+pub fn keys_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Map],
+        inputs_rest: None,
+        param_names: &["map"],
+        function: FunctionType::BuiltIn(keys),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The keys of `map`.".to_string()),
+    }
+}
+
+fn values(i: Input) -> Output {
+    i[0].map().into_values().collect::<Vec<_>>().data()
+}
+
+pub fn values_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Map],
+        inputs_rest: None,
+        param_names: &["map"],
+        function: FunctionType::BuiltIn(values),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The values of `map`.".to_string()),
+    }
+}
+
+fn entries(i: Input) -> Output {
+    i[0].map()
+        .into_iter()
+        .map(|(k, v)| Data::Array(vec![Data::String(k), v]))
+        .collect::<Vec<_>>()
+        .data()
+}
+
+pub fn entries_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Map],
+        inputs_rest: None,
+        param_names: &["map"],
+        function: FunctionType::BuiltIn(entries),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The `[key, value]` entries of `map`.".to_string()),
+    }
+}
+
+fn get(i: Input) -> Output {
+    Ok(i[0].map().remove(i[1].string()).unwrap_or(Data::Null))
+}
+
+pub fn get_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Map, DataType::String],
+        inputs_rest: None,
+        param_names: &["map", "key"],
+        function: FunctionType::BuiltIn(get),
+        output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The value of `map` at `key`, or null if it has none.".to_string()),
+    }
+}
+
+fn map_values(i: Input, state: &mut ExecutionState) -> Output {
+    let func = i[1].function().clone();
+
+    i[0].map()
+        .into_iter()
+        .map(|(k, v)| Ok((k, call(vec![v], func.clone(), state)?)))
+        .collect::<EResult<DataMap>>()?
+        .data()
+}
+
+pub fn map_values_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Map, DataType::Function],
+        inputs_rest: None,
+        param_names: &["map", "f"],
+        function: FunctionType::BuiltInWithState(map_values),
+        output: DataType::Map,
+        capabilities: &[],
+        doc: Some("`map` with `f` called on each value.".to_string()),
+    }
+}
+
+fn map_entries(i: Input, state: &mut ExecutionState) -> Output {
+    let func = i[1].function().clone();
+
+    i[0].map()
+        .into_iter()
+        .map(|(k, v)| {
+            let result = call(vec![Data::String(k), v], func.clone(), state)?;
+            entry(&result, "map_entries result")
+        })
+        .collect::<EResult<DataMap>>()?
+        .data()
+}
+
+pub fn map_entries_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Map, DataType::Function],
+        inputs_rest: None,
+        param_names: &["map", "f"],
+        function: FunctionType::BuiltInWithState(map_entries),
+        output: DataType::Map,
+        capabilities: &[],
+        doc: Some(
+            "A map of the `[key, value]` entries `f(key, value)` returns for each entry of `map`."
+                .to_string(),
+        ),
+    }
+}
+
+/// Looks `key` up in a Map (by String) or an Array (by whole Number index).
+fn lookup(data: Data, key: &Data) -> Option<Data> {
+    match (data, key) {
+        (Data::Map(mut m), Data::String(key)) => m.remove(key),
+        (Data::Array(a), Data::Number(n)) => n
+            .to_usize()
+            .filter(|_| n.fract().is_zero())
+            .and_then(|n| a.get(n).cloned()),
+        _ => None,
+    }
+}
+
+fn get_or(i: Input) -> Output {
+    Ok(lookup(i[0].clone(), &i[1]).unwrap_or(i[2].clone()))
+}
+
+pub fn get_or_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Any, DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["collection", "key", "default"],
+        function: FunctionType::BuiltIn(get_or),
+        output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The value of `collection` at `key`, or `default` if it has none.".to_string()),
+    }
+}
+
+fn get_path(i: Input) -> Output {
+    let mut current = i[0].clone();
+
+    for step in i[1].array() {
+        current = lookup(current, &step).unwrap_or(Data::Null);
+    }
+
+    Ok(current)
+}
+
+pub fn get_path_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Any, DataType::Array],
+        inputs_rest: None,
+        param_names: &["collection", "path"],
+        function: FunctionType::BuiltIn(get_path),
+        output: DataType::Any,
+        capabilities: &[],
+        doc: Some("The value reached by following the keys and indexes in `path` into `collection`, or null if one is missing.".to_string()),
+    }
+}
+
+/// Rebuilds `data` with `value` stored at `path`, creating Maps for missing keys.
+fn set_in(data: Data, path: &[Data], value: Data, depth: usize) -> EResult<Data> {
+    let Some((step, rest)) = path.split_first() else {
+        return Ok(value);
+    };
+
+    match (data, step) {
+        (Data::Null, Data::String(key)) => {
+            let child = set_in(Data::Null, rest, value, depth + 1)?;
+            DataMap::from([(key.clone(), child)]).data()
+        }
+        (Data::Map(mut m), Data::String(key)) => {
+            let child = m.remove(key).unwrap_or(Data::Null);
+            m.insert(key.clone(), set_in(child, rest, value, depth + 1)?);
+            m.data()
+        }
+        (Data::Array(mut a), Data::Number(n)) => {
+            let index = checked_index(*n, a.len())?;
+            a[index] = set_in(a[index].clone(), rest, value, depth + 1)?;
+            a.data()
+        }
+        (data, step) => Err(ExprError::InvalidDataType {
+            expected: match step {
+                Data::Number(_) => DataType::Array.to_string(),
+                _ => DataType::Map.to_string(),
+            },
+            found: data._type().to_string(),
+            loc: format!("set_path step {depth}"),
+        }),
+    }
+}
+
+/// Rebuilds `data` with `value` stored at `path`, for `a[i][j] := value`. Unlike `set_in`, only
+/// the last step may be a new key, and negative array indices count from the end. Errors come
+/// with the position of the step that failed.
+pub fn assign_in(data: Data, path: &[Data], value: Data) -> Result<Data, (usize, ExprError)> {
+    fn go(
+        data: Data,
+        path: &[Data],
+        value: Data,
+        depth: usize,
+    ) -> Result<Data, (usize, ExprError)> {
+        let Some((step, rest)) = path.split_first() else {
+            return Ok(value);
+        };
+
+        match (data, step) {
+            (Data::Map(mut m), Data::String(key)) => {
+                let child = match m.remove(key) {
+                    Some(child) => child,
+                    None if rest.is_empty() => Data::Null,
+                    None => return Err((depth, ExprError::KeyNotFound { key: key.clone() })),
+                };
+                m.insert(key.clone(), go(child, rest, value, depth + 1)?);
+                Ok(Data::Map(m))
+            }
+            (Data::Array(mut a), Data::Number(n)) => {
+                let index = wrapped_index(*n, a.len()).map_err(|e| (depth, e))?;
+                a[index] = go(a[index].clone(), rest, value, depth + 1)?;
+                Ok(Data::Array(a))
+            }
+            (data, step) => Err((
+                depth,
+                ExprError::InvalidDataType {
+                    expected: match step {
+                        Data::Number(_) => DataType::Array.to_string(),
+                        _ => DataType::Map.to_string(),
+                    },
+                    found: data._type().to_string(),
+                    loc: "indexed assignment".to_string(),
+                },
+            )),
+        }
+    }
+
+    go(data, path, value, 0)
+}
+
+fn set_path(i: Input) -> Output {
+    set_in(i[0].clone(), &i[1].array(), i[2].clone(), 0)
+}
+
+pub fn set_path_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Any, DataType::Array, DataType::Any],
+        inputs_rest: None,
+        param_names: &["collection", "path", "value"],
+        function: FunctionType::BuiltIn(set_path),
+        output: DataType::Any,
+        capabilities: &[],
+        doc: Some("`collection` with the value at `path` replaced by `value`.".to_string()),
+    }
+}
@@ -1,4 +1,13 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+};
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use strum::{Display, EnumString, VariantArray};
 
 mod numeric;
 pub use numeric::*;
@@ -18,9 +27,12 @@ pub use array::*;
 mod higher_order;
 pub use higher_order::*;
 
+mod map;
+pub use map::*;
+
 use crate::{
     data::{Data, DataType},
-    expr::{EResult, Expr},
+    expr::{EResult, ExecutionState, Expr, VariableMap},
 };
 
 pub type Input = Vec<Data>;
@@ -30,135 +42,410 @@ pub type FunctionMap = HashMap<String, FunctionDescriptor>;
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FunctionDescriptor {
     pub inputs: Vec<DataType>,
+    /// The type of any number of arguments after `inputs`, for a variadic function. A custom
+    /// one gets them as an array in its last parameter.
+    pub inputs_rest: Option<DataType>,
+    /// Names of a builtin's parameters, for passing arguments by name. Custom functions keep
+    /// theirs in `FunctionType::Custom` and leave this empty.
+    pub param_names: &'static [&'static str],
     pub function: FunctionType,
     pub output: DataType,
+    /// What the function needs from the outside world. Pure functions need nothing.
+    pub capabilities: &'static [Capability],
+    /// What the function does, shown by `help`. Declared functions take it from the `///`
+    /// comments before them.
+    pub doc: Option<String>,
+}
+
+impl FunctionDescriptor {
+    /// The names arguments can be passed by, in parameter order.
+    pub fn names(&self) -> Vec<&str> {
+        match &self.function {
+            FunctionType::Custom(_, names, ..) => names.iter().map(|n| n.as_str()).collect(),
+            FunctionType::BuiltIn(_) | FunctionType::BuiltInWithState(_) => {
+                self.param_names.to_vec()
+            }
+        }
+    }
+
+    /// The default value of parameter `i`, if it has one. Only custom functions have them.
+    pub fn default(&self, i: usize) -> Option<&Expr> {
+        match &self.function {
+            FunctionType::Custom(_, _, defaults, _) => defaults.get(i)?.as_ref(),
+            FunctionType::BuiltIn(_) | FunctionType::BuiltInWithState(_) => None,
+        }
+    }
+
+    /// The parameter list without parentheses, e.g. `a: Number, b: Number = 1` or
+    /// `xs: Number...`. Parameters without a name are just their type.
+    pub fn params(&self) -> String {
+        let names = self.names();
+        let param = |i: usize, t: &DataType| match names.get(i) {
+            Some(name) => format!("{name}: {t}"),
+            None => t.to_string(),
+        };
+
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(i, t)| match self.default(i) {
+                Some(default) => format!("{} = {default}", param(i, t)),
+                None => param(i, t),
+            })
+            .chain(
+                self.inputs_rest
+                    .as_ref()
+                    .map(|t| format!("{}...", param(self.inputs.len(), t))),
+            )
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// e.g. `fn(a: Number, b: Number) -> Number`
+    pub fn signature(&self) -> String {
+        format!("fn({}) -> {}", self.params(), self.output)
+    }
+}
+
+/// A custom function as it is written to a session file.
+#[derive(Serialize, Deserialize)]
+struct SavedFunction {
+    inputs: Vec<DataType>,
+    #[serde(default)]
+    inputs_rest: Option<DataType>,
+    params: Vec<String>,
+    #[serde(default)]
+    defaults: Vec<Option<Expr>>,
+    body: Vec<Expr>,
+    output: DataType,
+    #[serde(default)]
+    doc: Option<String>,
+    #[serde(default)]
+    captures: VariableMap,
+}
+
+/// Only custom functions can be serialized. Builtins are part of every state already, so
+/// there's no need to save them, and their pointers couldn't be loaded back anyway.
+impl Serialize for FunctionDescriptor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let FunctionType::Custom(body, params, defaults, captures) = &self.function else {
+            return Err(ser::Error::custom("builtin functions can't be saved"));
+        };
+
+        SavedFunction {
+            inputs: self.inputs.clone(),
+            inputs_rest: self.inputs_rest.clone(),
+            params: params.clone(),
+            defaults: defaults.to_vec(),
+            body: body.clone(),
+            output: self.output.clone(),
+            doc: self.doc.clone(),
+            captures: (**captures).clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FunctionDescriptor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = SavedFunction::deserialize(deserializer)?;
+
+        let rest = saved.inputs_rest.is_some() as usize;
+        if saved.params.len() != saved.inputs.len() + rest
+            || saved.defaults.len() > saved.params.len()
+        {
+            return Err(de::Error::custom(
+                "a function has a different number of parameters and types",
+            ));
+        }
+
+        Ok(Self {
+            inputs: saved.inputs,
+            inputs_rest: saved.inputs_rest,
+            param_names: &[],
+            function: FunctionType::Custom(
+                saved.body,
+                saved.params,
+                saved.defaults.into(),
+                Arc::new(saved.captures),
+            ),
+            output: saved.output,
+            capabilities: &[],
+            doc: saved.doc,
+        })
+    }
+}
+
+/// Access to the outside world that a builtin may require and an `ExecutionState` may allow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, Display, VariantArray)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Capability {
+    FsRead,
+    FsWrite,
+    Process,
+    Network,
+    Stdin,
 }
 
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum FunctionType {
     BuiltIn(fn(Input) -> Output),
-    Custom(Vec<Expr>, Vec<String>),
+    BuiltInWithState(fn(Input, &mut ExecutionState) -> Output),
+    /// The body, the parameter names and the parameters' default values, by position. A
+    /// parameter past the end of the defaults has none. Boxed as a slice to keep `Data` small.
+    /// Last are the local variables a closure captured where it was made, which its body sees
+    /// along with the globals. Declared functions capture nothing.
+    Custom(
+        Vec<Expr>,
+        Vec<String>,
+        Box<[Option<Expr>]>,
+        Arc<VariableMap>,
+    ),
 }
 
-pub fn builtints() -> FunctionMap {
-    let mut map = HashMap::new();
-
-    for (name, descriptor) in [
-        // numeric
-        ("sqrt", sqrt_descriptor()),
-        ("abs", abs_descriptor()),
-        ("abs_diff", abs_diff_descriptor()),
-        ("rand", rand_descriptor()),
-        ("rand_between", rand_between_descriptor()),
-        ("max", max_descriptor()),
-        ("min", min_descriptor()),
-        ("add", add_descriptor()),
-        ("sub", sub_descriptor()),
-        ("div", div_descriptor()),
-        ("mul", mul_descriptor()),
-        ("neg", neg_descriptor()),
-        ("mod", mod_descriptor()),
-        ("round", round_descriptor()),
-        ("ceil", ceil_descriptor()),
-        ("floor", floor_descriptor()),
-        ("pow", pow_descriptor()),
-        ("sign", sign_descriptor()),
-        ("sin", sin_descriptor()),
-        ("cos", cos_descriptor()),
-        ("tan", tan_descriptor()),
-        ("log", log_descriptor()),
-        ("log10", log10_descriptor()),
-        ("log2", log2_descriptor()),
-        ("trunc", trunc_descriptor()),
-        ("tanh", tanh_descriptor()),
-        ("exp", exp_descriptor()),
-        ("sinh", sinh_descriptor()),
-        ("cosh", cosh_descriptor()),
-        ("tanh", tanh_descriptor()),
-        ("cbrt", cbrt_descriptor()),
-        ("atanh", atanh_descriptor()),
-        ("atan", atan_descriptor()),
-        ("atan2", atan2_descriptor()),
-        ("asin", asin_descriptor()),
-        ("asinh", asinh_descriptor()),
-        ("acos", acos_descriptor()),
-        ("acosh", acosh_descriptor()),
-        ("parse_number", parse_to_number_descriptor()),
-        // boolean
-        ("or", or_descriptor()),
-        ("and", and_descriptor()),
-        ("not", not_descriptor()),
-        ("xor", xor_descriptor()),
-        ("ne", ne_descriptor()),
-        ("eq", eq_descriptor()),
-        ("ge", ge_descriptor()),
-        ("le", le_descriptor()),
-        ("gt", gt_descriptor()),
-        ("lt", lt_descriptor()),
-        // string
-        ("join", join_descriptor()),
-        ("join_after", join_after_descriptor()),
-        ("surround", surround_descriptor()),
-        ("string", string_descriptor()),
-        ("center", center_descriptor()),
-        ("count", count_descriptor()),
-        ("ends_with", ends_with_descriptor()),
-        ("starts_with", starts_with_descriptor()),
-        ("find", find_descriptor()),
-        ("is_numeric", is_numeric_descriptor()),
-        ("is_alphanumeric", is_alphanumeric_descriptor()),
-        ("is_alphabetic", is_alphabetic_descriptor()),
-        ("is_ascii", is_ascii_descriptor()),
-        ("matches", matches_descriptor()),
-        ("is_lowercase", is_lowercase_descriptor()),
-        ("is_uppercase", is_uppercase_descriptor()),
-        ("is_whitespace", is_whitespace_descriptor()),
-        ("trim", trim_descriptor()),
-        ("replace", replace_descriptor()),
-        ("split", split_descriptor()),
-        ("uppercase", uppercase_descriptor()),
-        ("lowercase", lowercase_descriptor()),
-        ("upper_camel_case", upper_camel_case_descriptor()),
-        ("lower_camel_case", lower_camel_case_descriptor()),
-        ("snake_case", snake_case_descriptor()),
-        ("kebab_case", kebab_case_descriptor()),
-        ("shouty_kebab_case", shouty_kebab_case_descriptor()),
-        ("shouty_snake_case", shouty_snake_case_descriptor()),
-        ("title_case", title_case_descriptor()),
-        ("train_case", train_case_descriptor()),
-        // array
-        ("join_array", join_array_descriptor()),
-        ("sort", sort_descriptor()),
-        ("length", length_descriptor()),
-        ("index", index_descriptor()),
-        ("append", append_descriptor()),
-        ("flatten", flatten_descriptor()),
-        ("extend", extend_descriptor()),
-        ("reverse", reverse_descriptor()),
-        ("without", without_descriptor()),
-        ("with_insert", with_insert_descriptor()),
-        ("range", range_descriptor()),
-        ("max_array", max_array_descriptor()),
-        ("min_array", min_array_descriptor()),
-        ("first", first_descriptor()),
-        ("last", last_descriptor()),
-        // higher order
-        ("map", map_descriptor()),
-        ("for_each", for_each_descriptor()),
-        ("filter", filter_descriptor()),
-        // ("reduce", reduce_descriptor()),
-        ("fold", fold_descriptor()),
-        // ("zip", zip_descriptor()),
-        // other
-        ("type", type_of_descriptor()),
-        ("print", print_descriptor()),
-        ("println", println_descriptor()),
-        ("input", input_descriptor()),
-        ("read_file", read_file_descriptor()),
-        ("write_file", write_file_descriptor()),
-    ] {
-        map.insert(name.to_string(), descriptor);
-    }
+static BUILTINS: OnceLock<Arc<FunctionMap>> = OnceLock::new();
+static BUILTINS_BUILT: AtomicUsize = AtomicUsize::new(0);
+
+/// The table of builtins. It is built the first time it is asked for and shared after that,
+/// so making a state doesn't rebuild it.
+pub fn builtints() -> Arc<FunctionMap> {
+    BUILTINS.get_or_init(|| Arc::new(build_builtins())).clone()
+}
+
+/// How many times the builtin table has been built in this process, which should be once.
+pub fn builtins_built() -> usize {
+    BUILTINS_BUILT.load(Ordering::Relaxed)
+}
+
+/// The builtins, grouped by the module that defines them as `help()` lists them.
+pub fn builtin_groups() -> Vec<(&'static str, Vec<(&'static str, FunctionDescriptor)>)> {
+    vec![
+        (
+            "numeric",
+            vec![
+                ("sqrt", sqrt_descriptor()),
+                ("abs", abs_descriptor()),
+                ("abs_diff", abs_diff_descriptor()),
+                ("rand", rand_descriptor()),
+                ("rand_between", rand_between_descriptor()),
+                ("max", max_descriptor()),
+                ("min", min_descriptor()),
+                ("add", add_descriptor()),
+                ("sub", sub_descriptor()),
+                ("div", div_descriptor()),
+                ("idiv", idiv_descriptor()),
+                ("mul", mul_descriptor()),
+                ("neg", neg_descriptor()),
+                ("mod", mod_descriptor()),
+                ("round", round_descriptor()),
+                ("ceil", ceil_descriptor()),
+                ("floor", floor_descriptor()),
+                ("pow", pow_descriptor()),
+                ("sign", sign_descriptor()),
+                ("sin", sin_descriptor()),
+                ("cos", cos_descriptor()),
+                ("tan", tan_descriptor()),
+                ("log", log_descriptor()),
+                ("log10", log10_descriptor()),
+                ("log2", log2_descriptor()),
+                ("trunc", trunc_descriptor()),
+                ("is_integer", is_integer_descriptor()),
+                ("is_zero", is_zero_descriptor()),
+                ("is_positive", is_positive_descriptor()),
+                ("is_negative", is_negative_descriptor()),
+                ("is_finite", is_finite_descriptor()),
+                ("fract", fract_descriptor()),
+                ("int_part", int_part_descriptor()),
+                ("band", band_descriptor()),
+                ("bor", bor_descriptor()),
+                ("bxor", bxor_descriptor()),
+                ("bnot", bnot_descriptor()),
+                ("shl", shl_descriptor()),
+                ("shr", shr_descriptor()),
+                ("tanh", tanh_descriptor()),
+                ("exp", exp_descriptor()),
+                ("sinh", sinh_descriptor()),
+                ("cosh", cosh_descriptor()),
+                ("cbrt", cbrt_descriptor()),
+                ("atanh", atanh_descriptor()),
+                ("atan", atan_descriptor()),
+                ("atan2", atan2_descriptor()),
+                ("asin", asin_descriptor()),
+                ("asinh", asinh_descriptor()),
+                ("acos", acos_descriptor()),
+                ("acosh", acosh_descriptor()),
+                ("parse_number", parse_to_number_descriptor()),
+                ("parse_number_lenient", parse_number_lenient_descriptor()),
+                (
+                    "parse_number_lenient_locale",
+                    parse_number_lenient_locale_descriptor(),
+                ),
+                (
+                    "parse_number_with_unit",
+                    parse_number_with_unit_descriptor(),
+                ),
+                ("to_fixed", to_fixed_descriptor()),
+                ("format_thousands", format_thousands_descriptor()),
+                ("format_currency", format_currency_descriptor()),
+                (
+                    "format_currency_styled",
+                    format_currency_styled_descriptor(),
+                ),
+            ],
+        ),
+        (
+            "boolean",
+            vec![
+                ("or", or_descriptor()),
+                ("and", and_descriptor()),
+                ("not", not_descriptor()),
+                ("xor", xor_descriptor()),
+                ("ne", ne_descriptor()),
+                ("eq", eq_descriptor()),
+                ("ge", ge_descriptor()),
+                ("le", le_descriptor()),
+                ("gt", gt_descriptor()),
+                ("lt", lt_descriptor()),
+            ],
+        ),
+        (
+            "string",
+            vec![
+                ("join", join_descriptor()),
+                ("join_after", join_after_descriptor()),
+                ("surround", surround_descriptor()),
+                ("string", string_descriptor()),
+                ("center", center_descriptor()),
+                ("grapheme_length", grapheme_length_descriptor()),
+                ("display_width", display_width_descriptor()),
+                ("count", count_descriptor()),
+                ("ends_with", ends_with_descriptor()),
+                ("starts_with", starts_with_descriptor()),
+                ("find", find_descriptor()),
+                ("find_byte", find_byte_descriptor()),
+                ("is_numeric", is_numeric_descriptor()),
+                ("is_alphanumeric", is_alphanumeric_descriptor()),
+                ("first_non_numeric", first_non_numeric_descriptor()),
+                ("first_non_alphabetic", first_non_alphabetic_descriptor()),
+                ("first_not_matching", first_not_matching_descriptor()),
+                ("is_alphabetic", is_alphabetic_descriptor()),
+                ("is_ascii", is_ascii_descriptor()),
+                ("matches", matches_descriptor()),
+                ("is_lowercase", is_lowercase_descriptor()),
+                ("is_uppercase", is_uppercase_descriptor()),
+                ("is_whitespace", is_whitespace_descriptor()),
+                ("trim", trim_descriptor()),
+                ("replace", replace_descriptor()),
+                ("split", split_descriptor()),
+                ("uppercase", uppercase_descriptor()),
+                ("lowercase", lowercase_descriptor()),
+                ("upper_camel_case", upper_camel_case_descriptor()),
+                ("lower_camel_case", lower_camel_case_descriptor()),
+                ("snake_case", snake_case_descriptor()),
+                ("kebab_case", kebab_case_descriptor()),
+                ("shouty_kebab_case", shouty_kebab_case_descriptor()),
+                ("shouty_snake_case", shouty_snake_case_descriptor()),
+                ("title_case", title_case_descriptor()),
+                ("train_case", train_case_descriptor()),
+            ],
+        ),
+        (
+            "array",
+            vec![
+                ("join_array", join_array_descriptor()),
+                ("sort", sort_descriptor()),
+                ("length", length_descriptor()),
+                ("index", index_descriptor()),
+                ("slice", slice_descriptor()),
+                ("append", append_descriptor()),
+                ("flatten", flatten_descriptor()),
+                ("extend", extend_descriptor()),
+                ("reverse", reverse_descriptor()),
+                ("without", without_descriptor()),
+                ("with_insert", with_insert_descriptor()),
+                ("range", range_descriptor()),
+                ("max_array", max_array_descriptor()),
+                ("min_array", min_array_descriptor()),
+                ("first", first_descriptor()),
+                ("last", last_descriptor()),
+                ("swap", swap_descriptor()),
+                ("rotate_left", rotate_left_descriptor()),
+                ("rotate_right", rotate_right_descriptor()),
+                ("pairwise", pairwise_descriptor()),
+                ("zip", zip_descriptor()),
+                ("contains", contains_descriptor()),
+                ("sum", sum_descriptor()),
+            ],
+        ),
+        (
+            "higher order",
+            vec![
+                ("map", map_descriptor()),
+                ("for_each", for_each_descriptor()),
+                ("filter", filter_descriptor()),
+                ("take_while", take_while_descriptor()),
+                ("any", any_descriptor()),
+                ("all", all_descriptor()),
+                // ("reduce", reduce_descriptor()),
+                ("fold", fold_descriptor()),
+                ("scan", scan_descriptor()),
+                ("cumsum", cumsum_descriptor()),
+                // ("zip", zip_descriptor()),
+            ],
+        ),
+        (
+            "map",
+            vec![
+                ("to_map", to_map_descriptor()),
+                ("keys", keys_descriptor()),
+                ("values", values_descriptor()),
+                ("entries", entries_descriptor()),
+                ("get", get_descriptor()),
+                ("map_values", map_values_descriptor()),
+                ("map_entries", map_entries_descriptor()),
+                ("get_or", get_or_descriptor()),
+                ("get_path", get_path_descriptor()),
+                ("set_path", set_path_descriptor()),
+            ],
+        ),
+        (
+            "other",
+            vec![
+                ("type", type_of_descriptor()),
+                ("print", print_descriptor()),
+                ("println", println_descriptor()),
+                ("full_print", full_print_descriptor()),
+                ("set_display_limit", set_display_limit_descriptor()),
+                ("set_number_display", set_number_display_descriptor()),
+                ("input", input_descriptor()),
+                ("exit", exit_descriptor()),
+                ("error", error_descriptor()),
+                ("read_file", read_file_descriptor()),
+                ("write_file", write_file_descriptor()),
+                ("save_session", save_session_descriptor()),
+                ("load_session", load_session_descriptor()),
+                ("args", args_descriptor(&[])),
+                ("version", version_descriptor()),
+                ("has_feature", has_feature_descriptor()),
+                ("bench", bench_descriptor()),
+                ("sizeof", sizeof_descriptor()),
+                ("depth", depth_descriptor()),
+                ("snapshot", snapshot_descriptor()),
+                ("restore", restore_descriptor()),
+                ("drop_snapshot", drop_snapshot_descriptor()),
+                ("help", help_descriptor()),
+            ],
+        ),
+    ]
+}
+
+fn build_builtins() -> FunctionMap {
+    BUILTINS_BUILT.fetch_add(1, Ordering::Relaxed);
 
-    map
+    builtin_groups()
+        .into_iter()
+        .flat_map(|(_, functions)| functions)
+        .map(|(name, descriptor)| (name.to_string(), descriptor))
+        .collect()
 }
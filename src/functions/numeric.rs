@@ -1,24 +1,80 @@
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rust_decimal::prelude::*;
 use rust_decimal_macros::dec;
 
 use super::{FunctionDescriptor, FunctionType, Input, Output};
 use crate::{
     data::{DataType, ToData},
-    expr::error::ExprError,
+    expr::{error::ExprError, EResult},
 };
 
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Makes `rand` and `rand_between` deterministic for the rest of the thread.
+pub fn seed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Runs `f` on `n` as a float, for the functions Decimal doesn't have. Results that aren't
+/// finite or don't fit in a Decimal are domain errors rather than panics.
+fn via_f64(name: &str, n: Decimal, f: fn(f64) -> f64) -> Output {
+    let domain_error = |reason: &str| ExprError::MathDomain {
+        operation: format!("{name}({n})"),
+        reason: reason.to_string(),
+    };
+
+    let result = f(n.to_f64().unwrap());
+    if !result.is_finite() {
+        return Err(domain_error("the result is not a finite number"));
+    }
+
+    Decimal::from_f64(result)
+        .ok_or_else(|| domain_error("the result is too large"))?
+        .data()
+}
+
+/// Turns `-0`, which rounding towards zero leaves behind, into `0`.
+fn unsigned_zero(n: Decimal) -> Decimal {
+    if n.is_zero() {
+        Decimal::ZERO
+    } else {
+        n
+    }
+}
+
+/// The error for arithmetic whose result doesn't fit in a Decimal.
+pub(super) fn overflow(operation: String) -> ExprError {
+    ExprError::MathDomain {
+        operation,
+        reason: "the result is too large".to_string(),
+    }
+}
+
 pub fn mod_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(mod_func),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some(
+            "The remainder of `a` divided by `b`, like `a % b`.\n\n>>> mod(-7, 3);\n-1".to_string(),
+        ),
     }
 }
 
 pub fn mod_func(i: Input) -> Output {
-    let rhs = i[0].number();
-    let lhs = i[1].number();
+    let lhs = i[0].number();
+    let rhs = i[1].number();
+
+    if rhs.is_zero() {
+        return Err(ExprError::DivideBy0);
+    }
 
     (lhs % rhs).data()
 }
@@ -26,72 +82,137 @@ pub fn mod_func(i: Input) -> Output {
 pub fn add_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(add),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`a` plus `b`, like `a + b`.".to_string()),
     }
 }
 
 pub fn add(i: Input) -> Output {
-    let rhs = i[0].number();
-    let lhs = i[1].number();
+    let lhs = i[0].number();
+    let rhs = i[1].number();
 
-    (lhs + rhs).data()
+    lhs.checked_add(rhs)
+        .ok_or_else(|| overflow(format!("{lhs} + {rhs}")))?
+        .data()
 }
 
 pub fn sub_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(sub),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`a` minus `b`, like `a - b`.\n\n>>> sub(10, 3);\n7".to_string()),
     }
 }
 
 pub fn sub(i: Input) -> Output {
-    let rhs = i[0].number();
-    let lhs = i[1].number();
+    let lhs = i[0].number();
+    let rhs = i[1].number();
 
-    (lhs - rhs).data()
+    lhs.checked_sub(rhs)
+        .ok_or_else(|| overflow(format!("{lhs} - {rhs}")))?
+        .data()
 }
 
 pub fn mul_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(mul),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`a` times `b`, like `a * b`.".to_string()),
     }
 }
 
 pub fn mul(i: Input) -> Output {
-    let rhs = i[0].number();
-    let lhs = i[1].number();
+    let lhs = i[0].number();
+    let rhs = i[1].number();
 
-    (lhs * rhs).data()
+    lhs.checked_mul(rhs)
+        .ok_or_else(|| overflow(format!("{lhs} * {rhs}")))?
+        .data()
 }
 
 pub fn div_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(div),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`a` divided by `b`, like `a / b`.".to_string()),
     }
 }
 
 pub fn div(i: Input) -> Output {
-    let rhs = i[0].number();
-    let lhs = i[1].number();
+    let lhs = i[0].number();
+    let rhs = i[1].number();
 
     if rhs == dec!(0) {
         Err(ExprError::DivideBy0)
     } else {
-        (lhs / rhs).data()
+        lhs.checked_div(rhs)
+            .ok_or_else(|| overflow(format!("{lhs} / {rhs}")))?
+            .data()
+    }
+}
+
+pub fn idiv_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
+        function: FunctionType::BuiltIn(idiv),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some(
+            "`a` divided by `b` and rounded down, like `a ~/ b`.\n\n>>> idiv(7, 2);\n3".to_string(),
+        ),
+    }
+}
+
+/// Division rounded down, so `-7 ~/ 2` is -4.
+pub fn idiv(i: Input) -> Output {
+    let lhs = i[0].number();
+    let rhs = i[1].number();
+
+    if rhs.is_zero() {
+        return Err(ExprError::DivideBy0);
+    }
+
+    // Dividing what's left after the remainder is exact, where flooring `lhs / rhs` could
+    // round up to the next integer once the quotient has 28 digits.
+    let remainder = lhs % rhs;
+    let mut quotient = (lhs - remainder)
+        .checked_div(rhs)
+        .ok_or_else(|| overflow(format!("{lhs} ~/ {rhs}")))?
+        .trunc();
+    if !remainder.is_zero() && remainder.is_sign_negative() != rhs.is_sign_negative() {
+        quotient -= Decimal::ONE;
     }
+
+    unsigned_zero(quotient).data()
 }
 
 pub fn neg_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(neg),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`n` with its sign flipped, like `-n`.".to_string()),
     }
 }
 
@@ -100,16 +221,18 @@ pub fn neg(i: Input) -> Output {
 }
 
 pub fn sqrt(i: Input) -> Output {
-    Decimal::from_f64(i[0].number().to_f64().unwrap().sqrt())
-        .unwrap()
-        .data()
+    via_f64("sqrt", i[0].number(), f64::sqrt)
 }
 
 pub fn sqrt_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(sqrt),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The square root of `n`.".to_string()),
     }
 }
 
@@ -120,8 +243,12 @@ pub fn abs(i: Input) -> Output {
 pub fn abs_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(abs),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`n` without its sign.".to_string()),
     }
 }
 
@@ -129,26 +256,39 @@ pub fn abs_diff(i: Input) -> Output {
     let a = i[0].number();
     let b = i[1].number();
 
-    (a.max(b) - a.min(b)).data()
+    a.max(b)
+        .checked_sub(a.min(b))
+        .ok_or_else(|| overflow(format!("abs_diff({a}, {b})")))?
+        .data()
 }
 
 pub fn abs_diff_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(abs_diff),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The distance between `a` and `b`.".to_string()),
     }
 }
 
 pub fn rand(_i: Input) -> Output {
-    Decimal::from_f64(rand::random::<f64>()).unwrap().data()
+    Decimal::from_f64(RNG.with(|rng| rng.borrow_mut().gen::<f64>()))
+        .unwrap()
+        .data()
 }
 
 pub fn rand_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![],
+        inputs_rest: None,
+        param_names: &[],
         function: FunctionType::BuiltIn(rand),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("A random number from 0 up to 1.".to_string()),
     }
 }
 
@@ -156,103 +296,214 @@ pub fn rand_between(i: Input) -> Output {
     let top = i[0].number();
     let bottom = i[1].number();
 
-    let top = top.to_i64().unwrap();
-    let bottom = bottom.to_i64().unwrap();
+    let whole = |n: Decimal| {
+        n.to_i64().ok_or_else(|| ExprError::MathDomain {
+            operation: format!("rand_between({top}, {bottom})"),
+            reason: "the bounds must fit in a 64 bit integer".to_string(),
+        })
+    };
+    let top = whole(top)?;
+    let bottom = whole(bottom)?;
 
-    (Decimal::from_i64(rand::thread_rng().gen_range(top.min(bottom)..=top.max(bottom))).unwrap())
-        .data()
+    let n = RNG.with(|rng| {
+        rng.borrow_mut()
+            .gen_range(top.min(bottom)..=top.max(bottom))
+    });
+
+    Decimal::from_i64(n).unwrap().data()
 }
 
 pub fn rand_between_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["top", "bottom"],
         function: FunctionType::BuiltIn(rand_between),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("A random integer between `top` and `bottom`.".to_string()),
     }
 }
 
 pub fn max(i: Input) -> Output {
-    i[0].number().max(i[1].number()).data()
+    i[1..]
+        .iter()
+        .fold(i[0].number(), |max, n| max.max(n.number()))
+        .data()
 }
 
 pub fn max_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: Some(DataType::Number),
+        param_names: &["a", "b", "rest"],
         function: FunctionType::BuiltIn(max),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The largest of the numbers given.".to_string()),
     }
 }
 
 pub fn min(i: Input) -> Output {
-    i[0].number().min(i[1].number()).data()
+    i[1..]
+        .iter()
+        .fold(i[0].number(), |min, n| min.min(n.number()))
+        .data()
 }
 
 pub fn min_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: Some(DataType::Number),
+        param_names: &["a", "b", "rest"],
         function: FunctionType::BuiltIn(min),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The smallest of the numbers given.".to_string()),
     }
 }
 
 fn ceil(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().ceil())
-        .unwrap()
-        .data()
+    unsigned_zero(i[0].number().ceil()).data()
 }
 
 pub fn ceil_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(ceil),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`n` rounded up to an integer.".to_string()),
     }
 }
 
 fn floor(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().floor())
-        .unwrap()
-        .data()
+    i[0].number().floor().data()
 }
 
 pub fn floor_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(floor),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`n` rounded down to an integer.".to_string()),
     }
 }
 
 fn round(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().round())
-        .unwrap()
-        .data()
+    // Halves round away from zero, like they always have.
+    unsigned_zero(
+        i[0].number()
+            .round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero),
+    )
+    .data()
 }
 
 pub fn round_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(round),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`n` rounded to the nearest integer.".to_string()),
+    }
+}
+
+/// `base ^ exponent` for whole exponents, exactly, by repeated squaring.
+fn integer_pow(base: Decimal, exponent: i64) -> EResult<Decimal> {
+    let overflow = || ExprError::MathDomain {
+        operation: format!("pow({base}, {exponent})"),
+        reason: "the result is too large".to_string(),
+    };
+
+    if exponent < 0 {
+        if base.is_zero() {
+            return Err(ExprError::DivideBy0);
+        }
+
+        return Decimal::ONE
+            .checked_div(integer_pow(base, -exponent)?)
+            .ok_or_else(overflow);
     }
+
+    let mut result = Decimal::ONE;
+    let mut square = base;
+    let mut exponent = exponent;
+
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.checked_mul(square).ok_or_else(overflow)?;
+        }
+
+        exponent >>= 1;
+        if exponent > 0 {
+            square = square.checked_mul(square).ok_or_else(overflow)?;
+        }
+    }
+
+    Ok(result)
 }
 
+/// `pow(0, 0)` is 1. Whole exponents are exact. A negative base with a fractional exponent
+/// only has a real result for odd roots, e.g. `pow(-8, 1 / 3)` is -2.
 fn pow(i: Input) -> Output {
     let a = i[0].number();
     let b = i[1].number();
-    Decimal::from_f64(a.to_f64().unwrap().powf(b.to_f64().unwrap()))
-        .unwrap()
+
+    if b.fract().is_zero() {
+        if let Some(exponent) = b.to_i64() {
+            return integer_pow(a, exponent)?.data();
+        }
+    }
+
+    let domain_error = |reason: &str| ExprError::MathDomain {
+        operation: format!("pow({a}, {b})"),
+        reason: reason.to_string(),
+    };
+
+    let base = a.to_f64().unwrap();
+    let exponent = b.to_f64().unwrap();
+
+    let result = if base < 0.0 {
+        let root = (1.0 / exponent).round();
+        let is_odd_root = (1.0 / exponent - root).abs() < 1e-9 && root % 2.0 != 0.0;
+
+        if !is_odd_root {
+            return Err(domain_error(
+                "a negative number only has real odd roots (like 1 / 3)",
+            ));
+        }
+
+        -(-base).powf(exponent)
+    } else {
+        base.powf(exponent)
+    };
+
+    if !result.is_finite() {
+        return Err(domain_error("the result is not a finite number"));
+    }
+
+    Decimal::from_f64(result)
+        .ok_or_else(|| domain_error("the result is too large"))?
         .data()
 }
 
 pub fn pow_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["base", "exponent"],
         function: FunctionType::BuiltIn(pow),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`base` raised to the power of `exponent`.".to_string()),
     }
 }
 
@@ -271,286 +522,581 @@ fn sign(i: Input) -> Output {
 pub fn sign_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(sign),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("1 for a positive `n`, -1 for a negative one and 0 for zero.".to_string()),
     }
 }
 
 fn sin(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().sin()).unwrap().data()
+    via_f64("sin", i[0].number(), f64::sin)
 }
 
 pub fn sin_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(sin),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The sine of `n` radians.".to_string()),
     }
 }
 
 fn cos(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().cos()).unwrap().data()
+    via_f64("cos", i[0].number(), f64::cos)
 }
 
 pub fn cos_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(cos),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The cosine of `n` radians.".to_string()),
     }
 }
 
 fn tan(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().tan()).unwrap().data()
+    via_f64("tan", i[0].number(), f64::tan)
 }
 
 pub fn tan_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(tan),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The tangent of `n` radians.".to_string()),
     }
 }
 
 fn log(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().log10())
-        .unwrap()
-        .data()
+    via_f64("log", i[0].number(), f64::log10)
 }
 
 pub fn log_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(log),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The natural logarithm of `n`.".to_string()),
     }
 }
 
 fn log2(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().log2())
-        .unwrap()
-        .data()
+    via_f64("log2", i[0].number(), f64::log2)
 }
 
 pub fn log2_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(log2),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The base 2 logarithm of `n`.".to_string()),
     }
 }
 
 fn log10(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().log10())
-        .unwrap()
-        .data()
+    via_f64("log10", i[0].number(), f64::log10)
 }
 
 pub fn log10_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(log10),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The base 10 logarithm of `n`.".to_string()),
     }
 }
 
 fn acos(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().acos())
-        .unwrap()
-        .data()
+    via_f64("acos", i[0].number(), f64::acos)
 }
 
 pub fn acos_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(acos),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The arccosine of `n`, in radians.".to_string()),
     }
 }
 
 fn acosh(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().acosh())
-        .unwrap()
-        .data()
+    via_f64("acosh", i[0].number(), f64::acosh)
 }
 
 pub fn acosh_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(acosh),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The inverse hyperbolic cosine of `n`.".to_string()),
     }
 }
 
 fn asin(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().asin())
-        .unwrap()
-        .data()
+    via_f64("asin", i[0].number(), f64::asin)
 }
 
 pub fn asin_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(asin),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The arcsine of `n`, in radians.".to_string()),
     }
 }
 
 fn asinh(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().asinh())
-        .unwrap()
-        .data()
+    via_f64("asinh", i[0].number(), f64::asinh)
 }
 
 pub fn asinh_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(asinh),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The inverse hyperbolic sine of `n`.".to_string()),
     }
 }
 
 fn atan(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().atan())
-        .unwrap()
-        .data()
+    via_f64("atan", i[0].number(), f64::atan)
 }
 
 pub fn atan_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(atan),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The arctangent of `n`, in radians.".to_string()),
     }
 }
 
 fn atan2(i: Input) -> Output {
     let a = i[0].number();
     let b = i[1].number();
-    Decimal::from_f64(a.to_f64().unwrap().atan2(b.to_f64().unwrap()))
-        .unwrap()
+    let result = a.to_f64().unwrap().atan2(b.to_f64().unwrap());
+
+    Decimal::from_f64(result)
+        .ok_or_else(|| ExprError::MathDomain {
+            operation: format!("atan2({a}, {b})"),
+            reason: "the result is not a finite number".to_string(),
+        })?
         .data()
 }
 
 pub fn atan2_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["y", "x"],
         function: FunctionType::BuiltIn(atan2),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The angle of the point (`x`, `y`) from the x axis, in radians.".to_string()),
     }
 }
 
 fn atanh(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().atanh())
-        .unwrap()
-        .data()
+    via_f64("atanh", i[0].number(), f64::atanh)
 }
 
 pub fn atanh_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(atanh),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The inverse hyperbolic tangent of `n`.".to_string()),
     }
 }
 
 fn cbrt(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().cbrt())
-        .unwrap()
-        .data()
+    via_f64("cbrt", i[0].number(), f64::cbrt)
 }
 
 pub fn cbrt_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(cbrt),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The cube root of `n`.".to_string()),
     }
 }
 
 fn cosh(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().cosh())
-        .unwrap()
-        .data()
+    via_f64("cosh", i[0].number(), f64::cosh)
 }
 
 pub fn cosh_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(cosh),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The hyperbolic cosine of `n`.".to_string()),
     }
 }
 
 fn exp(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().exp()).unwrap().data()
+    via_f64("exp", i[0].number(), f64::exp)
 }
 
 pub fn exp_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(exp),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("e raised to the power of `n`.".to_string()),
     }
 }
 
 fn sinh(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().sinh())
-        .unwrap()
-        .data()
+    via_f64("sinh", i[0].number(), f64::sinh)
 }
 
 pub fn sinh_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(sinh),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The hyperbolic sine of `n`.".to_string()),
     }
 }
 
 fn tanh(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().tanh())
-        .unwrap()
-        .data()
+    via_f64("tanh", i[0].number(), f64::tanh)
 }
 
 pub fn tanh_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(tanh),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The hyperbolic tangent of `n`.".to_string()),
     }
 }
 
 fn trunc(i: Input) -> Output {
-    let a = i[0].number();
-    Decimal::from_f64(a.to_f64().unwrap().trunc())
-        .unwrap()
-        .data()
+    unsigned_zero(i[0].number().trunc()).data()
 }
 
 pub fn trunc_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
         function: FunctionType::BuiltIn(trunc),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`n` rounded towards zero.".to_string()),
+    }
+}
+
+/// Whether `n` has no fractional part.
+fn is_integer(i: Input) -> Output {
+    let a = i[0].number();
+    (a.fract().is_zero()).data()
+}
+
+pub fn is_integer_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
+        function: FunctionType::BuiltIn(is_integer),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `n` has no fractional part.".to_string()),
+    }
+}
+
+/// Whether `n` is zero, of either sign.
+fn is_zero(i: Input) -> Output {
+    let a = i[0].number();
+    (a.is_zero()).data()
+}
+
+pub fn is_zero_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
+        function: FunctionType::BuiltIn(is_zero),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `n` is zero.".to_string()),
+    }
+}
+
+/// Whether `n` is above zero. Zero is neither positive nor negative, whatever its sign, so
+/// `is_positive(0)` and `is_negative(-0)` are both false.
+fn is_positive(i: Input) -> Output {
+    let a = i[0].number();
+    (!a.is_zero() && a.is_sign_positive()).data()
+}
+
+pub fn is_positive_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
+        function: FunctionType::BuiltIn(is_positive),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `n` is above zero.".to_string()),
+    }
+}
+
+/// Whether `n` is below zero, see [`is_positive`] for how zero is treated.
+fn is_negative(i: Input) -> Output {
+    let a = i[0].number();
+    (!a.is_zero() && a.is_sign_negative()).data()
+}
+
+pub fn is_negative_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
+        function: FunctionType::BuiltIn(is_negative),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `n` is below zero.".to_string()),
+    }
+}
+
+/// Numbers are decimals, which have no infinities or NaN, so this is always true. It exists
+/// so scripts ported from languages with floats don't need rewriting.
+fn is_finite(_i: Input) -> Output {
+    true.data()
+}
+
+pub fn is_finite_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
+        function: FunctionType::BuiltIn(is_finite),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Always true, since numbers are decimals without infinities.".to_string()),
+    }
+}
+
+/// The part of `n` after the decimal point, with the sign of `n`: `fract(-2.5)` is -0.5. A
+/// whole number gives 0, never -0.
+fn fract(i: Input) -> Output {
+    let a = i[0].number().fract();
+    if a.is_zero() { Decimal::ZERO } else { a }.data()
+}
+
+pub fn fract_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
+        function: FunctionType::BuiltIn(fract),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The part of `n` after the decimal point, with the sign of `n`.".to_string()),
+    }
+}
+
+/// `n` without its fractional part, rounding towards zero, so `int_part(n) + fract(n)` is `n`.
+/// Unlike `trunc` this never goes through a float. Like `fract`, it never gives -0.
+fn int_part(i: Input) -> Output {
+    unsigned_zero(i[0].number().trunc()).data()
+}
+
+pub fn int_part_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
+        function: FunctionType::BuiltIn(int_part),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("`n` without its fractional part.".to_string()),
+    }
+}
+
+/// `n` as an integer for the bit functions, which only take whole numbers that fit in 64 bits.
+fn bits(name: &str, n: Decimal) -> EResult<i64> {
+    n.to_i64()
+        .filter(|_| n.fract().is_zero())
+        .ok_or_else(|| ExprError::MathDomain {
+            operation: format!("{name}({n})"),
+            reason: "it only takes whole numbers that fit in a 64 bit integer".to_string(),
+        })
+}
+
+/// How far `shl` and `shr` shift `a`, from 0 to 63 bits.
+fn shift(name: &str, a: i64, by: Decimal) -> EResult<u32> {
+    by.to_u32()
+        .filter(|bits| by.fract().is_zero() && *bits < 64)
+        .ok_or_else(|| ExprError::MathDomain {
+            operation: format!("{name}({a}, {by})"),
+            reason: "it can only shift by a whole number of bits from 0 to 63".to_string(),
+        })
+}
+
+/// Bitwise and of two integers, in two's complement like the rest of the bit functions.
+fn band(i: Input) -> Output {
+    (bits("band", i[0].number())? & bits("band", i[1].number())?).data()
+}
+
+pub fn band_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
+        function: FunctionType::BuiltIn(band),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The bitwise and of the integers `a` and `b`.".to_string()),
+    }
+}
+
+fn bor(i: Input) -> Output {
+    (bits("bor", i[0].number())? | bits("bor", i[1].number())?).data()
+}
+
+pub fn bor_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
+        function: FunctionType::BuiltIn(bor),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The bitwise or of the integers `a` and `b`.".to_string()),
+    }
+}
+
+fn bxor(i: Input) -> Output {
+    (bits("bxor", i[0].number())? ^ bits("bxor", i[1].number())?).data()
+}
+
+pub fn bxor_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "b"],
+        function: FunctionType::BuiltIn(bxor),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The bitwise exclusive or of the integers `a` and `b`.".to_string()),
+    }
+}
+
+/// Flips every bit, so `bnot(n)` is `-n - 1`.
+fn bnot(i: Input) -> Output {
+    (!bits("bnot", i[0].number())?).data()
+}
+
+pub fn bnot_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["a"],
+        function: FunctionType::BuiltIn(bnot),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The integer `a` with every bit flipped.".to_string()),
+    }
+}
+
+/// `a` shifted left, failing rather than losing bits off the top.
+fn shl(i: Input) -> Output {
+    let a = bits("shl", i[0].number())?;
+    let by = shift("shl", a, i[1].number())?;
+
+    i64::try_from(i128::from(a) << by)
+        .map_err(|_| overflow(format!("shl({a}, {by})")))?
+        .data()
+}
+
+pub fn shl_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "bits"],
+        function: FunctionType::BuiltIn(shl),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The integer `a` shifted left by `bits`.".to_string()),
+    }
+}
+
+/// `a` shifted right, keeping its sign.
+fn shr(i: Input) -> Output {
+    let a = bits("shr", i[0].number())?;
+    let by = shift("shr", a, i[1].number())?;
+
+    (a >> by).data()
+}
+
+pub fn shr_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["a", "bits"],
+        function: FunctionType::BuiltIn(shr),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The integer `a` shifted right by `bits`, keeping its sign.".to_string()),
     }
 }
 
@@ -563,7 +1109,292 @@ fn parse_to_number(i: Input) -> Output {
 pub fn parse_to_number_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(parse_to_number),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The number written in `s`.".to_string()),
+    }
+}
+
+/// Currency symbols `parse_number_lenient` skips in front of a number.
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥', '₹'];
+
+/// The `(thousands, decimal)` separators of a number locale: `en` writes `1,234.5` and `eu`
+/// writes `1.234,5`.
+fn separators(locale: &str) -> EResult<(char, char)> {
+    match locale {
+        "en" => Ok((',', '.')),
+        "eu" => Ok(('.', ',')),
+        other => Err(ExprError::InvalidDataType {
+            expected: "en or eu".to_string(),
+            found: other.to_string(),
+            loc: "number locale".to_string(),
+        }),
+    }
+}
+
+/// Reads a number the way people write it, e.g. `" -$1,234.50 "` or `"3 kg"`, returning it and
+/// the unit after it (empty if there is none). Whitespace around the parts, a leading currency
+/// symbol and thousands separators are skipped. A thousands separator has to be followed by
+/// exactly three digits, so `1,5` is an error in `en` rather than a guess at fifteen or one and
+/// a half.
+fn lenient_number(s: &str, locale: &str) -> EResult<(Decimal, String)> {
+    let (thousands, point) = separators(locale)?;
+    let bad = || ExprError::BadNumber(s.to_string());
+
+    let mut rest = s.trim();
+    let mut negative = false;
+    if let Some(r) = rest.strip_prefix('-') {
+        negative = true;
+        rest = r.trim_start();
+    }
+    if let Some(r) = rest.strip_prefix(CURRENCY_SYMBOLS) {
+        rest = r.trim_start();
+    }
+    if let (Some(r), false) = (rest.strip_prefix('-'), negative) {
+        negative = true;
+        rest = r;
+    }
+
+    let is_unit = |c: char| c.is_alphabetic() || c == '%' || c == '/';
+    let (digits, unit) = rest.split_at(rest.find(is_unit).unwrap_or(rest.len()));
+    let (digits, unit) = (digits.trim_end(), unit.trim_end());
+    if !unit.chars().all(is_unit) {
+        return Err(bad());
+    }
+
+    let (int, fract) = digits
+        .split_once(point)
+        .map_or((digits, None), |(i, f)| (i, Some(f)));
+    let groups = int.split(thousands).collect::<Vec<_>>();
+    if groups.len() > 1
+        && (groups[0].is_empty() || groups[0].len() > 3 || groups[1..].iter().any(|g| g.len() != 3))
+    {
+        return Err(ExprError::InvalidDataType {
+            expected: format!("groups of three digits after `{thousands}`"),
+            found: s.to_string(),
+            loc: "parse_number_lenient".to_string(),
+        });
+    }
+
+    let mut plain = groups.concat();
+    if let Some(fract) = fract {
+        plain.push('.');
+        plain.push_str(fract);
+    }
+    if !plain.starts_with(|c: char| c.is_ascii_digit())
+        || !plain.chars().all(|c| c.is_ascii_digit() || c == '.')
+    {
+        return Err(bad());
+    }
+
+    let n = Decimal::from_str(&plain).map_err(|_| bad())?;
+    Ok((if negative { -n } else { n }, unit.to_string()))
+}
+
+fn parse_number_lenient(i: Input) -> Output {
+    lenient_number(i[0].string(), "en")?.0.data()
+}
+
+pub fn parse_number_lenient_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
+        function: FunctionType::BuiltIn(parse_number_lenient),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The number in `s` written the way people do, e.g. `\"$1,234.50\"`.".to_string()),
+    }
+}
+
+fn parse_number_lenient_locale(i: Input) -> Output {
+    lenient_number(i[0].string(), i[1].string())?.0.data()
+}
+
+pub fn parse_number_lenient_locale_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "locale"],
+        function: FunctionType::BuiltIn(parse_number_lenient_locale),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("Like `parse_number_lenient`, with the separators of `locale`.".to_string()),
+    }
+}
+
+/// `[number, unit]`, e.g. `[3, "kg"]` for `"3 kg"`, ready to pass on to a unit conversion.
+fn parse_number_with_unit(i: Input) -> Output {
+    let (n, unit) = lenient_number(i[0].string(), "en")?;
+
+    vec![n.data()?, unit.data()?].data()
+}
+
+pub fn parse_number_with_unit_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
+        function: FunctionType::BuiltIn(parse_number_with_unit),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some(
+            "The number in `s` and the unit after it, e.g. `[3, \"kg\"]` for `\"3 kg\"`."
+                .to_string(),
+        ),
+    }
+}
+
+fn decimal_places(n: Decimal, loc: &str) -> EResult<u32> {
+    n.to_u32()
+        .filter(|d| n.fract().is_zero() && *d <= 28)
+        .ok_or(ExprError::InvalidDataType {
+            expected: "integer between 0 and 28".to_string(),
+            found: n.to_string(),
+            loc: loc.to_string(),
+        })
+}
+
+/// Rounds half away from zero (so 2.345 becomes 2.35) and pads to exactly `decimals` places.
+fn fixed(n: Decimal, decimals: u32) -> String {
+    let rounded = n.round_dp_with_strategy(decimals, RoundingStrategy::MidpointAwayFromZero);
+
+    // Padding by hand, Decimal's own `{:.*}` overflows its buffer for long numbers.
+    let mut s = rounded.to_string();
+    if rounded.scale() == 0 && decimals > 0 {
+        s.push('.');
+    }
+    s.push_str(&"0".repeat((decimals - rounded.scale()) as usize));
+    s
+}
+
+/// Inserts `,` between groups of three digits in the integer part of a formatted number.
+fn group_thousands(s: &str) -> String {
+    let (sign, s) = s.strip_prefix('-').map_or(("", s), |s| ("-", s));
+    let (int, fract) = s.split_once('.').map_or((s, None), |(i, f)| (i, Some(f)));
+
+    let mut grouped = String::new();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+
+    match fract {
+        Some(fract) => format!("{sign}{grouped}.{fract}"),
+        None => format!("{sign}{grouped}"),
+    }
+}
+
+fn to_fixed(i: Input) -> Output {
+    let decimals = decimal_places(i[1].number(), "to_fixed decimals")?;
+
+    fixed(i[0].number(), decimals).data()
+}
+
+pub fn to_fixed_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number, DataType::Number],
+        inputs_rest: None,
+        param_names: &["n", "decimals"],
+        function: FunctionType::BuiltIn(to_fixed),
+        output: DataType::String,
+        capabilities: &[],
+        doc: Some("`n` written with exactly `decimals` decimal places.".to_string()),
+    }
+}
+
+fn format_thousands(i: Input) -> Output {
+    group_thousands(&i[0].number().to_string()).data()
+}
+
+pub fn format_thousands_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["n"],
+        function: FunctionType::BuiltIn(format_thousands),
+        output: DataType::String,
+        capabilities: &[],
+        doc: Some("`n` written with commas between groups of thousands.".to_string()),
+    }
+}
+
+/// Formats `n` as money. `style` is one of `prefix`, `suffix`, `parens` or `suffix_parens`;
+/// the `parens` styles render negative amounts as `($12.00)` instead of `-$12.00`.
+fn currency(n: Decimal, symbol: &str, decimals: u32, style: &str) -> EResult<String> {
+    let (suffix, parens) = match style {
+        "prefix" => (false, false),
+        "suffix" => (true, false),
+        "parens" => (false, true),
+        "suffix_parens" => (true, true),
+        other => {
+            return Err(ExprError::InvalidDataType {
+                expected: "prefix, suffix, parens or suffix_parens".to_string(),
+                found: other.to_string(),
+                loc: "format_currency style".to_string(),
+            })
+        }
+    };
+
+    let amount = group_thousands(&fixed(n.abs(), decimals));
+    let amount = if suffix {
+        format!("{amount}{symbol}")
+    } else {
+        format!("{symbol}{amount}")
+    };
+
+    // Rounding can turn a tiny negative amount into zero, which shouldn't get a sign.
+    let negative = n.is_sign_negative() && amount.chars().any(|c| ('1'..='9').contains(&c));
+
+    Ok(match (negative, parens) {
+        (false, _) => amount,
+        (true, false) => format!("-{amount}"),
+        (true, true) => format!("({amount})"),
+    })
+}
+
+fn format_currency(i: Input) -> Output {
+    let decimals = decimal_places(i[2].number(), "format_currency decimals")?;
+
+    currency(i[0].number(), i[1].string(), decimals, "prefix")?.data()
+}
+
+pub fn format_currency_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number, DataType::String, DataType::Number],
+        inputs_rest: None,
+        param_names: &["n", "symbol", "decimals"],
+        function: FunctionType::BuiltIn(format_currency),
+        output: DataType::String,
+        capabilities: &[],
+        doc: Some("`n` written as money, with `symbol` and `decimals` decimal places.".to_string()),
+    }
+}
+
+fn format_currency_styled(i: Input) -> Output {
+    let decimals = decimal_places(i[2].number(), "format_currency decimals")?;
+
+    currency(i[0].number(), i[1].string(), decimals, i[3].string())?.data()
+}
+
+pub fn format_currency_styled_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![
+            DataType::Number,
+            DataType::String,
+            DataType::Number,
+            DataType::String,
+        ],
+        inputs_rest: None,
+        param_names: &["n", "symbol", "decimals", "style"],
+        function: FunctionType::BuiltIn(format_currency_styled),
+        output: DataType::String,
+        capabilities: &[],
+        doc: Some("Like `format_currency`, with `style` one of `prefix`, `suffix`, `parens` or `suffix_parens`.".to_string()),
     }
 }
@@ -1,46 +1,242 @@
-use super::{FunctionDescriptor, FunctionType, Input, Output};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use super::{
+    builtin_groups, builtints, higher_order::call, Capability, FunctionDescriptor, FunctionType,
+    Input, Output,
+};
 use crate::{
-    data::{Data, DataType, ToData},
-    utils::strings::DotDisplay,
+    constants::{enabled_features, FEATURES},
+    data::{Data, DataMap, DataType, NumberDisplay, ToData},
+    expr::{error::ExprError, EResult, ExecutionState, Expr},
+    session,
 };
 
-fn println(i: Input) -> Output {
-    println!("{}", i[0].display().replace("\\n", "\n"));
+fn println(i: Input, state: &mut ExecutionState) -> Output {
+    state.output.write(&format!("{}\n", state.show(&i[0])))?;
+
+    Ok(Data::Null)
+}
+
+fn print(i: Input, state: &mut ExecutionState) -> Output {
+    state.output.write(&state.show(&i[0]))?;
+
+    Ok(Data::Null)
+}
+
+/// `println`, without cutting long arrays and maps short.
+fn full_print(i: Input, state: &mut ExecutionState) -> Output {
+    let shown = i[0].shown(0, state.number_display.get());
+    state.output.write(&format!("{shown}\n"))?;
+
+    Ok(Data::Null)
+}
+
+pub fn full_print_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Any],
+        inputs_rest: None,
+        param_names: &["value"],
+        function: FunctionType::BuiltInWithState(full_print),
+        output: DataType::Null,
+        capabilities: &[],
+        doc: Some(
+            "Prints `value` and a new line, without cutting long arrays and maps short."
+                .to_string(),
+        ),
+    }
+}
+
+/// Sets how many elements of an array or map are printed, 0 for all of them.
+fn set_display_limit(i: Input, state: &mut ExecutionState) -> Output {
+    let limit = i[0].number();
+    let limit = limit
+        .to_usize()
+        .filter(|_| limit.fract().is_zero())
+        .ok_or_else(|| ExprError::InvalidDataType {
+            expected: "non-negative integer".to_string(),
+            found: limit.to_string(),
+            loc: "set_display_limit".to_string(),
+        })?;
+
+    state.display_limit.set(limit);
 
     Ok(Data::Null)
 }
 
-fn print(i: Input) -> Output {
-    print!("{}", i[0].display().replace("\\n", "\n"));
+pub fn set_display_limit_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["limit"],
+        function: FunctionType::BuiltInWithState(set_display_limit),
+        output: DataType::Null,
+        capabilities: &[],
+        doc: Some(
+            "Sets how many elements of an array or map are printed, 0 for all of them.".to_string(),
+        ),
+    }
+}
+
+/// Sets how numbers are printed: `auto`, `scientific` or `engineering`.
+fn set_number_display(i: Input, state: &mut ExecutionState) -> Output {
+    let mode = i[0].string();
+    let mode = NumberDisplay::from_str(mode).map_err(|_| ExprError::InvalidDataType {
+        expected: "auto, scientific or engineering".to_string(),
+        found: mode.clone(),
+        loc: "set_number_display".to_string(),
+    })?;
+
+    state.number_display.set(mode);
+
+    Ok(Data::Null)
+}
+
+pub fn set_number_display_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["mode"],
+        function: FunctionType::BuiltInWithState(set_number_display),
+        output: DataType::Null,
+        capabilities: &[],
+        doc: Some(
+            "Sets how numbers are printed: `auto`, `scientific` or `engineering`.".to_string(),
+        ),
+    }
+}
+
+/// Saves a copy of the variables for `restore`, returning its handle.
+fn snapshot(_i: Input, state: &mut ExecutionState) -> Output {
+    let variables = state.variables.visible();
+
+    state.snapshots.borrow_mut().save(variables).data()
+}
+
+pub fn snapshot_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![],
+        inputs_rest: None,
+        param_names: &[],
+        function: FunctionType::BuiltInWithState(snapshot),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("Saves a copy of the variables for `restore`, returning its handle.".to_string()),
+    }
+}
+
+fn snapshot_handle(handle: &Data) -> EResult<usize> {
+    let handle = handle.number();
+
+    handle
+        .to_usize()
+        .filter(|_| handle.fract().is_zero())
+        .ok_or_else(|| ExprError::UnknownSnapshot {
+            handle: handle.to_string(),
+        })
+}
+
+/// Puts every variable that existed when the snapshot was taken back to its value then.
+/// Variables declared since are kept, so the one holding the handle survives and the same
+/// snapshot can be restored again. Functions aren't touched.
+fn restore(i: Input, state: &mut ExecutionState) -> Output {
+    let handle = snapshot_handle(&i[0])?;
+    let snapshots = state.snapshots.borrow();
+    let saved = snapshots
+        .get(handle)
+        .ok_or_else(|| ExprError::UnknownSnapshot {
+            handle: handle.to_string(),
+        })?;
+
+    for (name, value) in saved {
+        state.variables.set(name, value.clone());
+    }
 
     Ok(Data::Null)
 }
 
+pub fn restore_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["handle"],
+        function: FunctionType::BuiltInWithState(restore),
+        output: DataType::Null,
+        capabilities: &[],
+        doc: Some("Puts the variables saved by `snapshot` back to their values then.".to_string()),
+    }
+}
+
+/// Frees a snapshot. Its handle can't be restored afterwards.
+fn drop_snapshot(i: Input, state: &mut ExecutionState) -> Output {
+    let handle = snapshot_handle(&i[0])?;
+
+    match state.snapshots.borrow_mut().remove(handle) {
+        Some(_) => Ok(Data::Null),
+        None => Err(ExprError::UnknownSnapshot {
+            handle: handle.to_string(),
+        }),
+    }
+}
+
+pub fn drop_snapshot_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["handle"],
+        function: FunctionType::BuiltInWithState(drop_snapshot),
+        output: DataType::Null,
+        capabilities: &[],
+        doc: Some("Frees a snapshot, so its handle can't be restored.".to_string()),
+    }
+}
+
 pub fn print_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any],
-        function: FunctionType::BuiltIn(print),
+        inputs_rest: None,
+        param_names: &["value"],
+        function: FunctionType::BuiltInWithState(print),
         output: DataType::Null,
+        capabilities: &[],
+        doc: Some("Prints `value`.".to_string()),
     }
 }
 
 pub fn println_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any],
-        function: FunctionType::BuiltIn(println),
+        inputs_rest: None,
+        param_names: &["value"],
+        function: FunctionType::BuiltInWithState(println),
         output: DataType::Null,
+        capabilities: &[],
+        doc: Some("Prints `value` and a new line.".to_string()),
     }
 }
 
+/// The type of a value, or the name of a record's or enum's type.
 fn type_of(i: Input) -> Output {
-    i[0]._type().to_string().data()
+    match &i[0] {
+        Data::Record(name, _) | Data::Enum(name, _) => name.clone().data(),
+        other => other._type().to_string().data(),
+    }
 }
 
 pub fn type_of_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any],
+        inputs_rest: None,
+        param_names: &["value"],
         function: FunctionType::BuiltIn(type_of),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("The type of `value`, or the name of a record's or enum's type.".to_string()),
     }
 }
 
@@ -54,27 +250,80 @@ fn input(_i: Input) -> Output {
 pub fn input_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![],
+        inputs_rest: None,
+        param_names: &[],
         function: FunctionType::BuiltIn(input),
         output: DataType::String,
+        capabilities: &[Capability::Stdin],
+        doc: Some("Reads a line from stdin.".to_string()),
+    }
+}
+
+/// Fails with `message`, which `try` can catch like any other error.
+fn error(i: Input) -> Output {
+    Err(ExprError::Raised {
+        message: i[0].string().clone(),
+    })
+}
+
+pub fn error_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["message"],
+        function: FunctionType::BuiltIn(error),
+        output: DataType::Null,
+        capabilities: &[],
+        doc: Some("Fails with `message`, which `try` can catch.".to_string()),
+    }
+}
+
+fn exit(i: Input) -> Output {
+    Err(ExprError::Exit(i[0].number().to_i32().unwrap_or(1)))
+}
+
+pub fn exit_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Number],
+        inputs_rest: None,
+        param_names: &["code"],
+        function: FunctionType::BuiltIn(exit),
+        output: DataType::Null,
+        capabilities: &[Capability::Process],
+        doc: Some("Ends the program with the exit code `code`. `try` can't catch it.".to_string()),
     }
 }
 
 fn read_file(i: Input) -> Output {
-    dbg!(i[0].string());
-    let input = std::fs::read_to_string(i[0].string()).unwrap();
-    input.data()
+    let path = i[0].string();
+
+    std::fs::read_to_string(path)
+        .map_err(|source| ExprError::IoError {
+            path: path.clone(),
+            source,
+        })?
+        .data()
 }
 
 pub fn read_file_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["path"],
         function: FunctionType::BuiltIn(read_file),
         output: DataType::String,
+        capabilities: &[Capability::FsRead],
+        doc: Some("The contents of the file at `path`.".to_string()),
     }
 }
 
 pub fn write_file(i: Input) -> Output {
-    std::fs::write(i[0].string(), i[1].string()).unwrap();
+    let path = i[0].string();
+
+    std::fs::write(path, i[1].string()).map_err(|source| ExprError::IoError {
+        path: path.clone(),
+        source,
+    })?;
 
     Ok(Data::Null)
 }
@@ -82,7 +331,328 @@ pub fn write_file(i: Input) -> Output {
 pub fn write_file_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["path", "contents"],
         function: FunctionType::BuiltIn(write_file),
         output: DataType::Null,
+        capabilities: &[Capability::FsWrite],
+        doc: Some("Writes `contents` to the file at `path`, replacing it.".to_string()),
+    }
+}
+
+fn save_session(i: Input, state: &mut ExecutionState) -> Output {
+    session::save_session(state, i[0].string())?;
+
+    Ok(Data::Null)
+}
+
+pub fn save_session_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["path"],
+        function: FunctionType::BuiltInWithState(save_session),
+        output: DataType::Null,
+        capabilities: &[Capability::FsWrite],
+        doc: Some("Writes the variables and functions to the file at `path`.".to_string()),
+    }
+}
+
+fn load_session(i: Input, state: &mut ExecutionState) -> Output {
+    session::load_session(state, i[0].string())?;
+
+    Ok(Data::Null)
+}
+
+pub fn load_session_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["path"],
+        function: FunctionType::BuiltInWithState(load_session),
+        output: DataType::Null,
+        capabilities: &[Capability::FsRead],
+        doc: Some("Loads the variables and functions saved at `path`.".to_string()),
+    }
+}
+
+/// `args()` returns the arguments passed to the script after `--`.
+pub fn args_descriptor(args: &[String]) -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![],
+        inputs_rest: None,
+        param_names: &[],
+        function: FunctionType::Custom(
+            vec![Expr::Array(
+                args.iter().cloned().map(Expr::String).collect(),
+            )],
+            vec![],
+            Box::new([]),
+            Arc::default(),
+        ),
+        output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The arguments passed to the script after `--`.".to_string()),
+    }
+}
+
+fn version(_i: Input) -> Output {
+    DataMap::from([
+        ("name".to_string(), env!("CARGO_PKG_NAME").data()?),
+        ("version".to_string(), env!("CARGO_PKG_VERSION").data()?),
+        ("features".to_string(), enabled_features().data()?),
+    ])
+    .data()
+}
+
+pub fn version_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![],
+        inputs_rest: None,
+        param_names: &[],
+        function: FunctionType::BuiltIn(version),
+        output: DataType::Map,
+        capabilities: &[],
+        doc: Some("The name, version and enabled features of the interpreter.".to_string()),
+    }
+}
+
+fn has_feature(i: Input) -> Output {
+    FEATURES
+        .iter()
+        .any(|(name, enabled)| *enabled && name == i[0].string())
+        .data()
+}
+
+pub fn has_feature_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["name"],
+        function: FunctionType::BuiltIn(has_feature),
+        output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether the interpreter was built with the feature `name`.".to_string()),
+    }
+}
+
+/// Untimed runs before `bench` starts measuring, so first-call costs don't skew the results.
+const WARM_UP_ITERATIONS: usize = 3;
+
+fn bench(i: Input, state: &mut ExecutionState) -> Output {
+    let func = i[0].function().clone();
+    let iterations = i[1].number();
+    let iterations = iterations
+        .to_usize()
+        .filter(|n| *n > 0 && iterations.fract().is_zero())
+        .ok_or_else(|| ExprError::InvalidDataType {
+            expected: "positive integer".to_string(),
+            found: iterations.to_string(),
+            loc: "bench iterations".to_string(),
+        })?;
+
+    let run = |iteration, warm_up, state: &mut ExecutionState| {
+        let start = Instant::now();
+        call(vec![], func.clone(), state).map_err(|e| match e {
+            ExprError::Interrupted | ExprError::OutOfSteps | ExprError::Exit(_) => e,
+            e => ExprError::BenchFailed {
+                iteration,
+                warm_up,
+                source: Box::new(e),
+            },
+        })?;
+
+        Ok::<_, ExprError>(start.elapsed())
+    };
+
+    for iteration in 1..=WARM_UP_ITERATIONS {
+        run(iteration, true, state)?;
+    }
+
+    let times = (1..=iterations)
+        .map(|iteration| run(iteration, false, state))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let millis = |d: Duration| Decimal::from(d.as_nanos() as u64) / Decimal::from(1_000_000);
+    let total = times.iter().sum::<Duration>();
+
+    DataMap::from([
+        ("total".to_string(), millis(total).data()?),
+        (
+            "mean".to_string(),
+            (millis(total) / Decimal::from(iterations)).data()?,
+        ),
+        (
+            "min".to_string(),
+            millis(*times.iter().min().unwrap()).data()?,
+        ),
+        (
+            "max".to_string(),
+            millis(*times.iter().max().unwrap()).data()?,
+        ),
+    ])
+    .data()
+}
+
+pub fn bench_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Function, DataType::Number],
+        inputs_rest: None,
+        param_names: &["f", "iterations"],
+        function: FunctionType::BuiltInWithState(bench),
+        output: DataType::Map,
+        capabilities: &[],
+        doc: Some("Times `iterations` calls of `f`.".to_string()),
+    }
+}
+
+/// A rough count of the bytes `value` takes up: one `Data` per value, plus the text of
+/// strings and map keys. Walks the value with a stack rather than recursion, so huge values
+/// can't overflow it.
+fn sizeof(i: Input) -> Output {
+    let mut bytes = 0;
+    let mut stack = vec![&i[0]];
+
+    while let Some(value) = stack.pop() {
+        bytes += size_of::<Data>();
+
+        match value {
+            Data::String(s) => bytes += s.len(),
+            Data::Array(items) | Data::Tuple(items) => stack.extend(items),
+            Data::Map(map) | Data::Record(_, map) => {
+                for (key, value) in map {
+                    bytes += size_of::<String>() + key.len();
+                    stack.push(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    bytes.data()
+}
+
+pub fn sizeof_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Any],
+        inputs_rest: None,
+        param_names: &["value"],
+        function: FunctionType::BuiltIn(sizeof),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("A rough count of the bytes `value` takes up.".to_string()),
+    }
+}
+
+/// How deeply arrays, tuples and maps nest in `value`: 0 for anything else, 1 for a flat array.
+fn depth(i: Input) -> Output {
+    let mut deepest: usize = 0;
+    let mut stack = vec![(&i[0], 0)];
+
+    while let Some((value, depth)) = stack.pop() {
+        let children: Box<dyn Iterator<Item = &Data>> = match value {
+            Data::Array(items) | Data::Tuple(items) => Box::new(items.iter()),
+            Data::Map(map) | Data::Record(_, map) => Box::new(map.values()),
+            _ => continue,
+        };
+
+        deepest = deepest.max(depth + 1);
+        stack.extend(children.map(|child| (child, depth + 1)));
+    }
+
+    deepest.data()
+}
+
+pub fn depth_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::Any],
+        inputs_rest: None,
+        param_names: &["value"],
+        function: FunctionType::BuiltIn(depth),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("How deeply arrays, tuples and maps nest in `value`.".to_string()),
+    }
+}
+
+/// Prints the signature and description of each function named, `alias.name` for one of a
+/// module, or every function there is by module when there are no names.
+fn help(i: Input, state: &mut ExecutionState) -> Output {
+    if i.is_empty() {
+        let list = function_list(state);
+        state.output.write(&list)?;
+        return Ok(Data::Null);
+    }
+
+    for name in i.iter().map(Data::string) {
+        let desc = match name.split_once('.') {
+            Some((alias, f)) => state.modules.get(alias).and_then(|m| m.export(f)),
+            None => state.functions.get(name).cloned(),
+        }
+        .ok_or_else(|| ExprError::FunctionNotFound { name: name.clone() })?;
+
+        let mut text = format!("fn {name}({}) -> {}\n", desc.params(), desc.output);
+        for line in desc.doc.iter().flat_map(|doc| doc.lines()) {
+            text.push_str(&format!("    {line}\n"));
+        }
+        state.output.write(&text)?;
+    }
+
+    Ok(Data::Null)
+}
+
+/// One line per group of functions: the builtins by module, then the declared functions, then
+/// each imported module's.
+fn function_list(state: &ExecutionState) -> String {
+    let builtins = builtints();
+    let line = |group: &str, mut names: Vec<&str>| {
+        names.sort_unstable();
+        format!("{group}: {}\n", names.join(", "))
+    };
+
+    let mut list = String::new();
+    for (group, functions) in builtin_groups() {
+        let names = functions
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| state.functions.contains_key(*name))
+            .collect::<Vec<_>>();
+        if !names.is_empty() {
+            list.push_str(&line(group, names));
+        }
+    }
+
+    let declared = state
+        .functions
+        .keys()
+        .filter(|name| !builtins.contains_key(*name))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if !declared.is_empty() {
+        list.push_str(&line("declared", declared));
+    }
+
+    let mut modules = state.modules.iter().collect::<Vec<_>>();
+    modules.sort_unstable_by_key(|(alias, _)| *alias);
+    for (alias, module) in modules {
+        let exports = module.exports.iter().map(String::as_str).collect();
+        list.push_str(&line(alias, exports));
+    }
+
+    list
+}
+
+pub fn help_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![],
+        inputs_rest: Some(DataType::String),
+        param_names: &["names"],
+        function: FunctionType::BuiltInWithState(help),
+        output: DataType::Null,
+        capabilities: &[],
+        doc: Some(
+            "Prints how to call each function named, or lists every function without any names."
+                .to_string(),
+        ),
     }
 }
@@ -1,15 +1,36 @@
-use super::{FunctionDescriptor, FunctionType, Input, Output};
+//! String builtins. Every position a builtin takes or returns counts characters, not bytes, so
+//! `find`, `first_not_matching` and slicing compose on any text. `find_byte` is the one
+//! exception, for when the raw offset is needed.
+//!
+//! Widths are different: `center` and `display_width` count terminal columns, where a CJK
+//! character takes two and a combining accent none, so padded text lines up when printed.
+//! `grapheme_length` counts what a reader would call characters, e.g. 1 for `e\u{301}`.
+
+use super::{higher_order::call, FunctionDescriptor, FunctionType, Input, Output};
 use crate::{
     data::{Data, DataType, ToData},
-    expr::error::ExprError,
+    expr::{error::ExprError, ExecutionState},
     utils::strings::DotDisplay,
 };
 use heck::{
     ToKebabCase, ToLowerCamelCase, ToShoutyKebabCase, ToShoutySnakeCase, ToSnakeCase, ToTitleCase,
     ToTrainCase, ToUpperCamelCase,
 };
-use regex::bytes::Regex;
 use rust_decimal::prelude::*;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The character index of byte offset `byte` in `s`.
+pub(super) fn char_index(s: &str, byte: usize) -> usize {
+    s[..byte].chars().count()
+}
+
+/// The byte offset of character index `index` in `s`, or the length of `s` past its end.
+pub(super) fn byte_index(s: &str, index: usize) -> usize {
+    s.char_indices()
+        .nth(index)
+        .map_or(s.len(), |(byte, _)| byte)
+}
 
 fn string(i: Input) -> Output {
     i[0].display().data()
@@ -18,8 +39,12 @@ fn string(i: Input) -> Output {
 pub fn string_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any],
+        inputs_rest: None,
+        param_names: &["value"],
         function: FunctionType::BuiltIn(string),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`value` as a string.".to_string()),
     }
 }
 
@@ -30,8 +55,12 @@ fn join(i: Input) -> Output {
 pub fn join_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(join),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`a` and `b` written one after the other.".to_string()),
     }
 }
 
@@ -42,8 +71,12 @@ fn join_after(i: Input) -> Output {
 pub fn join_after_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["a", "b"],
         function: FunctionType::BuiltIn(join_after),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`b` and `a` written one after the other.".to_string()),
     }
 }
 
@@ -54,8 +87,12 @@ fn surround(i: Input) -> Output {
 pub fn surround_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::Any, DataType::Any, DataType::Any],
+        inputs_rest: None,
+        param_names: &["s", "before", "after"],
         function: FunctionType::BuiltIn(surround),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` written between `before` and `after`.".to_string()),
     }
 }
 
@@ -66,8 +103,12 @@ fn uppercase(i: Input) -> Output {
 pub fn uppercase_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(uppercase),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in upper case.".to_string()),
     }
 }
 
@@ -78,8 +119,12 @@ fn lowercase(i: Input) -> Output {
 pub fn lowercase_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(lowercase),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in lower case.".to_string()),
     }
 }
 
@@ -90,8 +135,12 @@ fn snake_case(i: Input) -> Output {
 pub fn snake_case_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(snake_case),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in snake_case.".to_string()),
     }
 }
 
@@ -102,8 +151,12 @@ fn kebab_case(i: Input) -> Output {
 pub fn kebab_case_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(kebab_case),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in kebab-case.".to_string()),
     }
 }
 
@@ -114,8 +167,12 @@ fn title_case(i: Input) -> Output {
 pub fn title_case_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(title_case),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in Title Case.".to_string()),
     }
 }
 
@@ -126,8 +183,12 @@ fn upper_camel_case(i: Input) -> Output {
 pub fn upper_camel_case_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(upper_camel_case),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in UpperCamelCase.".to_string()),
     }
 }
 
@@ -138,8 +199,12 @@ fn lower_camel_case(i: Input) -> Output {
 pub fn lower_camel_case_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(lower_camel_case),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in lowerCamelCase.".to_string()),
     }
 }
 
@@ -150,8 +215,12 @@ fn shouty_kebab_case(i: Input) -> Output {
 pub fn shouty_kebab_case_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(shouty_kebab_case),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in SHOUTY-KEBAB-CASE.".to_string()),
     }
 }
 
@@ -162,8 +231,12 @@ fn shouty_snake_case(i: Input) -> Output {
 pub fn shouty_snake_case_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(shouty_snake_case),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in SHOUTY_SNAKE_CASE.".to_string()),
     }
 }
 
@@ -174,16 +247,36 @@ fn train_case(i: Input) -> Output {
 pub fn train_case_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(train_case),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` in Train-Case.".to_string()),
     }
 }
 
+/// The most padding `center` adds on each side.
+const MAX_PADDING: usize = 1_000_000;
+
+/// Centers `s` in `width` columns, padding both sides equally with copies of `pad`'s first
+/// character (a space if it's empty). Wide pad characters fill as many columns as they take.
 fn center(i: Input) -> Output {
     let s = i[0].string();
-    let length = i[1].number().to_usize().unwrap();
-    let char = i[2].string().bytes().next().unwrap() as char;
-    let padding = (length - s.len()) / 2;
+    let width = i[1].number();
+    let char = i[2].string().chars().next().unwrap_or(' ');
+    let char_width = Decimal::from(char.width().unwrap_or(1).max(1));
+    // A width smaller than the string (including a negative one) means no padding.
+    let padding =
+        ((width - Decimal::from(s.width())).max(Decimal::ZERO) / Decimal::TWO / char_width).floor();
+    let padding = padding
+        .to_usize()
+        .filter(|p| *p <= MAX_PADDING)
+        .ok_or_else(|| ExprError::InvalidDataType {
+            expected: format!("width of at most {}", MAX_PADDING * 2),
+            found: width.to_string(),
+            loc: "center".to_string(),
+        })?;
     let pad_str = char.to_string().repeat(padding);
     format!("{}{}{}", pad_str, s, pad_str).data()
 }
@@ -191,8 +284,48 @@ fn center(i: Input) -> Output {
 pub fn center_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::Number, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "width", "pad"],
         function: FunctionType::BuiltIn(center),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some(
+            "`s` centered in `width` columns, padded with the first character of `pad`."
+                .to_string(),
+        ),
+    }
+}
+
+fn grapheme_length(i: Input) -> Output {
+    i[0].string().graphemes(true).count().data()
+}
+
+pub fn grapheme_length_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
+        function: FunctionType::BuiltIn(grapheme_length),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The number of characters in `s`, as a reader would count them.".to_string()),
+    }
+}
+
+/// How many terminal columns `s` takes up.
+fn display_width(i: Input) -> Output {
+    i[0].string().width().data()
+}
+
+pub fn display_width_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
+        function: FunctionType::BuiltIn(display_width),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("How many terminal columns `s` takes up.".to_string()),
     }
 }
 
@@ -206,8 +339,12 @@ fn count(i: Input) -> Output {
 pub fn count_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "pattern"],
         function: FunctionType::BuiltIn(count),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("How many times `pattern` appears in `s`.".to_string()),
     }
 }
 
@@ -221,8 +358,12 @@ fn ends_with(i: Input) -> Output {
 pub fn ends_with_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "suffix"],
         function: FunctionType::BuiltIn(ends_with),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `s` ends with `suffix`.".to_string()),
     }
 }
 
@@ -236,47 +377,162 @@ fn starts_with(i: Input) -> Output {
 pub fn starts_with_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "prefix"],
         function: FunctionType::BuiltIn(starts_with),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether `s` starts with `prefix`.".to_string()),
     }
 }
 
-fn find(i: Input) -> Output {
+fn find_byte(i: Input) -> Output {
     let str = i[0].string();
     let search = i[1].string();
 
     str.find(search).map(|n| n as isize).unwrap_or(-1).data()
 }
 
+pub fn find_byte_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "pattern"],
+        function: FunctionType::BuiltIn(find_byte),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The byte offset of the first `pattern` in `s`, or -1.".to_string()),
+    }
+}
+
+fn find(i: Input) -> Output {
+    let str = i[0].string();
+    let search = i[1].string();
+
+    str.find(search)
+        .map(|n| char_index(str, n) as isize)
+        .unwrap_or(-1)
+        .data()
+}
+
 pub fn find_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "pattern"],
         function: FunctionType::BuiltIn(find),
         output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The character index of the first `pattern` in `s`, or -1.".to_string()),
+    }
+}
+
+/// Whether every character matches. Empty strings have no characters to match, so they don't.
+fn all_chars(s: &str, predicate: fn(char) -> bool) -> bool {
+    !s.is_empty() && s.chars().all(predicate)
+}
+
+/// The character index of the first character that doesn't match, or -1.
+fn first_not(s: &str, predicate: fn(char) -> bool) -> isize {
+    s.chars()
+        .position(|c| !predicate(c))
+        .map(|n| n as isize)
+        .unwrap_or(-1)
+}
+
+fn first_non_numeric(i: Input) -> Output {
+    first_not(i[0].string(), char::is_numeric).data()
+}
+
+pub fn first_non_numeric_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
+        function: FunctionType::BuiltIn(first_non_numeric),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some("The index of the first character of `s` that isn't a digit, or -1.".to_string()),
+    }
+}
+
+fn first_non_alphabetic(i: Input) -> Output {
+    first_not(i[0].string(), char::is_alphabetic).data()
+}
+
+pub fn first_non_alphabetic_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
+        function: FunctionType::BuiltIn(first_non_alphabetic),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some(
+            "The index of the first character of `s` that isn't a letter, or -1.".to_string(),
+        ),
+    }
+}
+
+fn first_not_matching(i: Input, state: &mut ExecutionState) -> Output {
+    let func = i[1].function();
+
+    for (n, c) in i[0].string().chars().enumerate() {
+        let matched = call(vec![Data::String(c.to_string())], func.clone(), state)?
+            .condition("first_not_matching predicate")?;
+
+        if !matched {
+            return (n as isize).data();
+        }
+    }
+
+    (-1isize).data()
+}
+
+pub fn first_not_matching_descriptor() -> FunctionDescriptor {
+    FunctionDescriptor {
+        inputs: vec![DataType::String, DataType::Function],
+        inputs_rest: None,
+        param_names: &["s", "f"],
+        function: FunctionType::BuiltInWithState(first_not_matching),
+        output: DataType::Number,
+        capabilities: &[],
+        doc: Some(
+            "The index of the first character of `s` that `f` returns false for, or -1."
+                .to_string(),
+        ),
     }
 }
 
 fn is_alphanumeric(i: Input) -> Output {
-    i[0].string().chars().all(char::is_alphanumeric).data()
+    all_chars(i[0].string(), char::is_alphanumeric).data()
 }
 
 pub fn is_alphanumeric_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(is_alphanumeric),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether every character of `s` is a letter or digit.".to_string()),
     }
 }
 
 fn is_alphabetic(i: Input) -> Output {
-    i[0].string().chars().all(char::is_alphabetic).data()
+    all_chars(i[0].string(), char::is_alphabetic).data()
 }
 
 pub fn is_alphabetic_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(is_alphabetic),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether every character of `s` is a letter.".to_string()),
     }
 }
 
@@ -287,28 +543,35 @@ fn is_ascii(i: Input) -> Output {
 pub fn is_ascii_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(is_ascii),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether every character of `s` is ASCII.".to_string()),
     }
 }
 
 fn is_numeric(i: Input) -> Output {
-    i[0].string().chars().all(char::is_numeric).data()
+    all_chars(i[0].string(), char::is_numeric).data()
 }
 
 pub fn is_numeric_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(is_numeric),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether every character of `s` is a digit.".to_string()),
     }
 }
 
-fn matches(i: Input) -> Output {
+fn matches(i: Input, state: &mut ExecutionState) -> Output {
     let s = i[0].string();
-    let regex = i[1].string();
-
-    let regex = Regex::new(regex).map_err(|_| ExprError::InvalidRegex(regex.clone()))?;
+    let regex = state.regex.get(i[1].string())?;
+    state.regex.check_input(s)?;
 
     regex.is_match(s.as_bytes()).data()
 }
@@ -316,44 +579,60 @@ fn matches(i: Input) -> Output {
 pub fn matches_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::String],
-        function: FunctionType::BuiltIn(matches),
+        inputs_rest: None,
+        param_names: &["s", "regex"],
+        function: FunctionType::BuiltInWithState(matches),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether the regex `regex` matches `s`.".to_string()),
     }
 }
 
 fn is_lowercase(i: Input) -> Output {
-    i[0].string().chars().all(char::is_lowercase).data()
+    all_chars(i[0].string(), char::is_lowercase).data()
 }
 
 pub fn is_lowercase_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(is_lowercase),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether every character of `s` is lower case.".to_string()),
     }
 }
 
 fn is_uppercase(i: Input) -> Output {
-    i[0].string().chars().all(char::is_uppercase).data()
+    all_chars(i[0].string(), char::is_uppercase).data()
 }
 
 pub fn is_uppercase_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(is_uppercase),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether every character of `s` is upper case.".to_string()),
     }
 }
 
 fn is_whitespace(i: Input) -> Output {
-    i[0].string().chars().all(char::is_whitespace).data()
+    all_chars(i[0].string(), char::is_whitespace).data()
 }
 
 pub fn is_whitespace_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(is_whitespace),
         output: DataType::Bool,
+        capabilities: &[],
+        doc: Some("Whether every character of `s` is whitespace.".to_string()),
     }
 }
 
@@ -364,8 +643,12 @@ fn trim(i: Input) -> Output {
 pub fn trim_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String],
+        inputs_rest: None,
+        param_names: &["s"],
         function: FunctionType::BuiltIn(trim),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` without whitespace at either end.".to_string()),
     }
 }
 
@@ -380,8 +663,12 @@ fn replace(i: Input) -> Output {
 pub fn replace_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "from", "to"],
         function: FunctionType::BuiltIn(replace),
         output: DataType::String,
+        capabilities: &[],
+        doc: Some("`s` with every `from` replaced by `to`.".to_string()),
     }
 }
 
@@ -399,7 +686,11 @@ fn split(i: Input) -> Output {
 pub fn split_descriptor() -> FunctionDescriptor {
     FunctionDescriptor {
         inputs: vec![DataType::String, DataType::String],
+        inputs_rest: None,
+        param_names: &["s", "separator"],
         function: FunctionType::BuiltIn(split),
         output: DataType::Array,
+        capabilities: &[],
+        doc: Some("The parts of `s` between each `separator`.".to_string()),
     }
 }
@@ -0,0 +1,377 @@
+//! Feeds random source text through the whole pipeline (validate, check, lex, parse, run) and
+//! checks that nothing panics: every bad input has to come back as an error.
+//!
+//! Inputs come from two generators with fixed seeds, so failures reproduce: one strings
+//! together random tokens, the other builds loosely structured programs that get past the
+//! parser more often. Crashers that were found and fixed live in `fuzz_corpus.lils`, one per
+//! line, and are replayed on every run.
+
+use std::{
+    cell::Cell,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    eval::eval_with_state, expr::ExecutionState, functions::builtints, lexer::validate_input,
+    output::OutputSink,
+};
+
+const CORPUS: &str = include_str!("fuzz_corpus.lils");
+
+/// How many statements and loop iterations a single input may run, for generated infinite
+/// loops.
+const STEP_LIMIT: usize = 10_000;
+
+const FRAGMENTS: &[&str] = &[
+    "+",
+    "-",
+    "*",
+    "/",
+    "~/",
+    "%",
+    "(",
+    ")",
+    ",",
+    ";",
+    "\n",
+    "let",
+    "const",
+    ":=",
+    "=",
+    "+=",
+    "-=",
+    "*=",
+    "/=",
+    "~/=",
+    "%=",
+    "true",
+    "false",
+    ">",
+    "<",
+    ">=",
+    "<=",
+    "!",
+    "&&",
+    "||",
+    "^",
+    "==",
+    "!=",
+    "{",
+    "}",
+    "if",
+    "else",
+    "elif",
+    "[",
+    "]",
+    ".",
+    "?.",
+    "?",
+    "..",
+    "..=",
+    "...",
+    "while",
+    "do",
+    "loop",
+    "'a",
+    "'a:",
+    "for",
+    "in",
+    "fn",
+    "import",
+    "as",
+    "record",
+    "struct",
+    "enum",
+    "try",
+    "catch",
+    "assert",
+    "return",
+    "break",
+    "continue",
+    "->",
+    ":",
+    "|",
+    "0",
+    "1",
+    "-1",
+    "2.5",
+    "0.000000001",
+    "0xFF",
+    "0b102",
+    "79228162514264337593543950335",
+    "\"\"",
+    "\"a\"",
+    "\"é${x}\"",
+    "\"\\u{41}\"",
+    "x",
+    "y",
+    "f",
+    "Number",
+    "String",
+    "Any",
+    "Function",
+    "print",
+    "sqrt",
+    "pow",
+    "index",
+    "slice",
+    "range",
+    "map",
+    "filter",
+    "fold",
+    "length",
+    "to_map",
+    "get",
+    "center",
+    "find",
+    "PI",
+    "// allow(unreachable)\n",
+    "/// doc\n",
+    "/*",
+    "*/",
+];
+
+const NAMES: &[&str] = &["x", "y", "f", "PI"];
+const CALLS: &[(&str, usize)] = &[
+    ("sqrt", 1),
+    ("pow", 2),
+    ("index", 2),
+    ("slice", 3),
+    ("range", 2),
+    ("length", 1),
+    ("map", 2),
+    ("filter", 2),
+    ("fold", 3),
+    ("without", 2),
+    ("with_insert", 3),
+    ("center", 3),
+    ("find", 2),
+    ("zip", 2),
+    ("to_fixed", 2),
+    ("get", 2),
+    ("first", 1),
+    ("last", 1),
+    ("sum", 1),
+    ("max", 2),
+    ("round", 1),
+    ("log", 1),
+    ("fract", 1),
+    ("string", 1),
+    ("split", 2),
+    ("repeat", 2),
+];
+const NUMBERS: &[&str] = &[
+    "0",
+    "1",
+    "-1",
+    "2",
+    "-2.5",
+    "0.5",
+    "28",
+    "100000",
+    "0.0000000000000000000000000001",
+    "79228162514264337593543950335",
+    "-79228162514264337593543950335",
+];
+const OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "~/", "%", "==", "!=", "<", ">", "<=", ">=", "&&", "||", "^",
+];
+
+/// A call to a builtin with random literal arguments of the right count.
+fn random_call(rng: &mut StdRng, name: &str, arity: usize) -> String {
+    let args = (0..arity).map(|_| expression(rng, 1)).collect::<Vec<_>>();
+    format!("{name}({});", args.join(", "))
+}
+
+fn random_tokens(rng: &mut StdRng) -> String {
+    let length = rng.gen_range(1..30);
+
+    (0..length)
+        .map(|_| *FRAGMENTS.choose(rng).unwrap())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn expression(rng: &mut StdRng, depth: usize) -> String {
+    let leaf = depth == 0 || rng.gen_bool(0.3);
+
+    if leaf {
+        return match rng.gen_range(0..5) {
+            0 | 1 => NUMBERS.choose(rng).unwrap().to_string(),
+            2 => NAMES.choose(rng).unwrap().to_string(),
+            3 => ["\"\"", "\"héllo\"", "\"a,b\"", "\"${x}\""]
+                .choose(rng)
+                .unwrap()
+                .to_string(),
+            _ => ["true", "false", "[]"].choose(rng).unwrap().to_string(),
+        };
+    }
+
+    let depth = depth - 1;
+
+    match rng.gen_range(0..10) {
+        0 | 1 => {
+            let op = *OPERATORS.choose(rng).unwrap();
+            let (a, b) = (expression(rng, depth), expression(rng, depth));
+            format!("({a} {op} {b})")
+        }
+        2 | 3 => {
+            let (name, arity) = *CALLS.choose(rng).unwrap();
+            let args = (0..arity)
+                .map(|_| expression(rng, depth))
+                .collect::<Vec<_>>();
+            format!("{name}({})", args.join(", "))
+        }
+        4 => {
+            let items = (0..rng.gen_range(0..4))
+                .map(|_| expression(rng, depth))
+                .collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        5 => {
+            let (a, i) = (expression(rng, depth), expression(rng, depth));
+            format!("{a}[{i}]")
+        }
+        6 => {
+            let a = expression(rng, depth);
+            let (start, end) = (expression(rng, depth), expression(rng, depth));
+            format!("{a}[{start}:{end}]")
+        }
+        7 => format!("{{\"k\": {}}}", expression(rng, depth)),
+        8 => format!("|x| {}", expression(rng, depth)),
+        _ => format!("-{}", expression(rng, depth)),
+    }
+}
+
+fn statement(rng: &mut StdRng, depth: usize) -> String {
+    let name = *NAMES.choose(rng).unwrap();
+
+    match rng.gen_range(0..if depth == 0 { 4 } else { 9 }) {
+        0 => format!("let {name} := {};", expression(rng, 3)),
+        1 => format!("{name} += {};", expression(rng, 3)),
+        2 => {
+            let (i, value) = (expression(rng, 3), expression(rng, 3));
+            format!("{name}[{i}] := {value};")
+        }
+        3 => format!("{};", expression(rng, 3)),
+        4 => {
+            let cond = expression(rng, 2);
+            let (a, b) = (block(rng, depth - 1), block(rng, depth - 1));
+            format!("if {cond} {{ {a} }} else {{ {b} }};")
+        }
+        5 => {
+            let array = expression(rng, 2);
+            format!("for i in {array} {{ {} }}", block(rng, depth - 1))
+        }
+        6 => {
+            let cond = expression(rng, 2);
+            if rng.gen_bool(0.5) {
+                format!("while {cond} {{ {} }}", block(rng, depth - 1))
+            } else {
+                format!("do {{ {} }} while {cond};", block(rng, depth - 1))
+            }
+        }
+        7 => format!("fn f(x: Any) -> Any {{ {} }}", block(rng, depth - 1)),
+        _ => ["return;", "break;", "break 1;", "continue;", "return 1;"]
+            .choose(rng)
+            .unwrap()
+            .to_string(),
+    }
+}
+
+fn block(rng: &mut StdRng, depth: usize) -> String {
+    (0..rng.gen_range(0..4))
+        .map(|_| statement(rng, depth))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn program(rng: &mut StdRng) -> String {
+    let mut program = block(rng, 2);
+
+    // Cut some programs short, to get unterminated strings and unclosed blocks.
+    if rng.gen_bool(0.1) && !program.is_empty() {
+        let mut end = rng.gen_range(0..program.len());
+        while !program.is_char_boundary(end) {
+            end -= 1;
+        }
+        program.truncate(end);
+    }
+
+    program
+}
+
+/// Runs `input` through the pipeline in a sandbox, returning the panic message if it panics.
+fn run(input: &str) -> Result<(), String> {
+    let mut state = ExecutionState::sandboxed();
+    state.output = OutputSink::capture();
+    state.steps_left = Some(Rc::new(Cell::new(STEP_LIMIT)));
+
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let _ = validate_input(input);
+        let _ = crate::check::check(input);
+        let _ = eval_with_state(input, &mut state);
+    }))
+    .map_err(|e| {
+        e.downcast_ref::<String>()
+            .cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_default()
+    })
+}
+
+/// Runs every input, failing with all the ones that panicked.
+fn check(inputs: impl Iterator<Item = String>) {
+    let failures = inputs
+        .filter_map(|input| run(&input).err().map(|e| format!("{input:?}\n    {e}")))
+        .collect::<Vec<_>>();
+
+    assert!(
+        failures.is_empty(),
+        "{} inputs panicked:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn random_tokens_never_panic() {
+    let mut rng = StdRng::seed_from_u64(0);
+    check((0..2000).map(|_| random_tokens(&mut rng)));
+}
+
+#[test]
+fn random_programs_never_panic() {
+    let mut rng = StdRng::seed_from_u64(0);
+    check((0..2000).map(|_| program(&mut rng)));
+}
+
+#[test]
+fn random_builtin_calls_never_panic() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut builtins = builtints()
+        .iter()
+        .map(|(name, desc)| (name.clone(), desc.inputs.len()))
+        .collect::<Vec<_>>();
+    // The table is a HashMap, sort it so the same seed gives the same calls.
+    builtins.sort();
+
+    check(builtins.into_iter().flat_map(|(name, arity)| {
+        (0..50)
+            .map(|_| random_call(&mut rng, &name, arity))
+            .collect::<Vec<_>>()
+    }));
+}
+
+#[test]
+fn corpus_never_panics() {
+    check(
+        CORPUS
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.replace("\\n", "\n")),
+    );
+}
@@ -0,0 +1,108 @@
+//! `import "path";` runs another script file and keeps what it declares: its functions, enums
+//! and records, and its top level variables and constants. Paths are relative to the file
+//! doing the importing.
+//!
+//! `import "path" as name;` keeps the file's functions apart instead, callable as `name.f()`,
+//! so they can't collide with anything already declared.
+
+use std::{collections::HashSet, fs, path::PathBuf, sync::Arc};
+
+use crate::{
+    eval::parse,
+    execute_program,
+    expr::{error::ExprError, EResult, ExecutionState, Expr},
+    functions::{Capability, FunctionDescriptor, FunctionMap},
+};
+
+/// The functions of a file imported with `as`.
+#[derive(Debug, Clone)]
+pub struct Module {
+    /// The functions the file declares, the only ones callable through the module.
+    pub exports: HashSet<String>,
+    /// Every function the file ended up with, which its functions run among so they can call
+    /// each other without the module name.
+    pub functions: Arc<FunctionMap>,
+}
+
+impl Module {
+    /// The function `name`, if the file declares it.
+    pub fn export(&self, name: &str) -> Option<FunctionDescriptor> {
+        self.exports
+            .contains(name)
+            .then(|| self.functions.get(name).cloned())
+            .flatten()
+    }
+}
+
+/// Runs the file at `path` and merges what it declares into `state`, or adds it as the module
+/// `alias`.
+pub fn import(path: &str, alias: Option<&str>, state: &mut ExecutionState) -> EResult<()> {
+    if !state.allowed.contains(&Capability::FsRead) {
+        return Err(ExprError::SandboxViolation {
+            name: "import".to_string(),
+            capability: Capability::FsRead,
+        });
+    }
+
+    let relative = match state.files.last().and_then(|file| file.parent()) {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    };
+    let io_error = |source| ExprError::IoError {
+        path: relative.display().to_string(),
+        source,
+    };
+    let file = fs::canonicalize(&relative).map_err(io_error)?;
+
+    if state.files.contains(&file) {
+        let chain = state
+            .files
+            .iter()
+            .chain([&file])
+            .map(|f| f.display().to_string())
+            .collect::<Vec<_>>();
+
+        return Err(ExprError::CircularImport {
+            chain: chain.join(" -> "),
+        });
+    }
+
+    let source = fs::read_to_string(&file).map_err(io_error)?;
+    let program = parse(&source).map_err(|e| ExprError::InvalidImport {
+        path: relative.display().to_string(),
+        reason: e.to_string(),
+    })?;
+
+    let mut imported = state.clone();
+    imported.files.push(file);
+    execute_program(&program, &mut imported)?;
+
+    match alias {
+        Some(alias) => {
+            let exports = program
+                .iter()
+                .filter_map(|e| match e {
+                    Expr::FunctionDeclaration(name, _) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect();
+
+            state.modules.insert(
+                alias.to_string(),
+                Module {
+                    exports,
+                    functions: imported.functions,
+                },
+            );
+        }
+        None => {
+            state.functions = imported.functions;
+            state.variables = imported.variables;
+            state.constants = imported.constants;
+            state.enums = imported.enums;
+            state.modules = imported.modules;
+        }
+    }
+
+    Ok(())
+}
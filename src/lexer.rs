@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, iter::Peekable, ops::Range, str::CharIndices};
 
 use ariadne::{Label, Report, ReportKind, Source};
 use logos::Logos;
@@ -23,6 +23,10 @@ pub enum Token {
     #[token("/")]
     Divide,
 
+    /// Floor division. Not `//`, which starts a comment.
+    #[token("~/")]
+    IntDivide,
+
     #[token("%")]
     Modulo,
 
@@ -41,9 +45,34 @@ pub enum Token {
     #[token("let")]
     Let,
 
+    #[token("const")]
+    Const,
+
     #[token(":=")]
     AssignTo,
 
+    /// Only gives parameters their default values, assignment is `:=`.
+    #[token("=")]
+    Assign,
+
+    #[token("+=")]
+    AddAssign,
+
+    #[token("-=")]
+    SubAssign,
+
+    #[token("*=")]
+    MulAssign,
+
+    #[token("/=")]
+    DivAssign,
+
+    #[token("~/=")]
+    IntDivAssign,
+
+    #[token("%=")]
+    ModAssign,
+
     #[token("true")]
     True,
 
@@ -90,19 +119,17 @@ pub enum Token {
             ))
         }
     }, priority = 2)]
+    #[regex(r"\d+(\.\d+)?[eE][+-]?\d+", |lex| {
+        Decimal::from_scientific(lex.slice()).map_err(|_| LexerError::new(
+            LexerErrorKind::ExponentOutOfRange(lex.slice().to_string()),
+            lex.span()
+        ))
+    }, priority = 2)]
+    #[regex(r"0[xbo][0-9a-zA-Z_]*", lex_radix_number, priority = 3)]
     Number(Decimal),
 
-    #[regex(r#""([^"\\]|\\.)*""#, |lex| {
-        let slice: &str = lex.slice();
-        if slice.len() < 2 {
-            return Err(LexerError::new(
-                LexerErrorKind::UnterminatedString,
-                lex.span()
-            ));
-        }
-        Ok(rem_first_and_last(slice.to_string()))
-    }, priority = 2)]
-    String(String),
+    #[token("\"", lex_string)]
+    String(Vec<StringPart>),
 
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| {
         let slice: &str = lex.slice();
@@ -117,6 +144,10 @@ pub enum Token {
     }, priority = 1)]
     Ident(String),
 
+    /// A loop label, `'outer`, without its quote.
+    #[regex(r"'[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice()[1..].to_string())]
+    Label(String),
+
     #[token("{")]
     BlockStart,
 
@@ -130,8 +161,14 @@ pub enum Token {
     Else,
 
     #[regex(r"//.*", |lex| {let slice: &str = lex.slice(); slice.to_string()}, priority = 1)]
+    #[token("/*", lex_block_comment)]
+    #[regex(r"#!.*", lex_shebang)]
     Comment(String),
 
+    /// A `///` line, documenting the function declared after it.
+    #[regex(r"///([^/\n][^\n]*)?", |lex| lex.slice()[3..].trim().to_string(), priority = 2)]
+    DocComment(String),
+
     #[token("[")]
     ArrayStart,
 
@@ -141,12 +178,39 @@ pub enum Token {
     #[token(".")]
     Dot,
 
+    #[token("..")]
+    DotDot,
+
+    #[token("..=")]
+    DotDotEq,
+
+    #[token("...")]
+    Ellipsis,
+
+    #[token("?.")]
+    SafeDot,
+
+    #[token("?")]
+    Question,
+
     #[token("elif")]
     ElseIf,
 
     #[token("while")]
     While,
 
+    #[token("do")]
+    Do,
+
+    #[token("loop")]
+    Loop,
+
+    #[token("import")]
+    Import,
+
+    #[token("as")]
+    As,
+
     #[token("for")]
     For,
 
@@ -156,6 +220,33 @@ pub enum Token {
     #[token("fn")]
     Fn,
 
+    #[token("record")]
+    Record,
+
+    #[token("struct")]
+    Struct,
+
+    #[token("enum")]
+    Enum,
+
+    #[token("try")]
+    Try,
+
+    #[token("catch")]
+    Catch,
+
+    #[token("assert")]
+    Assert,
+
+    #[token("return")]
+    Return,
+
+    #[token("break")]
+    Break,
+
+    #[token("continue")]
+    Continue,
+
     #[token("->")]
     Arrow,
 
@@ -166,11 +257,224 @@ pub enum Token {
     Bar,
 }
 
-fn rem_first_and_last(value: String) -> String {
-    let mut chars = value.chars();
-    chars.next();
-    chars.next_back();
-    chars.collect()
+/// A `0x`, `0b` or `0o` integer literal.
+fn lex_radix_number(lex: &mut logos::Lexer<Token>) -> Result<Decimal, LexerError> {
+    let slice = lex.slice();
+    let radix = match &slice[..2] {
+        "0x" => 16,
+        "0b" => 2,
+        _ => 8,
+    };
+
+    u128::from_str_radix(&slice[2..], radix)
+        .ok()
+        .and_then(Decimal::from_u128)
+        .ok_or_else(|| {
+            LexerError::new(LexerErrorKind::InvalidNumber(slice.to_string()), lex.span())
+        })
+}
+
+/// A piece of a string literal: plain text, or the tokens of a `${...}` placeholder.
+#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+pub enum StringPart {
+    Text(String),
+    Code(Vec<Token>),
+}
+
+/// A `/* ... */` comment, which may span lines and contain other block comments.
+fn lex_block_comment(lex: &mut logos::Lexer<Token>) -> Result<String, LexerError> {
+    let rest = lex.remainder();
+    let mut depth = 1;
+    let mut i = 0;
+
+    while i < rest.len() {
+        if rest[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if rest[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+
+            if depth == 0 {
+                lex.bump(i);
+                return Ok(lex.slice().to_string());
+            }
+        } else {
+            i += rest[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    Err(LexerError::new(
+        LexerErrorKind::UnterminatedComment,
+        lex.span(),
+    ))
+}
+
+/// A `#!/usr/bin/env ls run` line, which is only allowed as the first line of a script so it
+/// can be made executable. It is kept as a comment so the spans after it stay right.
+fn lex_shebang(lex: &mut logos::Lexer<Token>) -> Result<String, LexerError> {
+    let start = lex.span().start;
+
+    if start == 0 {
+        Ok(lex.slice().to_string())
+    } else {
+        Err(LexerError::new(
+            LexerErrorKind::UnexpectedCharacter('#'),
+            start..start + 1,
+        ))
+    }
+}
+
+/// Lexes the rest of a string literal after its opening quote, decoding escapes.
+fn lex_string(lex: &mut logos::Lexer<Token>) -> Result<Vec<StringPart>, LexerError> {
+    let rest = lex.remainder();
+    let start = lex.span().end;
+    let unterminated =
+        |end: usize| LexerError::new(LexerErrorKind::UnterminatedString, start - 1..start + end);
+    // Spans the escape from its backslash at `i` up to wherever `chars` got to.
+    let invalid_escape = |i: usize, chars: &mut Peekable<CharIndices>| {
+        let end = chars.peek().map(|(j, _)| *j).unwrap_or(rest.len());
+        LexerError::new(
+            LexerErrorKind::InvalidEscape(rest[i..end].to_string()),
+            start + i..start + end,
+        )
+    };
+
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = rest.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                lex.bump(i + 1);
+
+                if !text.is_empty() || parts.is_empty() {
+                    parts.push(StringPart::Text(text));
+                }
+                return Ok(parts);
+            }
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 't')) => '\t',
+                    Some((_, 'r')) => '\r',
+                    Some((_, '0')) => '\0',
+                    Some((_, c @ ('"' | '\\' | '$'))) => c,
+                    Some((_, 'u')) => unicode_escape(&mut chars, rest)
+                        .ok_or_else(|| invalid_escape(i, &mut chars))?,
+                    Some(_) => return Err(invalid_escape(i, &mut chars)),
+                    None => break,
+                };
+
+                text.push(escaped);
+            }
+            '$' if chars.peek().is_some_and(|(_, c)| *c == '{') => {
+                chars.next();
+
+                let code_start = i + 2;
+                let Some(code_end) = placeholder_end(&rest[code_start..]) else {
+                    return Err(LexerError::new(
+                        LexerErrorKind::UnterminatedInterpolation,
+                        start + i..start + code_start,
+                    ));
+                };
+                let code = &rest[code_start..code_start + code_end];
+
+                let tokens = Token::lexer(code)
+                    .filter(|t| {
+                        !t.as_ref()
+                            .is_ok_and(|t| t.is_comment() || t.is_doc_comment())
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|mut e| {
+                        let offset = start + code_start;
+                        e.span = e.span.start + offset..e.span.end + offset;
+                        e
+                    })?;
+
+                if !text.is_empty() {
+                    parts.push(StringPart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(StringPart::Code(tokens));
+
+                // Skip over the placeholder and its closing brace.
+                while chars
+                    .next_if(|(j, _)| *j <= code_start + code_end)
+                    .is_some()
+                {}
+            }
+            c => text.push(c),
+        }
+    }
+
+    lex.bump(rest.len());
+    Err(unterminated(rest.len()))
+}
+
+/// Decodes the `{...}` of a `\u{...}` escape, e.g. `\u{1F600}`.
+fn unicode_escape(chars: &mut Peekable<CharIndices>, rest: &str) -> Option<char> {
+    let (open, _) = chars.next_if(|(_, c)| *c == '{')?;
+    let close = open + rest[open..].find('}')?;
+    let code = u32::from_str_radix(&rest[open + 1..close], 16).ok()?;
+
+    while chars.next_if(|(j, _)| *j <= close).is_some() {}
+
+    char::from_u32(code)
+}
+
+/// Finds the `}` closing a placeholder, skipping nested braces and strings.
+fn placeholder_end(code: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in code.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Escapes text so it reads back as the same string literal contents.
+pub fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\n' => "\\n".to_string(),
+            '\t' => "\\t".to_string(),
+            '\r' => "\\r".to_string(),
+            '\0' => "\\0".to_string(),
+            '"' | '\\' | '$' => format!("\\{c}"),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Writes string parts back out as they would appear in source.
+pub fn format_string_parts(parts: &[StringPart]) -> String {
+    let mut s = String::from("\"");
+
+    for part in parts {
+        match part {
+            StringPart::Text(text) => s.push_str(&escape(text)),
+            StringPart::Code(tokens) => {
+                let code = tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+                s.push_str(&format!("${{{}}}", code.join(" ")));
+            }
+        }
+    }
+
+    s.push('"');
+    s
 }
 
 impl Display for Token {
@@ -183,14 +487,23 @@ impl Display for Token {
                 Self::Minus => "-".into(),
                 Self::Multiply => "*".into(),
                 Self::Divide => "/".into(),
+                Self::IntDivide => "~/".into(),
                 Self::Modulo => "%".into(),
 
                 Self::Number(n) => n.display(),
-                Self::String(s) => s,
+                Self::String(parts) => format_string_parts(&parts),
                 Self::Ident(s) => s.clone(),
+                Self::Label(s) => format!("'{s}"),
 
                 Self::Let => "let ".into(),
+                Self::Const => "const ".into(),
                 Self::AssignTo => ":=".into(),
+                Self::AddAssign => "+=".into(),
+                Self::SubAssign => "-=".into(),
+                Self::MulAssign => "*=".into(),
+                Self::DivAssign => "/=".into(),
+                Self::IntDivAssign => "~/=".into(),
+                Self::ModAssign => "%=".into(),
 
                 Self::True => "true".into(),
                 Self::False => "false".into(),
@@ -215,8 +528,14 @@ impl Display for Token {
                 Self::RParen => ")".into(),
                 Self::Comma => ",".into(),
                 Self::Dot => ".".into(),
+                Self::DotDot => "..".into(),
+                Self::DotDotEq => "..=".into(),
+                Self::Ellipsis => "...".into(),
+                Self::SafeDot => "?.".into(),
+                Self::Question => "?".into(),
                 Self::Arrow => "->".into(),
                 Self::Colon => ":".into(),
+                Self::Assign => "=".into(),
                 Self::Eol => ";".into(),
 
                 Self::If => "if".into(),
@@ -224,11 +543,25 @@ impl Display for Token {
                 Self::ElseIf => "elif".into(),
                 Self::For => "for".into(),
                 Self::While => "while".into(),
+                Self::Do => "do".into(),
+                Self::Loop => "loop".into(),
+                Self::Import => "import".into(),
+                Self::As => "as".into(),
                 Self::In => "in".into(),
                 Self::Fn => "fn".into(),
+                Self::Record => "record".into(),
+                Self::Struct => "struct".into(),
+                Self::Enum => "enum".into(),
+                Self::Try => "try".into(),
+                Self::Catch => "catch".into(),
+                Self::Assert => "assert".into(),
+                Self::Return => "return".into(),
+                Self::Break => "break".into(),
+                Self::Continue => "continue".into(),
                 Self::Bar => "|".into(),
 
                 Self::Comment(s) => s,
+                Self::DocComment(s) => format!("/// {s}"),
             },
         )
     }
@@ -239,12 +572,16 @@ pub enum LexerErrorKind {
     InvalidNumber(String),
     InvalidIdentifier(String),
     UnterminatedString,
+    UnterminatedComment,
+    UnterminatedInterpolation,
+    InvalidEscape(String),
     UnexpectedCharacter(char),
     InvalidOperator(String),
     UnmatchedDelimiter(char),
-    InvalidFunction(String),
+    UnclosedDelimiter(char),
     UnknownToken(String),
     ParseDecimalError(String),
+    ExponentOutOfRange(String),
     Other(String),
 }
 
@@ -259,19 +596,35 @@ impl LexerError {
         Self { kind, span }
     }
 
-    pub fn print(&self, input: &str) {
-        let message = match &self.kind {
+    pub fn message(&self) -> String {
+        match &self.kind {
             LexerErrorKind::InvalidNumber(n) => format!("Invalid number: {}", n),
             LexerErrorKind::InvalidIdentifier(id) => format!("Invalid identifier: {}", id),
             LexerErrorKind::UnterminatedString => "Unterminated string literal".to_string(),
+            LexerErrorKind::UnterminatedComment => {
+                "This `/*` is never closed with a `*/`".to_string()
+            }
+            LexerErrorKind::InvalidEscape(e) => format!("Invalid escape sequence: {}", e),
+            LexerErrorKind::UnterminatedInterpolation => {
+                "This `${` is never closed with a `}`".to_string()
+            }
             LexerErrorKind::UnexpectedCharacter(c) => format!("Unexpected character: {}", c),
             LexerErrorKind::InvalidOperator(op) => format!("Invalid operator: {}", op),
             LexerErrorKind::UnmatchedDelimiter(d) => format!("Unmatched delimiter: {}", d),
-            LexerErrorKind::InvalidFunction(f) => format!("Invalid function call: {}", f),
+            LexerErrorKind::UnclosedDelimiter(d) => {
+                format!("this '{}' opened here is never closed", d)
+            }
             LexerErrorKind::UnknownToken(t) => format!("Unknown token: {}", t),
             LexerErrorKind::ParseDecimalError(e) => format!("Failed to parse decimal: {}", e),
+            LexerErrorKind::ExponentOutOfRange(n) => {
+                format!("`{n}` is too large or too precise to be a number")
+            }
             LexerErrorKind::Other(msg) => msg.clone(),
-        };
+        }
+    }
+
+    pub fn print(&self, input: &str) {
+        let message = self.message();
 
         let report = Report::build(ReportKind::Error, self.span.clone())
             .with_message(&message)
@@ -312,81 +665,186 @@ impl Default for LexerError {
 //     }
 // }
 //
-// Function to find function calls in the input
-fn detect_function_calls(input: &str) -> Result<(), LexerError> {
-    let function_pattern = regex::Regex::new(r"\b([a-zA-Z_][a-zA-Z0-9_]*)\s*\(").unwrap();
-    let valid_functions = ["sqrt", "rand", "rand_between", "max", "print", "join"];
-
-    for capture in function_pattern.captures_iter(input) {
-        let function_name = capture.get(1).unwrap().as_str();
-        if !valid_functions.contains(&function_name) {
-            return Err(LexerError::new(
-                LexerErrorKind::InvalidFunction(function_name.to_string()),
-                capture.get(1).unwrap().range(),
-            ));
-        }
-    }
-    Ok(())
-}
 
-// Helper function to validate the input before lexing
+/// Checks that every `(`, `{` and `[` is closed by its matching delimiter. An opener that is
+/// never closed is reported at the opener rather than at the end of the input, which is where
+/// the parser would otherwise complain. Other lexer errors are left for the lexer to report.
 pub fn validate_input(input: &str) -> Result<(), LexerError> {
-    // Check for function calls
-    detect_function_calls(input)?;
+    let mut stack: Vec<(char, std::ops::Range<usize>)> = Vec::new();
+
+    for (token, span) in Token::lexer(input).spanned() {
+        let c = match token {
+            Ok(Token::LParen) => '(',
+            Ok(Token::BlockStart) => '{',
+            Ok(Token::ArrayStart) => '[',
+            Ok(Token::RParen) => ')',
+            Ok(Token::BlockEnd) => '}',
+            Ok(Token::ArrayEnd) => ']',
+            _ => continue,
+        };
 
-    // Check for balanced delimiters
-    let mut stack = Vec::new();
-    for (i, c) in input.chars().enumerate() {
         match c {
-            '(' | '{' | '[' => stack.push((c, i)),
-            ')' | '}' | ']' => {
-                if let Some((open, _)) = stack.pop() {
-                    let is_match = match (open, c) {
-                        ('(', ')') | ('{', '}') | ('[', ']') => true,
-                        _ => false,
-                    };
-                    if !is_match {
+            '(' | '{' | '[' => stack.push((c, span)),
+            _ => {
+                let opener = match c {
+                    ')' => '(',
+                    '}' => '{',
+                    _ => '[',
+                };
+
+                match stack.pop() {
+                    Some((open, _)) if open == opener => {}
+                    // The closer belongs to something further out, so the innermost opener is
+                    // the one that was left open.
+                    Some((open, open_span)) if stack.iter().any(|(o, _)| *o == opener) => {
                         return Err(LexerError::new(
-                            LexerErrorKind::UnmatchedDelimiter(c),
-                            i..i + 1,
+                            LexerErrorKind::UnclosedDelimiter(open),
+                            open_span,
                         ));
                     }
-                } else {
-                    return Err(LexerError::new(
-                        LexerErrorKind::UnmatchedDelimiter(c),
-                        i..i + 1,
-                    ));
+                    _ => return Err(LexerError::new(LexerErrorKind::UnmatchedDelimiter(c), span)),
                 }
             }
-            _ => {}
         }
     }
 
-    if let Some((c, i)) = stack.pop() {
-        return Err(LexerError::new(
-            LexerErrorKind::UnmatchedDelimiter(c),
-            i..i + 1,
-        ));
+    if let Some((c, span)) = stack.pop() {
+        return Err(LexerError::new(LexerErrorKind::UnclosedDelimiter(c), span));
     }
 
     Ok(())
 }
 
-// Example usage in your main lexing function
-pub fn lex(input: &str) -> Result<Vec<Token>, LexerError> {
-    // Validate input first
-    validate_input(input)?;
+/// Lexes `input` into what the parser reads: comments are dropped and the line breaks that end
+/// a statement become `;`, see `end_lines`.
+pub fn tokens(input: &str) -> Result<Vec<Token>, LexerError> {
+    let tokens = Token::lexer(input)
+        .spanned()
+        .map(|(t, span)| t.map(|t| (t, span)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(end_lines(input, strip_comments(tokens))
+        .into_iter()
+        .map(|(t, _)| t)
+        .collect())
+}
+
+/// Drops comments, except `///` ones directly before a function declaration, which the
+/// parser keeps as its documentation.
+pub fn strip_comments(tokens: Vec<(Token, Range<usize>)>) -> Vec<(Token, Range<usize>)> {
+    let mut kept = Vec::with_capacity(tokens.len());
 
-    // Then proceed with lexing
-    let mut tokens = Vec::new();
-    let mut lexer = Token::lexer(input);
+    for (token, span) in tokens.into_iter().rev() {
+        let documents = matches!(kept.last(), Some((Token::Fn | Token::DocComment(_), _)));
 
-    while let Some(token_result) = lexer.next() {
-        match token_result {
-            Ok(token) => tokens.push(token),
-            Err(error) => return Err(error),
+        match token {
+            Token::Comment(_) => {}
+            Token::DocComment(_) if !documents => {}
+            token => kept.push((token, span)),
         }
     }
 
-    Ok(tokens)
+    kept.reverse();
+    kept
+}
+
+/// Makes `;` optional at the end of a line by adding one wherever a line ends in something a
+/// statement can end with and the next line doesn't carry the expression on, by starting with
+/// an operator, `.`, `else` or a closing bracket. Line breaks inside parentheses and brackets
+/// never end a statement, so calls and arrays can span lines. The added `;` has an empty span
+/// at the end of the line.
+pub fn end_lines(input: &str, tokens: Vec<(Token, Range<usize>)>) -> Vec<(Token, Range<usize>)> {
+    let mut ended = Vec::with_capacity(tokens.len());
+    // Opening brackets not closed yet, innermost last.
+    let mut open = vec![];
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some((token, span)) = tokens.next() {
+        match token {
+            Token::LParen | Token::ArrayStart | Token::BlockStart => open.push(token.clone()),
+            Token::RParen | Token::ArrayEnd | Token::BlockEnd => {
+                open.pop();
+            }
+            _ => {}
+        }
+
+        let end = span.end;
+        let can_end = can_end_statement(&token);
+        ended.push((token, span));
+
+        let Some((next, next_span)) = tokens.peek() else {
+            continue;
+        };
+        if can_end
+            && matches!(open.last(), None | Some(Token::BlockStart))
+            && input[end..next_span.start].contains('\n')
+            && !continues_line(next)
+        {
+            ended.push((Token::Eol, end..end));
+        }
+    }
+
+    ended
+}
+
+fn can_end_statement(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Number(_)
+            | Token::String(_)
+            | Token::Ident(_)
+            | Token::Label(_)
+            | Token::True
+            | Token::False
+            | Token::RParen
+            | Token::ArrayEnd
+            | Token::BlockEnd
+            | Token::Return
+            | Token::Break
+            | Token::Continue
+    )
+}
+
+/// Whether a line starting with `token` carries on the line before.
+fn continues_line(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Plus
+            | Token::Minus
+            | Token::Multiply
+            | Token::Divide
+            | Token::IntDivide
+            | Token::Modulo
+            | Token::GreaterThan
+            | Token::LessThan
+            | Token::GreaterEqual
+            | Token::LessEqual
+            | Token::And
+            | Token::Or
+            | Token::Xor
+            | Token::Equals
+            | Token::NotEquals
+            | Token::AssignTo
+            | Token::AddAssign
+            | Token::SubAssign
+            | Token::MulAssign
+            | Token::DivAssign
+            | Token::IntDivAssign
+            | Token::ModAssign
+            | Token::Dot
+            | Token::DotDot
+            | Token::DotDotEq
+            | Token::SafeDot
+            | Token::Question
+            | Token::Colon
+            | Token::Arrow
+            | Token::Comma
+            | Token::In
+            | Token::Else
+            | Token::ElseIf
+            | Token::RParen
+            | Token::ArrayEnd
+            | Token::BlockEnd
+            | Token::Eol
+    )
 }
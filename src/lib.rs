@@ -0,0 +1,97 @@
+//! The calculator language as a library: lexing, parsing, checking and running scripts, for
+//! code embedding the interpreter. The `calculator` binary is a command line front end to it.
+
+use std::sync::Arc;
+
+use data::Data;
+use expr::{error::ExprError, EResult, ExecutionState, Expr};
+use functions::builtints;
+use warning::{Warning, WarningKind};
+
+pub mod analysis;
+pub mod check;
+pub mod constants;
+pub mod data;
+pub mod eval;
+pub mod expr;
+pub mod functions;
+pub mod import;
+pub mod lexer;
+// rip
+// mod number;
+pub mod output;
+pub mod parser;
+pub mod patterns;
+pub mod repl;
+pub mod session;
+pub mod utils;
+pub mod warning;
+
+/// Runs `block` in a scope of its own, returning the value of its last statement.
+pub fn execute_block(block: &[Expr], state: &mut ExecutionState) -> EResult<Data> {
+    state.scoped([], |state| execute_statements(block, state))
+}
+
+/// Runs `block` in the innermost scope of `state`, for callers that have pushed the scope
+/// already, with the loop variable or parameters in it.
+fn execute_statements(block: &[Expr], state: &mut ExecutionState) -> EResult<Data> {
+    let mut output = Data::Null;
+
+    // Enums are declared up front like functions, so signatures can name them from anywhere
+    // in the block.
+    for e in block {
+        if let Expr::EnumDeclaration(name, variants) = e {
+            Arc::make_mut(&mut state.enums).insert(name.clone(), variants.clone());
+        }
+    }
+
+    for e in block {
+        if let Expr::FunctionDeclaration(name, desc) = e {
+            match desc.function.clone() {
+                functions::FunctionType::Custom(block, ..) => {
+                    let dt = block.last().unwrap_or(&Expr::Null).data_type(state);
+
+                    if !desc.output.admits(&dt, |name| state.is_record(name)) {
+                        return Err(ExprError::OutputTypeMismatch {
+                            name: name.clone(),
+                            expected: desc.output.clone(),
+                            found: dt,
+                        });
+                    }
+                }
+                _ => unreachable!(),
+            }
+
+            if builtints().contains_key(name) {
+                state.warnings.warn(Warning {
+                    kind: WarningKind::BuiltinShadowing,
+                    message: format!("Function `{name}` shadows the builtin of the same name."),
+                    span: None,
+                })?;
+            }
+
+            Arc::make_mut(&mut state.functions).insert(name.clone(), desc.clone());
+        }
+    }
+
+    for e in block {
+        state.check_interrupted()?;
+
+        if state.trace {
+            state.output.diagnostic(&format!("[trace] {e}\n"));
+        }
+
+        output = e.eval(state)?;
+    }
+
+    Ok(output)
+}
+
+/// Runs a whole script or calc expression, where a top level `return` ends execution with its
+/// value. What it declares is kept in `state`.
+pub fn execute_program(block: &[Expr], state: &mut ExecutionState) -> EResult<Data> {
+    match execute_statements(block, state) {
+        Err(ExprError::Return(output)) => Ok(*output),
+        result => result.map_err(ExprError::outside_loop),
+    }
+}
@@ -1,109 +1,227 @@
-use std::{env, fs, process::exit};
+use std::{
+    env, fs, io,
+    path::Path,
+    process::exit,
+    sync::{atomic::Ordering, Arc},
+};
 
+#[cfg(test)]
+use calculator::eval::eval_with_state;
+use calculator::{
+    check::check,
+    constants::enabled_features,
+    data::Data,
+    eval::{eval_all, EvalError},
+    execute_program,
+    expr::{error::ExprError, ExecutionState, Expr},
+    functions::{args_descriptor, seed_rng},
+    lexer::{end_lines, strip_comments, validate_input, Token},
+    parser::{parser, print_parser_error},
+    repl::repl,
+    utils::strings::{indent, DotDebug},
+    warning::{WarningPolicy, WarningSink},
+};
+// The tests, including the test modules below, reach the library through `crate::` like the
+// rest of the tree.
+#[cfg(test)]
+use calculator::{data::DataType, expr::EResult, functions::builtints, warning::WarningKind, *};
 use chumsky::Parser;
-use data::{Data, DataType};
-use expr::{format_block, ExecutionState, Expr};
-use lexer::Token;
+use cli::{parse_args, Command, RunOptions};
 use logos::Logos;
-use parser::{parser, print_parser_error};
-use utils::strings::{indent, DotDebug};
-
-mod data;
-mod expr;
-mod functions;
-mod lexer;
-// rip
-// mod number;
-mod constants;
-mod parser;
-mod utils;
+
+mod cli;
+#[cfg(test)]
+mod examples;
+#[cfg(test)]
+mod fuzz;
+#[cfg(test)]
+mod scoping;
 
 const HELP: &str = r#"Command line calculator.
 
+Usage:
+    calculator <command> [flags] <input> [-- script arguments]
+
 Commands:
-    calc: evaluate the second arguement (string)
+    calc: evaluate the second arguement (string), or every `--expr/-e` in order
     run: run script from file path (string)
+        (`import "other.ls";` runs another script, relative to the one importing it)
+        (a first line starting with `#!` is skipped, so scripts can be made executable)
+    check: report unreachable code in a script (string) without running it
+    repl: evaluate one line at a time, keeping variables and functions between lines
+        (results are kept in `out`, so `out[0]` is the first one,
+        `:save path` and `:load path` save and load the variables and functions)
+    version, --version: print the version and enabled features
+
+Flags (must come before the input):
+    --quiet, -q: don't print the AST and execution banner
+    --trace: print each statement to stderr before evaluating it
+    --sandbox: disable builtins that touch files, stdin, processes or the network
+    --allow <capabilities>: only allow these (comma separated) capabilities:
+        fs-read, fs-write, process, network, stdin
+    --seed <number>: seed the random number generator
+    --deny-warnings: treat warnings as errors
+    --no-warnings: don't report warnings
+    --expr, -e <expression>: (calc) evaluate another expression in the same state
+    --print-all: (calc) print every non-null result instead of just the last
+
+Everything after `--` is passed to the script, available through `args()`.
+`help()` lists every function, `help("name")` shows how to call one; `///` comments before
+a `fn` describe it.
+
+Examples:
+    >>> (1 + 7) * 3 / 4;
+    6
+    >>> let r := 3;
+    ... PI * r * r;
+    28.274333882308139146163790450
+    >>> "hello world".snake_case().uppercase();
+    HELLO_WORLD
+    >>> [3, 1, 2].sort().reverse();
+    [3, 2, 1]
 "#;
 
 fn main() {
-    match env::args()
-        .nth(1)
-        .unwrap_or_else(|| {
-            println!("{}", HELP);
-            exit(1)
-        })
-        .as_str()
-    {
-        "calc" => {
-            run(&env::args().nth(2).unwrap_or_else(|| {
-                println!("Expected expression as second arguement (e.g. `1 + 7 * (3 - 4) / 5`)");
-                exit(1);
-            }));
+    let (command, options) = parse_args(env::args().skip(1)).unwrap_or_else(|e| {
+        println!("{e}\n");
+        println!("{}", HELP);
+        exit(1)
+    });
+
+    match command {
+        Command::Calc {
+            expressions,
+            print_all,
+        } => {
+            let mut state = exec_state(&options);
+            handle_interrupts(&state);
+            let results = eval_all(&expressions, &mut state);
+
+            for warning in state.warnings.take() {
+                state.output.diagnostic(&warning.render("<expression>", ""));
+            }
+
+            match results {
+                Ok(results) => {
+                    let shown = if print_all {
+                        &results[..]
+                    } else {
+                        &results[results.len() - 1..]
+                    };
+
+                    for result in shown.iter().filter(|r| **r != Data::Null) {
+                        println!("{}", state.show(result));
+                    }
+                }
+                Err((_, e @ EvalError::Runtime(ExprError::Exit(_)))) => exit(exit_code(&e)),
+                Err((i, e)) => {
+                    println!("Error in expression {} (`{}`): {e}", i + 1, expressions[i]);
+                    exit(exit_code(&e));
+                }
+            }
         }
-        "run" => {
-            let path = env::args().nth(2).unwrap_or_else(|| {
-                println!("Expected file path as second argument.");
+        Command::Run(path) => {
+            let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+                println!("Could not read file: {e}");
                 exit(1)
             });
-            let text = fs::read_to_string(path).unwrap_or_else(|e| {
+
+            run(&text, Path::new(&path), &options);
+        }
+        Command::Check(path) => {
+            let text = fs::read_to_string(&path).unwrap_or_else(|e| {
                 println!("Could not read file: {e}");
                 exit(1)
             });
 
-            run(&text);
+            let warnings = match options.warnings {
+                WarningPolicy::Ignore => vec![],
+                _ => check(&text),
+            };
+            for warning in &warnings {
+                warning.print(&path, &text);
+            }
+
+            println!("{} warning(s)", warnings.len());
+            if options.warnings == WarningPolicy::Deny && !warnings.is_empty() {
+                exit(1);
+            }
         }
-        _ => {
-            println!("Invalid command.\n");
+        Command::Repl => {
+            let mut state = exec_state(&options);
+            handle_interrupts(&state);
 
+            match repl(io::stdin().lock(), &mut io::stdout(), &mut state) {
+                Ok(code) => exit(code),
+                Err(e) => {
+                    println!("{e}");
+                    exit(1);
+                }
+            }
+        }
+        Command::Help => {
             println!("{}", HELP);
         }
+        Command::Version => {
+            let features = enabled_features();
+
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            println!(
+                "features: {}",
+                if features.is_empty() {
+                    "none".to_string()
+                } else {
+                    features.join(", ")
+                }
+            );
+        }
     }
 }
 
-pub fn execute_block(block: &[Expr], state: &ExecutionState) -> (Data, ExecutionState) {
-    let mut inner_state = state.clone();
-    let mut output = Data::Null;
+/// Makes Ctrl-C stop whatever `state` is running instead of killing the process.
+fn handle_interrupts(state: &ExecutionState) {
+    let interrupted = state.interrupted.clone();
 
-    for e in block {
-        if let Expr::FunctionDeclaration(name, desc) = e {
-            match desc.function.clone() {
-                functions::FunctionType::Custom(block, _) => {
-                    let dt = block.last().unwrap_or(&Expr::Null).data_type(state);
+    if let Err(e) = ctrlc::set_handler(move || interrupted.store(true, Ordering::Relaxed)) {
+        eprintln!("Could not handle Ctrl-C: {e}");
+    }
+}
 
-                    if dt != desc.output && dt != DataType::Any && desc.output != DataType::Any {
-                        println!("Function `{name}` output type does not match block data type. If you don't know what the output will be, you can use the Any type.");
-                        exit(1);
-                    }
-                }
-                _ => unreachable!(),
-            }
-            inner_state.functions.insert(name.clone(), desc.clone());
-        }
+/// Builds the state a script or calc expression runs in from the command line options.
+fn exec_state(options: &RunOptions) -> ExecutionState {
+    if let Some(seed) = options.seed {
+        seed_rng(seed);
     }
 
-    for e in block {
-        match e.eval(&mut inner_state) {
-            Ok(result) => {
-                // println!("{}", e);
-                output = result;
-            }
-            Err(e) => {
-                println!("{}", e);
-                exit(3);
-            }
-        }
+    let mut state = ExecutionState::new();
+    state.trace = options.trace;
+    state.warnings = WarningSink::new(options.warnings);
+    if let Some(allowed) = &options.allowed {
+        state.allowed = allowed.clone();
     }
+    Arc::make_mut(&mut state.functions)
+        .insert("args".to_string(), args_descriptor(&options.script_args));
 
-    (output, inner_state)
+    state
 }
 
-fn run(input: &str) -> (Vec<Token>, Vec<Expr>, Data) {
+/// The process exit code for a failed evaluation: the one passed to `exit`, 130 when stopped
+/// by Ctrl-C, like shells use, 3 for other runtime errors.
+fn exit_code(e: &EvalError) -> i32 {
+    match e {
+        EvalError::Runtime(ExprError::Exit(code)) => *code,
+        EvalError::Runtime(ExprError::Interrupted) => 130,
+        _ => 3,
+    }
+}
+
+fn run(input: &str, path: &Path, options: &RunOptions) -> (Vec<Token>, Vec<Expr>, Data) {
     let lexer = Token::lexer(input);
 
     let mut tokens = vec![];
-    for (token, _span) in lexer.spanned() {
+    for (token, span) in lexer.spanned() {
         match token {
-            Ok(token) => tokens.push(token),
+            Ok(token) => tokens.push((token, span)),
             Err(e) => {
                 e.print(input);
                 exit(1);
@@ -111,15 +229,34 @@ fn run(input: &str) -> (Vec<Token>, Vec<Expr>, Data) {
         }
     }
 
-    let tokens = tokens
-        .iter()
-        .filter(|t| !t.is_comment())
-        .cloned()
+    if let Err(e) = validate_input(input) {
+        e.print(input);
+        exit(1);
+    }
+
+    let file_name = path.display().to_string();
+    let mut exec_state = exec_state(options);
+    // Imports are relative to the script.
+    exec_state.files.extend(fs::canonicalize(path));
+    handle_interrupts(&exec_state);
+    for warning in check(input) {
+        if let Err(e) = exec_state.warnings.warn(warning.clone()) {
+            warning.print(&file_name, input);
+            println!("{e}");
+            exit(1);
+        }
+    }
+
+    let tokens = end_lines(input, strip_comments(tokens))
+        .into_iter()
+        .map(|(t, _)| t)
         .collect::<Vec<_>>();
 
     let expressions = match parser().parse(tokens.clone()) {
         Ok(expr) => {
-            println!("[AST]\n{}", indent(&expr.debug()));
+            if !options.quiet {
+                println!("[AST]\n{}", indent(&expr.debug()));
+            }
             expr
         }
         Err(errs) => {
@@ -132,28 +269,73 @@ fn run(input: &str) -> (Vec<Token>, Vec<Expr>, Data) {
 
     // println!("{}", format_block(&expressions));
 
-    println!("\n---Execution---\n");
+    if !options.quiet {
+        println!("\n---Execution---\n");
+    }
+
+    let output = execute_program(&expressions, &mut exec_state);
 
-    let exec_state = ExecutionState::new();
-    let output = execute_block(&expressions, &exec_state);
+    for warning in exec_state.warnings.take() {
+        exec_state
+            .output
+            .diagnostic(&warning.render(&file_name, input));
+    }
+
+    let output = output.unwrap_or_else(|e| {
+        if !matches!(e, ExprError::Exit(_)) {
+            println!("{}", e);
+        }
+        exit(exit_code(&EvalError::Runtime(e)));
+    });
 
-    (tokens, expressions, output.0)
+    (tokens, expressions, output)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::*;
+    use functions::{Capability, FunctionDescriptor, FunctionType};
     use rust_decimal::prelude::*;
     use rust_decimal_macros::dec;
 
     fn test_num(input: &str, expected: Decimal) {
-        let (_, _, output) = run(input);
+        let (_, _, output) = run(input, Path::new(""), &RunOptions::default());
         assert_eq!(output, Data::Number(expected))
     }
 
+    /// Evaluates a script, returning the first error instead of exiting.
+    fn eval(input: &str) -> EResult<Data> {
+        eval_with(input, ExecutionState::new())
+    }
+
+    fn eval_with(input: &str, mut state: ExecutionState) -> EResult<Data> {
+        execute_program(&parse(input), &mut state)
+    }
+
+    fn parse(input: &str) -> Vec<Expr> {
+        let tokens = lexer::tokens(input).unwrap();
+
+        parser().parse(tokens).unwrap()
+    }
+
+    fn assert_condition_error(input: &str, construct: &str) {
+        match eval(input) {
+            Err(ExprError::InvalidDataType {
+                expected,
+                found,
+                loc,
+            }) => {
+                assert_eq!(expected, "Bool");
+                assert_eq!(found, "Number");
+                assert_eq!(loc, construct);
+            }
+            other => panic!("expected a condition error from {construct}, got {other:?}"),
+        }
+    }
+
     #[test]
     fn lex_num() {
-        let (tokens, _, _) = run("5.5;");
+        let (tokens, _, _) = run("5.5;", Path::new(""), &RunOptions::default());
 
         assert_eq!(
             tokens,
@@ -165,4 +347,3778 @@ mod tests {
     fn test_order() {
         test_num("2-5*2+7;", dec!(-1));
     }
+
+    #[test]
+    fn test_sub_and_mod_order() {
+        test_num("10 - 4;", dec!(6));
+        test_num("10 % 4;", dec!(2));
+        test_num("sub(10, 4);", dec!(6));
+        test_num("div(10, 4);", dec!(2.5));
+    }
+
+    #[test]
+    fn test_conditions_must_be_bool() {
+        assert_condition_error("if 1 { 2; };", "if condition");
+        assert_condition_error("if false { 2; } elif 1 { 3; };", "elif condition");
+        assert_condition_error("while 1 { 2; }", "while condition");
+        assert_condition_error("do { 2; } while 1;", "do-while condition");
+        assert_condition_error("[1, 2].filter(|x: Number| { x; });", "filter predicate");
+        assert_condition_error(
+            "[1, 2].take_while(|x: Number| { x; });",
+            "take_while predicate",
+        );
+        assert_condition_error("[1, 2].any(|x: Number| { x; });", "any predicate");
+        assert_condition_error("[1, 2].all(|x: Number| { x; });", "all predicate");
+    }
+
+    #[test]
+    fn test_bool_predicates() {
+        assert_eq!(
+            eval("[1, 5, 2, 7].filter(|x: Number| { x > 1; });").unwrap(),
+            Data::Array(vec![
+                Data::Number(dec!(5)),
+                Data::Number(dec!(2)),
+                Data::Number(dec!(7))
+            ])
+        );
+        assert_eq!(
+            eval("[1, 5, 2, 7].take_while(|x: Number| { x < 6; });").unwrap(),
+            Data::Array(vec![
+                Data::Number(dec!(1)),
+                Data::Number(dec!(5)),
+                Data::Number(dec!(2))
+            ])
+        );
+        assert_eq!(
+            eval("[1, 5].any(|x: Number| { x > 4; });").unwrap(),
+            Data::Bool(true)
+        );
+        assert_eq!(
+            eval("[1, 5].all(|x: Number| { x > 4; });").unwrap(),
+            Data::Bool(false)
+        );
+    }
+
+    fn numbers(n: &[i64]) -> Data {
+        Data::Array(n.iter().map(|n| Data::Number(Decimal::from(*n))).collect())
+    }
+
+    #[test]
+    fn test_rotate() {
+        assert_eq!(
+            eval("rotate_left([1, 2, 3], 0);").unwrap(),
+            numbers(&[1, 2, 3])
+        );
+        assert_eq!(
+            eval("rotate_left([1, 2, 3], 3);").unwrap(),
+            numbers(&[1, 2, 3])
+        );
+        assert_eq!(
+            eval("rotate_left([1, 2, 3], 4);").unwrap(),
+            numbers(&[2, 3, 1])
+        );
+        assert_eq!(
+            eval("rotate_right([1, 2, 3], 4);").unwrap(),
+            numbers(&[3, 1, 2])
+        );
+        assert_eq!(
+            eval("rotate_left([1, 2, 3], -1);").unwrap(),
+            numbers(&[3, 1, 2])
+        );
+        assert_eq!(eval("rotate_right([], 2);").unwrap(), numbers(&[]));
+    }
+
+    #[test]
+    fn test_swap_and_pairwise() {
+        assert_eq!(eval("swap([1, 2, 3], 0, 2);").unwrap(), numbers(&[3, 2, 1]));
+        assert!(matches!(
+            eval("swap([1, 2, 3], 0, 3);"),
+            Err(ExprError::IndexOutOfBounds { length: 3, .. })
+        ));
+        assert!(matches!(
+            eval("swap([1, 2, 3], -1, 0);"),
+            Err(ExprError::IndexOutOfBounds { .. })
+        ));
+        assert_eq!(
+            eval("pairwise([1, 2, 3]);").unwrap(),
+            Data::Array(vec![numbers(&[1, 2]), numbers(&[2, 3])])
+        );
+    }
+
+    #[test]
+    fn test_scan() {
+        assert_eq!(
+            eval("cumsum([1, 2, 3, 4, 5]);").unwrap(),
+            numbers(&[1, 3, 6, 10, 15])
+        );
+        assert_eq!(
+            eval("[3, 1, 4, 1, 5].scan(0, |acc: Number, n: Number| { acc.max(n); });").unwrap(),
+            numbers(&[3, 3, 4, 4, 5])
+        );
+        assert_eq!(
+            eval("[].scan(0, |acc: Number, n: Number| { acc + n; });").unwrap(),
+            numbers(&[])
+        );
+        assert!(matches!(
+            eval("[1, 2].scan(0, |acc: Number, n: Number| { acc / 0; });"),
+            Err(ExprError::DivideBy0)
+        ));
+    }
+
+    #[test]
+    fn test_map_aggregates() {
+        let prices = r#"let prices := to_map([["apple", 2], ["pear", 3], ["plum", 5]]);"#;
+
+        assert_eq!(
+            eval(&format!("{prices} prices.length();")).unwrap(),
+            Data::Number(dec!(3))
+        );
+        assert_eq!(
+            eval(&format!("{prices} prices.values().sum();")).unwrap(),
+            Data::Number(dec!(10))
+        );
+        assert_eq!(
+            eval(&format!(r#"{prices} prices.contains("pear");"#)).unwrap(),
+            Data::Bool(true)
+        );
+        assert_eq!(
+            eval(&format!(r#"{prices} prices.contains("kiwi");"#)).unwrap(),
+            Data::Bool(false)
+        );
+        assert_eq!(
+            eval(&format!(
+                "{prices} prices.map_values(|p: Number| {{ p * 2; }}).values().max_array();"
+            ))
+            .unwrap(),
+            Data::Number(dec!(10))
+        );
+        assert_eq!(
+            eval(&format!(
+                "{prices} prices.map_entries(|k: String, p: Number| {{ [k.uppercase(), p + 1]; }}).keys();"
+            ))
+            .unwrap(),
+            Data::Array(vec![
+                Data::String("APPLE".to_string()),
+                Data::String("PEAR".to_string()),
+                Data::String("PLUM".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_get_and_set_path() {
+        let users = r#"
+            let user := to_map([["name", "ana"], ["tags", ["a", "b"]]]);
+            let data := to_map([["users", [user]]]);
+        "#;
+        let get = |path: &str| eval(&format!("{users} data.get_path({path});")).unwrap();
+
+        assert_eq!(get(r#"["users", 0, "name"]"#), Data::String("ana".into()));
+        assert_eq!(get(r#"["users", 0, "tags", 1]"#), Data::String("b".into()));
+        assert_eq!(get(r#"["people", 0, "name"]"#), Data::Null);
+        assert_eq!(get(r#"["users", 4, "name"]"#), Data::Null);
+        assert_eq!(get(r#"["users", 0, "age"]"#), Data::Null);
+        assert_eq!(get(r#"["users", "0", "name"]"#), Data::Null);
+
+        assert_eq!(
+            eval(&format!(
+                r#"{users} data.set_path(["users", 0, "name"], "bo").get_path(["users", 0, "name"]);"#
+            ))
+            .unwrap(),
+            Data::String("bo".into())
+        );
+        assert_eq!(
+            eval(r#"to_map([]).set_path(["a", "b", "c"], 1).get_path(["a", "b", "c"]);"#).unwrap(),
+            Data::Number(dec!(1))
+        );
+        assert!(matches!(
+            eval(r#"to_map([["a", [1]]]).set_path(["a", 3], 1);"#),
+            Err(ExprError::IndexOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_number_formatting() {
+        let string = |input: &str| eval(input).unwrap().to_string();
+
+        assert_eq!(string("to_fixed(2.5, 3);"), "2.500");
+        assert_eq!(string("format_thousands(1234567.25);"), "1,234,567.25");
+        assert_eq!(string("format_thousands(-999);"), "-999");
+        assert_eq!(string(r#"format_currency(1234.5, "$", 2);"#), "$1,234.50");
+        assert_eq!(string(r#"format_currency(2.345, "$", 2);"#), "$2.35");
+        assert_eq!(string(r#"format_currency(2.3449, "$", 2);"#), "$2.34");
+        assert_eq!(string(r#"format_currency(-2.345, "$", 2);"#), "-$2.35");
+        assert_eq!(string(r#"format_currency(-12, "$", 2);"#), "-$12.00");
+        assert_eq!(string(r#"format_currency(-0.001, "$", 2);"#), "$0.00");
+        assert_eq!(
+            string(r#"format_currency_styled(-12, "$", 2, "parens");"#),
+            "($12.00)"
+        );
+        assert_eq!(
+            string(r#"format_currency_styled(1500, " €", 0, "suffix");"#),
+            "1,500 €"
+        );
+        assert!(matches!(
+            eval(r#"format_currency(1, "$", 1.5);"#),
+            Err(ExprError::InvalidDataType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_lenient_number_parsing() {
+        let cases = [
+            (r#"parse_number_lenient("1,234.5");"#, Some(dec!(1234.5))),
+            (r#"parse_number_lenient("$99.99");"#, Some(dec!(99.99))),
+            (
+                r#"parse_number_lenient("-$1,000,000");"#,
+                Some(dec!(-1000000)),
+            ),
+            (r#"parse_number_lenient("€ -3");"#, Some(dec!(-3))),
+            (r#"parse_number_lenient("3 kg");"#, Some(dec!(3))),
+            (r#"parse_number_lenient("  42 ");"#, Some(dec!(42))),
+            (r#"parse_number_lenient("50%");"#, Some(dec!(50))),
+            (
+                r#"parse_number_lenient_locale("1.234,5", "eu");"#,
+                Some(dec!(1234.5)),
+            ),
+            (
+                r#"parse_number_lenient_locale("12,5 km/h", "eu");"#,
+                Some(dec!(12.5)),
+            ),
+            (r#"parse_number_lenient("1,5");"#, None),
+            (r#"parse_number_lenient("12,34,567");"#, None),
+            (r#"parse_number_lenient("1 234");"#, None),
+            (r#"parse_number_lenient("kg");"#, None),
+            (r#"parse_number_lenient("$");"#, None),
+            (r#"parse_number_lenient("1.2.3");"#, None),
+            (r#"parse_number_lenient("3 kg2");"#, None),
+            (r#"parse_number_lenient_locale("1", "fr");"#, None),
+        ];
+
+        for (input, expected) in cases {
+            match expected {
+                Some(n) => test_num(input, n),
+                None => assert!(eval(input).is_err(), "{input} should fail"),
+            }
+        }
+
+        assert_eq!(
+            eval(r#"parse_number_with_unit(" 3.5kg ");"#).unwrap(),
+            Data::Array(vec![Data::Number(dec!(3.5)), Data::String("kg".into())])
+        );
+        assert_eq!(
+            eval(r#"parse_number_with_unit("7");"#).unwrap(),
+            Data::Array(vec![Data::Number(dec!(7)), Data::String("".into())])
+        );
+    }
+
+    fn args(args: &[&str]) -> Result<(Command, RunOptions), cli::ArgError> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_flags_before_path() {
+        let (command, options) = args(&["run", "--quiet", "--seed", "5", "script.lils"]).unwrap();
+
+        assert_eq!(command, Command::Run("script.lils".to_string()));
+        assert!(options.quiet);
+        assert!(!options.trace);
+        assert_eq!(options.seed, Some(5));
+        assert!(options.script_args.is_empty());
+
+        assert!(args(&["run", "script.lils", "--seed", "5"]).is_err());
+    }
+
+    #[test]
+    fn test_script_args_passthrough() {
+        let (command, options) =
+            args(&["calc", "--trace", "args();", "--", "--seed", "5", "--"]).unwrap();
+
+        assert_eq!(
+            command,
+            Command::Calc {
+                expressions: vec!["args();".to_string()],
+                print_all: false
+            }
+        );
+        assert!(options.trace);
+        assert_eq!(options.seed, None);
+        assert_eq!(options.script_args, vec!["--seed", "5", "--"]);
+
+        let (_, _, output) = run("args();", Path::new(""), &options);
+        assert_eq!(
+            output,
+            Data::Array(vec![
+                Data::String("--seed".into()),
+                Data::String("5".into()),
+                Data::String("--".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unknown_flag() {
+        let err = args(&["run", "--fast", "script.lils"]).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Unknown flag `--fast`. Valid options are: --quiet, --trace, --sandbox, --allow <capabilities>, --seed <number>, --deny-warnings, --no-warnings, --expr/-e <expression> (calc), --print-all (calc)."
+        );
+    }
+
+    #[test]
+    fn test_version_introspection() {
+        assert_eq!(
+            eval(r#"version().get("version");"#).unwrap(),
+            Data::String(env!("CARGO_PKG_VERSION").to_string())
+        );
+        assert_eq!(
+            eval(r#"has_feature("wasm");"#).unwrap(),
+            Data::Bool(cfg!(feature = "wasm"))
+        );
+        assert_eq!(
+            eval(r#"has_feature("teleportation");"#).unwrap(),
+            Data::Bool(false)
+        );
+        assert_eq!(args(&["--version"]).unwrap().0, Command::Version);
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let sandboxed = |input: &str| eval_with(input, ExecutionState::sandboxed());
+
+        assert!(matches!(
+            sandboxed(r#"read_file("x");"#),
+            Err(ExprError::SandboxViolation { name, capability: Capability::FsRead }) if name == "read_file"
+        ));
+        assert!(matches!(
+            sandboxed(r#"fn f() -> Null { write_file("x", "y"); } f();"#),
+            Err(ExprError::SandboxViolation { name, .. }) if name == "write_file"
+        ));
+        assert!(matches!(
+            sandboxed(r#"["x"].map(|p: String| { read_file(p); });"#),
+            Err(ExprError::SandboxViolation { name, .. }) if name == "read_file"
+        ));
+        assert_eq!(sandboxed("1 + 2 * 3;").unwrap(), Data::Number(dec!(7)));
+        assert_eq!(
+            args(&["run", "--sandbox", "x.lils"]).unwrap().1.allowed,
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_capability_allow_sets() {
+        let readonly = ExecutionState::with_capabilities(&[Capability::FsRead]);
+        let file = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+        assert!(eval_with(&format!(r#"read_file("{file}");"#), readonly.clone()).is_ok());
+        assert!(matches!(
+            eval_with(r#"write_file("x", "y");"#, readonly.clone()),
+            Err(ExprError::SandboxViolation {
+                capability: Capability::FsWrite,
+                ..
+            })
+        ));
+        assert!(matches!(
+            eval_with("input();", readonly),
+            Err(ExprError::SandboxViolation {
+                capability: Capability::Stdin,
+                ..
+            })
+        ));
+
+        let (_, options) = args(&["run", "--allow", "fs-read,network", "x.lils"]).unwrap();
+        assert_eq!(
+            options.allowed,
+            Some(vec![Capability::FsRead, Capability::Network])
+        );
+        assert!(args(&["run", "--allow", "time-travel", "x.lils"]).is_err());
+    }
+
+    #[test]
+    fn test_pure_functions_need_no_capabilities() {
+        let io = [
+            "input",
+            "read_file",
+            "write_file",
+            "save_session",
+            "load_session",
+            "exit",
+        ];
+
+        for (name, descriptor) in builtints().iter() {
+            assert_eq!(
+                descriptor.capabilities.is_empty(),
+                !io.contains(&name.as_str()),
+                "unexpected capabilities for `{name}`"
+            );
+        }
+    }
+
+    #[test]
+    fn test_calc_expressions() {
+        let (command, options) = args(&[
+            "calc",
+            "--print-all",
+            "-e",
+            "let x := 4;",
+            "--expr",
+            "x * 2;",
+            "x + 1;",
+        ])
+        .unwrap();
+
+        let Command::Calc {
+            expressions,
+            print_all,
+        } = command
+        else {
+            panic!("expected calc, got {command:?}");
+        };
+        assert!(print_all);
+
+        let results = eval_all(&expressions, &mut exec_state(&options)).unwrap();
+        assert_eq!(
+            results,
+            vec![Data::Null, Data::Number(dec!(8)), Data::Number(dec!(5))]
+        );
+
+        let expressions = ["let x := 4;", "y * 2;", "x;"].map(String::from);
+        let (i, e) = eval_all(&expressions, &mut ExecutionState::new()).unwrap_err();
+        assert_eq!(i, 1);
+        assert!(matches!(
+            e,
+            eval::EvalError::Runtime(ExprError::VariableNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_function_display() {
+        let custom = |inputs: Vec<DataType>, names: &[&str]| {
+            Data::Function(FunctionDescriptor {
+                inputs,
+                inputs_rest: None,
+                param_names: &[],
+                function: FunctionType::Custom(
+                    vec![],
+                    names.iter().map(|n| n.to_string()).collect(),
+                    Box::new([]),
+                    Arc::default(),
+                ),
+                output: DataType::Number,
+                capabilities: &[],
+                doc: None,
+            })
+        };
+
+        assert_eq!(custom(vec![], &[]).to_string(), "fn() -> Number");
+        assert_eq!(
+            custom(vec![DataType::Number], &["a"]).to_string(),
+            "fn(a: Number) -> Number"
+        );
+        assert_eq!(
+            custom(vec![DataType::Number, DataType::Number], &["a", "b"]).to_string(),
+            "fn(a: Number, b: Number) -> Number"
+        );
+
+        assert_eq!(
+            Data::Function(builtints()["rand"].clone()).to_string(),
+            "fn() -> Number"
+        );
+        assert_eq!(
+            Data::Function(builtints()["sqrt"].clone()).to_string(),
+            "fn(n: Number) -> Number"
+        );
+        assert_eq!(
+            Data::Function(builtints()["add"].clone()).to_string(),
+            "fn(a: Number, b: Number) -> Number"
+        );
+
+        assert_eq!(
+            eval("string(|a: Number, b: Number| -> Number { a + b; });").unwrap(),
+            Data::String("fn(a: Number, b: Number) -> Number".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_types() {
+        assert_eq!(data::format_types(vec![]), "()");
+        assert_eq!(data::format_types(vec![DataType::Number]), "(Number)");
+        assert_eq!(
+            data::format_types(vec![DataType::Number, DataType::String, DataType::Any]),
+            "(Number, String, Any)"
+        );
+    }
+
+    #[test]
+    fn test_wrong_arity_is_an_error() {
+        assert_eq!(
+            eval("rand(5);").unwrap_err().to_string(),
+            ExprError::InvalidFunctionArguements {
+                expected: "()".to_string(),
+                found: "(Number)".to_string(),
+            }
+            .to_string()
+        );
+        assert!(matches!(
+            eval("sqrt(4, 5);"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+    }
+
+    #[test]
+    fn test_trailing_expression() {
+        assert_eq!(
+            parse("fn double(x: Number) -> Number { x * 2 }"),
+            parse("fn double(x: Number) -> Number { x * 2; }")
+        );
+        assert_eq!(parse("1 + 2"), parse("1 + 2;"));
+        assert_eq!(
+            parse("fn f(x: Number) -> Number { if x > 0 { x } else { -x } }"),
+            parse("fn f(x: Number) -> Number { if x > 0 { x; } else { -x; }; }")
+        );
+
+        let with = "fn double(x: Number) -> Number { x * 2 } double(4);";
+        let without = "fn double(x: Number) -> Number { x * 2; } double(4);";
+        assert_eq!(eval(with).unwrap(), Data::Number(dec!(8)));
+        assert_eq!(eval(with).unwrap(), eval(without).unwrap());
+
+        assert_eq!(
+            parse("fn f() -> Any { for x in [1, 2] { x } while false { 1 } }"),
+            parse("fn f() -> Any { for x in [1, 2] { x; } while false { 1; } }")
+        );
+
+        // The output type is checked against the last statement either way.
+        for source in [
+            "fn f() -> Number { \"a\" } f();",
+            "fn f() -> Number { \"a\"; } f();",
+        ] {
+            assert!(
+                matches!(eval(source), Err(ExprError::OutputTypeMismatch { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_tail_expressions() {
+        let state = ExecutionState::new();
+        // The value and type of the last statement of a block, however it ends.
+        let tail = |source: &str| {
+            let program = parse(source);
+            let value = eval(source).unwrap();
+            (value, program.last().unwrap().data_type(&state))
+        };
+
+        for source in ["{ let y := 9; sqrt(y) }", "{ let y := 9; sqrt(y); }"] {
+            assert_eq!(
+                tail(source),
+                (Data::Number(dec!(3)), DataType::Number),
+                "{source}"
+            );
+        }
+        for source in [
+            r#"if 1 > 2 { "a" } else { "b" }"#,
+            r#"if 1 > 2 { "a"; } else { "b"; }"#,
+        ] {
+            assert_eq!(
+                tail(source),
+                (Data::String("b".into()), DataType::String),
+                "{source}"
+            );
+        }
+        for source in [
+            "fn f() -> Number { let a := 4; sqrt(a) }",
+            "fn f() -> Number { let a := 4; sqrt(a); }",
+        ] {
+            let program = parse(source);
+            let Expr::FunctionDeclaration(_, desc) = &program[0] else {
+                panic!("{source} declares a function");
+            };
+            let FunctionType::Custom(body, ..) = &desc.function else {
+                panic!("{source} declares a custom function");
+            };
+            assert_eq!(body.last().unwrap().data_type(&state), DataType::Number);
+            test_num(&format!("{source} f()"), dec!(2));
+        }
+    }
+
+    #[test]
+    fn test_unclosed_delimiters() {
+        use lexer::{LexerError, LexerErrorKind};
+
+        let unclosed_brace = "fn a() -> Number {\n    1;\n\nfn b() -> Number { 2; }\nb();\n";
+        assert_eq!(
+            validate_input(unclosed_brace),
+            Err(LexerError::new(
+                LexerErrorKind::UnclosedDelimiter('{'),
+                17..18
+            ))
+        );
+
+        let unclosed_bracket = "fn f() -> Array {\n    [1, 2;\n}\nf();";
+        assert_eq!(
+            validate_input(unclosed_bracket),
+            Err(LexerError::new(
+                LexerErrorKind::UnclosedDelimiter('['),
+                22..23
+            ))
+        );
+
+        assert_eq!(
+            validate_input("1 + 2);"),
+            Err(LexerError::new(
+                LexerErrorKind::UnmatchedDelimiter(')'),
+                5..6
+            ))
+        );
+        assert_eq!(validate_input(r#"print("(", [1, {2;}]);"#), Ok(()));
+    }
+
+    #[test]
+    fn test_repl() {
+        let (command, options) = args(&["repl", "--sandbox"]).unwrap();
+        assert_eq!(command, Command::Repl);
+
+        let input = "let x := 5;\nx * 2;\n\nnope;\nx + 1;\n";
+        let mut output = vec![];
+        repl(input.as_bytes(), &mut output, &mut exec_state(&options)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "[0]> [0]> out[0] = 10\n[1]> [1]> Error: Variable nope does not exist. \
+             Has it been declared?\n[1]> out[1] = 6\n[2]> \n"
+        );
+    }
+
+    #[test]
+    fn test_null_safe_calls() {
+        let config = r#"let config := to_map([["server", to_map([["port", 8080]])]]);"#;
+        let with_config = |code: &str| eval(&format!("{config} {code}"));
+
+        assert_eq!(
+            with_config(r#"config?.get("server")?.get("port");"#).unwrap(),
+            Data::Number(dec!(8080))
+        );
+        assert_eq!(
+            with_config(r#"config?.get("client")?.get("port");"#).unwrap(),
+            Data::Null
+        );
+        assert!(matches!(
+            with_config(r#"config.get("client").get("port");"#),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+
+        assert_eq!(
+            with_config(r#"config.get("server")?.get("port").string();"#).unwrap(),
+            Data::String("8080".to_string())
+        );
+        assert_eq!(
+            with_config(r#"config.get("client")?.get("port")?.string();"#).unwrap(),
+            Data::Null
+        );
+        assert!(matches!(
+            with_config(r#"config.get("client")?.get("port").get("x");"#),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+
+        assert_eq!(
+            with_config(r#"get_or(config, "client", "none");"#).unwrap(),
+            Data::String("none".to_string())
+        );
+        assert_eq!(
+            eval("get_or([1, 2], 1, 0);").unwrap(),
+            Data::Number(dec!(2))
+        );
+    }
+
+    #[test]
+    fn test_string_comparisons() {
+        assert_eq!(eval(r#""apple" < "banana";"#).unwrap(), Data::Bool(true));
+        assert_eq!(eval(r#""apple" >= "banana";"#).unwrap(), Data::Bool(false));
+        assert_eq!(eval(r#""b" > "B";"#).unwrap(), Data::Bool(true));
+        assert_eq!(eval(r#""ab" <= "ab";"#).unwrap(), Data::Bool(true));
+
+        assert_eq!(eval(r#"lt("apple", "banana");"#).unwrap(), Data::Bool(true));
+        assert_eq!(
+            eval(r#"gt("apple", "apples");"#).unwrap(),
+            Data::Bool(false)
+        );
+        assert_eq!(eval("ge(2, 10);").unwrap(), Data::Bool(false));
+
+        let err = eval(r#"1 < "2";"#).unwrap_err().to_string();
+        assert!(err.contains("(Number, String)"), "{err}");
+        assert!(matches!(
+            eval(r#"le("a", 1);"#),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+    }
+
+    #[test]
+    fn test_character_classes() {
+        assert_eq!(
+            eval(r#"first_non_numeric("123a5");"#).unwrap(),
+            Data::Number(dec!(3))
+        );
+        assert_eq!(
+            eval(r#"first_non_numeric("12345");"#).unwrap(),
+            Data::Number(dec!(-1))
+        );
+        assert_eq!(
+            eval(r#"first_non_alphabetic("héllo!");"#).unwrap(),
+            Data::Number(dec!(5))
+        );
+        assert_eq!(
+            eval(r#""ab_cd".first_not_matching(|c: String| { c != "_"; });"#).unwrap(),
+            Data::Number(dec!(2))
+        );
+        assert_eq!(
+            eval(r#""".first_not_matching(|c: String| { false; });"#).unwrap(),
+            Data::Number(dec!(-1))
+        );
+
+        // Empty strings have no characters of any class.
+        for predicate in [
+            "is_numeric",
+            "is_alphabetic",
+            "is_alphanumeric",
+            "is_whitespace",
+        ] {
+            assert_eq!(
+                eval(&format!(r#"{predicate}("");"#)).unwrap(),
+                Data::Bool(false),
+                "{predicate}"
+            );
+        }
+        assert_eq!(eval(r#"is_numeric("42");"#).unwrap(), Data::Bool(true));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_eq!(
+            eval("let i := 10; i += 5; i -= 3; i *= 2; i /= 4; i %= 4; i;").unwrap(),
+            Data::Number(dec!(2))
+        );
+        assert_eq!(
+            eval(r#"let s := "a"; s := "b"; s;"#).unwrap(),
+            Data::String("b".to_string())
+        );
+        assert_eq!(
+            parse("i += 1;"),
+            vec![Expr::Assign(
+                "i".to_string(),
+                Box::new(Expr::Add(
+                    Box::new(Expr::Variable("i".to_string())),
+                    Box::new(Expr::Num(dec!(1)))
+                ))
+            )]
+        );
+        assert!(matches!(
+            eval("i += 1;"),
+            Err(ExprError::UndeclaredAssignment { name }) if name == "i"
+        ));
+        assert!(matches!(
+            eval("i := 1;"),
+            Err(ExprError::UndeclaredAssignment { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unreachable_warnings() {
+        let kinds = |input: &str| check(input).into_iter().map(|w| w.kind).collect::<Vec<_>>();
+
+        let after_exit = "print(1);\nexit(0);\nprint(2);\nprint(3);\n";
+        let warnings = check(after_exit);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::Unreachable);
+        assert_eq!(
+            &after_exit[warnings[0].span.clone().unwrap()],
+            "print(2);\nprint(3);"
+        );
+
+        assert_eq!(
+            kinds("fn f(x: Number) -> Number { if x > 0 { exit(1); } else { exit(2); }; x }"),
+            vec![WarningKind::Unreachable]
+        );
+        assert_eq!(
+            kinds("fn f(x: Number) -> Number { if x > 0 { exit(1); }; x }"),
+            vec![]
+        );
+
+        let constant = "if false { 1; };\nwhile false { 2; }\nif true { 3; } else { 4; };\n";
+        let warnings = check(constant);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| &constant[w.span.clone().unwrap()])
+                .collect::<Vec<_>>(),
+            vec!["if false", "while false", "if true"]
+        );
+        assert!(warnings
+            .iter()
+            .all(|w| w.kind == WarningKind::ConstantCondition));
+        assert_eq!(kinds("while true { exit(0); }\nif true { 1; };"), vec![]);
+
+        assert_eq!(
+            kinds(
+                "exit(0);\nprint(2); // allow(unreachable)\nif false { 1; }; // allow(unreachable)"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            kinds("exit(0);\nprint(2); // allow(something_else)"),
+            vec![WarningKind::Unreachable]
+        );
+    }
+
+    #[test]
+    fn test_return() {
+        let sign = "fn sign_of(x: Number) -> String {
+            if x < 0 { return \"negative\"; };
+            if x == 0 { return \"zero\"; };
+            \"positive\"
+        }";
+        for (x, expected) in [("-3", "negative"), ("0", "zero"), ("2", "positive")] {
+            assert_eq!(
+                eval(&format!("{sign} sign_of({x});")).unwrap(),
+                Data::String(expected.to_string())
+            );
+        }
+
+        assert_eq!(
+            eval("fn first_big(xs: Array) -> Any { for x in xs { if x > 10 { return x; }; } null } first_big([1, 20, 30]);").unwrap(),
+            Data::Number(dec!(20))
+        );
+        assert_eq!(
+            eval("fn nothing() -> Null { return; print(1); } nothing();").unwrap(),
+            Data::Null
+        );
+        assert_eq!(
+            eval("[1, 2].map(|x: Number| -> Number { return x * 10; });").unwrap(),
+            numbers(&[10, 20])
+        );
+
+        assert_eq!(eval("1; return 2; 3;").unwrap(), Data::Number(dec!(2)));
+        assert_eq!(
+            check::check("fn f() -> Number { return 1; 2; }")[0].kind,
+            WarningKind::Unreachable
+        );
+    }
+
+    #[test]
+    fn test_io_error_kinds() {
+        use std::{error::Error, io::ErrorKind};
+
+        let dir = env::temp_dir().join("ls-io-error-kinds");
+        fs::create_dir_all(&dir).unwrap();
+        let missing = dir.join("missing.txt");
+
+        let read = |path: &std::path::Path| {
+            eval_with_state(
+                &format!("read_file({:?});", path.display().to_string()),
+                &mut ExecutionState::new(),
+            )
+            .unwrap_err()
+        };
+
+        assert_eq!(read(&missing).io_kind(), Some(ErrorKind::NotFound));
+        let directory = read(&dir).io_kind();
+        assert!(directory.is_some() && directory != Some(ErrorKind::NotFound));
+
+        let err = eval(r#"matches("a", "(");"#).unwrap_err();
+        assert!(err.regex_error().is_some());
+        assert!(err.source().is_some());
+        assert_eq!(err.to_string(), "Failed to parse regex: (");
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        // Without `break` this would never finish.
+        assert_eq!(
+            eval("let i := 0; while true { i += 1; if i == 5 { break; }; }").unwrap(),
+            Data::Null
+        );
+        assert_eq!(
+            eval(
+                "fn count() -> Number { let i := 0; while true { i += 1; if i == 5 { return i; }; } 0 } count();"
+            )
+            .unwrap(),
+            Data::Number(dec!(5))
+        );
+        assert_eq!(
+            eval(
+                "fn first_odd_after_3(xs: Array) -> Any { for x in xs { if x <= 3 { continue; }; if x % 2 == 1 { return x; }; } null } first_odd_after_3([1, 2, 3, 4, 5]);"
+            )
+            .unwrap(),
+            Data::Number(dec!(5))
+        );
+        assert_eq!(
+            eval("fn f(xs: Array) -> Any { for x in xs { if x > 1 { break; }; return x; } 0 } f([5, 1]);")
+                .unwrap(),
+            Data::Number(dec!(0))
+        );
+
+        assert!(matches!(
+            eval("break;"),
+            Err(ExprError::LoopControlOutsideLoop { keyword }) if keyword == "break"
+        ));
+        // The message doesn't assume a function, a script's top level has none.
+        for keyword in ["break", "continue"] {
+            assert_eq!(
+                eval(&format!("let x := 1;\n{keyword};"))
+                    .unwrap_err()
+                    .to_string(),
+                format!("`{keyword}` used outside of a loop.")
+            );
+        }
+        assert!(matches!(
+            eval("fn skip() -> Null { continue; } for x in [1] { skip(); }"),
+            Err(ExprError::LoopControlOutsideLoop { keyword }) if keyword == "continue"
+        ));
+    }
+
+    #[test]
+    fn test_warning_policies() {
+        let script = "exit(0);\nprint(1);\nif false { 2; };\nfn max(x: Number) -> Number { x }";
+
+        // The static pass finds the unreachable code and the constant condition.
+        let warnings = check(script);
+        assert_eq!(
+            warnings.iter().map(|w| w.kind).collect::<Vec<_>>(),
+            vec![WarningKind::Unreachable, WarningKind::ConstantCondition]
+        );
+        for warning in &warnings {
+            let rendered = warning.render("script.lils", script);
+            assert!(rendered.contains(&warning.message));
+            assert!(rendered.contains("script.lils:"), "{rendered}");
+            assert!(!rendered.contains("<unknown>"));
+        }
+
+        // Shadowing a builtin is found while hoisting.
+        let shadowing = "fn max(x: Number) -> Number { x } max(1);";
+        let (_, options) = args(&["run", "x.lils"]).unwrap();
+        let state = exec_state(&options);
+        eval_with(shadowing, state.clone()).unwrap();
+        let warnings = state.warnings.take();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::BuiltinShadowing);
+        assert!(warnings[0]
+            .render("x.lils", "")
+            .starts_with("Warning: Function `max`"));
+
+        let (_, options) = args(&["run", "--deny-warnings", "x.lils"]).unwrap();
+        assert!(matches!(
+            eval_with(shadowing, exec_state(&options)),
+            Err(ExprError::DeniedWarning { .. })
+        ));
+
+        let (_, options) = args(&["run", "--no-warnings", "x.lils"]).unwrap();
+        let state = exec_state(&options);
+        assert_eq!(
+            eval_with(shadowing, state.clone()).unwrap(),
+            Data::Number(dec!(1))
+        );
+        assert!(state.warnings.take().is_empty());
+
+        let mut output = vec![];
+        repl(
+            shadowing.as_bytes(),
+            &mut output,
+            &mut ExecutionState::new(),
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("shadows the builtin"), "{output}");
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        assert_eq!(
+            eval(r#"let x := 4; "x is ${x * 2}!";"#).unwrap(),
+            Data::String("x is 8!".to_string())
+        );
+        assert_eq!(
+            eval(r#""${"a, b".uppercase()} and ${"nested ${1 + 1}"}";"#).unwrap(),
+            Data::String("A, B and nested 2".to_string())
+        );
+        assert_eq!(
+            eval(r#""costs \${5}";"#).unwrap(),
+            Data::String("costs ${5}".to_string())
+        );
+        assert_eq!(eval(r#""";"#).unwrap(), Data::String(String::new()));
+        assert_eq!(parse(r#""a ${b} c";"#)[0].to_string(), r#""a ${b} c""#);
+
+        let unterminated = r#"print("total: ${1 + 2");"#;
+        let err = Token::lexer(unterminated)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            lexer::LexerError::new(lexer::LexerErrorKind::UnterminatedInterpolation, 14..16)
+        );
+        assert_eq!(&unterminated[14..16], "${");
+    }
+
+    #[test]
+    fn test_pow_edge_cases() {
+        assert_eq!(eval("pow(0, 0);").unwrap(), Data::Number(dec!(1)));
+        assert_eq!(eval("pow(2, 10);").unwrap(), Data::Number(dec!(1024)));
+        assert_eq!(eval("pow(2, -2);").unwrap(), Data::Number(dec!(0.25)));
+        assert_eq!(eval("pow(1.1, 2);").unwrap(), Data::Number(dec!(1.21)));
+        assert_eq!(eval("pow(-3, 3);").unwrap(), Data::Number(dec!(-27)));
+        assert!(matches!(eval("pow(0, -1);"), Err(ExprError::DivideBy0)));
+        assert!(matches!(
+            eval("pow(10, 100);"),
+            Err(ExprError::MathDomain { .. })
+        ));
+
+        assert_eq!(eval("pow(-8, 1 / 3);").unwrap(), Data::Number(dec!(-2)));
+        assert_eq!(eval("pow(4, 0.5);").unwrap(), Data::Number(dec!(2)));
+        assert!(matches!(
+            eval("pow(-4, 0.5);"),
+            Err(ExprError::MathDomain { .. })
+        ));
+
+        assert_eq!(eval("2.5.pow(2);").unwrap(), Data::Number(dec!(6.25)));
+        assert_eq!(eval("2.pow(-1);").unwrap(), Data::Number(dec!(0.5)));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(
+            eval(r#""a\nb\t\"c\" \\n \u{1F600}";"#).unwrap(),
+            Data::String("a\nb\t\"c\" \\n \u{1F600}".to_string())
+        );
+        assert_eq!(
+            parse(r#""line\n\"quoted\" \\ \$";"#)[0].to_string(),
+            r#""line\n\"quoted\" \\ \$""#
+        );
+
+        let bad = r#"let s := "ok \q here";"#;
+        let err = Token::lexer(bad)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            lexer::LexerError::new(
+                lexer::LexerErrorKind::InvalidEscape(r"\q".to_string()),
+                13..15
+            )
+        );
+        assert_eq!(&bad[13..15], r"\q");
+
+        assert!(Token::lexer(r#""\u{110000}";"#)
+            .collect::<Result<Vec<_>, _>>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_elif_chains_across_lines() {
+        let source = "fn describe(x: Number) -> String {
+    if x > 100 {
+        \"huge\";
+    }
+    elif x > 10 {
+        \"big\";
+    }
+    elif x > 0 {
+        if x == 1 { \"one\"; }
+        else { \"small\"; };
+    }
+    elif x == 0 {
+        \"zero\";
+    }
+    else {
+        \"negative\";
+    };
+}
+describe(1);";
+
+        let exprs = parse(source);
+        assert_eq!(eval(source).unwrap(), Data::String("one".to_string()));
+
+        let formatted = exprs
+            .iter()
+            .map(expr::format_statement)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(parse(&formatted), exprs);
+        assert!(
+            formatted.contains("    }\n    elif (x > 10) {\n        \"big\";\n    }\n"),
+            "{formatted}"
+        );
+        assert!(formatted.contains("    }\n    else {\n"), "{formatted}");
+    }
+
+    #[test]
+    fn test_bracket_indexing() {
+        assert_eq!(parse("a[1];"), parse("index(a, 1);"));
+        assert_eq!(parse("m[1][2];"), parse("index(index(m, 1), 2);"));
+        assert_eq!(parse("a[0].sqrt();"), parse("sqrt(index(a, 0));"));
+        assert_eq!(parse("-a[i + 1];"), parse("-index(a, i + 1);"));
+
+        test_num("[4, 9, 16][1].sqrt();", dec!(3));
+        test_num("[[1, 2], [3, 4]][1][0];", dec!(3));
+        test_num("let m := [[1, 2], [3, 4]]; m[0][1] + m[1][1];", dec!(6));
+        test_num(
+            "range(1, 5).map(|x: Number| -> Number { x * x })[2];",
+            dec!(9),
+        );
+        test_num("let a := [1, 2, 3]; a[a[0]];", dec!(2));
+    }
+
+    #[test]
+    fn test_print_ordering() {
+        let printed = |input: &str| {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            eval_with_state(input, &mut state).unwrap();
+            state.output.captured()
+        };
+
+        assert_eq!(
+            printed(r#"print("a"); println("b"); print(1 + 1); println(""); print("end");"#),
+            "ab\n2\nend"
+        );
+        assert_eq!(printed(r#"print("no newline");"#), "no newline");
+        assert_eq!(
+            printed(
+                r#"fn shout(s: String) { print(s); println("!"); }
+                for i in range(0, 3) { print(i); shout("x"); }"#
+            ),
+            "0x!\n1x!\n2x!\n"
+        );
+        assert_eq!(
+            printed(r#"[1, 2].for_each(|x: Number| { print(x); print(","); }); println("");"#),
+            "1,2,\n"
+        );
+    }
+
+    #[test]
+    fn test_slicing() {
+        assert_eq!(parse("a[1:3];"), parse("slice(a, 1, 3);"));
+        assert_eq!(
+            parse("a[:2];"),
+            vec![Expr::Function(
+                "slice".to_string(),
+                vec![
+                    Expr::Variable("a".to_string()),
+                    Expr::Null,
+                    Expr::Num(dec!(2))
+                ]
+            )]
+        );
+
+        assert_eq!(eval("[0, 1, 2, 3][1:3];").unwrap(), numbers(&[1, 2]));
+        assert_eq!(eval("[0, 1, 2, 3][:2];").unwrap(), numbers(&[0, 1]));
+        assert_eq!(eval("[0, 1, 2, 3][2:];").unwrap(), numbers(&[2, 3]));
+        assert_eq!(eval("[0, 1, 2, 3][:];").unwrap(), numbers(&[0, 1, 2, 3]));
+        assert_eq!(eval("[0, 1, 2, 3][2:100];").unwrap(), numbers(&[2, 3]));
+        assert_eq!(eval("[0, 1, 2, 3][3:1];").unwrap(), numbers(&[]));
+        assert_eq!(eval("[0, 1, 2, 3][-5:1];").unwrap(), numbers(&[0]));
+        test_num("[[0, 1, 2], [3]][0][1:][0];", dec!(1));
+
+        assert_eq!(
+            eval(r#""héllo world"[1:4];"#).unwrap(),
+            Data::String("éll".to_string())
+        );
+        assert_eq!(
+            eval(r#""hello"[3:];"#).unwrap(),
+            Data::String("lo".to_string())
+        );
+        assert_eq!(
+            eval(r#""hello"[10:];"#).unwrap(),
+            Data::String(String::new())
+        );
+        assert!(eval("5[1:2];").is_err());
+    }
+
+    #[test]
+    fn test_negative_indices() {
+        test_num("[1, 2, 3].index(-1);", dec!(3));
+        test_num("[1, 2, 3][-3];", dec!(1));
+        assert_eq!(eval("[1, 2, 3].without(-1);").unwrap(), numbers(&[1, 2]));
+        assert_eq!(eval("[1, 2, 3].without(0);").unwrap(), numbers(&[2, 3]));
+        assert_eq!(
+            eval("[1, 2, 3].with_insert(-1, 9);").unwrap(),
+            numbers(&[1, 2, 9, 3])
+        );
+        assert_eq!(
+            eval("[1, 2, 3].with_insert(3, 9);").unwrap(),
+            numbers(&[1, 2, 3, 9])
+        );
+        assert_eq!(eval("[1, 2, 3][-2:];").unwrap(), numbers(&[2, 3]));
+        assert_eq!(eval("[1, 2, 3][:-1];").unwrap(), numbers(&[1, 2]));
+
+        for input in [
+            "[1, 2, 3][-4];",
+            "[1, 2, 3][3];",
+            "[].index(-1);",
+            "[1, 2, 3].without(-4);",
+            "[1, 2, 3].with_insert(-4, 0);",
+            "[1, 2, 3].with_insert(5, 0);",
+        ] {
+            assert!(
+                matches!(eval(input), Err(ExprError::IndexOutOfBounds { .. })),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_numeric_predicates() {
+        let b = |input: &str| eval(input).unwrap() == Data::Bool(true);
+
+        // (input, is_integer, is_zero, is_positive, is_negative, fract, int_part)
+        let cases = [
+            ("2", true, false, true, false, dec!(0), dec!(2)),
+            ("-2", true, false, false, true, dec!(0), dec!(-2)),
+            ("2.5", false, false, true, false, dec!(0.5), dec!(2)),
+            ("-2.5", false, false, false, true, dec!(-0.5), dec!(-2)),
+            ("0.25", false, false, true, false, dec!(0.25), dec!(0)),
+            ("-0.25", false, false, false, true, dec!(-0.25), dec!(0)),
+            ("0", true, true, false, false, dec!(0), dec!(0)),
+            ("-0", true, true, false, false, dec!(0), dec!(0)),
+            (
+                "0.0000000001",
+                false,
+                false,
+                true,
+                false,
+                dec!(0.0000000001),
+                dec!(0),
+            ),
+        ];
+
+        for (n, integer, zero, positive, negative, fract, int_part) in cases {
+            assert_eq!(b(&format!("is_integer({n});")), integer, "is_integer({n})");
+            assert_eq!(b(&format!("is_zero({n});")), zero, "is_zero({n})");
+            assert_eq!(
+                b(&format!("is_positive({n});")),
+                positive,
+                "is_positive({n})"
+            );
+            assert_eq!(
+                b(&format!("is_negative({n});")),
+                negative,
+                "is_negative({n})"
+            );
+            assert!(b(&format!("is_finite({n});")), "is_finite({n})");
+            assert_eq!(eval(&format!("fract({n});")).unwrap(), Data::Number(fract));
+            assert_eq!(
+                eval(&format!("int_part({n});")).unwrap(),
+                Data::Number(int_part)
+            );
+            assert_eq!(
+                eval(&format!("int_part({n}) + fract({n}) == {n};")).unwrap(),
+                Data::Bool(true)
+            );
+        }
+
+        assert_eq!(eval("fract(-2);").unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn test_interrupts() {
+        // Stands in for the Ctrl-C handler, setting the flag partway through a script.
+        fn interrupt(_i: functions::Input, state: &mut ExecutionState) -> functions::Output {
+            state
+                .interrupted
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+            Ok(Data::Null)
+        }
+
+        let interruptible = || {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            Arc::make_mut(&mut state.functions).insert(
+                "interrupt".to_string(),
+                FunctionDescriptor {
+                    inputs: vec![],
+                    inputs_rest: None,
+                    param_names: &[],
+                    function: FunctionType::BuiltInWithState(interrupt),
+                    output: DataType::Null,
+                    capabilities: &[],
+                    doc: None,
+                },
+            );
+            state
+        };
+
+        let mut state = interruptible();
+        let result = eval_with_state(
+            r#"for i in range(0, 1000) { if i == 3 { interrupt(); }; print(i); print(","); }"#,
+            &mut state,
+        );
+        assert!(matches!(
+            result,
+            Err(EvalError::Runtime(ExprError::Interrupted))
+        ));
+        assert_eq!(state.output.captured(), "0,1,2,");
+
+        // Loops with empty bodies still stop.
+        let mut state = interruptible();
+        let result = eval_with_state("interrupt(); while true {}", &mut state);
+        assert!(matches!(
+            result,
+            Err(EvalError::Runtime(ExprError::Interrupted))
+        ));
+
+        // The REPL reports it and keeps going with the same session.
+        let mut state = interruptible();
+        let mut output = vec![];
+        repl(
+            "let x := 2;\ninterrupt(); 1;\nx * 3;\n".as_bytes(),
+            &mut output,
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "[0]> [0]> interrupted\n[0]> out[0] = 6\n[1]> \n"
+        );
+    }
+
+    #[test]
+    fn test_map_literals() {
+        let map = eval(r#"let n := 2; {"name": "bob", "age": n + 1, "tags": ["a"],};"#).unwrap();
+        assert_eq!(
+            map,
+            Data::Map(data::DataMap::from([
+                ("name".to_string(), Data::String("bob".to_string())),
+                ("age".to_string(), Data::Number(dec!(3))),
+                (
+                    "tags".to_string(),
+                    Data::Array(vec![Data::String("a".to_string())])
+                ),
+            ]))
+        );
+        assert_eq!(map.to_string(), "{age: 3, name: bob, tags: [a]}");
+
+        test_num(r#"{"a": {"b": 5}}.get("a").get("b");"#, dec!(5));
+        test_num(r#"let m := {"x": 1}; m.get("x") + 1;"#, dec!(2));
+        test_num("{ 1; 2; };", dec!(2));
+
+        let source = r#"let m := {"a": 1, "b": [1, 2]};"#;
+        let exprs = parse(source);
+        assert_eq!(parse(&format!("{};", exprs[0])), exprs);
+
+        assert!(matches!(
+            eval(r#"{"a": 1, "a": 2};"#),
+            Err(ExprError::DuplicateKey { key }) if key == "a"
+        ));
+        assert!(matches!(
+            eval(r#"{1: 2};"#),
+            Err(ExprError::InvalidDataType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_index_assignment() {
+        test_num(
+            "let grid := [[1, 2, 3], [4, 5, 6]]; grid[1][2] += 5; grid[1][2];",
+            dec!(11),
+        );
+        assert_eq!(
+            eval("let grid := [[1, 2], [3, 4]]; grid[0][-1] := 9; grid;").unwrap(),
+            Data::Array(vec![numbers(&[1, 9]), numbers(&[3, 4])])
+        );
+        test_num(
+            r#"let scores := {"amy": 3}; scores["bob"] := 10; scores["amy"] *= 2; scores["amy"] + scores["bob"];"#,
+            dec!(16),
+        );
+        test_num(
+            r#"let m := {"xs": [1, 2]}; m["xs"][0] -= 1; m["xs"][0];"#,
+            dec!(0),
+        );
+
+        let exprs = parse("grid[i + 1][0] := 2;");
+        assert_eq!(parse(&format!("{};", exprs[0])), exprs);
+
+        for (input, position) in [
+            ("let a := [[1]]; a[0][1] := 2;", 1),
+            ("let a := [[1]]; a[3][0] := 2;", 0),
+            (r#"let m := {"a": {}}; m["b"]["c"] := 2;"#, 0),
+            (r#"let m := {"a": 1}; m["a"]["c"] := 2;"#, 1),
+        ] {
+            match eval(input) {
+                Err(ExprError::AssignPath {
+                    name, position: p, ..
+                }) => {
+                    assert_eq!((name.len(), p), (1, position), "{input}")
+                }
+                other => panic!("{input}: {other:?}"),
+            }
+        }
+        assert!(matches!(
+            eval("b[0] := 1;"),
+            Err(ExprError::UndeclaredAssignment { .. })
+        ));
+        assert!(matches!(
+            eval(r#"{"a": 1}["b"];"#),
+            Err(ExprError::KeyNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_anonymous_functions() {
+        assert_eq!(
+            eval("[1, 2, 3].map(|x| x * 2);").unwrap(),
+            numbers(&[2, 4, 6])
+        );
+        assert_eq!(
+            eval("[1, 2, 3].map(fn(x: Number) -> Number { x * 2; });").unwrap(),
+            numbers(&[2, 4, 6])
+        );
+        assert_eq!(
+            eval("[1, 2, 3, 4].filter(|x| x % 2 == 0);").unwrap(),
+            numbers(&[2, 4])
+        );
+        test_num("[1, 2, 3].fold(0, |acc, x: Number| acc + x);", dec!(6));
+        test_num(
+            "let double := fn(x: Number) -> Number { x * 2; }; double(4);",
+            dec!(8),
+        );
+
+        // A function returning a function passes the output type check.
+        test_num(
+            "fn doubler(n: Number) -> Function { |x| x * 2; } let f := doubler(0); f(3);",
+            dec!(6),
+        );
+
+        let exprs = parse("let f := |x, y: Number| x * y;");
+        let Expr::VariableDeclaration(_, f) = &exprs[0] else {
+            panic!("{exprs:?}")
+        };
+        let Expr::FunctionValue(desc) = &**f else {
+            panic!("{f:?}")
+        };
+        assert_eq!(desc.inputs, vec![DataType::Any, DataType::Number]);
+        assert_eq!(desc.output, DataType::Any);
+        assert_eq!(parse(&format!("{};", exprs[0])), exprs);
+        assert_eq!(f.data_type(&ExecutionState::new()), DataType::Function);
+    }
+
+    #[test]
+    fn test_string_positions_are_characters() {
+        let string = |input: &str| eval(input).unwrap().to_string();
+
+        test_num(r#""héllo wörld".find("ö");"#, dec!(7));
+        test_num(r#""héllo wörld".find_byte("ö");"#, dec!(8));
+        test_num(r#""héllo".find("x");"#, dec!(-1));
+        assert_eq!(
+            string(r#"let s := "héllo wörld"; s[s.find("ö"):];"#),
+            "örld"
+        );
+        assert_eq!(
+            string(r#"let s := "日本語テキスト"; s[:s.find("テ")];"#),
+            "日本語"
+        );
+        test_num(r#""ééé1".first_non_alphabetic();"#, dec!(3));
+        assert_eq!(string(r#"center("é", 5, "·");"#), "··é··");
+    }
+
+    #[test]
+    fn test_functions_as_values() {
+        assert_eq!(
+            eval("fn double(x: Number) -> Number { x * 2; } map([1, 2, 3], double);").unwrap(),
+            numbers(&[2, 4, 6])
+        );
+        assert_eq!(eval("[4, 9].map(sqrt);").unwrap(), numbers(&[2, 3]));
+        assert_eq!(
+            eval("fn sqrt(x: Number) -> Number { x; } [4, 9].map(sqrt);").unwrap(),
+            numbers(&[4, 9])
+        );
+        test_num("let f := abs; f(-3);", dec!(3));
+        assert!(matches!(
+            eval("nothing_here;"),
+            Err(ExprError::VariableNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_higher_order_builtins() {
+        // Through the full lex, parse and run pipeline, like `calculator run`.
+        test_num(
+            "fn square(x: Number) -> Number { x * x; }
+            range(1, 6).filter(|x| x % 2 == 1).map(square).fold(0, |a, b| a + b);",
+            dec!(35),
+        );
+        test_num(
+            "let evens := [1, 2, 3, 4].filter(|x: Number| -> Bool { x % 2 == 0; }); evens.length();",
+            dec!(2),
+        );
+    }
+
+    #[test]
+    fn test_loop_else() {
+        let printed = |input: &str| {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            eval_with_state(input, &mut state).unwrap();
+            state.output.captured()
+        };
+
+        let search = |target: i64| {
+            format!(
+                r#"for x in [1, 2, 3] {{ if x == {target} {{ print("found"); break; }}; }} else {{ print("missing"); }}"#
+            )
+        };
+        assert_eq!(printed(&search(2)), "found");
+        assert_eq!(printed(&search(5)), "missing");
+
+        assert_eq!(
+            printed(r#"while false { print("body"); } else { print("done"); }"#),
+            "done"
+        );
+        assert_eq!(
+            printed(r#"while true { break; } else { print("done"); } print("after");"#),
+            "after"
+        );
+        // `continue` doesn't count as leaving the loop.
+        assert_eq!(
+            printed(r#"for x in [1] { continue; } else { print("done"); }"#),
+            "done"
+        );
+
+        // Each else belongs to its own loop.
+        assert_eq!(
+            printed(
+                r#"for a in [1, 2] {
+                    for b in [1, 2] { if b == a { break; }; } else { print("inner"); }
+                } else { print("outer"); }"#
+            ),
+            "outer"
+        );
+        assert_eq!(
+            printed(
+                r#"for a in [1] {
+                    for b in [2] { if b == a { break; }; } else { print("inner"); }
+                    break;
+                } else { print("outer"); }"#
+            ),
+            "inner"
+        );
+
+        let exprs = parse(&search(2));
+        assert_eq!(parse(&exprs[0].to_string()), exprs);
+        assert!(check(&search(2)).is_empty());
+    }
+
+    #[test]
+    fn test_functions_see_enclosing_environment() {
+        test_num(
+            "fn double(x: Number) -> Number { x * 2; }
+            fn quadruple(x: Number) -> Number { double(double(x)); }
+            quadruple(3);",
+            dec!(12),
+        );
+        test_num(
+            "fn factorial(n: Number) -> Number { if n <= 1 { 1; } else { n * factorial(n - 1); }; }
+            factorial(5);",
+            dec!(120),
+        );
+        test_num(
+            "let offset := 10; fn shift(x: Number) -> Number { x + offset; } shift(1);",
+            dec!(11),
+        );
+        test_num(
+            "fn circle(r: Number) -> Number { PI * r * r; } round(circle(1));",
+            dec!(3),
+        );
+        assert_eq!(
+            eval("let offset := 10; [1, 2].map(|x| x + offset);").unwrap(),
+            numbers(&[11, 12])
+        );
+        // A closure keeps the variables around where it was made.
+        test_num(
+            "fn make_adder(n: Number) -> Function { |x| x + n } let add3 := make_adder(3); add3(1);",
+            dec!(4),
+        );
+        test_num(
+            "fn make_adder(n: Number) -> Function { |x| x + n }
+            let adders := [make_adder(1), make_adder(10)];
+            let f := adders[1];
+            f(5);",
+            dec!(15),
+        );
+        test_num(
+            "fn count(xs: Array, limit: Number) -> Number { xs.filter(|x| x > limit).length() }
+            count([1, 5, 9], 4);",
+            dec!(2),
+        );
+
+        // Parameters shadow globals, and the body's changes stay inside it.
+        test_num(
+            "let x := 1; fn f(x: Number) -> Number { x := x + 5; x; } f(10) + x;",
+            dec!(16),
+        );
+    }
+
+    #[test]
+    fn test_rounding_keeps_precision() {
+        // More significant digits than an f64 holds.
+        test_num("floor(12345678901234567.8);", dec!(12345678901234567));
+        test_num("ceil(12345678901234567.2);", dec!(12345678901234568));
+        test_num("round(12345678901234567.5);", dec!(12345678901234568));
+        test_num("trunc(-12345678901234567.9);", dec!(-12345678901234567));
+        test_num(
+            "floor(79228162514264337593543950335);",
+            dec!(79228162514264337593543950335),
+        );
+
+        // Ordinary values behave as before, with halves rounding away from zero.
+        let cases = [
+            ("2.5", dec!(2), dec!(3), dec!(3), dec!(2)),
+            ("-2.5", dec!(-3), dec!(-2), dec!(-3), dec!(-2)),
+            ("0.4", dec!(0), dec!(1), dec!(0), dec!(0)),
+            ("-0.4", dec!(-1), dec!(0), dec!(0), dec!(0)),
+            ("7", dec!(7), dec!(7), dec!(7), dec!(7)),
+        ];
+        for (n, floor, ceil, round, trunc) in cases {
+            test_num(&format!("floor({n});"), floor);
+            test_num(&format!("ceil({n});"), ceil);
+            test_num(&format!("round({n});"), round);
+            test_num(&format!("trunc({n});"), trunc);
+        }
+        assert_eq!(eval("string(ceil(-0.4));").unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        let string = |input: &str| eval(input).unwrap().to_string();
+
+        assert_eq!(string(r#"center("ab", width: 6, pad: "*");"#), "**ab**");
+        assert_eq!(string(r#"center("ab", pad: "*", width: 6);"#), "**ab**");
+        assert_eq!(string(r#""ab".center(pad: "-", width: 4);"#), "-ab-");
+        assert_eq!(string("to_fixed(decimals: 2, n: 1.5);"), "1.50");
+        assert_eq!(string("slice([1, 2, 3, 4], end: 3, start: 1);"), "[2, 3]");
+        test_num(
+            "fn half(n: Number) -> Number { n / 2; } half(n: 5);",
+            dec!(2.5),
+        );
+        assert_eq!(string("let f := |a, b| a - b; f(b: 1, a: 3);"), "2");
+
+        assert_eq!(
+            eval(r#"center("ab", 6, padding: "*");"#)
+                .unwrap_err()
+                .to_string(),
+            "There is no parameter called `padding` in `center(s: String, width: Number, pad: String)`."
+        );
+        assert!(matches!(
+            eval(r#"center("ab", width: 6, width: 6, pad: "*");"#),
+            Err(ExprError::DuplicateArgument { .. })
+        ));
+        assert!(matches!(
+            eval(r#"center("ab", 6, width: 4, pad: "*");"#),
+            Err(ExprError::DuplicateArgument { .. })
+        ));
+        assert!(matches!(
+            eval(r#"center(s: "ab", pad: "*");"#),
+            Err(ExprError::MissingArgument { name, .. }) if name == "width"
+        ));
+        assert!(matches!(
+            eval(r#"center(s: "ab", 6, "*");"#),
+            Err(ExprError::PositionalAfterNamed { .. })
+        ));
+        assert_eq!(
+            eval(r#"center("ab", "6", pad: "*");"#)
+                .unwrap_err()
+                .to_string(),
+            "Invalid arguements passed to function. \
+             Expected: (s: String, width: Number, pad: String), found: (String, String, String)."
+        );
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let mut state = ExecutionState::new();
+        state.output = output::OutputSink::capture();
+
+        let cases = [
+            ("let x := 0; x != 0 && 10 / x > 1;", false),
+            ("let x := 0; x == 0 || 10 / x > 1;", true),
+            ("let x := 5; x != 0 && 10 / x > 1;", true),
+            ("false || true;", true),
+            ("true && false;", false),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                eval_with_state(input, &mut state).unwrap(),
+                Data::Bool(expected),
+                "{input}"
+            );
+        }
+
+        // The right side only runs when it's needed.
+        eval_with_state(
+            r#"fn loud(s: String) -> Bool { println(s); true; }
+            false && loud("and"); true || loud("or"); true && loud("ran");"#,
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(state.output.captured(), "ran\n");
+
+        // A non-bool left side is still an argument error.
+        assert!(matches!(
+            eval("1 || true;"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        assert_eq!(
+            eval("1 && false;").unwrap_err().to_string(),
+            "Invalid arguements passed to function. \
+             Expected: (a: Bool, b: Bool), found: (Number, Bool)."
+        );
+    }
+
+    #[test]
+    fn test_bench() {
+        let Data::Map(result) =
+            eval("fn work() -> Number { sum(range(0, 100)); } bench(work, 20);").unwrap()
+        else {
+            panic!("bench should return a map");
+        };
+        assert_eq!(
+            result.keys().collect::<Vec<_>>(),
+            ["max", "mean", "min", "total"]
+        );
+
+        let time = |key: &str| result[key].number();
+        assert!(time("total") >= time("mean") && time("mean") >= dec!(0));
+        assert!(time("max") >= time("mean") && time("mean") >= time("min"));
+
+        for iterations in ["0", "-1", "2.5"] {
+            assert!(matches!(
+                eval(&format!("bench(fn() -> Number {{ 1; }}, {iterations});")),
+                Err(ExprError::InvalidDataType { .. })
+            ));
+        }
+
+        // Errors from the function come through with the run they happened on.
+        let error = eval("fn f() -> Number { 1 / 0; } bench(f, 5);").unwrap_err();
+        assert!(matches!(
+            error,
+            ExprError::BenchFailed { iteration: 1, warm_up: true, ref source }
+                if matches!(**source, ExprError::DivideBy0)
+        ));
+        assert_eq!(
+            error.to_string(),
+            "`bench` failed on warm-up iteration 1: Attempted to divide by 0."
+        );
+    }
+
+    #[test]
+    fn test_repl_history() {
+        let input = "2 * 3;\nlet x := 1;\n\"a\";\nout[0] + 1;\nout;\nlet out := 1;\nout[0] := 1;\n";
+        let mut output = vec![];
+        let mut state = ExecutionState::sandboxed();
+        repl(input.as_bytes(), &mut output, &mut state).unwrap();
+
+        let lines = String::from_utf8(output).unwrap();
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "[0]> out[0] = 6",
+                "[1]> [1]> out[1] = a",
+                "[2]> out[2] = 7",
+                "[3]> out[3] = [6, a, 7]",
+                "[4]> Error: `out` is a constant and can't be assigned to.",
+                "[4]> Error: `out` is a constant and can't be assigned to.",
+                "[4]> ",
+            ]
+        );
+        assert_eq!(
+            state.constants["out"],
+            Data::Array(vec![
+                Data::Number(dec!(6)),
+                Data::String("a".to_string()),
+                Data::Number(dec!(7)),
+                Data::Array(vec![
+                    Data::Number(dec!(6)),
+                    Data::String("a".to_string()),
+                    Data::Number(dec!(7)),
+                ]),
+            ])
+        );
+        assert!(matches!(
+            eval("let PI := 3;"),
+            Err(ExprError::ConstantRedefined { .. })
+        ));
+    }
+
+    #[test]
+    fn test_integer_division() {
+        let cases = [
+            ("7 ~/ 2;", dec!(3)),
+            ("-7 ~/ 2;", dec!(-4)),
+            ("7 ~/ -2;", dec!(-4)),
+            ("-7 ~/ -2;", dec!(3)),
+            ("6 ~/ 3;", dec!(2)),
+            ("7.5 ~/ 0.5;", dec!(15)),
+            ("1 ~/ 3;", dec!(0)),
+            ("-1 ~/ 3;", dec!(-1)),
+            ("1 + 7 ~/ 2 * 2;", dec!(7)),
+            ("idiv(9, 4);", dec!(2)),
+            ("let x := 17; x ~/= 5; x;", dec!(3)),
+            // `//` is still a comment.
+            ("8 ~/ 3; // 8 // 3", dec!(2)),
+            (
+                "79228162514264337593543950335 ~/ 2;",
+                dec!(39614081257132168796771975167),
+            ),
+        ];
+        for (input, expected) in cases {
+            test_num(input, expected);
+        }
+
+        assert!(matches!(eval("1 ~/ 0;"), Err(ExprError::DivideBy0)));
+        assert!(matches!(eval("idiv(1, 0);"), Err(ExprError::DivideBy0)));
+        assert_eq!(parse("7 ~/ 2;")[0].to_string(), "(7 ~/ 2)");
+    }
+
+    #[test]
+    fn test_check_calls() {
+        let script = "let x := sqrt(4, 5);\nlet y := uppercase(3);\nprintln(x);";
+        let warnings = check(script);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| (w.kind, &script[w.span.clone().unwrap()]))
+                .collect::<Vec<_>>(),
+            [
+                (WarningKind::ArgumentMismatch, "sqrt(4, 5)"),
+                (WarningKind::ArgumentMismatch, "3"),
+            ]
+        );
+        assert_eq!(
+            warnings[0].message,
+            "`sqrt` takes 1 argument, but 2 are passed."
+        );
+        assert_eq!(
+            warnings[1].message,
+            "Argument 1 of `uppercase` should be a `String`, but this is a `Number`."
+        );
+
+        let fixed = r#"let x := sqrt(4); let y := uppercase("3"); y;"#;
+        assert!(check(fixed).is_empty());
+        assert_eq!(eval(fixed).unwrap(), Data::String("3".to_string()));
+
+        let mismatches = |input: &str| {
+            check(input)
+                .into_iter()
+                .filter(|w| w.kind == WarningKind::ArgumentMismatch)
+                .count()
+        };
+        // Declared functions, method calls, operators and nested calls.
+        assert_eq!(mismatches("fn f(n: Number) -> Number { n; } f(1, 2);"), 1);
+        assert_eq!(
+            mismatches(r#"fn f(s: String) -> String { s; } f(1 + 2);"#),
+            1
+        );
+        assert_eq!(mismatches(r#""ab".center(6, "*");"#), 0);
+        assert_eq!(mismatches(r#""ab".center(6);"#), 1);
+        assert_eq!(mismatches("sqrt(1 > 2);"), 1);
+        assert_eq!(
+            mismatches("sum([1, 2]); sum([1, 2][0]); length([1][0]);"),
+            0
+        );
+        assert_eq!(mismatches("sqrt(sqrt(1, 2));"), 1);
+        // Anything whose type isn't obvious is given the benefit of the doubt.
+        assert_eq!(mismatches("let x := 1; sqrt(x); sqrt(first([1]));"), 0);
+        assert_eq!(mismatches("sqrt(if true { 1 } else { 2 });"), 0);
+        assert_eq!(mismatches("let x := 2; sqrt(x > 0 ? x : 0);"), 0);
+        assert_eq!(mismatches("center(s: 1, width: 2, pad: 3);"), 0);
+        assert_eq!(mismatches("type(1); string([1]); print(true);"), 0);
+        // The comma between a closure's parameters doesn't separate arguments.
+        assert_eq!(
+            mismatches("fold([1, 2, 3], 0, |a, x| a + x); scan([1, 2], 0, |a, x| a + x);"),
+            0
+        );
+        assert_eq!(mismatches("fold([1, 2], 0, |a, x| a + x, 1);"), 1);
+        assert_eq!(mismatches("sqrt(1, 2); // allow(arguments)"), 0);
+        assert_eq!(mismatches("sqrt(1, 2); // allow(unreachable)"), 1);
+
+        // Argument types come from the parsed program, so calls and declared outputs count.
+        assert_eq!(mismatches(r#"sqrt(uppercase("a"));"#), 1);
+        assert_eq!(
+            mismatches(r#"fn name() -> String { "a"; } sqrt(name()); sqrt(length(name()));"#),
+            1
+        );
+        assert_eq!(
+            mismatches("sqrt(fold([1, 2], 0, |a, x| a + x)); sqrt([1, 2][0]);"),
+            0
+        );
+        assert_eq!(
+            mismatches("record P(x: Number); fn f(p: P) -> Number { p.x; } f(P(1)); f(1);"),
+            1
+        );
+        // Each warning points at its own call, wherever its receiver is.
+        let script = "abs_diff(4, 1).abs_diff(\"a\");";
+        let warnings = check(script);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(&script[warnings[0].span.clone().unwrap()], "\"a\"");
+        assert_eq!(
+            warnings[0].message,
+            "Argument 2 of `abs_diff` should be a `Number`, but this is a `String`."
+        );
+        let script = "fold([1], 0, |a, x| a + x, \"extra\");";
+        assert_eq!(
+            &script[check(script)[0].span.clone().unwrap()],
+            "fold([1], 0, |a, x| a + x, \"extra\")"
+        );
+    }
+
+    #[test]
+    fn test_range_syntax() {
+        let array = |input: &str| eval(input).unwrap().to_string();
+
+        assert_eq!(array("0..4;"), "[0, 1, 2, 3]");
+        assert_eq!(array("0..=4;"), "[0, 1, 2, 3, 4]");
+        assert_eq!(array("let n := 3; n - 1..n * 2;"), "[2, 3, 4, 5]");
+        assert_eq!(array("10..0;"), "[]");
+        assert_eq!(array("3..=2;"), "[]");
+        assert_eq!(array("-2..1;"), "[-2, -1, 0]");
+        assert_eq!(array("length(0..10) == 10;"), "true");
+
+        let mut state = ExecutionState::new();
+        state.output = output::OutputSink::capture();
+        eval_with_state("for i in 1..=3 { print(i); }", &mut state).unwrap();
+        assert_eq!(state.output.captured(), "123");
+
+        assert_eq!(parse("0..10;")[0].to_string(), "range(0, 10)");
+        assert!(eval_with_state("0..1..2;", &mut state).is_err());
+    }
+
+    #[test]
+    fn test_sizeof_and_depth() {
+        let number = |input: &str| eval(input).unwrap().number();
+        let data = size_of::<Data>() as i64;
+
+        test_num("depth(1);", dec!(0));
+        test_num("depth([]);", dec!(1));
+        test_num("depth([1, [2, [3]], [4]]);", dec!(3));
+        test_num(r#"depth({"a": [{"b": 1}], "c": 2});"#, dec!(3));
+
+        test_num("sizeof(1);", Decimal::from(data));
+        test_num("sizeof([1, [2, 3]]);", Decimal::from(data * 5));
+        test_num(r#"sizeof("abc");"#, Decimal::from(data + 3));
+        test_num(
+            r#"sizeof({"ab": 1});"#,
+            Decimal::from(data * 2 + size_of::<String>() as i64 + 2),
+        );
+
+        // Neither recurses, so a huge flat array is fine.
+        let huge = "let xs := range(0, 1000000);";
+        assert_eq!(number(&format!("{huge} depth(xs);")), dec!(1));
+        assert_eq!(
+            number(&format!("{huge} sizeof(xs);")),
+            Decimal::from(data * 1_000_001)
+        );
+
+        // Nor with deep nesting. (Dropping it recurses, so not too deep.)
+        let mut nested = Data::Array(vec![]);
+        for _ in 0..10_000 {
+            nested = Data::Array(vec![nested]);
+        }
+        let FunctionType::BuiltIn(depth) = builtints()["depth"].function else {
+            panic!("depth should be a plain builtin");
+        };
+        assert_eq!(depth(vec![nested]).unwrap(), Data::Number(dec!(10001)));
+    }
+
+    #[test]
+    fn test_chained_comparisons() {
+        let b = |input: &str| eval(input).unwrap() == Data::Bool(true);
+
+        assert!(b("let x := 5; 1 < x < 10;"));
+        assert!(!b("let x := 15; 1 < x < 10;"));
+        assert!(!b("let x := 0; 1 < x < 10;"));
+        assert!(b("let i := 0; let n := 3; 0 <= i < n;"));
+        assert!(!b("let i := 3; let n := 3; 0 <= i < n;"));
+        assert!(b("1 < 2 <= 2 < 3 != 4;"));
+        assert!(b("2 == 2 == 2;"));
+        assert!(b(r#""a" < "b" < "c";"#));
+        assert!(b("1 < 5 < 10 && 10 > 5 > 1;"));
+
+        // The middle is evaluated once, and later links are skipped once one is false.
+        let mut state = ExecutionState::new();
+        state.output = output::OutputSink::capture();
+        eval_with_state(
+            r#"fn loud(n: Number) -> Number { print(n); n; }
+            1 < loud(2) < 3; 5 < loud(1) < loud(7);"#,
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(state.output.captured(), "21");
+
+        assert_eq!(parse("0 <= i < n;")[0].to_string(), "(0 <= i < n)");
+        assert_eq!(parse("i < n;")[0].to_string(), "(i < n)");
+        assert!(matches!(
+            eval(r#"1 < "a" < 3;"#),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+    }
+
+    #[test]
+    fn test_display_limit() {
+        let printed = |code: &str| {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            eval_with_state(code, &mut state).unwrap();
+            state.output.captured()
+        };
+
+        let short = printed("println(range(0, 10000));");
+        assert!(short.len() < 1000, "{short}");
+        assert!(short.starts_with("[0, 1, 2, "), "{short}");
+        assert!(short.ends_with("98, 99, … 9900 more]\n"), "{short}");
+
+        let exact = (0..10000)
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        assert_eq!(
+            eval("string(range(0, 10000));").unwrap(),
+            Data::String(format!("[{exact}]"))
+        );
+        assert_eq!(
+            printed("full_print(range(0, 10000));"),
+            format!("[{exact}]\n")
+        );
+
+        // Nested collections and maps are cut short too, and the limit can change.
+        assert_eq!(
+            printed(r#"set_display_limit(2); println([[1, 2, 3], {"a": 1, "b": 2, "c": 3}]);"#),
+            "[[1, 2, … 1 more], {a: 1, b: 2, … 1 more}]\n"
+        );
+        assert_eq!(
+            printed("fn f() -> Null { set_display_limit(1); } f(); println([1, 2]);"),
+            "[1, … 1 more]\n"
+        );
+        assert_eq!(
+            printed("set_display_limit(0); println(range(0, 200));").len(),
+            printed("full_print(range(0, 200));").len()
+        );
+        assert!(matches!(
+            eval("set_display_limit(-1);"),
+            Err(ExprError::InvalidDataType { .. })
+        ));
+
+        // The REPL echo is cut short, and so are error messages showing values.
+        let mut output = vec![];
+        repl(
+            "range(0, 500);\nto_map([range(0, 500)]);\n".as_bytes(),
+            &mut output,
+            &mut ExecutionState::new(),
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("… 400 more]").count(), 2, "{output}");
+        assert!(output.len() < 2000, "{output}");
+    }
+
+    #[test]
+    fn test_ternary() {
+        test_num("let x := 5; let y := x > 0 ? 1 : -1; y;", dec!(1));
+        test_num("let x := -5; let y := x > 0 ? 1 : -1; y;", dec!(-1));
+        test_num("let x := 0; x < 0 ? -1 : x == 0 ? 0 : 1;", dec!(0));
+        test_num("1 + (true ? 2 : 3) * 2;", dec!(5));
+        test_num("let x := 2; max(x > 1 ? x : 1, 0);", dec!(2));
+        test_num("let a := [1, 2, 3]; a[length(a) > 2 ? 2 : 0];", dec!(3));
+        assert_eq!(
+            eval(r#"false || true ? "yes" : "no";"#).unwrap(),
+            Data::String("yes".into())
+        );
+
+        // Only the chosen side runs.
+        assert!(eval("true ? 1 : 1 / 0;").is_ok());
+        assert!(eval("1 ? 1 : 2;").is_err());
+    }
+
+    #[test]
+    fn test_regex_limits() {
+        let long_pattern = format!(r#"matches("a", "{}");"#, "a".repeat(20_000));
+        assert!(matches!(
+            eval(&long_pattern),
+            Err(ExprError::InvalidRegex { .. })
+        ));
+
+        let mut state = ExecutionState::new();
+        let big = format!(
+            r#"let s := "{}b"; matches(s, "a+b$");"#,
+            "a".repeat(1_000_000)
+        );
+        assert_eq!(eval_with_state(&big, &mut state).unwrap(), Data::Bool(true));
+
+        state.regex.set_limits(patterns::RegexLimits {
+            max_input_length: 1_000,
+            ..Default::default()
+        });
+        assert!(matches!(
+            eval_with_state(&big, &mut state),
+            Err(EvalError::Runtime(ExprError::RegexInputTooLong { .. }))
+        ));
+
+        state.regex.set_limits(patterns::RegexLimits {
+            size_limit: 100,
+            ..Default::default()
+        });
+        assert!(matches!(
+            eval_with_state(r#"matches("a", "a{50}");"#, &mut state),
+            Err(EvalError::Runtime(ExprError::InvalidRegex { .. }))
+        ));
+
+        state.regex.set_limits(patterns::RegexLimits::default());
+        assert_eq!(
+            eval_with_state(r#"matches("abc", "^a.c$");"#, &mut state).unwrap(),
+            Data::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        test_num("0xFF;", dec!(255));
+        test_num("0xff + 1;", dec!(256));
+        test_num("0b1010;", dec!(10));
+        test_num("0o755;", dec!(493));
+        test_num("-0x10;", dec!(-16));
+        test_num("0b0;", dec!(0));
+        test_num("let x := 0x10; x * 0b10;", dec!(32));
+        test_num("[0x1, 0x2][0b1];", dec!(2));
+        assert_eq!(eval("0xFF == 255;").unwrap(), Data::Bool(true));
+
+        for bad in [
+            "0x;",
+            "0b102;",
+            "0o8;",
+            "0xG;",
+            "0x1_0;",
+            "0xFFFFFFFFFFFFFFFFFFFFFFFFFF;",
+        ] {
+            assert!(lexer::tokens(bad).is_err(), "{bad} should not lex");
+        }
+    }
+
+    #[test]
+    fn test_records() {
+        let point = "record Point(x: Number, y: Number);";
+
+        test_num(
+            &format!("{point} let p := Point(1, 2); p.x + p.y;"),
+            dec!(3),
+        );
+        test_num(&format!("{point} Point(y: 5, x: 1).y;"), dec!(5));
+        test_num(
+            &format!("{point} record Line(a: Map, b: Map); Line(Point(1, 2), Point(3, 4)).b.x;"),
+            dec!(3),
+        );
+        assert_eq!(
+            eval(&format!("{point} type(Point(1, 2));")).unwrap(),
+            Data::String("Point".into())
+        );
+        assert_eq!(
+            eval(&format!("{point} Point(1, 2);")).unwrap().to_string(),
+            "Point {x: 1, y: 2}"
+        );
+        // Records are maps to everything else.
+        assert_eq!(
+            eval(&format!("{point} keys(Point(1, 2));"))
+                .unwrap()
+                .to_string(),
+            "[x, y]"
+        );
+        // A function can return its record type, or Map.
+        test_num(
+            &format!("{point} fn origin() -> Point {{ Point(0, 0) }} origin().y;"),
+            dec!(0),
+        );
+        test_num(
+            &format!("{point} fn origin() -> Map {{ Point(0, 0) }} origin().x;"),
+            dec!(0),
+        );
+        assert!(matches!(
+            eval(&format!("{point} fn origin() -> Number {{ Point(0, 0) }}")),
+            Err(ExprError::OutputTypeMismatch { expected, found, .. })
+                if expected == DataType::Number && found == DataType::Named("Point".into())
+        ));
+
+        assert_eq!(
+            eval::parse("record P(x: Number, x: Number);")
+                .unwrap_err()
+                .to_string(),
+            "Field `x` appears more than once in record `P`"
+        );
+        assert_eq!(
+            eval::parse("record P(x: number);").unwrap_err().to_string(),
+            "Invalid type `number` for field `x` of record `P`"
+        );
+        assert_eq!(
+            eval("record P(x: Nmbr);").unwrap_err().to_string(),
+            "Unknown type `Nmbr` in field `x` of record `P`."
+        );
+
+        assert!(matches!(
+            eval(&format!("{point} Point(1);")),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        assert!(matches!(
+            eval(&format!(r#"{point} Point(1, "2");"#)),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        assert!(matches!(
+            eval(&format!("{point} Point(1, 2).z;")),
+            Err(ExprError::UnknownField { .. })
+        ));
+        assert!(matches!(
+            eval(r#"let m := {"x": 1}; m.x;"#),
+            Err(ExprError::InvalidDataType { .. })
+        ));
+        assert!(matches!(
+            eval("let n := 1; n.x;"),
+            Err(ExprError::InvalidDataType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_snapshots() {
+        test_num(
+            "let x := 1; let a := [1]; let snap := snapshot();
+             x := 2; a := append(a, 2);
+             restore(snap); x + length(a);",
+            dec!(2),
+        );
+        test_num(
+            "let x := 1; let snap := snapshot();
+             x := 2; restore(snap); x := 3; restore(snap); x;",
+            dec!(1),
+        );
+        // Functions aren't rolled back.
+        test_num(
+            "let snap := snapshot(); fn f(n: Number) -> Number { n; } restore(snap); f(4);",
+            dec!(4),
+        );
+
+        assert!(matches!(
+            eval("restore(42);"),
+            Err(ExprError::UnknownSnapshot { .. })
+        ));
+        assert!(matches!(
+            eval("restore(0.5);"),
+            Err(ExprError::UnknownSnapshot { .. })
+        ));
+        assert!(matches!(
+            eval("let snap := snapshot(); drop_snapshot(snap); restore(snap);"),
+            Err(ExprError::UnknownSnapshot { .. })
+        ));
+        assert!(matches!(
+            eval("let snap := snapshot(); drop_snapshot(snap); drop_snapshot(snap);"),
+            Err(ExprError::UnknownSnapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn test_scientific_literals() {
+        let number = |input: &str| match Token::lexer(input).next() {
+            Some(Ok(Token::Number(n))) => n,
+            other => panic!("{input} lexed as {other:?}"),
+        };
+
+        assert_eq!(number("1e6"), dec!(1000000));
+        assert_eq!(number("2.5E-3"), dec!(0.0025));
+        assert_eq!(number("6.022e23"), dec!(602200000000000000000000));
+        assert_eq!(number("1e+2"), dec!(100));
+        assert_eq!(number("7e0"), dec!(7));
+
+        for out_of_range in ["1e30", "1e-40"] {
+            let err = Token::lexer(out_of_range).next().unwrap().unwrap_err();
+            assert_eq!(
+                err,
+                lexer::LexerError::new(
+                    lexer::LexerErrorKind::ExponentOutOfRange(out_of_range.to_string()),
+                    0..out_of_range.len()
+                )
+            );
+        }
+
+        test_num("2e3 + 1;", dec!(2001));
+        test_num("-1.5e2;", dec!(-150));
+        // Without digits after it, the `e` isn't part of the number.
+        assert_eq!(
+            Token::lexer("2e").collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![Token::Number(dec!(2)), Token::Ident("e".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_unicode_widths() {
+        let string = |input: &str| eval(input).unwrap().to_string();
+
+        test_num(r#"grapheme_length("e\u{301}");"#, dec!(1));
+        test_num(
+            r#"grapheme_length("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}");"#,
+            dec!(1),
+        );
+        test_num(r#"grapheme_length("héllo");"#, dec!(5));
+        test_num(r#"display_width("e\u{301}");"#, dec!(1));
+        test_num(r#"display_width("日本語");"#, dec!(6));
+        test_num(r#"display_width("abc");"#, dec!(3));
+
+        // Centered text lines up in a column whatever its characters' widths.
+        for text in ["日本", "abcd", "e\u{301}e\u{301}ab", "a日b"] {
+            let centered = string(&format!(r#"center("{text}", 10, " ");"#));
+            assert_eq!(
+                eval(&format!(r#"display_width("{centered}");"#)).unwrap(),
+                Data::Number(dec!(10)),
+                "{centered:?}"
+            );
+        }
+        assert_eq!(string(r#"center("日本", 10, " ");"#), "   日本   ");
+        assert_eq!(string(r#"center("ab", 10, "日");"#), "日日ab日日");
+        assert_eq!(string(r#"center("e\u{301}", 5, "-");"#), "--e\u{301}--");
+    }
+
+    #[test]
+    fn test_block_comments() {
+        test_num("2 /* three */ + 3;", dec!(5));
+        test_num(
+            "let x := 1;
+             /*
+             x := 2;
+             /* nested */
+             x := 3;
+             */
+             x;",
+            dec!(1),
+        );
+        test_num("/* ünïcödé */ 4; /**/", dec!(4));
+        test_num("1 /* // */ + 1;", dec!(2));
+
+        let unterminated = "let x := 1; /* outer /* inner */ x;";
+        let err = Token::lexer(unterminated)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            lexer::LexerError::new(lexer::LexerErrorKind::UnterminatedComment, 12..14)
+        );
+        assert_eq!(&unterminated[12..14], "/*");
+    }
+
+    #[test]
+    fn test_dependency_analysis() {
+        let free = |source: &str| analysis::free_variables(source).unwrap();
+        let calls = |source: &str| analysis::called_functions(source).unwrap();
+
+        assert_eq!(
+            free("price * (1 + rate) - discount;"),
+            ["price", "rate", "discount"]
+        );
+        // Constants and functions aren't inputs.
+        assert_eq!(free("PI * r * r + sqrt(E);"), ["r"]);
+        // Locals only count before they are declared.
+        assert_eq!(free("let x := y + 1; x * z;"), ["y", "z"]);
+        assert_eq!(free("total := total + 1;"), ["total"]);
+        // Loop variables and parameters shadow outer names inside their body only.
+        assert_eq!(
+            free("for i in items { i * scale; } i;"),
+            ["items", "scale", "i"]
+        );
+        assert_eq!(
+            free("fn area(r: Number) -> Number { PI * r * r * k; } area(r) + area(2);"),
+            ["k", "r"]
+        );
+        assert_eq!(free("let f := |x| x + offset; f(1);"), ["offset"]);
+        assert_eq!(free("if ok { let t := 1; t; } else { t; }"), ["ok", "t"]);
+        assert!(free("let a := 1; { let b := a; b; }").is_empty());
+
+        assert_eq!(
+            calls("sqrt(x) + helper(2); fn helper(n: Number) -> Number { abs(n); }"),
+            ["add", "sqrt", "helper", "abs"]
+        );
+        assert_eq!(
+            calls("[1, 2].map(|x| double(x)).sum();"),
+            ["sum", "map", "double"]
+        );
+        assert_eq!(calls("let f := |x| x; f(1) + g(2);"), ["add", "g"]);
+        assert_eq!(calls("x?.round();"), ["round"]);
+        // Operators count like the calls that `a[i]` and `a..b` stand for.
+        assert_eq!(calls("a + y;"), ["add"]);
+        assert_eq!(calls("let [a, ...b] := xs; a + b[0];"), ["add", "index"]);
+        assert_eq!(calls("-x[1:] == 0..n;"), ["eq", "neg", "slice", "range"]);
+        assert_eq!(calls("0 < x <= 1 && !done;"), ["and", "lt", "le", "not"]);
+
+        assert!(analysis::free_variables("1 +;").is_err());
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let dir = env::temp_dir().join("ls-session-round-trip");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.json").display().to_string();
+
+        let mut state = ExecutionState::new();
+        eval_with_state(
+            &format!(
+                "fn double(x: Number) -> Number {{ x * 2; }}
+                let xs := [1, \"two\", [3]];
+                let precise := 0.1234567890123456789012345678;
+                let f := |x| x + 1;
+                save_session({path:?});"
+            ),
+            &mut state,
+        )
+        .unwrap();
+
+        let mut fresh = ExecutionState::new();
+        eval_with_state(&format!("load_session({path:?});"), &mut fresh).unwrap();
+        assert!(fresh.warnings.take().is_empty());
+        assert_eq!(
+            eval_with_state("double(21);", &mut fresh).unwrap(),
+            Data::Number(dec!(42))
+        );
+        assert_eq!(
+            eval_with_state("f(precise);", &mut fresh).unwrap(),
+            Data::Number(dec!(1.1234567890123456789012345678))
+        );
+        assert_eq!(fresh.variables["xs"], state.variables["xs"]);
+        assert_eq!(fresh.variables["precise"], state.variables["precise"]);
+        assert!(!fresh.variables.contains_key("args"));
+
+        // Loading over existing definitions replaces them, with a warning naming them.
+        let mut existing = ExecutionState::new();
+        eval_with_state(
+            &format!("let xs := 1; let precise := 0.1234567890123456789012345678; load_session({path:?}); xs;"),
+            &mut existing,
+        )
+        .unwrap();
+        let warnings = existing.warnings.take();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::SessionOverwrite);
+        assert!(warnings[0].message.contains("`xs`"));
+        assert!(!warnings[0].message.contains("precise"));
+        assert_eq!(existing.variables["xs"], state.variables["xs"]);
+
+        // The REPL commands do the same.
+        let mut output = vec![];
+        let mut repl_state = ExecutionState::new();
+        let input = format!(":load {path}\ndouble(4);\n:save\n:nope\n");
+        repl(input.as_bytes(), &mut output, &mut repl_state).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("out[0] = 8"), "{output}");
+        assert!(output.contains("Unknown command `:save`"), "{output}");
+        assert!(output.contains("Unknown command `:nope`"), "{output}");
+
+        // Bad files are errors, not panics.
+        let load = |contents: &str| {
+            fs::write(&path, contents).unwrap();
+            eval_with_state(
+                &format!("load_session({path:?});"),
+                &mut ExecutionState::new(),
+            )
+            .unwrap_err()
+        };
+        for contents in [
+            "not json",
+            "{\"version\": 1}",
+            "{\"version\": 99, \"variables\": {}, \"functions\": {}}",
+        ] {
+            assert!(
+                matches!(
+                    load(contents),
+                    EvalError::Runtime(ExprError::InvalidFormat { .. })
+                ),
+                "{contents}"
+            );
+        }
+        assert!(matches!(
+            eval_with_state(
+                &format!(
+                    "load_session({:?});",
+                    dir.join("missing.json").display().to_string()
+                ),
+                &mut ExecutionState::new()
+            ),
+            Err(EvalError::Runtime(ExprError::IoError { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_optional_semicolons() {
+        test_num("1 + 2", dec!(3));
+        test_num("let x := 1\nlet y := 2\nx + y", dec!(3));
+        test_num("let x := 1\nx += 4\nx", dec!(5));
+        test_num("let x := 1; x := 2\nx", dec!(2));
+        assert!(eval("let x := 1").is_ok());
+
+        // Lines starting with an operator, `.` or `else`, or inside brackets, carry on.
+        test_num("let x := 1\n    + 2\n    * 3\nx", dec!(7));
+        test_num("[1, 2, 3]\n    .map(|x| x * 2)\n    .sum()", dec!(12));
+        test_num("max(\n    1,\n    2\n)", dec!(2));
+        test_num("let xs := [\n    1\n    , 2\n]\nlength(xs)", dec!(2));
+        test_num("if false {\n    1\n}\nelse {\n    2\n}", dec!(2));
+        test_num("true\n    ? 1\n    : 2", dec!(1));
+
+        // Functions and loops don't need one, but a stray `;` is fine.
+        test_num(
+            "fn double(x: Number) -> Number {\n    x * 2\n}\nfor i in [1, 2] {\n    i\n};\ndouble(3);;",
+            dec!(6),
+        );
+        test_num(
+            "let f := |x| {\n    let y := x + 1\n    y * 2\n}\nf(1)",
+            dec!(4),
+        );
+
+        assert!(eval::parse("1 2").is_err());
+
+        // Unreachable code is found across lines too.
+        let warnings = check("fn f(x: Number) -> Number {\n    return x\n    x + 1\n}\nf(1)");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::Unreachable);
+    }
+
+    #[test]
+    fn test_number_display_modes() {
+        let shown = |mode: &str, n: &str| {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            eval_with_state(
+                &format!("set_number_display(\"{mode}\"); print({n}); string({n});"),
+                &mut state,
+            )
+            .map(|exact| (state.output.captured(), exact.to_string()))
+            .unwrap()
+        };
+
+        let cases = [
+            ("auto", "4700000", "4700000"),
+            ("auto", "123000000000000000000000", "1.23e23"),
+            ("auto", "0.0000000047", "4.7e-9"),
+            ("auto", "-0.5", "-0.5"),
+            ("auto", "0", "0"),
+            ("scientific", "123000000000000000000000", "1.23e23"),
+            ("scientific", "0.0000047", "4.7e-6"),
+            ("scientific", "-470", "-4.7e2"),
+            ("scientific", "0", "0"),
+            ("engineering", "123000000000000000000000", "123e21"),
+            ("engineering", "0.0000047", "4.7e-6"),
+            ("engineering", "0.000047", "47e-6"),
+            ("engineering", "1000", "1e3"),
+            ("engineering", "12345.678", "12.345678e3"),
+            ("engineering", "0", "0"),
+        ];
+        for (mode, n, expected) in cases {
+            let (printed, exact) = shown(mode, n);
+            assert_eq!(printed, expected, "{n} in {mode}");
+            // `string()` is unaffected.
+            assert_eq!(exact, eval(&format!("{n};")).unwrap().to_string());
+        }
+
+        // The mode is kept across lines, and applies inside arrays.
+        let mut output = vec![];
+        let input = "set_number_display(\"scientific\");\n[1500, 0.002];\n";
+        repl(input.as_bytes(), &mut output, &mut ExecutionState::new()).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("out[0] = [1.5e3, 2e-3]"));
+
+        assert!(matches!(
+            eval("set_number_display(\"roman\");"),
+            Err(ExprError::InvalidDataType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_const_declarations() {
+        test_num("const G := 9.81; G * 2;", dec!(19.62));
+        test_num(
+            "const G := 9.81\nfn weight(m: Number) -> Number { m * G; }\nweight(2)",
+            dec!(19.62),
+        );
+        test_num("let g := 1; const g := 2; g;", dec!(2));
+
+        for redefinition in [
+            "const G := 1; let G := 2;",
+            "const G := 1; G := 2;",
+            "const G := 1; G += 2;",
+            "const G := [1]; G[0] := 2;",
+            "const G := 1; const G := 2;",
+            "const G := 1; fn f(x: Number) -> Number { let G := x; G; } f(1);",
+            "let PI := 3;",
+            "PI := 3;",
+            "const E := 3;",
+        ] {
+            assert!(
+                matches!(eval(redefinition), Err(ExprError::ConstantRedefined { .. })),
+                "{redefinition}"
+            );
+        }
+
+        // Constants persist between REPL lines.
+        let mut state = ExecutionState::new();
+        eval_with_state("const C := 299792458;", &mut state).unwrap();
+        assert!(matches!(
+            eval_with_state("C := 1;", &mut state),
+            Err(EvalError::Runtime(ExprError::ConstantRedefined { .. }))
+        ));
+        assert_eq!(
+            eval_with_state("C;", &mut state).unwrap(),
+            Data::Number(dec!(299792458))
+        );
+    }
+
+    #[test]
+    fn test_eval_many() {
+        let mut base = ExecutionState::sandboxed();
+        eval_with_state(
+            "let rate := 0.2; fn tax(x: Number) -> Number { x * rate; }",
+            &mut base,
+        )
+        .unwrap();
+        let shared = base.freeze();
+
+        let inputs = [
+            "tax(100);",
+            "let rate := 1; tax(100);",
+            "rate;",
+            "const K := 2; K;",
+            "K;",
+            "tax(",
+            "read_file(\"x\");",
+            "rate := 5; rate;",
+            "rate;",
+        ];
+        let shown = |results: Vec<Result<Data, EvalError>>| {
+            results
+                .into_iter()
+                .map(|r| r.map_err(|e| e.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let one_at_a_time = shown(
+            inputs
+                .iter()
+                .map(|input| eval_with_state(input, &mut base.clone()))
+                .collect(),
+        );
+
+        assert_eq!(shown(eval::eval_many(&shared, &inputs)), one_at_a_time);
+        assert_eq!(shown(eval::par_eval_many(&shared, &inputs)), one_at_a_time);
+        assert_eq!(one_at_a_time[0], Ok(Data::Number(dec!(20))));
+        // Each input starts from the base, not from what the others did.
+        assert_eq!(one_at_a_time[1], Ok(Data::Number(dec!(100))));
+        assert_eq!(one_at_a_time[2], Ok(Data::Number(dec!(0.2))));
+        assert!(one_at_a_time[4].is_err());
+        assert!(one_at_a_time[6].is_err(), "children keep the sandbox");
+        assert_eq!(one_at_a_time[7], Ok(Data::Number(dec!(5))));
+        // Assigning a frozen variable changes the child's copy, not the shared one.
+        assert_eq!(one_at_a_time[8], Ok(Data::Number(dec!(0.2))));
+
+        for _ in 0..100 {
+            ExecutionState::new();
+            shared.child();
+        }
+        assert_eq!(functions::builtins_built(), 1);
+    }
+
+    #[test]
+    fn test_destructuring() {
+        test_num(
+            "let [a, b, c] := [1, 2, 3]; a * 100 + b * 10 + c;",
+            dec!(123),
+        );
+        test_num(
+            "fn divmod(n: Number) -> Array { [n ~/ 7, n % 7]; } let [q, r] := divmod(23); q * 10 + r;",
+            dec!(32),
+        );
+        test_num("let [first] := [5, 6, 7]; first;", dec!(5));
+        test_num(
+            "let [x, ...rest] := [1, 2, 3]; length(rest) * 10 + x;",
+            dec!(21),
+        );
+        test_num("let [x, y, ...rest] := [1, 2]; length(rest);", dec!(0));
+        test_num("let [...all] := [1, 2]; sum(all);", dec!(3));
+
+        // The right side runs once.
+        let mut state = ExecutionState::new();
+        state.output = output::OutputSink::capture();
+        eval_with_state("let [a, b] := [print(\"!\"), 2];", &mut state).unwrap();
+        assert_eq!(state.output.captured(), "!");
+
+        assert!(matches!(
+            eval("let [a, b, c] := [1, 2];"),
+            Err(ExprError::DestructureTooShort {
+                expected: 3,
+                found: 2
+            })
+        ));
+        assert!(matches!(
+            eval("let [a] := 1;"),
+            Err(ExprError::InvalidDataType { .. })
+        ));
+        assert!(matches!(
+            eval("let [a, PI] := [1, 2];"),
+            Err(ExprError::ConstantRedefined { .. })
+        ));
+        assert_eq!(
+            analysis::free_variables("let [a, ...b] := xs; a + b + c;").unwrap(),
+            ["xs", "c"]
+        );
+    }
+
+    #[test]
+    fn test_tuples() {
+        let tuple = eval("(1, \"x\");").unwrap();
+        assert_eq!(
+            tuple,
+            Data::Tuple(vec![Data::Number(dec!(1)), Data::String("x".into())])
+        );
+        assert_eq!(tuple._type(), DataType::Tuple);
+        assert_eq!(tuple.to_string(), "(1, x)");
+        assert_eq!(eval("(1,);").unwrap().to_string(), "(1,)");
+        assert_eq!(eval("type((1, 2));").unwrap().to_string(), "Tuple");
+
+        // Without a comma, parentheses are only grouping.
+        test_num("(1 + 2) * 3;", dec!(9));
+        test_num("let t := (10, 20, 30); t[1] + index(t, -1);", dec!(50));
+        test_num("length((1, 2, 3));", dec!(3));
+        assert_eq!(
+            eval("(1, (2, 3)) == (1, (2, 3));").unwrap(),
+            Data::Bool(true)
+        );
+        assert_eq!(eval("(1, 2) == [1, 2];").unwrap(), Data::Bool(false));
+        assert_eq!(
+            eval("fn swap(p: Tuple) -> Tuple { (p[1], p[0]); } swap((1, 2));")
+                .unwrap()
+                .to_string(),
+            "(2, 1)"
+        );
+
+        assert_eq!(
+            eval("zip([1, 2, 3], [\"a\", \"b\"]);").unwrap().to_string(),
+            "[(1, a), (2, b)]"
+        );
+        let mut state = ExecutionState::new();
+        state.output = output::OutputSink::capture();
+        eval_with_state(
+            "for pair in zip([1, 2], [\"a\", \"b\"]) { print(pair[1]); print(pair[0] * 10); }",
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(state.output.captured(), "a10b20");
+    }
+
+    #[test]
+    fn test_do_while() {
+        let run = |src: &str| {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            eval_with_state(src, &mut state).unwrap();
+            state.output.captured()
+        };
+
+        // The body runs once before the condition is checked.
+        assert_eq!(run("do { print(\"once\"); } while false;"), "once");
+        assert_eq!(
+            run("let i := 0; do { print(i); i += 1; } while i < 3;"),
+            "012"
+        );
+        assert_eq!(
+            run("let i := 0; do { i += 1; if i == 2 { break; }; print(i); } while true;"),
+            "1"
+        );
+        assert_eq!(
+            run("let i := 0\ndo {\n  i += 1\n  print(i)\n} while i < 2\nprint(\"done\")"),
+            "12done"
+        );
+        assert_eq!(
+            eval::parse("do { 1; } while x;").unwrap()[0].to_string(),
+            "do {\n    1;\n} while x"
+        );
+    }
+
+    #[test]
+    fn test_loop_break_value() {
+        test_num(
+            "let i := 0; loop { i += 1; if i * i > 50 { break i; }; };",
+            dec!(8),
+        );
+        test_num("let n := loop { break 2 + 3; }; n * 2;", dec!(10));
+        assert_eq!(eval("loop { break; };").unwrap(), Data::Null);
+
+        // Other loops drop the value.
+        assert_eq!(eval("while true { break 1; }").unwrap(), Data::Null);
+        assert!(matches!(
+            eval("fn f() { break 1; } f();"),
+            Err(ExprError::LoopControlOutsideLoop { .. })
+        ));
+        assert_eq!(
+            eval::parse("loop { break 1; };").unwrap()[0].to_string(),
+            "loop {\n    break 1;\n}"
+        );
+    }
+
+    #[test]
+    fn test_labeled_loops() {
+        let run = |src: &str| {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            eval_with_state(src, &mut state).unwrap();
+            state.output.captured()
+        };
+
+        assert_eq!(
+            run("'outer: for x in [1, 2, 3] { for y in [1, 2, 3] { if y == 2 { continue 'outer; }; if x == 3 { break 'outer; }; print(x * 10 + y); } }"),
+            "1121"
+        );
+        // An unlabeled `break` still only leaves the innermost loop.
+        assert_eq!(
+            run("'outer: for x in [1, 2] { for y in [1, 2] { break; } print(x); }"),
+            "12"
+        );
+        // Skipping the `else` of the loop it breaks, but not of the ones it leaves.
+        assert_eq!(
+            run("'outer: for x in [1] { for y in [1] { break 'outer; } else { print(\"inner\"); } } else { print(\"outer\"); }"),
+            ""
+        );
+        test_num("'outer: loop { while true { break 'outer 7; } };", dec!(7));
+        assert_eq!(
+            run("let i := 0\n'a: do {\n  i += 1\n  loop { break 'a; }\n} while true\nprint(i)"),
+            "1"
+        );
+
+        assert!(matches!(
+            eval("for x in [1] { break 'nope; }"),
+            Err(ExprError::UnknownLabel { label }) if label == "nope"
+        ));
+        assert!(matches!(
+            eval("'f: for x in [1] { fn g() { continue 'f; } g(); }"),
+            Err(ExprError::UnknownLabel { label }) if label == "f"
+        ));
+        assert_eq!(
+            eval::parse("'a: while x { break 'a 1; }").unwrap()[0].to_string(),
+            "'a: while x {\n    break 'a 1;\n}"
+        );
+        assert!(check::check("'a: for x in [1] { break 'a; }\nprint(1);").is_empty());
+    }
+
+    #[test]
+    fn test_spread() {
+        assert_eq!(
+            eval("let xs := [2, 3]; [1, ...xs, 4];")
+                .unwrap()
+                .to_string(),
+            "[1, 2, 3, 4]"
+        );
+        assert_eq!(
+            eval("[...[], ...[1], ...[[2]]];").unwrap().to_string(),
+            "[1, [2]]"
+        );
+        test_num("let args := [2, 10]; pow(...args);", dec!(1024));
+        test_num(
+            "let f := |a, b, c| a * 100 + b * 10 + c; f(1, ...[2, 3]);",
+            dec!(123),
+        );
+        test_num("[1, 2].fold(...[0, |a, b| a + b]);", dec!(3));
+
+        // Arguments are counted after spreading.
+        assert!(matches!(
+            eval("sqrt(...[1, 2]);"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        assert!(matches!(
+            eval("[1, ...2];"),
+            Err(ExprError::InvalidDataType { loc, .. }) if loc == "spread"
+        ));
+        assert!(check::check("pow(...[2, 10]);").is_empty());
+        assert_eq!(
+            eval::parse("f(...xs, [...ys]);").unwrap()[0].to_string(),
+            "f(...xs, [...ys])"
+        );
+    }
+
+    #[test]
+    fn test_default_parameters() {
+        let greet = "fn greet(name: String, greeting: String = \"hello\") -> String { \"${greeting}, ${name}\"; }";
+        assert_eq!(
+            eval(&format!("{greet} greet(\"ann\");")).unwrap(),
+            Data::String("hello, ann".into())
+        );
+        assert_eq!(
+            eval(&format!("{greet} greet(\"ann\", \"hi\");")).unwrap(),
+            Data::String("hi, ann".into())
+        );
+        assert_eq!(
+            eval(&format!("{greet} greet(greeting: \"hey\", name: \"bo\");")).unwrap(),
+            Data::String("hey, bo".into())
+        );
+        assert!(matches!(
+            eval(&format!("{greet} greet();")),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        assert!(matches!(
+            eval(&format!("{greet} greet(greeting: \"hey\");")),
+            Err(ExprError::MissingArgument { name, .. }) if name == "name"
+        ));
+
+        // Defaults can use the parameters before them, and are evaluated on each call.
+        test_num(
+            "fn area(w: Number, h: Number = w) -> Number { w * h; } area(3) + area(2, 5);",
+            dec!(19),
+        );
+        test_num(
+            "let n := 1; fn f(x: Number = n * 10) -> Number { x; } n := 2; f();",
+            dec!(20),
+        );
+        // A default still has to have the parameter's type.
+        assert!(matches!(
+            eval("fn f(x: Number = \"a\") -> Number { x; } f();"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+
+        assert!(eval::parse("fn f(a: Number = 1, b: Number) { a; }").is_err());
+        assert_eq!(
+            eval::parse("fn f(a: Number, b: Number = 1) -> Number { a; }").unwrap()[0].to_string(),
+            "fn f(a: Number, b: Number = 1) -> Number {\n    a;\n}"
+        );
+
+        let script = "fn f(a: Number, b: Number = 1) -> Number { a + b; }\nf(1);\nf(1, 2);\nf();\n";
+        assert_eq!(
+            check::check(script)
+                .iter()
+                .map(|w| w.message.as_str())
+                .collect::<Vec<_>>(),
+            ["`f` takes 1 to 2 arguments, but 0 are passed."]
+        );
+    }
+
+    #[test]
+    fn test_variadic_functions() {
+        test_num("max(3, 9, 4);", dec!(9));
+        test_num("min(3, 9, -4, 0);", dec!(-4));
+        test_num("max(1, 2);", dec!(2));
+        assert!(matches!(
+            eval("max(1);"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        assert!(matches!(
+            eval("max(1, 2, \"3\");"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+
+        let sum_all = "fn sum_all(xs: Number...) -> Number { sum(xs); }";
+        test_num(&format!("{sum_all} sum_all(1, 2, 3);"), dec!(6));
+        test_num(&format!("{sum_all} sum_all();"), dec!(0));
+        test_num(&format!("{sum_all} sum_all(...[4, 5]);"), dec!(9));
+        test_num(
+            "fn f(first: Number, rest: Any...) -> Number { first + length(rest); } f(10, \"a\", [], 1);",
+            dec!(13),
+        );
+        assert!(matches!(
+            eval(&format!("{sum_all} sum_all(1, \"2\");")),
+            Err(ExprError::InvalidFunctionArguements { expected, .. })
+                if expected == "(xs: Number...)"
+        ));
+
+        assert!(eval::parse("fn f(xs: Number..., y: Number) { 1; }").is_err());
+        assert!(eval::parse("fn f(xs: Number... = 1) { 1; }").is_err());
+        assert_eq!(
+            check::check("max(1);\nmax(1, 2, 3);\nmax(1, \"a\", 3);")
+                .iter()
+                .map(|w| w.message.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "`max` takes at least 2 arguments, but 1 is passed.",
+                "Argument 2 of `max` should be a `Number`, but this is a `String`."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_imports() {
+        let dir = env::temp_dir().join("ls-imports");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib/vector.ls"),
+            "import \"../util.ls\";\nfn length(v: Any) -> Number { sqrt(square(v[0]) + square(v[1])); }\nlet origin := [0, 0];",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("util.ls"),
+            "fn square(x: Number) -> Number { x * x; }",
+        )
+        .unwrap();
+        fs::write(dir.join("a.ls"), "import \"b.ls\";").unwrap();
+        fs::write(dir.join("b.ls"), "import \"a.ls\";").unwrap();
+        fs::write(dir.join("broken.ls"), "let := ;").unwrap();
+        fs::write(
+            dir.join("types.ls"),
+            "record P(x: Number);\nenum Color { Red, Green }",
+        )
+        .unwrap();
+
+        let import = |source: &str| {
+            let mut state = ExecutionState::new();
+            state.files.push(dir.join("main.ls"));
+            eval_with_state(source, &mut state)
+        };
+
+        assert_eq!(
+            import("import \"lib/vector.ls\"; length([3, 4]) + length(origin) + square(2);")
+                .unwrap(),
+            Data::Number(dec!(9))
+        );
+        // Signatures can name the enums and records an import declares.
+        assert_eq!(
+            import("import \"types.ls\"; fn f(p: P, c: Color) -> Number { c == Color.Red ? p.x : 0 } f(P(2), Color.Red);")
+                .unwrap(),
+            Data::Number(dec!(2))
+        );
+        assert!(matches!(
+            import("import \"a.ls\";"),
+            Err(EvalError::Runtime(ExprError::CircularImport { chain }))
+                if chain.matches("a.ls").count() == 2
+        ));
+        assert!(matches!(
+            import("import \"missing.ls\";"),
+            Err(EvalError::Runtime(ExprError::IoError { path, .. })) if path.ends_with("missing.ls")
+        ));
+        assert!(matches!(
+            import("import \"broken.ls\";"),
+            Err(EvalError::Runtime(ExprError::InvalidImport { path, .. })) if path.ends_with("broken.ls")
+        ));
+        assert!(eval::parse("import \"${x}.ls\";").is_err());
+        assert!(matches!(
+            eval_with("import \"a.ls\";", ExecutionState::sandboxed()),
+            Err(ExprError::SandboxViolation { name, .. }) if name == "import"
+        ));
+    }
+
+    #[test]
+    fn test_import_modules() {
+        let dir = env::temp_dir().join("ls-modules");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("vector.ls"),
+            "fn square(x: Number) -> Number { x * x; }\nfn length(v: Any) -> Number { sqrt(square(v[0]) + square(v[1])); }",
+        )
+        .unwrap();
+
+        let import = |source: &str| {
+            let mut state = ExecutionState::new();
+            state.files.push(dir.join("main.ls"));
+            eval_with_state(
+                &format!("import \"vector.ls\" as vec;\n{source}"),
+                &mut state,
+            )
+        };
+
+        assert_eq!(
+            import("vec.length([3, 4]) + length([1, 2, 3]);").unwrap(),
+            Data::Number(dec!(8))
+        );
+        assert_eq!(import("vec.square(3);").unwrap(), Data::Number(dec!(9)));
+        assert!(matches!(
+            import("square(3);"),
+            Err(EvalError::Runtime(ExprError::FunctionNotFound { name })) if name == "square"
+        ));
+        // Only what the file declares is reachable through the module.
+        assert!(matches!(
+            import("vec.sqrt(4);"),
+            Err(EvalError::Runtime(ExprError::FunctionNotFound { name })) if name == "vec.sqrt"
+        ));
+        assert_eq!(
+            import("let vec := [1, 2]; vec.length();").unwrap(),
+            Data::Number(dec!(2))
+        );
+
+        let source = "import \"vector.ls\" as vec;\nvec.length([3, 4]);";
+        assert!(check::check(source).is_empty());
+        assert!(analysis::free_variables(source).unwrap().is_empty());
+        assert_eq!(
+            eval::parse(source).unwrap()[0].to_string(),
+            "import \"vector.ls\" as vec"
+        );
+    }
+
+    #[test]
+    fn test_structs() {
+        let point = "struct Point {\n    x: Number,\n    y: Number\n}\n";
+
+        test_num(
+            &format!("{point}let p := Point {{ x: 1, y: 2 }}\np.x + p.y"),
+            dec!(3),
+        );
+        test_num(&format!("{point}Point(3, 4).y"), dec!(4));
+        test_num(&format!("{point}Point {{ y: 5, x: 1 }}.y"), dec!(5));
+        assert_eq!(
+            eval(&format!("{point}Point {{ x: 1, y: 2 }} == Point(1, 2)")).unwrap(),
+            Data::Bool(true)
+        );
+        assert_eq!(
+            eval(&format!(
+                "{point}record Other(x: Number, y: Number);\nOther(1, 2) == Point(1, 2)"
+            ))
+            .unwrap(),
+            Data::Bool(false)
+        );
+        assert_eq!(
+            eval(&format!("{point}Point {{ x: 1, y: 2 }}"))
+                .unwrap()
+                .to_string(),
+            "Point {x: 1, y: 2}"
+        );
+        // Blocks after a condition are still blocks.
+        test_num("let x := true; if x { 2; } else { 3; }", dec!(2));
+
+        assert!(matches!(
+            eval(&format!("{point}Point {{ x: 1 }}")),
+            Err(ExprError::MissingArgument { .. })
+        ));
+        assert!(matches!(
+            eval(&format!("{point}Point {{ x: 1, y: 2, z: 3 }}")),
+            Err(ExprError::UnknownArgument { .. })
+        ));
+        assert_eq!(
+            eval::parse("struct Point { x: Number, x: Number }")
+                .unwrap_err()
+                .to_string(),
+            "Field `x` appears more than once in record `Point`"
+        );
+        assert_eq!(
+            check::check(&format!("{point}Point(1);"))
+                .iter()
+                .map(|w| w.message.as_str())
+                .collect::<Vec<_>>(),
+            ["`Point` takes 2 arguments, but 1 is passed."]
+        );
+    }
+
+    #[test]
+    fn test_enums() {
+        let color = "enum Color {\n    Red,\n    Green,\n    Blue\n}\n";
+
+        assert_eq!(
+            eval(&format!("{color}Color.Red == Color.Red")).unwrap(),
+            Data::Bool(true)
+        );
+        assert_eq!(
+            eval(&format!("{color}Color.Red != Color.Blue")).unwrap(),
+            Data::Bool(true)
+        );
+        assert_eq!(
+            eval(&format!("{color}Color.Green")).unwrap().to_string(),
+            "Color.Green"
+        );
+        assert_eq!(
+            eval(&format!("{color}type(Color.Green)")).unwrap(),
+            Data::String("Color".into())
+        );
+        test_num(
+            &format!(
+                "{color}fn score(c: Color) -> Number {{ if c == Color.Red {{ 1; }} elif c == Color.Green {{ 2; }} else {{ 3; }} }}\nscore(Color.Green) + score(Color.Blue)"
+            ),
+            dec!(5),
+        );
+        test_num(
+            &format!("{color}struct Pixel {{ x: Number, color: Color }}\nlet p := Pixel {{ x: 1, color: Color.Blue }}\np.color == Color.Blue ? p.x : 0"),
+            dec!(1),
+        );
+
+        assert!(matches!(
+            eval(&format!("{color}enum Shape {{ Circle }}\nColor.Red == Shape.Circle")),
+            Err(ExprError::EnumMismatch { left, right }) if left == "Color" && right == "Shape"
+        ));
+        assert!(matches!(
+            eval(&format!("{color}Color.Purple")),
+            Err(ExprError::UnknownVariant { name, variant }) if name == "Color" && variant == "Purple"
+        ));
+        assert!(matches!(
+            eval(&format!("{color}fn f(c: Color) -> Null {{ }}\nf(1)")),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        let parse_error = |input: &str| eval::parse(input).unwrap_err().to_string();
+        assert_eq!(
+            parse_error("enum Color { Red, Red }"),
+            "Variant `Red` appears more than once in enum `Color`"
+        );
+        assert_eq!(
+            parse_error("enum color { Red }"),
+            "Enum `color` should start with a capital letter"
+        );
+        assert_eq!(
+            parse_error("fn f(c: color) -> Null { }"),
+            "Invalid type `color` in function `f` signature"
+        );
+
+        // A signature can only name types that exist, and each enum is a type of its own.
+        assert_eq!(
+            eval("fn f(x: Nmber) -> Null { }").unwrap_err().to_string(),
+            "Unknown type `Nmber` in function `f` signature."
+        );
+        assert_eq!(
+            eval("let f := |c: Colour| c; f").unwrap_err().to_string(),
+            "Unknown type `Colour` in closure signature."
+        );
+        // They can be declared after the function naming them.
+        test_num(
+            &format!("fn f(c: Color) -> Number {{ 1 }}\n{color}f(Color.Red)"),
+            dec!(1),
+        );
+        assert!(matches!(
+            eval(&format!("{color}enum Shape {{ Circle }}\nfn f(c: Color) -> Null {{ }}\nf(Shape.Circle)")),
+            Err(ExprError::InvalidFunctionArguements { expected, found })
+                if expected == "(c: Color)" && found == "(Shape)"
+        ));
+        test_num(
+            "struct P { x: Number, y: Number }\nfn norm(p: P) -> Number { p.x * p.x + p.y * p.y }\nnorm(P { x: 3, y: 4 })",
+            dec!(25),
+        );
+        assert!(matches!(
+            eval("record P(x: Number)\nrecord Q(x: Number)\nfn f(p: P) -> Number { p.x }\nf(Q(1))"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        // Types declared by earlier input, like a previous REPL line, can be named too.
+        let mut state = ExecutionState::new();
+        eval_with_state(color, &mut state).unwrap();
+        eval_with_state("fn f(c: Color) -> Bool { c == Color.Red }", &mut state).unwrap();
+        assert_eq!(
+            eval_with_state("f(Color.Red)", &mut state).unwrap(),
+            Data::Bool(true)
+        );
+
+        assert!(check::check(color).is_empty());
+        assert!(analysis::free_variables(&format!("{color}Color.Red"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_try_catch() {
+        test_num("try { 1 / 0; } catch e { 5; }", dec!(5));
+        test_num("try { 2; } catch e { 5; }", dec!(2));
+        assert_eq!(
+            eval("try { 1 / 0; } catch e { e.kind; }").unwrap(),
+            Data::String("DivideBy0".into())
+        );
+        assert_eq!(
+            eval("try { error(\"bad input\"); } catch e { e.message; }").unwrap(),
+            Data::String("bad input".into())
+        );
+        assert_eq!(
+            eval("let x := try { sqrt(\"4\"); } catch e { type(e); }\nx").unwrap(),
+            Data::String("Error".into())
+        );
+        // Errors inside functions are caught by the caller's `try`, but `return` isn't an error.
+        test_num(
+            "fn f(x: Number) -> Number { if x < 0 { error(\"negative\"); }; return x * 2; }\ntry { f(3); } catch e { 0; }",
+            dec!(6),
+        );
+        test_num(
+            "fn f(x: Number) -> Number { if x < 0 { error(\"negative\"); }; x; }\ntry { f(-1); } catch e { -1; }",
+            dec!(-1),
+        );
+        test_num(
+            "for x in [1, 2] { try { break; } catch e { print(e); } }\n7",
+            dec!(7),
+        );
+        // An error in `catch` goes on up.
+        assert!(matches!(
+            eval("try { 1 / 0; } catch e { error(e.message); }"),
+            Err(ExprError::Raised { message }) if message == "Attempted to divide by 0."
+        ));
+        assert!(matches!(
+            eval("error(\"uncaught\");"),
+            Err(ExprError::Raised { message }) if message == "uncaught"
+        ));
+        assert!(eval::parse("try { 1; } catch { 2; }").is_err());
+        assert!(check::check("try { 1 / 0; } catch e { print(e.message); }").is_empty());
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let limited = |steps| {
+            let mut state = ExecutionState::new();
+            state.steps_left = Some(std::rc::Rc::new(std::cell::Cell::new(steps)));
+            state
+        };
+
+        let result = eval_with_state("while true {}", &mut limited(100));
+        assert!(matches!(
+            result,
+            Err(EvalError::Runtime(ExprError::OutOfSteps))
+        ));
+        let result = eval_with_state("try { loop {} } catch e { 1; }", &mut limited(100));
+        assert!(matches!(
+            result,
+            Err(EvalError::Runtime(ExprError::OutOfSteps))
+        ));
+        assert_eq!(
+            eval_with_state(
+                "let x := 0; for i in 0..5 { x += i; }; x;",
+                &mut limited(100)
+            )
+            .unwrap(),
+            Data::Number(dec!(10))
+        );
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut state = ExecutionState::new();
+        state.output = output::OutputSink::capture();
+        let result = eval_with_state("print(1); exit(4); print(2);", &mut state);
+        assert!(matches!(
+            result,
+            Err(EvalError::Runtime(ExprError::Exit(4)))
+        ));
+        assert_eq!(state.output.captured(), "1");
+        assert_eq!(exit_code(&result.unwrap_err()), 4);
+
+        // Neither `try` nor a function call stops it.
+        assert!(matches!(
+            eval("try { exit(2); } catch e { 5; }"),
+            Err(ExprError::Exit(2))
+        ));
+        assert!(matches!(
+            eval("fn quit() -> Null { exit(0); } quit(); 1;"),
+            Err(ExprError::Exit(0))
+        ));
+        assert!(matches!(
+            eval("bench(fn() { exit(3); }, 2);"),
+            Err(ExprError::Exit(3))
+        ));
+
+        // The REPL stops reading and hands back the code.
+        let mut output = vec![];
+        let code = repl(
+            "1;\nexit(5);\n2;\n".as_bytes(),
+            &mut output,
+            &mut ExecutionState::new(),
+        )
+        .unwrap();
+        assert_eq!(code, 5);
+        assert_eq!(String::from_utf8(output).unwrap(), "[0]> out[0] = 1\n[1]> ");
+    }
+
+    #[test]
+    fn test_assert() {
+        test_num(
+            "let x := 5\nassert x == 5\nassert x > 1, \"x should be big\"\nx",
+            dec!(5),
+        );
+        assert!(matches!(
+            eval("let x := 4; assert x == 5;"),
+            Err(ExprError::AssertionFailed { message }) if message == "`(x == 5)`"
+        ));
+        assert!(matches!(
+            eval("let x := 4; assert x == 5, \"x should be ${5}\";"),
+            Err(ExprError::AssertionFailed { message }) if message == "`(x == 5)`, x should be 5"
+        ));
+        assert_eq!(
+            eval("assert false;").unwrap_err().to_string(),
+            "Assertion failed: `false`."
+        );
+        assert!(matches!(
+            eval("assert 1;"),
+            Err(ExprError::InvalidDataType { loc, .. }) if loc == "assert condition"
+        ));
+        // The message is only evaluated when the assertion fails.
+        test_num("assert true, 1 / 0; 1;", dec!(1));
+        assert_eq!(
+            eval("try { assert 1 > 2; } catch e { e.kind; }").unwrap(),
+            Data::String("AssertionFailed".into())
+        );
+    }
+
+    #[test]
+    fn test_repetition() {
+        assert_eq!(eval("\"ab\" * 3;").unwrap(), Data::String("ababab".into()));
+        assert_eq!(eval("3 * \"ab\";").unwrap(), Data::String("ababab".into()));
+        assert_eq!(eval("\"ab\" * 0;").unwrap(), Data::String("".into()));
+        assert_eq!(eval("[0] * 5;").unwrap().to_string(), "[0, 0, 0, 0, 0]");
+        assert_eq!(eval("2 * [1, 2];").unwrap().to_string(), "[1, 2, 1, 2]");
+        assert_eq!(
+            eval("let s := \"-\"; s *= 4; s;").unwrap(),
+            Data::String("----".into())
+        );
+        test_num("2 * 3;", dec!(6));
+        test_num(
+            "fn line(n: Number) -> String { \"=\" * n; } grapheme_length(line(4));",
+            dec!(4),
+        );
+
+        for source in ["\"ab\" * -1;", "[1] * 1.5;", "\"ab\" * 100000000;"] {
+            assert!(
+                matches!(eval(source), Err(ExprError::MathDomain { .. })),
+                "{source}"
+            );
+        }
+        assert!(matches!(
+            eval("\"ab\" * \"cd\";"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+        assert!(matches!(
+            eval("[1] * [2];"),
+            Err(ExprError::InvalidFunctionArguements { .. })
+        ));
+    }
+
+    #[test]
+    fn test_add() {
+        test_num("1 + 2;", dec!(3));
+        test_num("0.1 + 0.2;", dec!(0.3));
+        assert_eq!(
+            eval("[1, 2] + [3, 4];").unwrap().to_string(),
+            "[1, 2, 3, 4]"
+        );
+        assert_eq!(eval("[] + [1] + [];").unwrap().to_string(), "[1]");
+        assert_eq!(
+            eval("let xs := [1]; xs += [\"a\"]; xs;")
+                .unwrap()
+                .to_string(),
+            "[1, a]"
+        );
+        test_num(
+            "fn both(a: Array, b: Array) -> Array { a + b; } length(both([1], [2, 3]));",
+            dec!(3),
+        );
+
+        // Strings are joined with interpolation, not `+`.
+        for source in ["\"a\" + \"b\";", "[1] + 2;", "1 + [2];", "[1] + \"a\";"] {
+            assert!(
+                matches!(
+                    eval(source),
+                    Err(ExprError::InvalidFunctionArguements { .. })
+                ),
+                "{source}"
+            );
+        }
+        assert!(matches!(
+            eval("79228162514264337593543950335 + 1;"),
+            Err(ExprError::MathDomain { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bit_functions() {
+        test_num("band(12, 10);", dec!(8));
+        test_num("bor(12, 10);", dec!(14));
+        test_num("bxor(12, 10);", dec!(6));
+        test_num("bnot(5);", dec!(-6));
+        test_num("band(-1, 255);", dec!(255));
+        test_num("shl(1, 10);", dec!(1024));
+        test_num("shr(1024, 3);", dec!(128));
+        test_num("shr(-8, 1);", dec!(-4));
+        test_num(
+            "let flags := bor(1, 4); band(flags, 4) != 0 ? 1 : 0;",
+            dec!(1),
+        );
+
+        for source in [
+            "band(1.5, 1);",
+            "bor(1, 9223372036854775808);",
+            "shl(1, 64);",
+            "shr(1, -1);",
+            "shl(1, 0.5);",
+            "shl(4611686018427387904, 1);",
+        ] {
+            assert!(
+                matches!(eval(source), Err(ExprError::MathDomain { .. })),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_while_updates_variables() {
+        test_num(
+            "let i := 0; let sum := 0; while i < 10 { i += 1; sum += i; } sum;",
+            dec!(55),
+        );
+        test_num("let i := 0; do { i += 1; } while i < 3; i;", dec!(3));
+        // Updates made before a `continue` or `break` are kept.
+        test_num(
+            "let i := 0; let odd := 0; while i < 10 { i += 1; if i % 2 == 0 { continue; }; odd += 1; } odd;",
+            dec!(5),
+        );
+        test_num(
+            "let i := 0; while true { i += 1; if i == 4 { break; }; } i;",
+            dec!(4),
+        );
+        // Variables declared in the body don't outlive the loop.
+        assert!(eval("let i := 0; while i < 2 { let j := i; i += 1; } j;").is_err());
+    }
+
+    #[test]
+    fn test_for_updates_variables() {
+        assert_eq!(
+            eval("let items := []; for i in range(0, 5) { items := append(items, i * i); } items;")
+                .unwrap(),
+            Data::Array(
+                [0, 1, 4, 9, 16]
+                    .into_iter()
+                    .map(|n| Data::Number(n.into()))
+                    .collect()
+            )
+        );
+        test_num(
+            "let total := 0; for x in [1, 2, 3, 4] { if x == 2 { continue; }; total += x; } total;",
+            dec!(8),
+        );
+        // Each iteration sees what the one before assigned.
+        test_num(
+            "let a := 0; let b := 1; for i in range(0, 10) { let next := a + b; a := b; b := next; } a;",
+            dec!(55),
+        );
+        assert!(eval("for x in [1] { let y := x; } y;").is_err());
+        // The loop variable doesn't replace an outer variable of the same name.
+        test_num("let x := 10; for x in [1, 2] { x += 1; } x;", dec!(10));
+    }
+
+    #[test]
+    fn test_else_if() {
+        let describe = "fn describe(x: Number) -> String {
+    if x > 100 { \"huge\" }
+    else if x > 10 { \"big\" }
+    elif x > 0 { \"small\" }
+    else if x == 0 { \"zero\" }
+    else { \"negative\" }
+}
+";
+        for (x, expected) in [
+            (500, "huge"),
+            (50, "big"),
+            (5, "small"),
+            (0, "zero"),
+            (-5, "negative"),
+        ] {
+            assert_eq!(
+                eval(&format!("{describe}describe({x});")).unwrap(),
+                Data::String(expected.to_string())
+            );
+        }
+
+        assert_eq!(
+            parse("if a { 1; } else if b { 2; } else { 3; };"),
+            parse("if a { 1; } elif b { 2; } else { 3; };")
+        );
+
+        // Without a final `else` no branch may run, so what follows is reachable.
+        let kinds = |input: &str| check(input).into_iter().map(|w| w.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds(
+                "fn f(x: Number) -> Number { if x > 0 { exit(1); } else if x < 0 { exit(2); }; x }"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            kinds("fn f(x: Number) -> Number { if x > 0 { exit(1); } else if x < 0 { exit(2); } else { exit(3); }; x }"),
+            vec![WarningKind::Unreachable]
+        );
+    }
+
+    #[test]
+    fn test_negation() {
+        test_num("-2.abs();", dec!(-2));
+        test_num("(-2).abs();", dec!(2));
+        test_num("-max(1, 2);", dec!(-2));
+        test_num("--5;", dec!(5));
+        test_num("-sqrt(4);", dec!(-2));
+        test_num("-(1 + 2);", dec!(-3));
+        test_num("let x := -3; - x.abs();", dec!(-3));
+        test_num("-2 * 3;", dec!(-6));
+        test_num("2 - -3;", dec!(5));
+        test_num("max(-1, -2);", dec!(-1));
+
+        assert_eq!(
+            eval::parse("-2.abs();").unwrap(),
+            eval::parse("-(2.abs());").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_shebang() {
+        use lexer::{LexerError, LexerErrorKind};
+
+        test_num("#!/usr/bin/env ls run\n1 + 2;", dec!(3));
+        assert_eq!(
+            eval::parse("#!/usr/bin/env ls run\nlet x := 1;").unwrap(),
+            eval::parse("let x := 1;").unwrap()
+        );
+
+        // Errors after it are reported where they are.
+        let source = "#!/usr/bin/env ls run\nlet s := \"abc";
+        assert_eq!(
+            lexer::tokens(source),
+            Err(LexerError::new(LexerErrorKind::UnterminatedString, 31..35))
+        );
+
+        for source in ["1;\n#!/usr/bin/env ls run", " #!/usr/bin/env ls run"] {
+            let start = source.find('#').unwrap();
+            assert_eq!(
+                lexer::tokens(source),
+                Err(LexerError::new(
+                    LexerErrorKind::UnexpectedCharacter('#'),
+                    start..start + 1
+                )),
+                "{source:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_help() {
+        let printed = |input: &str| {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            eval_with_state(input, &mut state).map(|_| state.output.captured())
+        };
+
+        assert_eq!(
+            printed("/// Adds one.\n/// Any number works.\nfn inc(x: Number) -> Number { x + 1 }\nhelp(\"inc\");")
+                .unwrap(),
+            "fn inc(x: Number) -> Number\n    Adds one.\n    Any number works.\n"
+        );
+        assert_eq!(
+            printed(r#"help("sqrt");"#).unwrap(),
+            "fn sqrt(n: Number) -> Number\n    The square root of `n`.\n"
+        );
+        // Only `///` right before a declaration documents it.
+        assert_eq!(
+            printed("/// Not this.\nlet y := 1;\n//// Nor this.\nfn f() {}\nhelp(\"f\");").unwrap(),
+            "fn f() -> Null\n"
+        );
+        assert!(matches!(
+            printed(r#"help("nope");"#),
+            Err(EvalError::Runtime(ExprError::FunctionNotFound { name })) if name == "nope"
+        ));
+
+        let list = printed("fn f() {}\nhelp();").unwrap();
+        assert!(list
+            .lines()
+            .any(|line| line.starts_with("numeric: ") && line.contains("sqrt")));
+        assert!(list
+            .lines()
+            .any(|line| line.starts_with("string: ") && line.contains("trim")));
+        assert!(list.contains("declared: f\n"));
+
+        // A documented function formats back to the same program.
+        let program =
+            eval::parse("/// Doubles.\nfn double(x: Number) -> Number { x * 2 }").unwrap();
+        let formatted = program
+            .iter()
+            .map(expr::format_statement)
+            .collect::<String>();
+        assert_eq!(eval::parse(&formatted).unwrap(), program);
+    }
+
+    #[test]
+    fn test_for_strings() {
+        assert_eq!(
+            eval(r#"let out := ""; for c in "héllo" { out := "${c}${out}"; } out;"#).unwrap(),
+            Data::String("olléh".to_string())
+        );
+        test_num(
+            r#"let n := 0; for c in "banana" { if c == "a" { n += 1; }; } n;"#,
+            dec!(3),
+        );
+        test_num(r#"let n := 0; for c in "" { n += 1; } n;"#, dec!(0));
+        test_num(r#"length("héllo");"#, dec!(5));
+        assert_eq!(
+            eval(r#"let s := "abc"; let out := []; for i in range(0, length(s)) { out := append(out, s[i:i + 1]); } out;"#)
+                .unwrap(),
+            eval(r#"["a", "b", "c"];"#).unwrap()
+        );
+
+        let Err(ExprError::InvalidDataType { expected, .. }) = eval("for x in true { }") else {
+            panic!("a Bool isn't iterable");
+        };
+        assert_eq!(expected, "Array, String or Number");
+    }
+
+    #[test]
+    fn test_for_numbers() {
+        test_num("let n := 0; for i in 10 { n += 1; } n;", dec!(10));
+        test_num(
+            "let total := 0; for i in 5 { total += i; } total;",
+            dec!(10),
+        );
+        test_num("let n := 0; for i in 0 { n += 1; } n;", dec!(0));
+        assert_eq!(
+            eval("let out := []; for i in 3 { out := append(out, i); } out;").unwrap(),
+            eval("range(0, 3);").unwrap()
+        );
+
+        for source in ["for i in -1 { }", "for i in 2.5 { }"] {
+            assert!(
+                matches!(
+                    eval(source),
+                    Err(ExprError::InvalidDataType { expected, .. }) if expected == "non-negative integer"
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_for_with_index() {
+        assert_eq!(
+            eval(r#"let out := []; for i, x in ["a", "b", "c"] { out := append(out, "${i}${x}"); } out;"#)
+                .unwrap(),
+            eval(r#"["0a", "1b", "2c"];"#).unwrap()
+        );
+        assert_eq!(
+            eval(r#"let out := []; for i, c in "hé" { out := append(out, [i, c]); } out;"#)
+                .unwrap(),
+            eval(r#"[[0, "h"], [1, "é"]];"#).unwrap()
+        );
+        test_num(
+            "let total := 0; for i, n in 4 { total += i * n; } total;",
+            dec!(14),
+        );
+        test_num(
+            "let total := 0; for x in [5, 6] { total += x; } total;",
+            dec!(11),
+        );
+
+        let source = "for i, x in xs { let y := i + x + z; }";
+        assert_eq!(analysis::free_variables(source).unwrap(), ["xs", "z"]);
+        assert!(check::check(source).is_empty());
+
+        let program = eval::parse("'a: for i, x in [1, 2] { continue 'a; }").unwrap();
+        let formatted = program
+            .iter()
+            .map(expr::format_statement)
+            .collect::<String>();
+        assert_eq!(eval::parse(&formatted).unwrap(), program);
+    }
+
+    #[test]
+    fn test_example_scripts() {
+        // The AoC solution reads its input from the author's machine, so only the scripts at
+        // the top level are run.
+        let scripts = fs::read_dir("scripts")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|e| e == "lils"));
+
+        for path in scripts {
+            let mut state = ExecutionState::new();
+            state.output = output::OutputSink::capture();
+            state.files.push(fs::canonicalize(&path).unwrap());
+
+            // A script that never ends is interrupted, which fails it.
+            let interrupted = state.interrupted.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_secs(10));
+                interrupted.store(true, Ordering::Relaxed);
+            });
+
+            let source = fs::read_to_string(&path).unwrap();
+            if let Err(e) = eval_with_state(&source, &mut state) {
+                panic!("{}: {e}", path.display());
+            }
+        }
+    }
 }
@@ -0,0 +1,94 @@
+use std::{
+    cell::RefCell,
+    io::{self, LineWriter, Stdout, Write},
+    rc::Rc,
+};
+
+use crate::expr::{error::ExprError, EResult};
+
+/// Where `print` and `println` write to. Clones share the same destination, so output from
+/// function bodies and loops ends up in the same place, in order.
+#[derive(Debug, Clone)]
+pub struct OutputSink {
+    target: Rc<RefCell<Target>>,
+}
+
+#[derive(Debug)]
+enum Target {
+    Stdout {
+        writer: LineWriter<Stdout>,
+        /// Whether the last thing written didn't end with a newline.
+        mid_line: bool,
+    },
+    /// Keeps everything written, for tests and code embedding the interpreter.
+    Capture(Vec<u8>),
+}
+
+impl Default for OutputSink {
+    fn default() -> Self {
+        Self::stdout()
+    }
+}
+
+impl OutputSink {
+    pub fn stdout() -> Self {
+        Self {
+            target: Rc::new(RefCell::new(Target::Stdout {
+                writer: LineWriter::new(io::stdout()),
+                mid_line: false,
+            })),
+        }
+    }
+
+    pub fn capture() -> Self {
+        Self {
+            target: Rc::new(RefCell::new(Target::Capture(vec![]))),
+        }
+    }
+
+    /// Writes `text` and flushes it, so it is out before the statement that printed it
+    /// finishes.
+    pub fn write(&self, text: &str) -> EResult<()> {
+        let io_error = |source| ExprError::IoError {
+            path: "<stdout>".to_string(),
+            source,
+        };
+
+        match &mut *self.target.borrow_mut() {
+            Target::Stdout { writer, mid_line } => {
+                writer.write_all(text.as_bytes()).map_err(io_error)?;
+                writer.flush().map_err(io_error)?;
+
+                if !text.is_empty() {
+                    *mid_line = !text.ends_with('\n');
+                }
+            }
+            Target::Capture(buffer) => buffer.extend_from_slice(text.as_bytes()),
+        }
+
+        Ok(())
+    }
+
+    /// Everything written so far, empty unless this is a capture sink.
+    pub fn captured(&self) -> String {
+        match &*self.target.borrow() {
+            Target::Stdout { .. } => String::new(),
+            Target::Capture(buffer) => String::from_utf8_lossy(buffer).into_owned(),
+        }
+    }
+
+    /// Writes `text` to stderr, first moving to a new line if stdout was left mid-line so the
+    /// two don't end up on the same line of the terminal.
+    pub fn diagnostic(&self, text: &str) {
+        if let Target::Stdout { writer, mid_line } = &mut *self.target.borrow_mut() {
+            let _ = writer.flush();
+
+            if *mid_line {
+                eprintln!();
+                *mid_line = false;
+            }
+        }
+
+        eprint!("{text}");
+    }
+}
@@ -1,17 +1,21 @@
-use std::{process::exit, str::FromStr};
+// chumsky's `select!` closures return its (large) `Simple` error type.
+#![allow(clippy::result_large_err)]
+
+use std::{ops::Range, str::FromStr, sync::Arc};
 
 use ariadne::{Color, Label, Report, ReportKind, Source};
 use chumsky::{
-    error::Simple,
+    error::{Simple, SimpleReason},
     prelude::{end, just, recursive},
     select, Parser,
 };
+use rust_decimal::Decimal;
 
 use crate::{
-    data::{Data, DataType},
-    expr::Expr,
+    data::DataType,
+    expr::{Comparison, Expr},
     functions::{FunctionDescriptor, FunctionType},
-    lexer::Token,
+    lexer::{StringPart, Token},
     utils::strings::{DotDebug, DotDisplay},
 };
 
@@ -19,54 +23,99 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
     let statement = recursive(|stmt| {
         let block = just(Token::BlockStart)
             .ignore_then(stmt.clone().repeated())
+            .then_ignore(just(Token::Eol).repeated())
             .then_ignore(just(Token::BlockEnd));
 
+        // `'outer:` before a loop, for `break 'outer` to name.
+        let label = select! { Token::Label(l) => l }
+            .then_ignore(just(Token::Colon))
+            .or_not();
+
         let expr = recursive(|p| {
+            // `(a)` is grouping, a comma makes it a tuple: `(a, b)` or `(a,)`.
             let parenthesized = p
                 .clone()
-                .delimited_by(just(Token::LParen), just(Token::RParen));
+                .then(
+                    just(Token::Comma)
+                        .ignore_then(p.clone().separated_by(just(Token::Comma)).allow_trailing())
+                        .or_not(),
+                )
+                .delimited_by(just(Token::LParen), just(Token::RParen))
+                .map(|(first, rest)| match rest {
+                    None => first,
+                    Some(rest) => Expr::Tuple([first].into_iter().chain(rest).collect()),
+                });
 
             let integer = select! {
                 Token::Number(n) => Expr::Num(n),
             };
 
-            let negative_integer = just(Token::Minus)
-                .then(integer)
-                .map(|(_minus, expr)| Expr::Neg(Box::new(expr)));
-
             let bool = select! {
                 Token::True => Expr::Bool(true),
                 Token::False => Expr::Bool(false),
             };
 
             let string = select! {
-                Token::String(s) => Expr::String(s),
-            };
+                Token::String(parts) => parts,
+            }
+            .try_map(string_literal);
 
             let variable = select! {
                 Token::Ident(name) => Expr::Variable(name),
             };
 
+            // `...xs` splices the elements of an array into an array literal or argument list.
+            let spread = just(Token::Ellipsis)
+                .ignore_then(p.clone())
+                .map(|e| Expr::Spread(Box::new(e)));
+
+            // `name: value` passes an argument by parameter name instead of position.
+            let argument = select! {
+                Token::Ident(name) => name,
+            }
+            .then_ignore(just(Token::Colon))
+            .then(p.clone())
+            .map(|(name, value)| Expr::NamedArgument(name, Box::new(value)))
+            .or(spread.clone())
+            .or(p.clone());
+
             let function = select! {
                 Token::Ident(name) => name,
             }
             .then(
-                p.clone()
+                argument
                     .separated_by(just(Token::Comma))
                     .allow_trailing()
                     .delimited_by(just(Token::LParen), just(Token::RParen)),
             )
             .map(|(name, args)| Expr::Function(name, args));
 
+            // `Point { x: 1, y: 2 }` calls the constructor with named arguments, `Point(x: 1,
+            // y: 2)`.
+            let record_literal = select! {
+                Token::Ident(name) => name,
+            }
+            .then(
+                select! { Token::Ident(field) => field }
+                    .then_ignore(just(Token::Colon))
+                    .then(p.clone())
+                    .map(|(field, value)| Expr::NamedArgument(field, Box::new(value)))
+                    .separated_by(just(Token::Comma))
+                    .at_least(1)
+                    .allow_trailing()
+                    .delimited_by(just(Token::BlockStart), just(Token::BlockEnd)),
+            )
+            .map(|(name, args)| Expr::Function(name, args));
+
+            // `else if` is the same as `elif`.
+            let else_if = just(Token::ElseIf)
+                .ignored()
+                .or(just(Token::Else).then(just(Token::If)).ignored());
+
             let if_block = just(Token::If)
                 .then(p.clone())
                 .then(block.clone())
-                .then(
-                    just(Token::ElseIf)
-                        .then(p.clone())
-                        .then(block.clone())
-                        .repeated(),
-                )
+                .then(else_if.then(p.clone()).then(block.clone()).repeated())
                 .then(just(Token::Else).then(block.clone()).or_not())
                 .map(|((((_, cond), if_block), elifs), else_block)| {
                     Expr::If(
@@ -80,85 +129,147 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
                     )
                 });
 
+            let try_catch = just(Token::Try)
+                .ignore_then(block.clone())
+                .then_ignore(just(Token::Catch))
+                .then(select! { Token::Ident(name) => name })
+                .then(block.clone())
+                .map(|((block, name), catch_block)| Expr::TryCatch(block, name, catch_block));
+
             let block_mapped = block.clone().map(Expr::Block);
 
-            let array = p
+            let infinite_loop = label
                 .clone()
+                .then_ignore(just(Token::Loop))
+                .then(block.clone())
+                .map(|(label, block)| Expr::Loop(label, block));
+
+            let array = spread
+                .or(p.clone())
                 .separated_by(just(Token::Comma))
                 .allow_trailing()
                 .delimited_by(just(Token::ArrayStart), just(Token::ArrayEnd))
                 .map(Expr::Array);
 
-            let closure = just(Token::Bar)
-                .ignore_then(
-                    select! {Token::Ident(name) => name}
-                        .then_ignore(just(Token::Colon))
-                        .then(select! {Token::Ident(t) => t})
-                        .repeated()
-                        .separated_by(just(Token::Comma)),
-                )
-                .then_ignore(just(Token::Bar))
+            // `{}` is left to mean an empty block.
+            let map = p
+                .clone()
+                .then_ignore(just(Token::Colon))
+                .then(p.clone())
+                .separated_by(just(Token::Comma))
+                .at_least(1)
+                .allow_trailing()
+                .delimited_by(just(Token::BlockStart), just(Token::BlockEnd))
+                .map(Expr::Map);
+
+            // Parameters of a closure or anonymous function, where a missing type means Any.
+            let params = select! {Token::Ident(name) => name}
                 .then(
-                    just(Token::Arrow)
+                    just(Token::Colon)
                         .ignore_then(select! {Token::Ident(t) => t})
                         .or_not(),
                 )
+                .separated_by(just(Token::Comma))
+                .allow_trailing();
+            let output_type = just(Token::Arrow)
+                .ignore_then(select! {Token::Ident(t) => t})
+                .or_not();
+
+            // `|x: Number| -> Number { x * 2; }`, or `|x| x * 2` with an expression body.
+            let closure = params
+                .clone()
+                .delimited_by(just(Token::Bar), just(Token::Bar))
+                .then(output_type.clone())
+                .then(
+                    block
+                        .clone()
+                        .map(|block| (block, false))
+                        .or(p.clone().map(|e| (vec![e], true))),
+                )
+                .boxed()
+                .try_map(
+                    |((inputs, output_type), (block, expression_body)), span: Range<usize>| {
+                        // A bare expression is there for its value, a block may not be.
+                        let default_output = if expression_body { "Any" } else { "Null" };
+                        function_value(inputs, output_type, default_output, block, span)
+                    },
+                );
+
+            // `fn(x: Number) -> Number { x * 2; }`
+            let anonymous_function = just(Token::Fn)
+                .ignore_then(params.delimited_by(just(Token::LParen), just(Token::RParen)))
+                .then(output_type)
                 .then(block.clone())
                 .boxed()
-                .map(|((inputs, output_type), block)| {
-                    let dt = |s: &str| -> DataType {
-                        DataType::from_str(s).unwrap_or_else(|_| {
-                            println!("Invalid type in closure signature");
-
-                            exit(2);
-                        })
-                    };
-
-                    let output_type = dt(&output_type.unwrap_or("Null".to_string()));
-
-                    let inputs = inputs
-                        .iter()
-                        .flatten()
-                        .map(|(name, t)| (name.clone(), dt(t)))
-                        .collect::<Vec<_>>();
-
-                    let input_types = inputs.iter().map(|(_, t)| t.clone()).collect::<Vec<_>>();
-                    let input_names = inputs.iter().map(|(n, _)| n.clone()).collect::<Vec<_>>();
-
-                    let function = FunctionDescriptor {
-                        inputs: input_types,
-                        output: output_type,
-                        function: FunctionType::Custom(block, input_names),
-                    };
-
-                    Expr::FunctionValue(function)
+                .try_map(|((inputs, output_type), block), span: Range<usize>| {
+                    function_value(inputs, output_type, "Null", block, span)
                 });
 
-            let atom = block_mapped
+            let atom = map
+                .or(block_mapped)
+                .or(infinite_loop)
                 .or(parenthesized)
                 .or(integer)
-                .or(negative_integer)
                 .or(bool)
                 .or(function.clone())
+                .or(record_literal)
                 .or(variable)
                 .or(if_block)
+                .or(try_catch)
                 .or(array)
                 .or(string)
                 .or(closure)
+                .or(anonymous_function)
                 .boxed();
 
+            // `a[i]` is sugar for `index(a, i)` and `a[start:end]` for `slice(a, start, end)`, so
+            // they fold in just like method calls. An omitted slice bound is passed as null.
+            let indexing = p
+                .clone()
+                .or_not()
+                .then(just(Token::Colon).ignore_then(p.clone().or_not()).or_not())
+                .delimited_by(just(Token::ArrayStart), just(Token::ArrayEnd))
+                .try_map(|(start, end), span: Range<usize>| {
+                    let call = match (start, end) {
+                        (Some(i), None) => Expr::Function("index".to_string(), vec![i]),
+                        (start, Some(end)) => Expr::Function(
+                            "slice".to_string(),
+                            vec![start.unwrap_or(Expr::Null), end.unwrap_or(Expr::Null)],
+                        ),
+                        (None, None) => {
+                            return Err(Simple::custom(span, "Expected an index or slice in `[]`"))
+                        }
+                    };
+
+                    Ok((Token::ArrayStart, call))
+                });
+
             let atom = atom
                 .clone()
-                .then(just(Token::Dot).ignore_then(function).repeated())
+                .then(
+                    just(Token::Dot)
+                        .or(just(Token::SafeDot))
+                        .then(function)
+                        .or(indexing)
+                        // `.name` without a call after it reads a record field.
+                        .or(just(Token::Dot).then(variable))
+                        .repeated(),
+                )
                 .map(|(initial, method_calls)| {
                     method_calls
                         .into_iter()
-                        .fold(initial, |acc, method| match method {
+                        .fold(initial, |acc, (dot, method)| match method {
                             Expr::Function(name, mut args) => {
                                 let mut new_args = vec![acc];
                                 new_args.append(&mut args);
-                                Expr::Function(name, new_args)
+
+                                if dot == Token::SafeDot {
+                                    Expr::SafeCall(name, new_args)
+                                } else {
+                                    Expr::Function(name, new_args)
+                                }
                             }
+                            Expr::Variable(field) => Expr::Field(Box::new(acc), field),
                             _ => unreachable!(),
                         })
                 })
@@ -167,6 +278,8 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
             let not = just(Token::Not)
                 .then(atom.clone())
                 .map(|(_, expr)| Expr::Not(Box::new(expr)));
+            // The only negation: tighter than `*`, looser than method calls, so `-2.abs()` is
+            // `-(2.abs())`.
             let unary = just(Token::Minus)
                 .repeated()
                 .then(atom.or(not))
@@ -178,13 +291,15 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
                 .then(
                     just(Token::Multiply)
                         .or(just(Token::Divide))
+                        .or(just(Token::IntDivide))
                         .or(just(Token::Modulo))
                         .then(unary)
                         .repeated(),
                 )
-                .foldl(|rhs, (op, lhs)| match op {
+                .foldl(|lhs, (op, rhs)| match op {
                     Token::Multiply => Expr::Mul(Box::new(lhs), Box::new(rhs)),
                     Token::Divide => Expr::Div(Box::new(lhs), Box::new(rhs)),
+                    Token::IntDivide => Expr::IntDiv(Box::new(lhs), Box::new(rhs)),
                     Token::Modulo => Expr::Mod(Box::new(lhs), Box::new(rhs)),
                     _ => unreachable!(),
                 })
@@ -205,7 +320,31 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
                 })
                 .boxed();
 
-            let boolean_1 = binary_2
+            // `a..b` and `a..=b` are sugar for `range`. They don't chain.
+            let ranges = binary_2
+                .clone()
+                .then(
+                    just(Token::DotDot)
+                        .or(just(Token::DotDotEq))
+                        .then(binary_2.clone())
+                        .or_not(),
+                )
+                .map(|(start, end)| match end {
+                    None => start,
+                    Some((Token::DotDot, end)) => {
+                        Expr::Function("range".to_string(), vec![start, end])
+                    }
+                    Some((_, end)) => Expr::Function(
+                        "range".to_string(),
+                        vec![
+                            start,
+                            Expr::Add(Box::new(end), Box::new(Expr::Num(Decimal::ONE))),
+                        ],
+                    ),
+                })
+                .boxed();
+
+            let boolean_1 = ranges
                 .clone()
                 .then(
                     just(Token::GreaterEqual)
@@ -214,17 +353,35 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
                         .or(just(Token::LessThan))
                         .or(just(Token::Equals))
                         .or(just(Token::NotEquals))
-                        .then(binary_2.clone())
+                        .map(|op| match op {
+                            Token::GreaterEqual => Comparison::Ge,
+                            Token::GreaterThan => Comparison::Gt,
+                            Token::LessEqual => Comparison::Le,
+                            Token::LessThan => Comparison::Lt,
+                            Token::Equals => Comparison::Eq,
+                            Token::NotEquals => Comparison::Ne,
+                            _ => unreachable!(),
+                        })
+                        .then(ranges)
                         .repeated(),
                 )
-                .foldl(|lhs, (op, rhs)| match op {
-                    Token::GreaterEqual => Expr::Ge(Box::new(lhs), Box::new(rhs)),
-                    Token::GreaterThan => Expr::Gt(Box::new(lhs), Box::new(rhs)),
-                    Token::LessEqual => Expr::Le(Box::new(lhs), Box::new(rhs)),
-                    Token::LessThan => Expr::Lt(Box::new(lhs), Box::new(rhs)),
-                    Token::Equals => Expr::Eq(Box::new(lhs), Box::new(rhs)),
-                    Token::NotEquals => Expr::Ne(Box::new(lhs), Box::new(rhs)),
-                    _ => unreachable!(),
+                .map(|(lhs, mut rest)| {
+                    if rest.len() > 1 {
+                        return Expr::Chain(Box::new(lhs), rest);
+                    }
+
+                    let Some((comparison, rhs)) = rest.pop() else {
+                        return lhs;
+                    };
+                    let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+                    match comparison {
+                        Comparison::Ge => Expr::Ge(lhs, rhs),
+                        Comparison::Gt => Expr::Gt(lhs, rhs),
+                        Comparison::Le => Expr::Le(lhs, rhs),
+                        Comparison::Lt => Expr::Lt(lhs, rhs),
+                        Comparison::Eq => Expr::Eq(lhs, rhs),
+                        Comparison::Ne => Expr::Ne(lhs, rhs),
+                    }
                 });
 
             let boolean_2 = boolean_1
@@ -244,22 +401,95 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
                 })
                 .boxed();
 
+            // `cond ? a : b` is sugar for `if cond { a } else { b }`. The else side is a whole
+            // expression, so `a ? b : c ? d : e` nests to the right.
+            let ternary = boolean_2
+                .then(
+                    just(Token::Question)
+                        .ignore_then(p.clone())
+                        .then_ignore(just(Token::Colon))
+                        .then(p.clone())
+                        .or_not(),
+                )
+                .map(|(cond, branches)| match branches {
+                    None => cond,
+                    Some((then, otherwise)) => {
+                        Expr::If(Box::new(cond), vec![then], vec![], Some(vec![otherwise]))
+                    }
+                })
+                .boxed();
+
             #[allow(clippy::let_and_return)]
-            boolean_2
+            ternary
         });
 
-        let while_loop = just(Token::While)
-            .ignore_then(expr.clone())
+        // Runs when the loop finishes without a `break`.
+        let loop_else = just(Token::Else).ignore_then(block.clone()).or_not();
+
+        let while_loop = label
+            .clone()
+            .then_ignore(just(Token::While))
+            .then(expr.clone())
             .then(block.clone())
-            .map(|(expr, block)| Expr::While(Box::new(expr), block))
+            .then(loop_else.clone())
+            .map(|(((label, expr), block), else_block)| {
+                Expr::While(label, Box::new(expr), block, else_block)
+            })
             .boxed();
 
-        let for_loop = just(Token::For)
-            .ignore_then(select! {Token::Ident(k) => k})
+        let for_loop = label
+            .clone()
+            .then_ignore(just(Token::For))
+            // `for i, x in` binds the index too.
+            .then(
+                select! {Token::Ident(k) => k}
+                    .then_ignore(just(Token::Comma))
+                    .or_not(),
+            )
+            .then(select! {Token::Ident(k) => k})
             .then_ignore(just(Token::In))
             .then(expr.clone())
             .then(block.clone())
-            .map(|((name, expr), block)| Expr::For(name, Box::new(expr), block))
+            .then(loop_else)
+            .map(|(((((label, index), name), expr), block), else_block)| {
+                Expr::For(label, index, name, Box::new(expr), block, else_block)
+            })
+            .boxed();
+
+        // The last statement of a block may leave off its `;`, like in Rust. An expression's
+        // value is the block's value either way.
+        let terminator = just(Token::Eol)
+            .ignored()
+            .or(just(Token::BlockEnd).rewind().ignored())
+            .or(end());
+
+        let do_while = label
+            .then_ignore(just(Token::Do))
+            .then(block.clone())
+            .then_ignore(just(Token::While))
+            .then(expr.clone())
+            .map(|((label, block), cond)| Expr::DoWhile(label, block, Box::new(cond)))
+            .then_ignore(terminator.clone())
+            .boxed();
+
+        let import = just(Token::Import)
+            .ignore_then(select! { Token::String(parts) => parts })
+            .try_map(
+                |parts, span: Range<usize>| match string_literal(parts, span.clone())? {
+                    Expr::String(path) => Ok(path),
+                    _ => Err(Simple::custom(
+                        span,
+                        "An import path can't have `${}` in it",
+                    )),
+                },
+            )
+            .then(
+                just(Token::As)
+                    .ignore_then(select! { Token::Ident(name) => name })
+                    .or_not(),
+            )
+            .map(|(path, alias)| Expr::Import(path, alias))
+            .then_ignore(terminator.clone())
             .boxed();
 
         let variable_declaration = just(Token::Let)
@@ -267,18 +497,51 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
             .then_ignore(just(Token::AssignTo))
             .then(expr.clone())
             .map(|((_, name), value)| Expr::VariableDeclaration(name, Box::new(value)))
-            .then_ignore(just(Token::Eol))
+            .then_ignore(terminator.clone())
             .boxed();
 
-        let function_declaration = just(Token::Fn)
-            .ignore_then(select! {Token::Ident(n) => n})
+        // `let [a, b, ...rest] := value`
+        let rest = just(Token::Ellipsis).ignore_then(select! { Token::Ident(k) => k });
+        let destructure = just(Token::Let)
+            .ignore_then(
+                select! { Token::Ident(k) => k }
+                    .separated_by(just(Token::Comma))
+                    .at_least(1)
+                    .then(just(Token::Comma).ignore_then(rest.clone()).or_not())
+                    .or(rest.map(|rest| (vec![], Some(rest))))
+                    .delimited_by(just(Token::ArrayStart), just(Token::ArrayEnd)),
+            )
+            .then_ignore(just(Token::AssignTo))
+            .then(expr.clone())
+            .map(|((names, rest), value)| Expr::Destructure(names, rest, Box::new(value)))
+            .then_ignore(terminator.clone())
+            .boxed();
+
+        let constant_declaration = just(Token::Const)
+            .ignore_then(select! { Token::Ident(k) => k })
+            .then_ignore(just(Token::AssignTo))
+            .then(expr.clone())
+            .map(|(name, value)| Expr::ConstantDeclaration(name, Box::new(value)))
+            .then_ignore(terminator.clone())
+            .boxed();
+
+        // `///` comments right before a declaration document it, one line each.
+        let function_declaration = select! {Token::DocComment(line) => line}
+            .repeated()
+            .then_ignore(just(Token::Fn))
+            .then(select! {Token::Ident(n) => n})
             .then(
                 select! {Token::Ident(n) => n}
                     .then_ignore(just(Token::Colon))
                     .then(select! {Token::Ident(t) => t})
-                    .repeated()
-                    .delimited_by(just(Token::LParen), just(Token::RParen))
-                    .separated_by(just(Token::Comma)),
+                    // `name: Type...` takes the rest of the arguments as an array.
+                    .then(just(Token::Ellipsis).or_not().map(|e| e.is_some()))
+                    // `name: Type = value` gives a parameter a default value.
+                    .then(just(Token::Assign).ignore_then(expr.clone()).or_not())
+                    .map(|(((param, t), rest), default)| (param, t, rest, default))
+                    .separated_by(just(Token::Comma))
+                    .allow_trailing()
+                    .delimited_by(just(Token::LParen), just(Token::RParen)),
             )
             .boxed()
             .then(
@@ -288,56 +551,378 @@ pub fn parser() -> impl Parser<Token, Vec<Expr>, Error = Simple<Token>> {
             )
             .then(block.clone())
             .boxed()
-            .map(|(((name, inputs), output_type), block)| {
-                let dt = |s: &str| -> DataType {
-                    DataType::from_str(s).unwrap_or_else(|_| {
-                        println!("Invalid type in function `{name}` signature");
+            .try_map(
+                |((((doc, name), inputs), output_type), block), span: Range<usize>| {
+                    let dt = |s: &str| -> Result<DataType, Simple<Token>> {
+                        data_type(s).ok_or_else(|| {
+                            Simple::custom(
+                                span.clone(),
+                                format!("Invalid type `{s}` in function `{name}` signature"),
+                            )
+                        })
+                    };
 
-                        exit(2);
-                    })
-                };
+                    let output_type = dt(&output_type.unwrap_or("Null".to_string()))?;
+
+                    let invalid = |message: String| Err(Simple::custom(span.clone(), message));
+
+                    let mut inputs = inputs;
+                    let inputs_rest = match inputs.last() {
+                        Some((param, _, true, Some(_))) => {
+                            return invalid(format!(
+                                "Parameter `{param}` of `{name}` takes the rest of the arguments, \
+                                 so it can't have a default value"
+                            ))
+                        }
+                        Some((_, t, true, None)) => {
+                            let rest = dt(t)?;
+                            inputs.pop().map(|(param, ..)| (param, rest))
+                        }
+                        _ => None,
+                    };
+                    if let Some((param, ..)) = inputs.iter().find(|(_, _, rest, _)| *rest) {
+                        return invalid(format!(
+                            "Only the last parameter of `{name}` can take the rest of the \
+                             arguments, not `{param}`"
+                        ));
+                    }
+
+                    // Only trailing parameters can be left out, so once one has a default
+                    // every one after it needs one too.
+                    let first_default = inputs.iter().position(|(.., default)| default.is_some());
+                    if let Some((param, ..)) = first_default
+                        .and_then(|i| inputs[i..].iter().find(|(.., default)| default.is_none()))
+                    {
+                        return invalid(format!(
+                            "Parameter `{param}` of `{name}` needs a default value, since one \
+                             before it has one"
+                        ));
+                    }
+
+                    let mut input_types = vec![];
+                    let mut input_names = vec![];
+                    let mut defaults = vec![];
+                    for (param, t, _, default) in inputs {
+                        input_types.push(dt(&t)?);
+                        input_names.push(param);
+                        defaults.push(default);
+                    }
+                    let inputs_rest = inputs_rest.map(|(param, t)| {
+                        input_names.push(param);
+                        t
+                    });
+
+                    let function = FunctionDescriptor {
+                        inputs: input_types,
+                        inputs_rest,
+                        param_names: &[],
+                        output: output_type,
+                        capabilities: &[],
+                        doc: (!doc.is_empty()).then(|| doc.join("\n")),
+                        function: FunctionType::Custom(
+                            block,
+                            input_names,
+                            defaults.into(),
+                            Arc::default(),
+                        ),
+                    };
 
-                let output_type = dt(&output_type.unwrap_or("Null".to_string()));
+                    Ok(Expr::FunctionDeclaration(name, function))
+                },
+            );
 
-                let inputs = inputs
-                    .iter()
-                    .flatten()
-                    .map(|(name, t)| (name.clone(), dt(t)))
-                    .collect::<Vec<_>>();
+        let fields = select! {Token::Ident(n) => n}
+            .then_ignore(just(Token::Colon))
+            .then(select! {Token::Ident(t) => t})
+            .separated_by(just(Token::Comma))
+            .allow_trailing();
+
+        // `record Point(x: Number, y: Number);` declares `Point` as a function building a record
+        // from its arguments. `struct Point { x: Number, y: Number }` is the same.
+        let record_declaration = just(Token::Record)
+            .ignore_then(select! {Token::Ident(n) => n})
+            .then(
+                fields
+                    .clone()
+                    .delimited_by(just(Token::LParen), just(Token::RParen)),
+            )
+            .then_ignore(just(Token::Eol))
+            .or(just(Token::Struct)
+                .ignore_then(select! {Token::Ident(n) => n})
+                .then(
+                    fields
+                        .then_ignore(just(Token::Eol).or_not())
+                        .delimited_by(just(Token::BlockStart), just(Token::BlockEnd)),
+                ))
+            .try_map(|(name, fields), span| record_constructor(name, fields, span))
+            .boxed();
 
-                let input_types = inputs.iter().map(|(_, t)| t.clone()).collect::<Vec<_>>();
-                let input_names = inputs.iter().map(|(n, _)| n.clone()).collect::<Vec<_>>();
+        // `enum Color { Red, Green, Blue }` declares `Color.Red` and so on.
+        let enum_declaration = just(Token::Enum)
+            .ignore_then(select! {Token::Ident(n) => n})
+            .then(
+                select! {Token::Ident(v) => v}
+                    .separated_by(just(Token::Comma))
+                    .at_least(1)
+                    .allow_trailing()
+                    .then_ignore(just(Token::Eol).or_not())
+                    .delimited_by(just(Token::BlockStart), just(Token::BlockEnd)),
+            )
+            .try_map(|(name, variants), span: Range<usize>| {
+                if !is_enum_name(&name) {
+                    return Err(Simple::custom(
+                        span,
+                        format!("Enum `{name}` should start with a capital letter"),
+                    ));
+                }
+                if let Some(variant) = variants
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, v)| variants[..i].contains(v).then_some(v))
+                {
+                    return Err(Simple::custom(
+                        span,
+                        format!("Variant `{variant}` appears more than once in enum `{name}`"),
+                    ));
+                }
+
+                Ok(Expr::EnumDeclaration(name, variants))
+            })
+            .boxed();
 
-                let function = FunctionDescriptor {
-                    inputs: input_types,
-                    output: output_type,
-                    function: FunctionType::Custom(block, input_names),
+        // `x op= value` is sugar for `x := x op value`.
+        let assignment = select! { Token::Ident(k) => k }
+            .then(
+                expr.clone()
+                    .delimited_by(just(Token::ArrayStart), just(Token::ArrayEnd))
+                    .repeated(),
+            )
+            .then(
+                just(Token::AssignTo)
+                    .or(just(Token::AddAssign))
+                    .or(just(Token::SubAssign))
+                    .or(just(Token::MulAssign))
+                    .or(just(Token::DivAssign))
+                    .or(just(Token::IntDivAssign))
+                    .or(just(Token::ModAssign)),
+            )
+            .then(expr.clone())
+            .then_ignore(terminator.clone())
+            .map(|(((name, path), op), value)| {
+                // What `name[a][b]` reads, for compound assignment.
+                let current =
+                    Box::new(path.iter().fold(Expr::Variable(name.clone()), |acc, step| {
+                        Expr::Function("index".to_string(), vec![acc, step.clone()])
+                    }));
+                let value = Box::new(value);
+
+                let value = match op {
+                    Token::AssignTo => value,
+                    Token::AddAssign => Box::new(Expr::Add(current, value)),
+                    Token::SubAssign => Box::new(Expr::Sub(current, value)),
+                    Token::MulAssign => Box::new(Expr::Mul(current, value)),
+                    Token::DivAssign => Box::new(Expr::Div(current, value)),
+                    Token::IntDivAssign => Box::new(Expr::IntDiv(current, value)),
+                    Token::ModAssign => Box::new(Expr::Mod(current, value)),
+                    _ => unreachable!(),
                 };
 
-                Expr::FunctionDeclaration(name, function)
-            });
-
-        // let variable_change = select! { Token::Ident(k) => k }
-        //     .then_ignore(just(Token::AssignTo))
-        //     .then(expr.clone())
-        //     .then_ignore(just(Token::Eol))
-        //     .map(|(name, value)| Expr::VariableChange(name, Box::new(value)))
-        //     .boxed();
-
-        variable_declaration
-            .or(expr.clone().then_ignore(just(Token::Eol)))
-            .or(while_loop)
-            .or(for_loop)
-            .or(function_declaration)
+                if path.is_empty() {
+                    Expr::Assign(name, value)
+                } else {
+                    Expr::AssignPath(name, path, value)
+                }
+            })
+            .boxed();
+
+        let return_statement = just(Token::Return)
+            .ignore_then(expr.clone().or_not())
+            .then_ignore(terminator.clone())
+            .map(|value| Expr::Return(value.map(Box::new)));
+
+        let assert = just(Token::Assert)
+            .ignore_then(expr.clone())
+            .then(just(Token::Comma).ignore_then(expr.clone()).or_not())
+            .then_ignore(terminator.clone())
+            .map(|(cond, message)| Expr::Assert(Box::new(cond), message.map(Box::new)));
+
+        let target = select! { Token::Label(l) => l }.or_not();
+        let loop_control = just(Token::Break)
+            .ignore_then(target)
+            .then(expr.clone().or_not())
+            .map(|(label, value)| Expr::Break(label, value.map(Box::new)))
+            .or(just(Token::Continue)
+                .ignore_then(target)
+                .map(Expr::Continue))
+            .then_ignore(terminator.clone());
+
+        // A stray `;` is an empty statement, e.g. after a loop, which doesn't need one.
+        just(Token::Eol).repeated().ignore_then(
+            variable_declaration
+                .or(destructure)
+                .or(constant_declaration)
+                .or(assignment)
+                .or(return_statement)
+                .or(assert)
+                .or(loop_control)
+                .or(expr.clone().then_ignore(terminator))
+                .or(while_loop)
+                .or(do_while)
+                .or(for_loop)
+                .or(function_declaration)
+                .or(import)
+                .or(record_declaration)
+                .or(enum_declaration),
+        )
     });
 
-    statement.repeated().then_ignore(end())
+    statement
+        .repeated()
+        .then_ignore(just(Token::Eol).repeated())
+        .then_ignore(end())
+}
+
+/// The type a signature names: a builtin type, or an enum or record for a capitalized name.
+/// Enums and records can be declared later or imported, so whether one of that name exists is
+/// checked when the declaration runs.
+fn data_type(name: &str) -> Option<DataType> {
+    DataType::from_str(name)
+        .ok()
+        .or_else(|| is_enum_name(name).then(|| DataType::Named(name.to_string())))
+}
+
+fn is_enum_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+/// Declares the record `name` with `fields` (name and type), as a function taking the fields
+/// in order.
+fn record_constructor(
+    name: String,
+    fields: Vec<(String, String)>,
+    span: Range<usize>,
+) -> Result<Expr, Simple<Token>> {
+    let mut names: Vec<String> = vec![];
+    let mut types = vec![];
+
+    for (field, t) in fields {
+        if names.contains(&field) {
+            return Err(Simple::custom(
+                span,
+                format!("Field `{field}` appears more than once in record `{name}`"),
+            ));
+        }
+        types.push(data_type(&t).ok_or_else(|| {
+            Simple::custom(
+                span.clone(),
+                format!("Invalid type `{t}` for field `{field}` of record `{name}`"),
+            )
+        })?);
+        names.push(field);
+    }
+
+    let constructor = FunctionDescriptor {
+        inputs: types,
+        inputs_rest: None,
+        param_names: &[],
+        output: DataType::Named(name.clone()),
+        capabilities: &[],
+        doc: None,
+        function: FunctionType::Custom(
+            vec![Expr::Construct(name.clone(), names.clone())],
+            names,
+            Box::new([]),
+            Arc::default(),
+        ),
+    };
+
+    Ok(Expr::FunctionDeclaration(name, constructor))
+}
+
+/// Builds a closure or anonymous function from its parsed signature and body.
+fn function_value(
+    inputs: Vec<(String, Option<String>)>,
+    output_type: Option<String>,
+    default_output: &str,
+    block: Vec<Expr>,
+    span: Range<usize>,
+) -> Result<Expr, Simple<Token>> {
+    let dt = |s: &str| -> Result<DataType, Simple<Token>> {
+        data_type(s).ok_or_else(|| {
+            Simple::custom(
+                span.clone(),
+                format!("Invalid type `{s}` in closure signature"),
+            )
+        })
+    };
+
+    let output_type = dt(output_type.as_deref().unwrap_or(default_output))?;
+
+    let inputs = inputs
+        .iter()
+        .map(|(name, t)| Ok((name.clone(), dt(t.as_deref().unwrap_or("Any"))?)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (input_names, input_types) = inputs.into_iter().unzip();
+
+    let function = FunctionDescriptor {
+        inputs: input_types,
+        inputs_rest: None,
+        param_names: &[],
+        output: output_type,
+        capabilities: &[],
+        doc: None,
+        // Filled in with what it captures when it's evaluated.
+        function: FunctionType::Custom(block, input_names, Box::new([]), Arc::default()),
+    };
+
+    Ok(Expr::FunctionValue(function))
+}
+
+/// Plain strings become `Expr::String`, ones with `${...}` placeholders are parsed into an
+/// `Expr::Interpolate`.
+fn string_literal(parts: Vec<StringPart>, span: Range<usize>) -> Result<Expr, Simple<Token>> {
+    if let [StringPart::Text(text)] = parts.as_slice() {
+        return Ok(Expr::String(text.clone()));
+    }
+
+    parts
+        .into_iter()
+        .map(|part| match part {
+            StringPart::Text(text) => Ok(Expr::String(text)),
+            StringPart::Code(tokens) => match parser().parse(tokens) {
+                Ok(mut exprs) if exprs.len() == 1 => Ok(exprs.remove(0)),
+                Ok(_) => Err(Simple::custom(
+                    span.clone(),
+                    "Expected a single expression inside `${...}`",
+                )),
+                Err(errs) => Err(Simple::custom(
+                    span.clone(),
+                    format!(
+                        "Invalid expression inside `${{...}}`: {}",
+                        error_message(&errs[0])
+                    ),
+                )),
+            },
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Expr::Interpolate)
+}
+
+/// What a parser error says went wrong. chumsky's `Display` only tells what was found and what
+/// was expected, leaving out the message of an error raised with `Simple::custom`, like a
+/// duplicate field in a record.
+pub fn error_message(err: &Simple<Token>) -> String {
+    match err.reason() {
+        SimpleReason::Custom(message) => message.clone(),
+        _ => err.to_string(),
+    }
 }
 
 pub fn print_parser_error(err: Simple<Token>, source: &[Token]) {
     Report::build(ReportKind::Error, err.span())
         .with_code(3)
-        .with_message(err.to_string())
+        .with_message(error_message(&err))
         .with_label(
             Label::new(err.span())
                 .with_message(err.reason().debug())
@@ -0,0 +1,109 @@
+//! Regexes written by scripts. The regex crate never backtracks, so matching takes time linear
+//! in the input; what's left to bound is how big a pattern may compile to and how much input it
+//! runs over, which `RegexLimits` does.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::expr::{error::ExprError, EResult};
+
+/// How many compiled patterns are kept before the cache starts over.
+const MAX_CACHED: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegexLimits {
+    /// The longest pattern accepted, in bytes.
+    pub max_pattern_length: usize,
+    /// The most memory a compiled pattern may take, see `RegexBuilder::size_limit`.
+    pub size_limit: usize,
+    /// The most memory the lazy DFA may use while matching, see
+    /// `RegexBuilder::dfa_size_limit`.
+    pub dfa_size_limit: usize,
+    /// The longest input a pattern is run against, in bytes.
+    pub max_input_length: usize,
+}
+
+impl Default for RegexLimits {
+    fn default() -> Self {
+        Self {
+            max_pattern_length: 10_000,
+            size_limit: 10 << 20,
+            dfa_size_limit: 2 << 20,
+            max_input_length: 16 << 20,
+        }
+    }
+}
+
+/// Compiled patterns by source, so a regex used in a loop is only compiled once. Clones share
+/// the same cache and limits.
+#[derive(Debug, Clone, Default)]
+pub struct RegexCache {
+    limits: Rc<Cell<RegexLimits>>,
+    compiled: Rc<RefCell<HashMap<String, Regex>>>,
+}
+
+impl RegexCache {
+    pub fn limits(&self) -> RegexLimits {
+        self.limits.get()
+    }
+
+    /// Replaces the limits, dropping everything compiled under the old ones.
+    pub fn set_limits(&self, limits: RegexLimits) {
+        self.limits.set(limits);
+        self.compiled.borrow_mut().clear();
+    }
+
+    /// Compiles `pattern` within the limits, or returns the cached copy.
+    pub fn get(&self, pattern: &str) -> EResult<Regex> {
+        if let Some(regex) = self.compiled.borrow().get(pattern) {
+            return Ok(regex.clone());
+        }
+
+        let limits = self.limits();
+        let invalid = |source| ExprError::InvalidRegex {
+            pattern: pattern.to_string(),
+            source,
+        };
+
+        if pattern.len() > limits.max_pattern_length {
+            return Err(invalid(regex::Error::Syntax(format!(
+                "the pattern is {} bytes long, the limit is {}",
+                pattern.len(),
+                limits.max_pattern_length
+            ))));
+        }
+
+        let regex = RegexBuilder::new(pattern)
+            .size_limit(limits.size_limit)
+            .dfa_size_limit(limits.dfa_size_limit)
+            .build()
+            .map_err(invalid)?;
+
+        let mut compiled = self.compiled.borrow_mut();
+        if compiled.len() >= MAX_CACHED {
+            compiled.clear();
+        }
+        compiled.insert(pattern.to_string(), regex.clone());
+
+        Ok(regex)
+    }
+
+    /// Checks that `input` is short enough to run a pattern against.
+    pub fn check_input(&self, input: &str) -> EResult<()> {
+        let limit = self.limits().max_input_length;
+
+        if input.len() > limit {
+            Err(ExprError::RegexInputTooLong {
+                length: input.len(),
+                limit,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
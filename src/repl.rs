@@ -0,0 +1,117 @@
+use std::{
+    io::{self, BufRead, Write},
+    sync::{atomic::Ordering, Arc},
+};
+
+use crate::{
+    data::Data,
+    eval::{eval_with_state, EvalError},
+    expr::{error::ExprError, EResult, ExecutionState},
+    functions::{load_session_descriptor, save_session_descriptor},
+    session::{load_session, save_session},
+};
+
+/// The constant holding every non-null result so far, so `out[3]` recalls an earlier one.
+pub const HISTORY: &str = "out";
+
+/// Reads `input` a line at a time, evaluating each against the same `state` and writing the
+/// results (or errors) to `output`. Returns the exit code when `input` runs out, e.g. on
+/// Ctrl-D, which is 0, or when a line calls `exit`.
+///
+/// The prompt shows the index the next result will get in `out`. Lines starting with `:` are
+/// commands rather than code, see `command`.
+pub fn repl<R, W>(input: R, output: &mut W, state: &mut ExecutionState) -> io::Result<i32>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut lines = input.lines();
+    Arc::make_mut(&mut state.constants).insert(HISTORY.to_string(), Data::Array(vec![]));
+
+    loop {
+        write!(output, "[{}]> ", history(state).len())?;
+        output.flush()?;
+
+        let Some(line) = lines.next() else {
+            writeln!(output)?;
+            return Ok(0);
+        };
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(line) = line.trim().strip_prefix(':') {
+            let result = command(line, state);
+
+            for warning in state.warnings.take() {
+                write!(output, "{}", warning.render("<repl>", line))?;
+            }
+            match result {
+                Ok(true) => {}
+                Ok(false) => writeln!(
+                    output,
+                    "Unknown command `:{line}`, try `:save path` or `:load path`."
+                )?,
+                Err(e) => writeln!(output, "Error: {e}")?,
+            }
+            continue;
+        }
+
+        // Ctrl-C at the prompt shouldn't stop the next line.
+        state.interrupted.store(false, Ordering::Relaxed);
+        let result = eval_with_state(&line, state);
+
+        for warning in state.warnings.take() {
+            write!(output, "{}", warning.render("<repl>", &line))?;
+        }
+
+        match result {
+            Ok(Data::Null) => {}
+            Ok(result) => {
+                let shown = state.show(&result);
+                writeln!(output, "{HISTORY}[{}] = {shown}", history(state).len())?;
+
+                // In place: `out` is the only copy of the results.
+                if let Some(Data::Array(history)) =
+                    Arc::make_mut(&mut state.constants).get_mut(HISTORY)
+                {
+                    history.push(result);
+                }
+            }
+            Err(EvalError::Runtime(ExprError::Interrupted)) => writeln!(output, "interrupted")?,
+            Err(EvalError::Runtime(ExprError::Exit(code))) => return Ok(code),
+            Err(e) => writeln!(output, "Error: {e}")?,
+        }
+    }
+}
+
+/// The results kept in `out` so far.
+fn history(state: &ExecutionState) -> &[Data] {
+    match state.constants.get(HISTORY) {
+        Some(Data::Array(history)) => history,
+        _ => &[],
+    }
+}
+
+/// Runs `:save path` or `:load path`, which do what `save_session` and `load_session` do and
+/// need the same capabilities. Returns false for anything else.
+fn command(line: &str, state: &mut ExecutionState) -> EResult<bool> {
+    let (name, path) = line.split_once(' ').unwrap_or((line, ""));
+    let path = path.trim();
+
+    match name {
+        "save" if !path.is_empty() => {
+            state.check_capabilities("save_session", &save_session_descriptor())?;
+            save_session(state, path)?;
+        }
+        "load" if !path.is_empty() => {
+            state.check_capabilities("load_session", &load_session_descriptor())?;
+            load_session(state, path)?;
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
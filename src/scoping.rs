@@ -0,0 +1,151 @@
+//! Pins down what a loop variable or parameter does to a variable of the same name outside:
+//! it shadows it for the body and the outer value is back afterwards, untouched.
+//!
+//! Blocks and calls get a scope of their own too: what they declare ends with them, and an
+//! assignment changes the variable where it was declared, except in a function body, where it
+//! changes the body's own copy. A body sees the globals and what its closure captured, never the
+//! caller's locals.
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+use crate::{
+    data::Data,
+    eval::{eval_with_state, EvalError},
+    expr::{error::ExprError, ExecutionState},
+    output::OutputSink,
+};
+
+fn run(input: &str) -> Result<Data, EvalError> {
+    eval_with_state(input, &mut ExecutionState::sandboxed())
+}
+
+fn number(input: &str) -> Decimal {
+    match run(input) {
+        Ok(Data::Number(n)) => n,
+        other => panic!("{input:?} gave {other:?}"),
+    }
+}
+
+#[test]
+fn loop_variable_does_not_clobber_outer() {
+    assert_eq!(
+        number("let x := 10; for x in [1, 2, 3] { x; } x;"),
+        dec!(10)
+    );
+    assert_eq!(
+        number("let x := 10; for x in [1, 2, 3] { x := x * 100; } x;"),
+        dec!(10)
+    );
+}
+
+#[test]
+fn loop_variable_is_not_visible_after_loop() {
+    assert!(matches!(
+        run("for i in [1, 2] { i; } i;"),
+        Err(EvalError::Runtime(ExprError::VariableNotFound { .. }))
+    ));
+}
+
+#[test]
+fn parameter_shadows_constant() {
+    assert_eq!(
+        number("fn f(PI: Number) -> Number { PI * 2; } f(3);"),
+        dec!(6)
+    );
+    assert_eq!(
+        number("fn f(PI: Number) -> Number { PI += 1; PI; } f(3);"),
+        dec!(4)
+    );
+    assert_eq!(
+        number("fn f(PI: Number) -> Number { PI; } f(3); PI;"),
+        Decimal::PI
+    );
+}
+
+#[test]
+fn parameter_shadows_global() {
+    assert_eq!(
+        number("let n := 1; fn f(n: Number) -> Number { n := n + 10; n; } f(5) + n;"),
+        dec!(16)
+    );
+}
+
+#[test]
+fn nested_loops_reuse_name() {
+    let mut state = ExecutionState::sandboxed();
+    state.output = OutputSink::capture();
+
+    eval_with_state(
+        "for x in [1, 2] { for x in [10, 20] { print(x); } print(x); }",
+        &mut state,
+    )
+    .unwrap();
+
+    assert_eq!(state.output.captured(), "1020110202");
+}
+
+#[test]
+fn let_in_body_does_not_persist_across_iterations() {
+    let mut state = ExecutionState::sandboxed();
+    state.output = OutputSink::capture();
+
+    eval_with_state(
+        "for i in [1, 2, 3] { let seen := 0; seen += i; print(seen); }",
+        &mut state,
+    )
+    .unwrap();
+
+    assert_eq!(state.output.captured(), "123");
+    assert!(matches!(
+        run("for i in [1, 2] { if i == 2 { seen; }; let seen := i; }"),
+        Err(EvalError::Runtime(ExprError::VariableNotFound { .. }))
+    ));
+}
+
+#[test]
+fn assignment_in_block_changes_outer() {
+    assert_eq!(
+        number("let x := 1; if true { x := 2; }; { x += 1; }; x;"),
+        dec!(3)
+    );
+    assert_eq!(
+        number("let n := 0; for i in [1, 2, 3] { if i > 1 { n += i; }; } n;"),
+        dec!(5)
+    );
+}
+
+#[test]
+fn declarations_end_with_their_block() {
+    assert!(matches!(
+        run("if true { let y := 1; }; y;"),
+        Err(EvalError::Runtime(ExprError::VariableNotFound { .. }))
+    ));
+    assert!(run("{ fn g() -> Number { 1; } } g();").is_err());
+    assert_eq!(number("let y := 1; { let y := 2; y; }; y;"), dec!(1));
+}
+
+#[test]
+fn function_does_not_see_caller_locals() {
+    assert!(matches!(
+        run("fn read() -> Number { local; } fn f() -> Number { let local := 1; read(); } f();"),
+        Err(EvalError::Runtime(ExprError::VariableNotFound { name })) if name == "local"
+    ));
+    assert!(matches!(
+        run("fn read() -> Number { i; } for i in [1] { read(); }"),
+        Err(EvalError::Runtime(ExprError::VariableNotFound { .. }))
+    ));
+}
+
+#[test]
+fn assignment_in_function_does_not_reach_caller() {
+    assert_eq!(
+        number("let g := 1; fn f() -> Number { g := 5; g; } f() + g;"),
+        dec!(6)
+    );
+    // Functions it calls see the global, not its copy.
+    assert_eq!(
+        number("let g := 1; fn read() -> Number { g; } fn f() -> Number { g := 5; read(); } f();"),
+        dec!(1)
+    );
+}
@@ -0,0 +1,136 @@
+//! Saves what a session has defined, its variables and custom functions, to a JSON file and
+//! loads it back into another, so functions built up in the REPL outlive it. Builtins and
+//! constants aren't saved, every state has them already.
+
+use std::{collections::BTreeMap, fs, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::Data,
+    expr::{error::ExprError, EResult, ExecutionState},
+    functions::{builtints, FunctionDescriptor, FunctionType},
+    warning::{Warning, WarningKind},
+};
+
+/// Bumped whenever the file layout changes. Files of any other version are refused.
+pub const SESSION_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Session {
+    version: u32,
+    variables: BTreeMap<String, Data>,
+    functions: BTreeMap<String, FunctionDescriptor>,
+}
+
+/// Read before the rest of the file, so a file from another version is reported as such
+/// rather than as whatever part of it no longer parses.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+fn is_custom(desc: &FunctionDescriptor) -> bool {
+    matches!(desc.function, FunctionType::Custom(..))
+}
+
+/// Writes the variables and custom functions of `state` to `path`. Variables holding a builtin
+/// are left out, a builtin inside an array or map can't be saved and is an error.
+pub fn save_session(state: &ExecutionState, path: &str) -> EResult<()> {
+    let builtins = builtints();
+
+    let session = Session {
+        version: SESSION_VERSION,
+        variables: state
+            .variables
+            .visible()
+            .into_iter()
+            .filter(|(_, value)| !matches!(value, Data::Function(desc) if !is_custom(desc)))
+            .collect(),
+        // `args` is a builtin written as a custom function, it's set up again on every run.
+        functions: state
+            .functions
+            .iter()
+            .filter(|(name, desc)| is_custom(desc) && !builtins.get(*name).is_some_and(is_custom))
+            .map(|(name, desc)| (name.clone(), desc.clone()))
+            .collect(),
+    };
+
+    let invalid = |e: serde_json::Error| ExprError::InvalidFormat {
+        path: path.to_string(),
+        reason: e.to_string(),
+    };
+    let json = serde_json::to_string_pretty(&session).map_err(invalid)?;
+
+    fs::write(path, json).map_err(|source| ExprError::IoError {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Loads the session saved at `path` into `state`. Anything of the same name is overwritten,
+/// with a warning listing what was replaced.
+pub fn load_session(state: &mut ExecutionState, path: &str) -> EResult<()> {
+    let json = fs::read_to_string(path).map_err(|source| ExprError::IoError {
+        path: path.to_string(),
+        source,
+    })?;
+    let invalid = |reason: String| ExprError::InvalidFormat {
+        path: path.to_string(),
+        reason,
+    };
+
+    let header = serde_json::from_str::<Header>(&json).map_err(|e| invalid(e.to_string()))?;
+    if header.version != SESSION_VERSION {
+        return Err(invalid(format!(
+            "it is version {}, this interpreter reads version {SESSION_VERSION}",
+            header.version
+        )));
+    }
+    let session = serde_json::from_str::<Session>(&json).map_err(|e| invalid(e.to_string()))?;
+
+    let mut clobbered = vec![];
+    overwrite(
+        |name, value| state.variables.set(&name, value),
+        session.variables,
+        &mut clobbered,
+    );
+    let functions = Arc::make_mut(&mut state.functions);
+    overwrite(
+        |name, desc| functions.insert(name, desc),
+        session.functions,
+        &mut clobbered,
+    );
+
+    if !clobbered.is_empty() {
+        clobbered.sort();
+        state.warnings.warn(Warning {
+            kind: WarningKind::SessionOverwrite,
+            message: format!(
+                "Loading `{path}` replaced {}.",
+                clobbered
+                    .iter()
+                    .map(|name| format!("`{name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            span: None,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Inserts everything in `loaded` with `insert`, adding the names of any different values it
+/// replaced to `clobbered`.
+fn overwrite<T: PartialEq + Clone>(
+    mut insert: impl FnMut(String, T) -> Option<T>,
+    loaded: BTreeMap<String, T>,
+    clobbered: &mut Vec<String>,
+) {
+    for (name, value) in loaded {
+        if insert(name.clone(), value.clone()).is_some_and(|old| old != value) {
+            clobbered.push(name);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::{format, Debug, Display, Write};
+use std::fmt::{Debug, Display, Write};
 
 pub fn indent(string: &str) -> String {
     string.lines().fold(String::new(), |mut output, l| {
@@ -0,0 +1,122 @@
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use ariadne::{Color, Label, Report, ReportKind, Source};
+
+use crate::expr::{error::ExprError, EResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    /// A statement that comes after one that always leaves the block.
+    Unreachable,
+    /// An `if`, `elif` or `while` whose condition is a literal.
+    ConstantCondition,
+    /// A function declaration with the same name as a builtin.
+    BuiltinShadowing,
+    /// A call with the wrong number of arguments, or an argument that is obviously the wrong
+    /// type.
+    ArgumentMismatch,
+    /// A loaded session replacing a variable or function that was already defined.
+    SessionOverwrite,
+}
+
+impl WarningKind {
+    /// The name used in `// allow(...)` comments.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unreachable | Self::ConstantCondition => "unreachable",
+            Self::BuiltinShadowing => "shadowing",
+            Self::ArgumentMismatch => "arguments",
+            Self::SessionOverwrite => "overwrite",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    /// Where in the source the warning applies. Warnings found while running have none.
+    pub span: Option<Range<usize>>,
+}
+
+impl Warning {
+    /// The warning as the CLI shows it, pointing into `input`, which was read from `path`.
+    pub fn render(&self, path: &str, input: &str) -> String {
+        let Some(span) = &self.span else {
+            return format!("Warning: {}\n", self.message);
+        };
+
+        let mut out = vec![];
+        Report::build(ReportKind::Warning, (path, span.clone()))
+            .with_message(&self.message)
+            .with_label(
+                Label::new((path, span.clone()))
+                    .with_message(format!(
+                        "add `// allow({})` to the end of the line to silence this",
+                        self.kind.name()
+                    ))
+                    .with_color(Color::Yellow),
+            )
+            .finish()
+            .write((path, Source::from(input)), &mut out)
+            .unwrap();
+
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    pub fn print(&self, path: &str, input: &str) {
+        eprint!("{}", self.render(path, input));
+    }
+}
+
+/// What to do with warnings, set by `--deny-warnings` and `--no-warnings`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WarningPolicy {
+    #[default]
+    Warn,
+    Deny,
+    Ignore,
+}
+
+/// Collects warnings from the static check and from execution. Clones share the same
+/// warnings, so every state cloned from one that has a sink reports into it.
+#[derive(Debug, Clone, Default)]
+pub struct WarningSink {
+    warnings: Rc<RefCell<Vec<Warning>>>,
+    pub policy: WarningPolicy,
+}
+
+impl WarningSink {
+    pub fn new(policy: WarningPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// Records `warning`, or fails with it if warnings are denied.
+    pub fn warn(&self, warning: Warning) -> EResult<()> {
+        match self.policy {
+            WarningPolicy::Warn => {
+                let mut warnings = self.warnings.borrow_mut();
+                // Blocks run many times in loops and functions, only report each once.
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+            WarningPolicy::Deny => {
+                return Err(ExprError::DeniedWarning {
+                    message: warning.message,
+                })
+            }
+            WarningPolicy::Ignore => {}
+        }
+
+        Ok(())
+    }
+
+    /// Removes and returns everything collected so far.
+    pub fn take(&self) -> Vec<Warning> {
+        self.warnings.take()
+    }
+}